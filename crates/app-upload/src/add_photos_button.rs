@@ -0,0 +1,79 @@
+use gloo::file::{Blob, FileList};
+use leptos::{logging::debug_warn, prelude::*, task::spawn_local};
+use models::{FileSize, ImageRecordId};
+use send_wrapper::SendWrapper;
+use web_sys::Event;
+
+use crate::{MAX_UPLOAD_SIZE, photo::upload_action_fn};
+
+/// A button for uploading additional photos. `on_uploaded` is called with
+/// the [`ImageRecordId`] of each photo once it has been uploaded.
+#[component]
+pub fn AddPhotosButton(on_uploaded: Callback<ImageRecordId>) -> impl IntoView {
+  use lsc::icons::*;
+
+  let class = "bg-base-2 dark:bg-basedark-2 text-base-dim border-2 \
+               border-dashed border-base-8 dark:border-basedark-8 rounded-lg \
+               flex flex-row items-center justify-center gap-2 p-4 \
+               cursor-pointer";
+
+  let pending = RwSignal::new(0_usize);
+  let error = RwSignal::new(None::<String>);
+
+  let handler = move |ev: Event| {
+    // get file list
+    let element: web_sys::HtmlInputElement = event_target(&ev);
+    let Some(file_list) = element.files() else {
+      debug_warn!("failed to get file list of event target");
+      return;
+    };
+
+    error.set(None);
+    for file in FileList::from(file_list).iter() {
+      if file.size() > MAX_UPLOAD_SIZE {
+        error.set(Some(format!(
+          "\"{}\" is too large ({}).",
+          file.name(),
+          FileSize::new(file.size())
+        )));
+        continue;
+      }
+
+      let blob = SendWrapper::new(Blob::from(file.clone()));
+      pending.update(|p| *p += 1);
+      spawn_local(async move {
+        match upload_action_fn(blob).await {
+          Ok(image_id) => on_uploaded.run(image_id),
+          Err(e) => error.set(Some(e)),
+        }
+        pending.update(|p| *p -= 1);
+      });
+    }
+
+    // reset input
+    element.set_value("");
+  };
+
+  let status_text = move || match pending() {
+    0 => None,
+    1 => Some("Uploading 1 photo...".to_owned()),
+    n => Some(format!("Uploading {n} photos...")),
+  };
+
+  view! {
+    <div class="flex flex-col gap-1">
+      <label class=class for="add-photos-input">
+        <UploadIcon {..} class="size-6" />
+        <span>"Add Photos"</span>
+        <input
+          type="file" class="hidden" id="add-photos-input" accept="image/*"
+          multiple="multiple" on:change=handler
+        />
+      </label>
+      <p class="text-sm text-base-dim">{ status_text }</p>
+      <p class="text-sm text-dangera-11 dark:text-dangerdarka-11">
+        { move || error() }
+      </p>
+    </div>
+  }
+}
@@ -7,13 +7,13 @@ use std::collections::HashMap;
 
 use base_components::Section;
 use leptos::prelude::*;
-use models::{Ulid, UsdPriceNaive};
+use models::{LicenseOffer, PhotoGroupMetadata, Ulid};
 use next_step_button::NextStepButton;
 use reactive_stores::Store;
 
 pub use self::uploaded_photo::UploadedPhoto;
 use self::{
-  group_configurator::GroupConfigurator,
+  group_configurator::{GroupConfigurator, GroupMetadataConfigurator},
   uploaded_photo_preview::UploadedPhotoPreviewer,
 };
 
@@ -32,6 +32,10 @@ pub(super) fn ConfiguringGroupStep() -> impl IntoView {
       </div>
     </Section>
 
+    <Section>
+      <GroupMetadataConfigurator />
+    </Section>
+
     <Section>
       <UploadedPhotoPreviewer />
     </Section>
@@ -40,6 +44,7 @@ pub(super) fn ConfiguringGroupStep() -> impl IntoView {
 
 #[derive(Debug, Store)]
 pub(super) struct ConfiguringGroupState {
-  pub photos:             HashMap<Ulid, UploadedPhoto>,
-  pub usage_rights_price: Option<UsdPriceNaive>,
+  pub photos:   HashMap<Ulid, UploadedPhoto>,
+  pub licenses: Option<Vec<LicenseOffer>>,
+  pub meta:     PhotoGroupMetadata,
 }
@@ -20,7 +20,7 @@ pub(super) fn NextStepButton() -> impl IntoView {
   let photos = state.photos();
 
   let ready_to_advance = Memo::new(move |_| {
-    state.usage_rights_price().get().is_some() && !photos.read().is_empty()
+    state.licenses().read().is_some() && !photos.read().is_empty()
   });
 
   let disabled_signal = Signal::derive(move || !ready_to_advance());
@@ -31,13 +31,13 @@ pub(super) fn NextStepButton() -> impl IntoView {
       .values()
       .map(super::uploaded_photo::UploadedPhoto::image_id)
       .collect::<Vec<_>>();
-    let usage_rights_price = state
-      .usage_rights_price()
-      .get()
-      .expect("`usage_rights_price` is `None`");
-    create_photo_group_from_images(artifact_ids, PhotoGroupConfig {
-      usage_rights_price,
-    })
+    let licenses = state.licenses().get().expect("`licenses` is `None`");
+    let meta = state.meta().get();
+    create_photo_group_from_images(
+      artifact_ids,
+      PhotoGroupConfig { licenses },
+      meta,
+    )
   });
 
   let handler = move |_| {
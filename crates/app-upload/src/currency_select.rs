@@ -0,0 +1,38 @@
+use std::str::FromStr;
+
+use leptos::prelude::*;
+use models::Currency;
+
+/// A dropdown for choosing a price's currency.
+#[component]
+pub fn CurrencySelect(
+  /// The selected currency.
+  currency: RwSignal<Currency>,
+) -> impl IntoView {
+  let class = "h-10 rounded-md px-2 text-base text-base-12 \
+               dark:text-basedark-12 bg-surface dark:bg-surfacedark border \
+               border-graya-7 dark:border-graydarka-7 outline-none \
+               focus:outline-primary-8 focus:dark:outline-primarydark-8";
+
+  let options = Currency::ALL
+    .iter()
+    .map(|&c| {
+      let code = c.code();
+      view! {
+        <option value=code selected=move || currency() == c>{ code }</option>
+      }
+    })
+    .collect_view();
+
+  let on_change = move |e| {
+    if let Ok(c) = Currency::from_str(&event_target_value(&e)) {
+      currency.set(c);
+    }
+  };
+
+  view! {
+    <select class=class id="currency" on:change=on_change>
+      { options }
+    </select>
+  }
+}
@@ -3,7 +3,11 @@
 
 //! The photo upload flow for `PicturePro`.
 
+mod add_photos_button;
 mod configuring_group;
+mod currency_select;
+mod license_pricing;
+mod metadata_fields;
 mod photo;
 mod selecting_photos;
 mod server_fns;
@@ -17,13 +21,49 @@ use reactive_stores::Store;
 pub(crate) const MAX_UPLOAD_SIZE: u64 = 50 * 1000 * 1000; // 50MB
 
 use base_components::{Section, Title};
+use models::{
+  Currency, Money, ParseMoneyError, photo_group_usage_rights_minimum_price,
+};
 
+pub use self::{
+  add_photos_button::AddPhotosButton,
+  currency_select::CurrencySelect,
+  license_pricing::{
+    LicensePriceInput, LicensePricingFields, validate_license_inputs,
+  },
+  metadata_fields::MetadataFields,
+};
 use self::{
   configuring_group::{ConfiguringGroupState, ConfiguringGroupStep},
   selecting_photos::{SelectingPhotosState, SelectingPhotosStep},
   upload_finished::{UploadFinishedState, UploadFinishedStep},
 };
 
+/// Validates a user-entered photo group price.
+pub fn validate_price_input(
+  input: &str,
+  currency: Currency,
+) -> Result<Money, String> {
+  let price = Money::parse(input, currency).map_err(|e| match e {
+    ParseMoneyError::Empty => "Price is required.".to_owned(),
+    ParseMoneyError::Negative => "Price cannot be negative.".to_owned(),
+    ParseMoneyError::TooPrecise(currency) => match currency.exponent() {
+      0 => format!("{currency} prices can't have decimal places."),
+      n => format!("{currency} prices can have at most {n} decimal places."),
+    },
+    ParseMoneyError::Overflow => "Price is too large.".to_owned(),
+    ParseMoneyError::Invalid | ParseMoneyError::UnknownCurrency(_) => {
+      "Price must be a number.".to_owned()
+    }
+  })?;
+
+  let minimum = photo_group_usage_rights_minimum_price(currency);
+  if price < minimum {
+    return Err(format!("Minimum price is {minimum}."));
+  }
+  Ok(price)
+}
+
 /// The upload photo page.
 #[component]
 pub fn UploadPhotoPage() -> impl IntoView {
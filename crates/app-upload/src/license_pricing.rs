@@ -0,0 +1,149 @@
+use leptos::prelude::*;
+use models::{Currency, LicenseOffer, LicenseTier};
+
+use crate::{CurrencySelect, validate_price_input};
+
+/// The user-entered prices for a [`LicenseTier`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct LicensePriceInput {
+  /// The license tier.
+  pub tier:        LicenseTier,
+  /// The entered price for the whole group.
+  pub group_price: String,
+  /// The entered price for a single photo.
+  pub photo_price: String,
+}
+
+impl LicensePriceInput {
+  /// Inputs for every license tier, prefilled from existing offers.
+  pub fn from_offers(offers: &[LicenseOffer]) -> Vec<Self> {
+    LicenseTier::ALL
+      .iter()
+      .map(|&tier| {
+        let offer = offers.iter().find(|o| o.tier == tier);
+        Self {
+          tier,
+          group_price: offer
+            .map(|o| o.group_price.to_decimal_string())
+            .unwrap_or_default(),
+          photo_price: offer
+            .and_then(|o| o.photo_price)
+            .map(|p| p.to_decimal_string())
+            .unwrap_or_default(),
+        }
+      })
+      .collect()
+  }
+
+  /// Whether anything has been entered for the tier.
+  pub fn is_empty(&self) -> bool {
+    self.group_price.trim().is_empty() && self.photo_price.trim().is_empty()
+  }
+}
+
+/// Validates user-entered license prices. Tiers without a group price aren't
+/// offered.
+pub fn validate_license_inputs(
+  inputs: &[LicensePriceInput],
+  currency: Currency,
+) -> Result<Vec<LicenseOffer>, String> {
+  let mut offers = Vec::new();
+  for input in inputs.iter().filter(|i| !i.is_empty()) {
+    let tier = input.tier;
+    if input.group_price.trim().is_empty() {
+      return Err(format!(
+        "{tier} needs a full set price to sell single photos."
+      ));
+    }
+    let group_price = validate_price_input(input.group_price.trim(), currency)
+      .map_err(|e| format!("{tier}: {e}"))?;
+    let photo_price = match input.photo_price.trim() {
+      "" => None,
+      p => Some(
+        validate_price_input(p, currency)
+          .map_err(|e| format!("{tier} single photo: {e}"))?,
+      ),
+    };
+    offers.push(LicenseOffer {
+      tier,
+      group_price,
+      photo_price,
+    });
+  }
+
+  if offers.is_empty() {
+    return Err("At least one license needs a price.".to_owned());
+  }
+  Ok(offers)
+}
+
+/// Inputs for a photo group's license tier prices.
+#[component]
+pub fn LicensePricingFields(
+  /// The entered prices, one per license tier.
+  inputs: RwSignal<Vec<LicensePriceInput>>,
+  /// The currency the prices are in.
+  currency: RwSignal<Currency>,
+) -> impl IntoView {
+  use lsc::field::*;
+
+  let rows = (0..inputs.read_untracked().len())
+    .map(|i| {
+      let tier = inputs.read_untracked()[i].tier;
+      let group_id = format!("group-price-{i}");
+      let photo_id = format!("photo-price-{i}");
+
+      let read_group = move || inputs.read()[i].group_price.clone();
+      let write_group = move |e| {
+        let value = event_target_value(&e);
+        inputs.update(|v| v[i].group_price = value);
+      };
+      let read_photo = move || inputs.read()[i].photo_price.clone();
+      let write_photo = move |e| {
+        let value = event_target_value(&e);
+        inputs.update(|v| v[i].photo_price = value);
+      };
+
+      view! {
+        <div class="flex flex-col gap-2">
+          <div>
+            <p class="font-semibold">{ tier.name() }</p>
+            <p class="text-sm text-base-dim">{ tier.description() }</p>
+          </div>
+          <div class="flex flex-row flex-wrap gap-4">
+            <div class="flex flex-col gap-1">
+              <label class="text-sm text-base-dim" for=group_id.clone()>
+                "Full Set"
+              </label>
+              <Field size={FieldSize::Large} {..}
+                placeholder="Not offered" id=group_id type="text"
+                inputmode="decimal"
+                on:input=write_group prop:value=read_group
+              />
+            </div>
+            <div class="flex flex-col gap-1">
+              <label class="text-sm text-base-dim" for=photo_id.clone()>
+                "Single Photo"
+              </label>
+              <Field size={FieldSize::Large} {..}
+                placeholder="Not sold alone" id=photo_id type="text"
+                inputmode="decimal"
+                on:input=write_photo prop:value=read_photo
+              />
+            </div>
+          </div>
+        </div>
+      }
+    })
+    .collect_view();
+
+  view! {
+    <div class="flex flex-col gap-4">
+      <div class="flex flex-row items-center gap-2">
+        <label class="text-base-dim" for="currency">"Currency"</label>
+        <CurrencySelect currency=currency />
+      </div>
+      { rows }
+    </div>
+  }
+}
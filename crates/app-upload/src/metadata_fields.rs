@@ -0,0 +1,101 @@
+use std::str::FromStr;
+
+use leptos::prelude::*;
+use models::PhotoGroupMetadata;
+
+fn optional(value: String) -> Option<String> {
+  (!value.trim().is_empty()).then_some(value)
+}
+
+/// Inputs for a photo group's descriptive metadata.
+#[component]
+pub fn MetadataFields(
+  /// The metadata being edited.
+  meta: RwSignal<PhotoGroupMetadata>,
+) -> impl IntoView {
+  use lsc::field::*;
+
+  let textarea_class =
+    "w-full min-h-32 rounded-md p-3 text-base text-base-12 \
+     dark:text-basedark-12 bg-surface dark:bg-surfacedark border \
+     border-graya-7 dark:border-graydarka-7 outline-none \
+     outline-offset-[-1px] focus:outline-primary-8 \
+     focus:dark:outline-primarydark-8 placeholder:text-basea-10 \
+     placeholder:dark:text-basedarka-10";
+
+  let read_title = move || meta.read().title.clone().unwrap_or_default();
+  let write_title = move |e| {
+    meta.update(|m| m.title = optional(event_target_value(&e)));
+  };
+  let read_description =
+    move || meta.read().description.clone().unwrap_or_default();
+  let write_description = move |e| {
+    meta.update(|m| m.description = optional(event_target_value(&e)));
+  };
+  let read_event_date = move || {
+    meta
+      .read()
+      .event_date
+      .map(|d| d.to_string())
+      .unwrap_or_default()
+  };
+  let write_event_date = move |e| {
+    let value = event_target_value(&e);
+    meta.update(|m| m.event_date = chrono::NaiveDate::from_str(&value).ok());
+  };
+  let read_location = move || meta.read().location.clone().unwrap_or_default();
+  let write_location = move |e| {
+    meta.update(|m| m.location = optional(event_target_value(&e)));
+  };
+  // tags are kept as typed until the group is saved, where they're normalized
+  let read_tags = move || meta.read().tags.join(", ");
+  let write_tags = move |e| {
+    let value = event_target_value(&e);
+    meta.update(|m| {
+      m.tags = value.split(',').map(str::to_owned).collect();
+    });
+  };
+
+  view! {
+    <div class="flex flex-col gap-4">
+      <div class="flex flex-col gap-1">
+        <label class="text-base-dim" for="title">"Title"</label>
+        <Field size={FieldSize::Large} {..}
+          placeholder="Spring Formal 2025" id="title" type="text"
+          on:input=write_title prop:value=read_title
+        />
+      </div>
+      <div class="flex flex-col gap-1">
+        <label class="text-base-dim" for="description">"Description"</label>
+        <textarea
+          class=textarea_class id="description"
+          placeholder="Supports *Markdown*."
+          on:input=write_description prop:value=read_description
+        />
+      </div>
+      <div class="flex flex-col gap-4 sm:flex-row">
+        <div class="flex flex-col gap-1">
+          <label class="text-base-dim" for="event-date">"Event Date"</label>
+          <Field size={FieldSize::Large} {..}
+            id="event-date" type="date"
+            on:input=write_event_date prop:value=read_event_date
+          />
+        </div>
+        <div class="flex flex-col gap-1 flex-1">
+          <label class="text-base-dim" for="location">"Location"</label>
+          <Field size={FieldSize::Large} {..}
+            placeholder="Main Hall" id="location" type="text"
+            on:input=write_location prop:value=read_location
+          />
+        </div>
+      </div>
+      <div class="flex flex-col gap-1">
+        <label class="text-base-dim" for="tags">"Tags"</label>
+        <Field size={FieldSize::Large} {..}
+          placeholder="formal, dance, 2025" id="tags" type="text"
+          on:input=write_tags prop:value=read_tags
+        />
+      </div>
+    </div>
+  }
+}
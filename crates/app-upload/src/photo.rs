@@ -118,7 +118,7 @@ pub(super) enum PhotoUploadStatus {
   Oversized(FileSize),
 }
 
-async fn upload_action_fn(
+pub(crate) async fn upload_action_fn(
   blob: SendWrapper<Blob>,
 ) -> Result<ImageRecordId, String> {
   use gloo::net::http::*;
@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 
 use leptos::prelude::*;
+use models::PhotoGroupMetadata;
 use reactive_stores::Store;
 
 use crate::{
@@ -54,8 +55,9 @@ pub(super) fn NextStepButton() -> impl IntoView {
       .collect();
 
     let new_state = ConfiguringGroupState {
-      photos:             uploaded_photos,
-      usage_rights_price: None,
+      photos:   uploaded_photos,
+      licenses: None,
+      meta:     PhotoGroupMetadata::default(),
     };
     *context.write() = UploadState::ConfiguringGroup(new_state);
   };
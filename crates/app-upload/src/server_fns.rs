@@ -1,5 +1,7 @@
 use leptos::prelude::*;
-use models::{ImageRecordId, PhotoGroupConfig, PhotoGroupRecordId};
+use models::{
+  ImageRecordId, PhotoGroupConfig, PhotoGroupMetadata, PhotoGroupRecordId,
+};
 
 /// Create a [`PhotoGroup`](models::PhotoGroup) from a list of
 /// [`Artifact`](models::Artifact)s and a [`PhotoGroupConfig`].
@@ -9,6 +11,8 @@ pub async fn create_photo_group_from_images(
   image_ids: Vec<ImageRecordId>,
   /// The photo group config to use.
   config: PhotoGroupConfig,
+  /// The photo group metadata to use.
+  meta: PhotoGroupMetadata,
 ) -> Result<PhotoGroupRecordId, ServerFnError> {
   use models::AuthStatus;
   use prime_domain::{CreatePhotoGroupFromImagesError, PrimeDomainService};
@@ -21,13 +25,16 @@ pub async fn create_photo_group_from_images(
   let pd: PrimeDomainService = expect_context();
 
   match pd
-    .create_photo_group_from_images(image_ids, config, user.id)
+    .create_photo_group_from_images(image_ids, config, meta, user.id)
     .await
   {
     Ok(pg) => Ok(pg),
     Err(CreatePhotoGroupFromImagesError::MissingImage(a)) => {
       Err(ServerFnError::new(format!("missing artifact: {a}")))
     }
+    Err(CreatePhotoGroupFromImagesError::InvalidConfig(e)) => {
+      Err(ServerFnError::new(format!("invalid config: {e}")))
+    }
     Err(e) => {
       tracing::error!("failed to create photo group from artifacts: {e}");
       Err(ServerFnError::new("internal error"))
@@ -0,0 +1,231 @@
+//! Changing a user's own account details, and deleting their account.
+
+use models::{
+  EmailAddress, HumanName, User, UserAuthCredentials, UserRecordId,
+};
+use repos::{
+  DeleteModelError, FetchModelByIndexError, FetchModelError, PatchModelError,
+};
+use tracing::instrument;
+
+use crate::{
+  hash_password, AuthDomainService, Reauthentication, ReauthenticationError,
+};
+
+/// An error that occurs when changing a user's name.
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum ChangeNameError {
+  /// Indicates that the user doesn't exist.
+  #[error("The user doesn't exist")]
+  UserNotFound,
+  /// Indicates that an error occurred while fetching the user.
+  #[error("Failed to fetch user")]
+  FetchError(#[from] FetchModelError),
+  /// Indicates that an error occurred while updating the user.
+  #[error("Failed to update user")]
+  PatchError(#[from] PatchModelError),
+}
+
+/// An error that occurs when changing a user's email address.
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum ChangeEmailError {
+  /// Indicates that the user doesn't exist.
+  #[error("The user doesn't exist")]
+  UserNotFound,
+  /// Indicates that it couldn't be confirmed that it's the user.
+  #[error("Failed to confirm it's the user: {0}")]
+  Reauthentication(#[from] ReauthenticationError),
+  /// Indicates that the new email address is already in use.
+  #[error("The email address is already in use: \"{0}\"")]
+  EmailAlreadyUsed(EmailAddress),
+  /// Indicates that an error occurred while fetching the user.
+  #[error("Failed to fetch user")]
+  FetchError(#[from] FetchModelError),
+  /// Indicates that an error occurred while fetching users by index.
+  #[error("Failed to fetch users by index")]
+  FetchByIndexError(#[from] FetchModelByIndexError),
+  /// Indicates that an error occurred while updating the user.
+  #[error("Failed to update user")]
+  PatchError(#[from] PatchModelError),
+}
+
+/// An error that occurs when changing a user's password.
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum ChangePasswordError {
+  /// Indicates that the user doesn't exist.
+  #[error("The user doesn't exist")]
+  UserNotFound,
+  /// Indicates that it couldn't be confirmed that it's the user.
+  #[error("Failed to confirm it's the user: {0}")]
+  Reauthentication(#[from] ReauthenticationError),
+  /// Indicates than an error occurred while hashing the password.
+  #[error("Failed to hash password")]
+  PasswordHashing(miette::Report),
+  /// Indicates that an error occurred while fetching the user.
+  #[error("Failed to fetch user")]
+  FetchError(#[from] FetchModelError),
+  /// Indicates that an error occurred while updating the user.
+  #[error("Failed to update user")]
+  PatchError(#[from] PatchModelError),
+}
+
+/// An error that occurs when confirming it's a user.
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum ConfirmIdentityError {
+  /// Indicates that the user doesn't exist.
+  #[error("The user doesn't exist")]
+  UserNotFound,
+  /// Indicates that it couldn't be confirmed that it's the user.
+  #[error("Failed to confirm it's the user: {0}")]
+  Reauthentication(#[from] ReauthenticationError),
+  /// Indicates that an error occurred while fetching the user.
+  #[error("Failed to fetch user")]
+  FetchError(#[from] FetchModelError),
+}
+
+/// An error that occurs when deleting a user.
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum DeleteUserError {
+  /// Indicates that the user doesn't exist.
+  #[error("The user doesn't exist")]
+  UserNotFound,
+  /// Indicates that an error occurred while deleting the user.
+  #[error("Failed to delete user")]
+  DeleteError(#[from] DeleteModelError),
+}
+
+impl AuthDomainService {
+  /// Change a [`User`]'s name.
+  #[instrument(skip(self))]
+  pub async fn change_name(
+    &self,
+    id: UserRecordId,
+    name: HumanName,
+  ) -> Result<User, ChangeNameError> {
+    let mut user = self
+      .user_repo
+      .fetch_user_by_id(id)
+      .await?
+      .ok_or(ChangeNameError::UserNotFound)?;
+    user.name = name;
+    Ok(self.user_repo.update_user(user).await?)
+  }
+
+  /// Change a [`User`]'s email address, after confirming it's them. The new
+  /// address has to be verified again, and the user's sessions end because
+  /// their credentials changed.
+  #[instrument(skip(self, reauth))]
+  pub async fn change_email(
+    &self,
+    id: UserRecordId,
+    reauth: &Reauthentication,
+    new_email: EmailAddress,
+  ) -> Result<User, ChangeEmailError> {
+    let user = self
+      .user_repo
+      .fetch_user_by_id(id)
+      .await?
+      .ok_or(ChangeEmailError::UserNotFound)?;
+    let mut user = self.reauthenticate(user, reauth).await?;
+    if user.email == new_email {
+      return Ok(user);
+    }
+    if self
+      .user_repo
+      .fetch_user_by_email(new_email.clone())
+      .await?
+      .is_some()
+    {
+      return Err(ChangeEmailError::EmailAlreadyUsed(new_email));
+    }
+
+    user.email = new_email.clone();
+    user.auth = match user.auth {
+      UserAuthCredentials::EmailAndPassword { password_hash, .. } => {
+        UserAuthCredentials::EmailAndPassword {
+          email: new_email,
+          password_hash,
+        }
+      }
+      UserAuthCredentials::Passkey { .. } => {
+        UserAuthCredentials::Passkey { email: new_email }
+      }
+      UserAuthCredentials::Oidc { .. } => {
+        UserAuthCredentials::Oidc { email: new_email }
+      }
+      UserAuthCredentials::MagicLink { .. } => {
+        UserAuthCredentials::MagicLink { email: new_email }
+      }
+    };
+    user.email_verified = false;
+    user.email_verification = None;
+    // links sent to the old address shouldn't outlive the change
+    user.password_reset = None;
+    user.magic_link = None;
+    user.reauthentication = None;
+    let user = self.user_repo.update_user(user).await?;
+
+    if let Err(e) = self.send_verification_email(user.id).await {
+      tracing::warn!("failed to send verification email to new address: {e}");
+    }
+
+    Ok(user)
+  }
+
+  /// Change a [`User`]'s password, after confirming it's them, or set one
+  /// for a user who has none. The user's sessions end because their
+  /// credentials changed.
+  #[instrument(skip(self, reauth, new_password))]
+  pub async fn change_password(
+    &self,
+    id: UserRecordId,
+    reauth: &Reauthentication,
+    new_password: &str,
+  ) -> Result<User, ChangePasswordError> {
+    let user = self
+      .user_repo
+      .fetch_user_by_id(id)
+      .await?
+      .ok_or(ChangePasswordError::UserNotFound)?;
+    let mut user = self.reauthenticate(user, reauth).await?;
+
+    let password_hash = hash_password(new_password)
+      .map_err(ChangePasswordError::PasswordHashing)?;
+    user.auth = UserAuthCredentials::EmailAndPassword {
+      email: user.email.clone(),
+      password_hash,
+    };
+    user.password_reset = None;
+
+    Ok(self.user_repo.update_user(user).await?)
+  }
+
+  /// Confirm it's a [`User`], before an action that can't be undone.
+  #[instrument(skip(self, reauth))]
+  pub async fn confirm_identity(
+    &self,
+    id: UserRecordId,
+    reauth: &Reauthentication,
+  ) -> Result<(), ConfirmIdentityError> {
+    let user = self
+      .user_repo
+      .fetch_user_by_id(id)
+      .await?
+      .ok_or(ConfirmIdentityError::UserNotFound)?;
+    self.reauthenticate(user, reauth).await?;
+    Ok(())
+  }
+
+  /// Permanently delete a [`User`]. Their photo groups and sessions have to
+  /// be cleaned up separately.
+  #[instrument(skip(self))]
+  pub async fn delete_user(
+    &self,
+    id: UserRecordId,
+  ) -> Result<(), DeleteUserError> {
+    if !self.user_repo.delete_user(id).await? {
+      return Err(DeleteUserError::UserNotFound);
+    }
+    Ok(())
+  }
+}
@@ -0,0 +1,136 @@
+//! Proving that users own their email addresses.
+
+use chrono::TimeDelta;
+use mailer::{templates::VerifyEmail, SendMailError};
+use models::{User, UserRecordId};
+use repos::{FetchModelError, PatchModelError};
+use tracing::instrument;
+
+use crate::{
+  token::{
+    check_token, cooldown_remaining, generate_token, parse_token, TokenCheck,
+  },
+  AuthDomainService,
+};
+
+/// The path that verification links point to.
+pub const VERIFY_EMAIL_PATH: &str = "/verify-email/";
+
+/// How long a verification link stays valid.
+const VERIFICATION_TOKEN_TTL: TimeDelta = TimeDelta::hours(24);
+
+/// How long a user must wait before another verification email is sent.
+pub const VERIFICATION_RESEND_COOLDOWN: TimeDelta = TimeDelta::minutes(1);
+
+/// An error that occurs when sending a verification email.
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum SendVerificationEmailError {
+  /// Indicates that the user doesn't exist.
+  #[error("The user doesn't exist")]
+  UserNotFound,
+  /// Indicates that the user's email address is already verified.
+  #[error("The email address is already verified")]
+  AlreadyVerified,
+  /// Indicates that a verification email was sent too recently.
+  #[error("A verification email was sent recently; try again in {0} seconds")]
+  Throttled(u64),
+  /// Indicates that an error occurred while fetching the user.
+  #[error("Failed to fetch user")]
+  FetchError(#[from] FetchModelError),
+  /// Indicates that an error occurred while updating the user.
+  #[error("Failed to update user")]
+  PatchError(#[from] PatchModelError),
+  /// Indicates that an error occurred while sending the email.
+  #[error("Failed to send verification email")]
+  MailError(#[from] SendMailError),
+}
+
+/// An error that occurs when verifying an email address.
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum VerifyEmailError {
+  /// Indicates that the token is malformed, unknown, or already used.
+  #[error("The verification link is invalid")]
+  InvalidToken,
+  /// Indicates that the token has expired.
+  #[error("The verification link has expired")]
+  Expired,
+  /// Indicates that an error occurred while fetching the user.
+  #[error("Failed to fetch user")]
+  FetchError(#[from] FetchModelError),
+  /// Indicates that an error occurred while updating the user.
+  #[error("Failed to update user")]
+  PatchError(#[from] PatchModelError),
+}
+
+impl AuthDomainService {
+  /// Send a [`User`] a link that verifies their email address. Any earlier
+  /// link stops working.
+  #[instrument(skip(self))]
+  pub async fn send_verification_email(
+    &self,
+    id: UserRecordId,
+  ) -> Result<(), SendVerificationEmailError> {
+    let mut user = self
+      .user_repo
+      .fetch_user_by_id(id)
+      .await?
+      .ok_or(SendVerificationEmailError::UserNotFound)?;
+    if user.email_verified {
+      return Err(SendVerificationEmailError::AlreadyVerified);
+    }
+    if let Some(wait) = cooldown_remaining(
+      user.email_verification.as_ref(),
+      VERIFICATION_RESEND_COOLDOWN,
+    ) {
+      return Err(SendVerificationEmailError::Throttled(wait));
+    }
+
+    let (token, verification) = generate_token(user.id, VERIFICATION_TOKEN_TTL);
+    let previous = user.email_verification.replace(verification);
+    let mut user = self.user_repo.update_user(user).await?;
+
+    let template = VerifyEmail {
+      name: user.name.clone(),
+      url:  format!(
+        "{base_url}{VERIFY_EMAIL_PATH}{token}",
+        base_url = self.base_url.0
+      ),
+    };
+    if let Err(e) = self.mailer.send(user.email.clone(), &template).await {
+      // don't hold the user to the cooldown for an email they never got
+      user.email_verification = previous;
+      if let Err(e) = self.user_repo.update_user(user).await {
+        tracing::error!("failed to restore previous email verification: {e}");
+      }
+      return Err(e.into());
+    }
+
+    Ok(())
+  }
+
+  /// Verify a [`User`]'s email address with the token from a verification
+  /// link.
+  #[instrument(skip(self, token))]
+  pub async fn verify_email(
+    &self,
+    token: &str,
+  ) -> Result<User, VerifyEmailError> {
+    let (id, secret) =
+      parse_token(token).ok_or(VerifyEmailError::InvalidToken)?;
+
+    let mut user = self
+      .user_repo
+      .fetch_user_by_id(id)
+      .await?
+      .ok_or(VerifyEmailError::InvalidToken)?;
+    match check_token(user.email_verification.as_ref(), secret) {
+      TokenCheck::Valid => (),
+      TokenCheck::Expired => return Err(VerifyEmailError::Expired),
+      TokenCheck::Invalid => return Err(VerifyEmailError::InvalidToken),
+    }
+
+    user.email_verified = true;
+    user.email_verification = None;
+    Ok(self.user_repo.update_user(user).await?)
+  }
+}
@@ -1,30 +1,84 @@
 //! Provides the [`AuthDomainService`], the entry point for users,
 //! authentication, and authorization logic.
 
+mod account;
+mod email_verification;
+mod magic_link;
+mod oidc;
+mod passkey;
+mod password_reset;
+mod reauthentication;
+mod throttle;
+mod token;
+mod totp;
+mod two_factor;
+mod webauthn;
+
+use std::{
+  net::IpAddr,
+  sync::{Arc, LazyLock},
+};
+
 use axum_login::AuthUser as AxumLoginAuthUser;
 pub use axum_login::AuthnBackend;
 use hex::health::{self, HealthAware};
+pub use mailer;
+use mailer::Mailer;
 use miette::{miette, IntoDiagnostic};
 use models::{
-  AuthUser, EmailAddress, HumanName, User, UserAuthCredentials,
+  AuthUser, BaseUrl, EmailAddress, HumanName, User, UserAuthCredentials,
   UserCreateRequest, UserSubmittedAuthCredentials,
 };
-use repos::{FetchModelByIndexError, FetchModelError, UserRepository};
+use repos::{
+  FetchModelByIndexError, FetchModelError, LoginAttemptError,
+  LoginAttemptRepositoryLike, PatchModelError, UserRepositoryLike,
+};
 use tracing::instrument;
 
+use self::magic_link::LinkSigner;
+pub use self::{
+  account::*, email_verification::*, magic_link::*, oidc::*, passkey::*,
+  password_reset::*, reauthentication::*, two_factor::*,
+};
+
 /// The authentication session type.
 pub type AuthSession = axum_login::AuthSession<AuthDomainService>;
 
 /// A dynamic [`AuthDomainService`] trait object.
 #[derive(Clone, Debug)]
 pub struct AuthDomainService {
-  user_repo: UserRepository,
+  user_repo:          Arc<dyn UserRepositoryLike>,
+  login_attempt_repo: Arc<dyn LoginAttemptRepositoryLike>,
+  mailer:             Mailer,
+  base_url:           BaseUrl,
+  oidc_providers:     Arc<[OidcProviderConfig]>,
+  http:               reqwest::Client,
+  link_signer:        LinkSigner,
 }
 
 impl AuthDomainService {
-  /// Creates a new [`AuthDomainService`].
+  /// Creates a new [`AuthDomainService`]. Links in emails are built from
+  /// `base_url`. No `OpenID` Connect providers are configured, and signup
+  /// links are signed with a random secret; see
+  /// [`with_oidc_providers`](Self::with_oidc_providers) and
+  /// [`with_link_secret`](Self::with_link_secret).
   #[must_use]
-  pub fn new(user_repo: UserRepository) -> Self { Self { user_repo } }
+  pub fn new(
+    user_repo: impl UserRepositoryLike,
+    login_attempt_repo: impl LoginAttemptRepositoryLike,
+    mailer: Mailer,
+    base_url: BaseUrl,
+  ) -> Self {
+    Self {
+      user_repo: Arc::new(user_repo),
+      login_attempt_repo: Arc::new(login_attempt_repo),
+      mailer,
+      base_url,
+      oidc_providers: Arc::new([]),
+      http: reqwest::Client::new(),
+      link_signer: LinkSigner::new_random(),
+    }
+  }
 }
 
 /// An error that occurs during user creation.
@@ -42,6 +96,10 @@ pub enum CreateUserError {
   /// Indicates that an error occurred while fetching users by index.
   #[error("Failed to fetch users by index")]
   FetchByIndexError(#[from] FetchModelByIndexError),
+  /// Indicates that the credentials can't be used to sign up, e.g. a
+  /// passkey assertion.
+  #[error("These credentials can't be used to sign up")]
+  UnsupportedCredentials,
 }
 
 /// An error that occurs during user authentication.
@@ -56,6 +114,102 @@ pub enum AuthenticationError {
   /// Indicates than an error occurred while hashing the password.
   #[error("Failed to hash password")]
   PasswordHashing(miette::Report),
+  /// Indicates that the password was correct, but the user has a second
+  /// factor and no code was given.
+  #[error("A second factor code is required")]
+  SecondFactorRequired,
+  /// Indicates that an error occurred while recording a second factor use.
+  #[error("Failed to update user")]
+  PatchError(#[from] PatchModelError),
+  /// Indicates that an error occurred while creating a user for a new
+  /// `OpenID` Connect identity or signup link.
+  #[error("Failed to create the user")]
+  CreateError(miette::Report),
+  /// Indicates that an `OpenID` Connect provider couldn't be asked about a
+  /// login.
+  #[error("Failed to check login with provider")]
+  OidcError(#[from] OidcError),
+  /// Indicates that there were too many recent password logins from the
+  /// client or against the account.
+  #[error("Too many login attempts; try again in {retry_after} seconds")]
+  Throttled {
+    /// How many seconds to wait before trying again.
+    retry_after: u64,
+  },
+  /// Indicates that an error occurred while counting login attempts.
+  #[error("Failed to count login attempts")]
+  LoginAttemptError(#[from] LoginAttemptError),
+}
+
+/// Hashes a password with a fresh salt.
+fn hash_password(
+  password: &str,
+) -> Result<models::PasswordHash, miette::Report> {
+  use argon2::PasswordHasher;
+
+  let salt = argon2::password_hash::SaltString::generate(
+    &mut argon2::password_hash::rand_core::OsRng,
+  );
+  let argon = argon2::Argon2::default();
+  Ok(models::PasswordHash(
+    argon
+      .hash_password(password.as_bytes(), &salt)
+      .map_err(|e| miette!("failed to hash password: {e}"))?
+      .to_string(),
+  ))
+}
+
+/// A name for an account created without asking for one: the local part of
+/// its email address, or a placeholder.
+fn default_name(email: &EmailAddress) -> HumanName {
+  let local_part = email.as_ref().split('@').next().unwrap_or_default();
+  HumanName::try_new(local_part).unwrap_or_else(|_| {
+    HumanName::try_new("PicturePro user").expect("name is valid")
+  })
+}
+
+/// A hash of a password nobody has, checked when there's no real hash to
+/// check, so that a login takes as long whether or not the account exists.
+static DUMMY_PASSWORD_HASH: LazyLock<models::PasswordHash> =
+  LazyLock::new(|| {
+    hash_password("not anybody's password").expect("hashing can't fail")
+  });
+
+/// Checks a password against a stored hash.
+fn verify_password_hash(
+  password_hash: &models::PasswordHash,
+  password: &str,
+) -> Result<bool, miette::Report> {
+  use argon2::PasswordVerifier;
+
+  let password_hash = argon2::PasswordHash::new(&password_hash.0)
+    .map_err(|e| miette!("failed to parse password hash: {e}"))?;
+
+  let argon = argon2::Argon2::default();
+  match argon.verify_password(password.as_bytes(), &password_hash) {
+    Ok(()) => Ok(true),
+    Err(argon2::password_hash::Error::Password) => Ok(false),
+    Err(e) => Err(miette!("failed to verify password against hash: {e}")),
+  }
+}
+
+/// Checks a password against the [`User`]'s stored hash.
+fn verify_password(
+  user: &User,
+  password: &str,
+) -> Result<bool, miette::Report> {
+  match &user.auth {
+    UserAuthCredentials::EmailAndPassword { password_hash, .. } => {
+      verify_password_hash(password_hash, password)
+    }
+    // passkey-only, provider-only and link-only accounts have no password
+    // to match, but take as long not to
+    UserAuthCredentials::Passkey { .. }
+    | UserAuthCredentials::Oidc { .. }
+    | UserAuthCredentials::MagicLink { .. } => {
+      verify_password_hash(&DUMMY_PASSWORD_HASH, password).map(|_| false)
+    }
+  }
 }
 
 impl AuthDomainService {
@@ -77,100 +231,138 @@ impl AuthDomainService {
     self.user_repo.fetch_user_by_email(email).await
   }
 
-  /// Sign up a [`User`].
+  /// Sign up a [`User`], and send them a verification email. Failing to send
+  /// the email doesn't fail the signup; the user can ask for another.
   #[instrument(skip(self))]
   pub async fn user_signup(
     &self,
     name: HumanName,
     auth: UserSubmittedAuthCredentials,
   ) -> Result<User, CreateUserError> {
-    use argon2::PasswordHasher;
-
-    let email = match auth.clone() {
-      UserSubmittedAuthCredentials::EmailAndPassword { email, .. } => email,
+    let (email, password) = match auth {
+      UserSubmittedAuthCredentials::EmailAndPassword { email, password }
+      | UserSubmittedAuthCredentials::EmailPasswordAndCode {
+        email,
+        password,
+        ..
+      } => (email, password),
+      UserSubmittedAuthCredentials::Passkey { .. }
+      | UserSubmittedAuthCredentials::MagicLink { .. }
+      | UserSubmittedAuthCredentials::Oidc { .. } => {
+        return Err(CreateUserError::UnsupportedCredentials);
+      }
     };
 
     if self.fetch_user_by_email(email.clone()).await?.is_some() {
       return Err(CreateUserError::EmailAlreadyUsed(email));
     }
 
-    let auth: UserAuthCredentials = match auth {
-      UserSubmittedAuthCredentials::EmailAndPassword { email, password } => {
-        let salt = argon2::password_hash::SaltString::generate(
-          &mut argon2::password_hash::rand_core::OsRng,
-        );
-        let argon = argon2::Argon2::default();
-        let password_hash = models::PasswordHash(
-          argon
-            .hash_password(password.as_bytes(), &salt)
-            .map_err(|e| {
-              CreateUserError::PasswordHashing(miette!(
-                "failed to hash password: {e}"
-              ))
-            })?
-            .to_string(),
-        );
-
-        UserAuthCredentials::EmailAndPassword {
-          email,
-          password_hash,
-        }
-      }
+    let auth = UserAuthCredentials::EmailAndPassword {
+      email:         email.clone(),
+      password_hash: hash_password(&password)
+        .map_err(CreateUserError::PasswordHashing)?,
     };
 
     let req = UserCreateRequest { name, email, auth };
 
-    self
+    let user = self
       .user_repo
       .create_user(req)
       .await
       .into_diagnostic()
-      .map_err(CreateUserError::CreateError)
+      .map_err(CreateUserError::CreateError)?;
+
+    if let Err(e) = self.send_verification_email(user.id).await {
+      tracing::warn!("failed to send verification email to new user: {e}");
+    }
+
+    Ok(user)
   }
 
-  /// Authenticate a [`User`].
+  /// Authenticate a [`User`]. Users with a second factor have to give a
+  /// code with their password, or
+  /// [`SecondFactorRequired`](AuthenticationError::SecondFactorRequired) is
+  /// returned once the password checks out.
+  ///
+  /// Password logins are throttled per account; see
+  /// [`user_authenticate_from`](Self::user_authenticate_from) to throttle
+  /// per client too.
   #[instrument(skip(self))]
   pub async fn user_authenticate(
     &self,
     creds: UserSubmittedAuthCredentials,
   ) -> Result<Option<User>, AuthenticationError> {
-    use argon2::PasswordVerifier;
+    self.user_authenticate_from(creds, None).await
+  }
 
-    let Some(user) = (match creds.clone() {
-      UserSubmittedAuthCredentials::EmailAndPassword { email, .. } => {
-        self.user_repo.fetch_user_by_email(email.clone()).await?
+  /// Authenticate a [`User`], as with
+  /// [`user_authenticate`](Self::user_authenticate), for a client with the
+  /// given address. Password logins get slower with each recent attempt from
+  /// the client or against the account, and are refused with
+  /// [`Throttled`](AuthenticationError::Throttled) until enough time has
+  /// passed.
+  #[instrument(skip(self))]
+  pub async fn user_authenticate_from(
+    &self,
+    creds: UserSubmittedAuthCredentials,
+    client: Option<IpAddr>,
+  ) -> Result<Option<User>, AuthenticationError> {
+    let (email, password, code) = match creds {
+      UserSubmittedAuthCredentials::EmailAndPassword { email, password } => {
+        (email, password, None)
       }
-    }) else {
-      return Ok(None);
+      UserSubmittedAuthCredentials::EmailPasswordAndCode {
+        email,
+        password,
+        code,
+      } => (email, password, Some(code)),
+      UserSubmittedAuthCredentials::Passkey {
+        challenge,
+        assertion,
+      } => return self.authenticate_passkey(&challenge, &assertion).await,
+      UserSubmittedAuthCredentials::MagicLink { token } => {
+        return self.authenticate_magic_link(&token).await
+      }
+      UserSubmittedAuthCredentials::Oidc {
+        pending,
+        state,
+        code,
+      } => return self.authenticate_oidc(&pending, &state, &code).await,
     };
 
-    match (creds, user.auth.clone()) {
-      (
-        UserSubmittedAuthCredentials::EmailAndPassword { password, .. },
-        UserAuthCredentials::EmailAndPassword { password_hash, .. },
-      ) => {
-        let password_hash = argon2::PasswordHash::new(&password_hash.0)
-          .map_err(|e| {
-            AuthenticationError::PasswordHashing(miette!(
-              "failed to parse password hash: {e}"
-            ))
-          })?;
-
-        let argon = argon2::Argon2::default();
-        let correct =
-          (match argon.verify_password(password.as_bytes(), &password_hash) {
-            Ok(()) => Ok(true),
-            Err(argon2::password_hash::Error::Password) => Ok(false),
-            Err(e) => Err(e),
-          })
-          .map_err(|e| {
-            AuthenticationError::PasswordHashing(miette!(
-              "failed to verify password against hash: {e}"
-            ))
-          })?;
+    let attempt = self.begin_login_attempt(&email, client).await?;
+    let result = self.authenticate_password(email, &password, code).await;
+    self
+      .finish_login_attempt(attempt, matches!(result, Ok(Some(_))))
+      .await;
+    result
+  }
+
+  /// Checks an email and password, and a second factor code if the user has
+  /// one.
+  async fn authenticate_password(
+    &self,
+    email: EmailAddress,
+    password: &str,
+    code: Option<String>,
+  ) -> Result<Option<User>, AuthenticationError> {
+    let Some(user) = self.user_repo.fetch_user_by_email(email).await? else {
+      verify_password_hash(&DUMMY_PASSWORD_HASH, password)
+        .map_err(AuthenticationError::PasswordHashing)?;
+      return Ok(None);
+    };
+    if !verify_password(&user, password)
+      .map_err(AuthenticationError::PasswordHashing)?
+    {
+      return Ok(None);
+    }
 
-        Ok(correct.then_some(user))
+    match code {
+      Some(code) => Ok(self.check_second_factor(user, &code).await?),
+      None if user.has_two_factor() => {
+        Err(AuthenticationError::SecondFactorRequired)
       }
+      None => Ok(Some(user)),
     }
   }
 }
@@ -180,9 +372,10 @@ impl health::HealthReporter for AuthDomainService {
   fn name(&self) -> &'static str { stringify!(AuthDomainService) }
 
   async fn health_check(&self) -> health::ComponentHealth {
-    health::AdditiveComponentHealth::from_futures(vec![self
-      .user_repo
-      .health_report()])
+    health::AdditiveComponentHealth::from_futures(vec![
+      self.user_repo.health_report(),
+      self.login_attempt_repo.health_report(),
+    ])
     .await
     .into()
   }
@@ -218,15 +411,128 @@ impl AuthnBackend for AuthDomainService {
 
 #[cfg(test)]
 mod tests {
+  use mailer::MemoryTransport;
   use models::{EmailAddress, HumanName};
-  use repos::db::Database;
+  use repos::{
+    db::kv::KeyValueStore, fake::FakeLoginAttemptRepository, UserRepository,
+  };
 
   use super::*;
 
+  fn service() -> (AuthDomainService, MemoryTransport) {
+    let (service, outbox, _) = service_with_attempts();
+    (service, outbox)
+  }
+
+  fn service_with_attempts() -> (
+    AuthDomainService,
+    MemoryTransport,
+    FakeLoginAttemptRepository,
+  ) {
+    let outbox = MemoryTransport::new();
+    let attempts = FakeLoginAttemptRepository::new();
+    let service = AuthDomainService::new(
+      UserRepository::new(KeyValueStore::new_mock()),
+      attempts.clone(),
+      Mailer::new(
+        EmailAddress::try_new("noreply@example.com").unwrap(),
+        outbox.clone(),
+      ),
+      BaseUrl("https://picturepro.test".to_owned()),
+    );
+    (service, outbox, attempts)
+  }
+
+  async fn sign_up(service: &AuthDomainService) -> User {
+    sign_up_as(service, "test@example.com").await
+  }
+
+  async fn sign_up_as(service: &AuthDomainService, email: &str) -> User {
+    let name = HumanName::try_new("Test User 1").unwrap();
+    let creds = UserSubmittedAuthCredentials::EmailAndPassword {
+      email:    EmailAddress::try_new(email).unwrap(),
+      password: "hunter42".to_string(),
+    };
+    service.user_signup(name, creds).await.unwrap()
+  }
+
+  fn password(password: &str) -> Reauthentication {
+    Reauthentication::Password(password.to_owned())
+  }
+
+  /// Pulls the token for the link at `path` out of the last email sent.
+  fn last_token(outbox: &MemoryTransport, path: &str) -> String {
+    let body = outbox.sent().last().unwrap().body.clone();
+    let (_, rest) = body.split_once(path).unwrap();
+    rest.split_whitespace().next().unwrap().to_owned()
+  }
+
+  /// Plays logging in at the mock provider, through to the redirect back.
+  async fn oidc_log_in(
+    service: &AuthDomainService,
+    provider: &crate::oidc::mock_provider::MockProvider,
+    claims: serde_json::Value,
+  ) -> Option<User> {
+    let authorization = service.begin_oidc_login("mock").await.unwrap();
+    let (state, code) = provider.authorize(&authorization.url, claims);
+    let creds = UserSubmittedAuthCredentials::Oidc {
+      pending: authorization.pending,
+      state,
+      code,
+    };
+    service.user_authenticate(creds).await.unwrap()
+  }
+
+  fn oidc_claims(sub: &str, email: &str, verified: bool) -> serde_json::Value {
+    serde_json::json!({
+      "sub": sub,
+      "email": email,
+      "email_verified": verified,
+      "name": "Provider User",
+    })
+  }
+
+  /// Turns on a user's TOTP second factor.
+  /// Enables TOTP for a user, returning the secret and the recovery codes.
+  async fn enable_totp(
+    service: &AuthDomainService,
+    id: models::UserRecordId,
+  ) -> (Vec<u8>, Vec<String>) {
+    service.begin_totp_enrollment(id).await.unwrap();
+    let secret = service
+      .fetch_user_by_id(id)
+      .await
+      .unwrap()
+      .unwrap()
+      .totp
+      .unwrap()
+      .secret;
+    let code = format!("{:06}", totp::code_at(&secret, totp::current_step()));
+    let recovery_codes =
+      service.confirm_totp_enrollment(id, &code).await.unwrap();
+    (secret, recovery_codes)
+  }
+
+  /// Logs in twice at once with the same credentials, returning how many of
+  /// the logins succeeded.
+  async fn race_logins(
+    service: &AuthDomainService,
+    creds: UserSubmittedAuthCredentials,
+  ) -> usize {
+    let log_in = || {
+      let (service, creds) = (service.clone(), creds.clone());
+      tokio::spawn(async move { service.user_authenticate(creds).await })
+    };
+    let (first, second) = tokio::join!(log_in(), log_in());
+    [first, second]
+      .into_iter()
+      .filter(|login| login.as_ref().unwrap().as_ref().unwrap().is_some())
+      .count()
+  }
+
   #[tokio::test]
   async fn test_user_signup() {
-    let user_repo = UserRepository::new(Database::new_mock());
-    let service = AuthDomainService::new(user_repo);
+    let (service, _) = service();
 
     let name = HumanName::try_new("Test User 1").unwrap();
     let email = EmailAddress::try_new("test@example.com").unwrap();
@@ -237,8 +543,6 @@ mod tests {
     let user = service.user_signup(name, creds.clone()).await.unwrap();
     assert_eq!(user.email, email);
 
-    dbg!(&service);
-
     let name = HumanName::try_new("Test User 2").unwrap();
     let user2 = service.user_signup(name, creds.clone()).await;
     assert!(matches!(user2, Err(CreateUserError::EmailAlreadyUsed(_))));
@@ -246,8 +550,7 @@ mod tests {
 
   #[tokio::test]
   async fn test_user_authenticate() {
-    let user_repo = UserRepository::new(Database::new_mock());
-    let service = AuthDomainService::new(user_repo);
+    let (service, _) = service();
 
     let name = HumanName::try_new("Test User 1").unwrap();
     let email = EmailAddress::try_new("test@example.com").unwrap();
@@ -268,4 +571,867 @@ mod tests {
     let auth_user = service.user_authenticate(creds).await.unwrap();
     assert_eq!(auth_user, None);
   }
+
+  #[tokio::test]
+  async fn signup_sends_verification_email() {
+    let (service, outbox) = service();
+    let user = sign_up(&service).await;
+    assert!(!user.email_verified);
+
+    let sent = outbox.sent();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].to, user.email);
+    assert!(sent[0]
+      .body
+      .contains("https://picturepro.test/verify-email/"));
+  }
+
+  #[tokio::test]
+  async fn verify_email_marks_user_verified_once() {
+    let (service, outbox) = service();
+    let user = sign_up(&service).await;
+    let token = last_token(&outbox, VERIFY_EMAIL_PATH);
+
+    let verified = service.verify_email(&token).await.unwrap();
+    assert_eq!(verified.id, user.id);
+    assert!(verified.email_verified);
+    assert!(verified.email_verification.is_none());
+
+    let reused = service.verify_email(&token).await;
+    assert!(matches!(reused, Err(VerifyEmailError::InvalidToken)));
+
+    let resend = service.send_verification_email(user.id).await;
+    assert!(matches!(
+      resend,
+      Err(SendVerificationEmailError::AlreadyVerified)
+    ));
+  }
+
+  #[tokio::test]
+  async fn verify_email_rejects_bad_tokens() {
+    let (service, outbox) = service();
+    let user = sign_up(&service).await;
+    let token = last_token(&outbox, VERIFY_EMAIL_PATH);
+
+    for bad in [
+      "",
+      "not-a-token",
+      &format!("{}.wrong-secret", user.id),
+      &token.replace('.', ""),
+    ] {
+      let result = service.verify_email(bad).await;
+      assert!(
+        matches!(result, Err(VerifyEmailError::InvalidToken)),
+        "{bad}"
+      );
+    }
+  }
+
+  #[tokio::test]
+  async fn resending_verification_email_is_throttled() {
+    let (service, outbox) = service();
+    let user = sign_up(&service).await;
+
+    let resend = service.send_verification_email(user.id).await;
+    assert!(matches!(
+      resend,
+      Err(SendVerificationEmailError::Throttled(_))
+    ));
+    assert_eq!(outbox.sent().len(), 1);
+  }
+
+  #[tokio::test]
+  async fn password_reset_replaces_password_once() {
+    let (service, outbox) = service();
+    let user = sign_up(&service).await;
+    let creds =
+      |password: &str| UserSubmittedAuthCredentials::EmailAndPassword {
+        email:    user.email.clone(),
+        password: password.to_string(),
+      };
+
+    service
+      .request_password_reset(user.email.clone())
+      .await
+      .unwrap();
+    assert_eq!(outbox.sent().len(), 2);
+    let token = last_token(&outbox, RESET_PASSWORD_PATH);
+
+    let reset = service
+      .reset_password(&token, "correct horse")
+      .await
+      .unwrap();
+    assert_ne!(reset.auth_hash(), user.auth_hash());
+    assert!(reset.password_reset.is_none());
+    assert!(reset.email_verified);
+
+    let old = service.user_authenticate(creds("hunter42")).await.unwrap();
+    assert_eq!(old, None);
+    let new = service
+      .user_authenticate(creds("correct horse"))
+      .await
+      .unwrap();
+    assert_eq!(new.map(|u| u.id), Some(user.id));
+
+    let reused = service.reset_password(&token, "another").await;
+    assert!(matches!(reused, Err(ResetPasswordError::InvalidToken)));
+  }
+
+  #[tokio::test]
+  async fn password_reset_for_unknown_email_sends_nothing() {
+    let (service, outbox) = service();
+    sign_up(&service).await;
+
+    service
+      .request_password_reset(
+        EmailAddress::try_new("nobody@example.com").unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(outbox.sent().len(), 1);
+  }
+
+  #[tokio::test]
+  async fn password_reset_rejects_wrong_secret() {
+    let (service, outbox) = service();
+    let user = sign_up(&service).await;
+
+    service
+      .request_password_reset(user.email.clone())
+      .await
+      .unwrap();
+    let token = last_token(&outbox, RESET_PASSWORD_PATH);
+    let bad = format!("{}.{}", user.id, "x".repeat(43));
+
+    let result = service.reset_password(&bad, "correct horse").await;
+    assert!(matches!(result, Err(ResetPasswordError::InvalidToken)));
+    service
+      .reset_password(&token, "correct horse")
+      .await
+      .unwrap();
+  }
+
+  #[tokio::test(flavor = "multi_thread")]
+  async fn concurrent_password_resets_use_the_link_once() {
+    let (service, outbox) = service();
+    let user = sign_up(&service).await;
+    service
+      .request_password_reset(user.email.clone())
+      .await
+      .unwrap();
+    let token = last_token(&outbox, RESET_PASSWORD_PATH);
+
+    let reset = |password: &'static str| {
+      let (service, token) = (service.clone(), token.clone());
+      tokio::spawn(
+        async move { service.reset_password(&token, password).await },
+      )
+    };
+    let (first, second) =
+      tokio::join!(reset("correct horse"), reset("another"));
+
+    let results = [first.unwrap(), second.unwrap()];
+    assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 1);
+    assert!(results
+      .iter()
+      .any(|r| matches!(r, Err(ResetPasswordError::InvalidToken))));
+  }
+
+  #[tokio::test]
+  async fn change_name_keeps_sessions() {
+    let (service, _) = service();
+    let user = sign_up(&service).await;
+
+    let name = HumanName::try_new("Renamed User").unwrap();
+    let renamed = service.change_name(user.id, name.clone()).await.unwrap();
+    assert_eq!(renamed.name, name);
+    assert_eq!(renamed.auth_hash(), user.auth_hash());
+  }
+
+  #[tokio::test]
+  async fn change_email_moves_index_and_requires_reverification() {
+    let (service, outbox) = service();
+    let user = sign_up(&service).await;
+    sign_up_as(&service, "taken@example.com").await;
+    let new_email = EmailAddress::try_new("new@example.com").unwrap();
+
+    let wrong = service
+      .change_email(user.id, &password("wrong"), new_email.clone())
+      .await;
+    assert!(matches!(
+      wrong,
+      Err(ChangeEmailError::Reauthentication(
+        ReauthenticationError::IncorrectPassword
+      ))
+    ));
+    let taken = service
+      .change_email(
+        user.id,
+        &password("hunter42"),
+        EmailAddress::try_new("taken@example.com").unwrap(),
+      )
+      .await;
+    assert!(matches!(taken, Err(ChangeEmailError::EmailAlreadyUsed(_))));
+
+    let changed = service
+      .change_email(user.id, &password("hunter42"), new_email.clone())
+      .await
+      .unwrap();
+    assert_ne!(changed.auth_hash(), user.auth_hash());
+    assert!(!changed.email_verified);
+    assert_eq!(outbox.sent().last().unwrap().to, new_email);
+
+    let by_old = service.fetch_user_by_email(user.email.clone()).await;
+    assert_eq!(by_old.unwrap(), None);
+    let by_new = service.fetch_user_by_email(new_email.clone()).await;
+    assert_eq!(by_new.unwrap().map(|u| u.id), Some(user.id));
+
+    let creds = UserSubmittedAuthCredentials::EmailAndPassword {
+      email:    new_email,
+      password: "hunter42".to_string(),
+    };
+    let authed = service.user_authenticate(creds).await.unwrap();
+    assert_eq!(authed.map(|u| u.id), Some(user.id));
+  }
+
+  #[tokio::test]
+  async fn change_password_requires_current_password() {
+    let (service, _) = service();
+    let user = sign_up(&service).await;
+
+    let wrong = service
+      .change_password(user.id, &password("wrong"), "new pass")
+      .await;
+    assert!(matches!(
+      wrong,
+      Err(ChangePasswordError::Reauthentication(
+        ReauthenticationError::IncorrectPassword
+      ))
+    ));
+
+    let changed = service
+      .change_password(user.id, &password("hunter42"), "new pass")
+      .await
+      .unwrap();
+    assert_ne!(changed.auth_hash(), user.auth_hash());
+
+    let creds =
+      |password: &str| UserSubmittedAuthCredentials::EmailAndPassword {
+        email:    user.email.clone(),
+        password: password.to_string(),
+      };
+    let old = service.user_authenticate(creds("hunter42")).await.unwrap();
+    assert_eq!(old, None);
+    let new = service.user_authenticate(creds("new pass")).await.unwrap();
+    assert_eq!(new.map(|u| u.id), Some(user.id));
+  }
+
+  #[tokio::test]
+  async fn delete_user_frees_the_email_address() {
+    let (service, _) = service();
+    let user = sign_up(&service).await;
+
+    let wrong = service.confirm_identity(user.id, &password("wrong")).await;
+    assert!(matches!(
+      wrong,
+      Err(ConfirmIdentityError::Reauthentication(
+        ReauthenticationError::IncorrectPassword
+      ))
+    ));
+    service
+      .confirm_identity(user.id, &password("hunter42"))
+      .await
+      .unwrap();
+
+    service.delete_user(user.id).await.unwrap();
+    assert_eq!(service.fetch_user_by_id(user.id).await.unwrap(), None);
+    let again = service.delete_user(user.id).await;
+    assert!(matches!(again, Err(DeleteUserError::UserNotFound)));
+
+    let user2 = sign_up(&service).await;
+    assert_ne!(user2.id, user.id);
+  }
+
+  #[tokio::test]
+  async fn totp_adds_a_second_login_step() {
+    let (service, _) = service();
+    let user = sign_up(&service).await;
+    let creds = |code: Option<&str>| match code {
+      Some(code) => UserSubmittedAuthCredentials::EmailPasswordAndCode {
+        email:    user.email.clone(),
+        password: "hunter42".to_string(),
+        code:     code.to_string(),
+      },
+      None => UserSubmittedAuthCredentials::EmailAndPassword {
+        email:    user.email.clone(),
+        password: "hunter42".to_string(),
+      },
+    };
+
+    service.begin_totp_enrollment(user.id).await.unwrap();
+    // unconfirmed enrollments aren't enforced
+    assert!(service
+      .user_authenticate(creds(None))
+      .await
+      .unwrap()
+      .is_some());
+
+    let secret = service
+      .fetch_user_by_id(user.id)
+      .await
+      .unwrap()
+      .unwrap()
+      .totp
+      .unwrap()
+      .secret;
+    let step = totp::current_step();
+    let code = |step| format!("{:06}", totp::code_at(&secret, step));
+    let recovery_codes = service
+      .confirm_totp_enrollment(user.id, &code(step))
+      .await
+      .unwrap();
+
+    assert!(matches!(
+      service.user_authenticate(creds(None)).await,
+      Err(AuthenticationError::SecondFactorRequired)
+    ));
+    // the code used to confirm can't be replayed
+    let replay = service.user_authenticate(creds(Some(&code(step)))).await;
+    assert_eq!(replay.unwrap(), None);
+    let next = service
+      .user_authenticate(creds(Some(&code(step + 1))))
+      .await
+      .unwrap();
+    assert_eq!(next.map(|u| u.id), Some(user.id));
+
+    let recovery = creds(Some(&recovery_codes[0]));
+    let first = service.user_authenticate(recovery.clone()).await.unwrap();
+    assert_eq!(first.map(|u| u.id), Some(user.id));
+    let second = service.user_authenticate(recovery).await.unwrap();
+    assert_eq!(second, None);
+
+    let enabled = service.fetch_user_by_id(user.id).await.unwrap().unwrap();
+    assert_ne!(enabled.auth_hash(), user.auth_hash());
+    let disabled = service
+      .disable_two_factor(user.id, &password("hunter42"))
+      .await
+      .unwrap();
+    assert_ne!(disabled.auth_hash(), enabled.auth_hash());
+    assert!(service
+      .user_authenticate(creds(None))
+      .await
+      .unwrap()
+      .is_some());
+  }
+
+  #[tokio::test]
+  async fn passkeys_log_in_without_a_password() {
+    use crate::webauthn::{decode, soft_authenticator::SoftAuthenticator};
+
+    let (service, _) = service();
+    let user = sign_up(&service).await;
+    let mut authenticator = SoftAuthenticator::new(&service.base_url);
+
+    let ceremony = service.begin_passkey_registration(user.id).await.unwrap();
+    let options: serde_json::Value =
+      serde_json::from_str(&ceremony.options_json).unwrap();
+    let handle = decode(options["user"]["id"].as_str().unwrap(), "").unwrap();
+    let attestation =
+      authenticator.register(&ceremony.challenge.challenge, &handle);
+    // a registration challenge can't be used for someone else
+    let other = sign_up_as(&service, "other@example.com").await;
+    let wrong = service
+      .finish_passkey_registration(other.id, &ceremony.challenge, &attestation)
+      .await;
+    assert!(matches!(
+      wrong,
+      Err(FinishPasskeyRegistrationError::WrongChallenge)
+    ));
+    service
+      .finish_passkey_registration(user.id, &ceremony.challenge, &attestation)
+      .await
+      .unwrap();
+
+    let ceremony = service.begin_passkey_login();
+    let creds = UserSubmittedAuthCredentials::Passkey {
+      assertion: authenticator.authenticate(&ceremony.challenge.challenge),
+      challenge: ceremony.challenge,
+    };
+    let logged_in = service.user_authenticate(creds.clone()).await.unwrap();
+    assert_eq!(logged_in.map(|u| u.id), Some(user.id));
+    let replay = service.user_authenticate(creds).await.unwrap();
+    assert_eq!(replay, None);
+
+    service.remove_password(user.id, "hunter42").await.unwrap();
+    let password_login = service
+      .user_authenticate(UserSubmittedAuthCredentials::EmailAndPassword {
+        email:    user.email.clone(),
+        password: "hunter42".to_string(),
+      })
+      .await
+      .unwrap();
+    assert_eq!(password_login, None);
+
+    let user = service.fetch_user_by_id(user.id).await.unwrap().unwrap();
+    let credential_id =
+      crate::webauthn::encode(&user.passkeys[0].credential_id);
+    let last = service.remove_passkey(user.id, &credential_id).await;
+    assert!(matches!(last, Err(RemovePasskeyError::LastCredential)));
+  }
+
+  #[tokio::test]
+  async fn removing_a_passkey_logs_out_other_sessions() {
+    use crate::webauthn::{decode, soft_authenticator::SoftAuthenticator};
+
+    let (service, _) = service();
+    let user = sign_up(&service).await;
+    let mut authenticator = SoftAuthenticator::new(&service.base_url);
+    let ceremony = service.begin_passkey_registration(user.id).await.unwrap();
+    let options: serde_json::Value =
+      serde_json::from_str(&ceremony.options_json).unwrap();
+    let handle = decode(options["user"]["id"].as_str().unwrap(), "").unwrap();
+    let attestation =
+      authenticator.register(&ceremony.challenge.challenge, &handle);
+    let registered = service
+      .finish_passkey_registration(user.id, &ceremony.challenge, &attestation)
+      .await
+      .unwrap();
+    assert_ne!(registered.auth_hash(), user.auth_hash());
+
+    // logging in with the passkey doesn't end sessions on its own
+    let ceremony = service.begin_passkey_login();
+    let session = service
+      .user_authenticate(UserSubmittedAuthCredentials::Passkey {
+        assertion: authenticator.authenticate(&ceremony.challenge.challenge),
+        challenge: ceremony.challenge,
+      })
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(session.auth_hash(), registered.auth_hash());
+
+    let credential_id =
+      crate::webauthn::encode(&session.passkeys[0].credential_id);
+    let removed = service
+      .remove_passkey(user.id, &credential_id)
+      .await
+      .unwrap();
+    assert_ne!(removed.auth_hash(), session.auth_hash());
+  }
+
+  #[tokio::test]
+  async fn oidc_login_links_accounts_by_verified_email() {
+    use crate::oidc::mock_provider::MockProvider;
+
+    let provider = MockProvider::start().await;
+    let (service, outbox) = service();
+    let service = service.with_oidc_providers(vec![provider.config()]);
+    let log_in =
+      |claims: serde_json::Value| oidc_log_in(&service, &provider, claims);
+    let user = sign_up(&service).await;
+    service
+      .verify_email(&last_token(&outbox, VERIFY_EMAIL_PATH))
+      .await
+      .unwrap();
+
+    let unverified = log_in(oidc_claims("1", "test@example.com", false)).await;
+    assert_eq!(unverified, None);
+
+    let linked = log_in(oidc_claims("1", "test@example.com", true))
+      .await
+      .unwrap();
+    assert_eq!(linked.id, user.id);
+    assert_eq!(linked.oidc_identities.len(), 1);
+    assert_eq!(linked.auth, user.auth);
+    let again = log_in(oidc_claims("1", "test@example.com", true)).await;
+    assert_eq!(again.map(|u| u.id), Some(user.id));
+    let someone_else = log_in(oidc_claims("2", "test@example.com", true)).await;
+    assert_eq!(someone_else, None);
+
+    // an unverified signup loses its password to the address's owner
+    let squatter = sign_up_as(&service, "owner@example.com").await;
+    let owner = log_in(oidc_claims("3", "owner@example.com", true))
+      .await
+      .unwrap();
+    assert_eq!(owner.id, squatter.id);
+    assert!(owner.email_verified);
+    assert!(matches!(owner.auth, UserAuthCredentials::Oidc { .. }));
+
+    let created = log_in(oidc_claims("4", "new@example.com", true))
+      .await
+      .unwrap();
+    assert_eq!(created.name.as_ref(), "Provider User");
+    assert!(created.email_verified);
+    assert!(matches!(created.auth, UserAuthCredentials::Oidc { .. }));
+
+    // the redirect can't be replayed, or answered with another login's state
+    let authorization = service.begin_oidc_login("mock").await.unwrap();
+    let (state, code) = provider.authorize(
+      &authorization.url,
+      oidc_claims("4", "new@example.com", true),
+    );
+    let creds = |state: &str, code: &str| UserSubmittedAuthCredentials::Oidc {
+      pending: authorization.pending.clone(),
+      state:   state.to_owned(),
+      code:    code.to_owned(),
+    };
+    let wrong_state = service.user_authenticate(creds("forged", &code)).await;
+    assert_eq!(wrong_state.unwrap(), None);
+    let first = service.user_authenticate(creds(&state, &code)).await;
+    assert_eq!(first.unwrap().map(|u| u.id), Some(created.id));
+    let replay = service.user_authenticate(creds(&state, &code)).await;
+    assert_eq!(replay.unwrap(), None);
+  }
+
+  #[tokio::test(flavor = "multi_thread")]
+  async fn concurrent_logins_use_a_second_factor_code_once() {
+    let (service, _) = service();
+    let user = sign_up(&service).await;
+    let (secret, recovery_codes) = enable_totp(&service, user.id).await;
+    let creds =
+      |code: String| UserSubmittedAuthCredentials::EmailPasswordAndCode {
+        email: user.email.clone(),
+        password: "hunter42".to_string(),
+        code,
+      };
+
+    let code =
+      format!("{:06}", totp::code_at(&secret, totp::current_step() + 1));
+    assert_eq!(race_logins(&service, creds(code)).await, 1);
+    assert_eq!(
+      race_logins(&service, creds(recovery_codes[0].clone())).await,
+      1
+    );
+  }
+
+  #[tokio::test]
+  async fn oidc_login_refuses_accounts_with_a_second_factor() {
+    use crate::oidc::mock_provider::MockProvider;
+
+    let provider = MockProvider::start().await;
+    let (service, outbox) = service();
+    let service = service.with_oidc_providers(vec![provider.config()]);
+    let log_in = |sub: &str, email: &str| {
+      oidc_log_in(&service, &provider, oidc_claims(sub, email, true))
+    };
+
+    let user = sign_up(&service).await;
+    service
+      .verify_email(&last_token(&outbox, VERIFY_EMAIL_PATH))
+      .await
+      .unwrap();
+    let linked = log_in("1", "test@example.com").await;
+    assert_eq!(linked.map(|u| u.id), Some(user.id));
+
+    // neither a linked identity nor a new one skips the code
+    enable_totp(&service, user.id).await;
+    let linked = log_in("1", "test@example.com").await;
+    assert_eq!(linked, None);
+    let other = sign_up_as(&service, "other@example.com").await;
+    service
+      .verify_email(&last_token(&outbox, VERIFY_EMAIL_PATH))
+      .await
+      .unwrap();
+    enable_totp(&service, other.id).await;
+    let unlinked = log_in("2", "other@example.com").await;
+    assert_eq!(unlinked, None);
+    let other = service.fetch_user_by_id(other.id).await.unwrap().unwrap();
+    assert!(other.oidc_identities.is_empty());
+
+    // an unverified signup's second factor doesn't keep out the owner
+    let squatter = sign_up_as(&service, "owner@example.com").await;
+    enable_totp(&service, squatter.id).await;
+    let owner = log_in("3", "owner@example.com").await.unwrap();
+    assert_eq!(owner.id, squatter.id);
+    assert!(!owner.has_two_factor());
+  }
+
+  #[tokio::test]
+  async fn magic_links_log_in_and_sign_up_once() {
+    use base64::Engine;
+
+    let (service, outbox) = service();
+    let log_in = |token: &str| {
+      service.user_authenticate(UserSubmittedAuthCredentials::MagicLink {
+        token: token.to_owned(),
+      })
+    };
+
+    // an existing user gets a single-use link that verifies their address
+    let user = sign_up(&service).await;
+    service
+      .request_magic_link(user.email.clone())
+      .await
+      .unwrap();
+    let token = last_token(&outbox, MAGIC_LINK_PATH);
+    let logged_in = log_in(&token).await.unwrap().unwrap();
+    assert_eq!(logged_in.id, user.id);
+    assert!(logged_in.email_verified);
+    assert!(log_in(&token).await.unwrap().is_none());
+
+    // an unknown address gets a link that creates a passwordless account
+    let email = EmailAddress::try_new("buyer@example.com").unwrap();
+    service.request_magic_link(email.clone()).await.unwrap();
+    assert_eq!(outbox.sent().last().unwrap().to, email);
+    let token = last_token(&outbox, MAGIC_LINK_PATH);
+    assert!(service
+      .fetch_user_by_email(email.clone())
+      .await
+      .unwrap()
+      .is_none());
+    let created = log_in(&token).await.unwrap().unwrap();
+    assert_eq!(created.email, email);
+    assert_eq!(created.name.as_ref(), "buyer");
+    assert!(created.email_verified);
+    assert!(matches!(
+      created.auth,
+      UserAuthCredentials::MagicLink { .. }
+    ));
+    assert!(log_in(&token).await.unwrap().is_none());
+
+    // nor can signup links be forged
+    let forged = token.replace(
+      &token[..token.find('.').unwrap()],
+      &base64::prelude::BASE64_URL_SAFE_NO_PAD.encode("victim@example.com"),
+    );
+    assert!(log_in(&forged).await.unwrap().is_none());
+  }
+
+  #[tokio::test]
+  async fn signup_links_are_rate_limited() {
+    let (service, outbox) = service();
+    let client: IpAddr = "203.0.113.7".parse().unwrap();
+    let request = |email: &str| {
+      service.request_magic_link_from(
+        EmailAddress::try_new(email).unwrap(),
+        Some(client),
+      )
+    };
+
+    // a second request inside the cooldown sends nothing, however the
+    // address is written
+    request("buyer@example.com").await.unwrap();
+    assert_eq!(outbox.sent().len(), 1);
+    request("buyer@example.com").await.unwrap();
+    request("Buyer@Example.com").await.unwrap();
+    assert_eq!(outbox.sent().len(), 1);
+
+    // nor can one client send to address after address; its refused
+    // requests above count too
+    for i in 0..20 {
+      request(&format!("buyer{i}@example.com")).await.unwrap();
+    }
+    assert_eq!(outbox.sent().len(), 8);
+  }
+
+  #[tokio::test]
+  async fn passwordless_accounts_confirm_with_passkeys_or_email_links() {
+    use crate::webauthn::{decode, soft_authenticator::SoftAuthenticator};
+
+    let (service, outbox) = service();
+    let email = EmailAddress::try_new("buyer@example.com").unwrap();
+    service.request_magic_link(email).await.unwrap();
+    let user = service
+      .user_authenticate(UserSubmittedAuthCredentials::MagicLink {
+        token: last_token(&outbox, MAGIC_LINK_PATH),
+      })
+      .await
+      .unwrap()
+      .unwrap();
+
+    // there's no password to give
+    let none = service.confirm_identity(user.id, &password("")).await;
+    assert!(matches!(
+      none,
+      Err(ConfirmIdentityError::Reauthentication(
+        ReauthenticationError::IncorrectPassword
+      ))
+    ));
+
+    // a passkey answers a challenge issued to its own user
+    let mut authenticator = SoftAuthenticator::new(&service.base_url);
+    let ceremony = service.begin_passkey_registration(user.id).await.unwrap();
+    let options: serde_json::Value =
+      serde_json::from_str(&ceremony.options_json).unwrap();
+    let handle = decode(options["user"]["id"].as_str().unwrap(), "").unwrap();
+    let attestation =
+      authenticator.register(&ceremony.challenge.challenge, &handle);
+    service
+      .finish_passkey_registration(user.id, &ceremony.challenge, &attestation)
+      .await
+      .unwrap();
+    let ceremony = service
+      .begin_passkey_reauthentication(user.id)
+      .await
+      .unwrap();
+    let reauth = Reauthentication::Passkey {
+      assertion: authenticator.authenticate(&ceremony.challenge.challenge),
+      challenge: ceremony.challenge,
+    };
+    service.confirm_identity(user.id, &reauth).await.unwrap();
+    let replay = service.confirm_identity(user.id, &reauth).await;
+    assert!(matches!(
+      replay,
+      Err(ConfirmIdentityError::Reauthentication(
+        ReauthenticationError::PasskeyRejected
+      ))
+    ));
+    let login = service.begin_passkey_login();
+    let reauth = Reauthentication::Passkey {
+      assertion: authenticator.authenticate(&login.challenge.challenge),
+      challenge: login.challenge,
+    };
+    let login = service.confirm_identity(user.id, &reauth).await;
+    assert!(matches!(
+      login,
+      Err(ConfirmIdentityError::Reauthentication(
+        ReauthenticationError::PasskeyRejected
+      ))
+    ));
+
+    // an emailed link confirms once, and then the account can be deleted
+    service
+      .request_reauthentication_link(user.id)
+      .await
+      .unwrap();
+    assert_eq!(outbox.sent().last().unwrap().to, user.email);
+    let again = service.request_reauthentication_link(user.id).await;
+    assert!(matches!(
+      again,
+      Err(RequestReauthenticationLinkError::Throttled(_))
+    ));
+    let reauth =
+      Reauthentication::EmailLink(last_token(&outbox, REAUTHENTICATION_PATH));
+    service.confirm_identity(user.id, &reauth).await.unwrap();
+    let reused = service.confirm_identity(user.id, &reauth).await;
+    assert!(matches!(
+      reused,
+      Err(ConfirmIdentityError::Reauthentication(
+        ReauthenticationError::InvalidLink
+      ))
+    ));
+
+    service.delete_user(user.id).await.unwrap();
+    assert_eq!(service.fetch_user_by_id(user.id).await.unwrap(), None);
+  }
+
+  #[tokio::test]
+  async fn password_logins_back_off_and_lock_out() {
+    use models::LoginAttempts;
+    use repos::{LoginAttemptRepositoryLike, LoginAttemptSubject};
+
+    let (service, outbox, attempts) = service_with_attempts();
+    let user = sign_up(&service).await;
+    let client: IpAddr = "203.0.113.7".parse().unwrap();
+    let log_in = |email: &str, password: &str| {
+      service.user_authenticate_from(
+        UserSubmittedAuthCredentials::EmailAndPassword {
+          email:    EmailAddress::try_new(email).unwrap(),
+          password: password.to_owned(),
+        },
+        Some(client),
+      )
+    };
+
+    // a success clears the account's count
+    assert!(log_in("test@example.com", "wrong").await.unwrap().is_none());
+    assert!(log_in("test@example.com", "hunter42")
+      .await
+      .unwrap()
+      .is_some());
+    let account = LoginAttemptSubject::Account(user.email.clone());
+    let count = attempts.fetch_login_attempts(&account).await.unwrap();
+    assert_eq!(count, LoginAttempts::default());
+
+    // unknown addresses are throttled just like real ones
+    for email in ["test@example.com", "nobody@example.com"] {
+      for _ in 0..5 {
+        assert!(log_in(email, "wrong").await.unwrap().is_none());
+      }
+      let throttled = log_in(email, "hunter42").await;
+      assert!(
+        matches!(throttled, Err(AuthenticationError::Throttled { .. })),
+        "{email}"
+      );
+    }
+
+    // the attempt that reaches the lockout tells the owner
+    let sent = outbox.sent().len();
+    let last_at = chrono::Utc::now() - chrono::TimeDelta::hours(1);
+    attempts
+      .update_login_attempts(
+        &account,
+        Box::new(move |_| {
+          Some(LoginAttempts {
+            count:        9,
+            last_at:      Some(last_at),
+            locked_until: None,
+          })
+        }),
+      )
+      .await
+      .unwrap();
+    assert!(log_in("test@example.com", "wrong").await.unwrap().is_none());
+    assert_eq!(outbox.sent().len(), sent + 1);
+    assert_eq!(outbox.sent().last().unwrap().to, user.email);
+    let locked = log_in("test@example.com", "hunter42").await;
+    assert!(matches!(
+      locked,
+      Err(AuthenticationError::Throttled { retry_after }) if retry_after > 60
+    ));
+  }
+
+  #[tokio::test(flavor = "multi_thread")]
+  async fn parallel_password_guesses_are_all_throttled() {
+    let (service, _) = service();
+    let email = EmailAddress::try_new("test@example.com").unwrap();
+
+    // every attempt is checked and counted before any password is hashed
+    let attempts = (0..20)
+      .map(|_| {
+        let (service, email) = (service.clone(), email.clone());
+        tokio::spawn(async move {
+          service.begin_login_attempt(&email, None).await.is_ok()
+        })
+      })
+      .collect::<Vec<_>>();
+    let mut admitted = 0;
+    for attempt in attempts {
+      admitted += usize::from(attempt.await.unwrap());
+    }
+    assert_eq!(admitted, 5);
+  }
+
+  #[tokio::test]
+  async fn reauthentication_passwords_are_throttled() {
+    let (service, _) = service();
+    let user = sign_up(&service).await;
+
+    for _ in 0..5 {
+      let wrong = service.confirm_identity(user.id, &password("wrong")).await;
+      assert!(matches!(
+        wrong,
+        Err(ConfirmIdentityError::Reauthentication(
+          ReauthenticationError::IncorrectPassword
+        ))
+      ));
+    }
+    let throttled = service
+      .confirm_identity(user.id, &password("hunter42"))
+      .await;
+    assert!(matches!(
+      throttled,
+      Err(ConfirmIdentityError::Reauthentication(
+        ReauthenticationError::Throttled { .. }
+      ))
+    ));
+    // the guesses count towards password logins too
+    let login = service
+      .user_authenticate(UserSubmittedAuthCredentials::EmailAndPassword {
+        email:    user.email.clone(),
+        password: "hunter42".to_string(),
+      })
+      .await;
+    assert!(matches!(login, Err(AuthenticationError::Throttled { .. })));
+  }
 }
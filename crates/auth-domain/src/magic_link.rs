@@ -0,0 +1,253 @@
+//! Logging in, and signing up, with emailed single-use links.
+//!
+//! Users with an account get a token stored against them, like a password
+//! reset. Addresses without an account get a signed link instead, so that
+//! asking for one doesn't create anything; the account is created when the
+//! link is opened.
+
+use std::{fmt, net::IpAddr, sync::Arc};
+
+use base64::Engine;
+use chrono::TimeDelta;
+use hmac::{Hmac, Mac};
+use mailer::{templates::LogInLink, SendMailError};
+use miette::IntoDiagnostic;
+use models::{EmailAddress, User, UserAuthCredentials, UserCreateRequest};
+use rand::RngCore;
+use repos::{FetchModelByIndexError, LoginAttemptError, PatchModelError};
+use sha2::Sha256;
+use tracing::instrument;
+
+use crate::{
+  default_name,
+  token::{
+    check_token, cooldown_remaining, generate_token, parse_token, TokenCheck,
+  },
+  AuthDomainService, AuthenticationError,
+};
+
+/// The path that login links point to.
+pub const MAGIC_LINK_PATH: &str = "/magic-link/";
+
+/// How long a login link stays valid.
+const MAGIC_LINK_TTL: TimeDelta = TimeDelta::minutes(15);
+
+/// How long a user must wait before another login link is sent.
+const MAGIC_LINK_COOLDOWN: TimeDelta = TimeDelta::minutes(1);
+
+/// An error that occurs when requesting a login link.
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum RequestMagicLinkError {
+  /// Indicates that an error occurred while fetching the user.
+  #[error("Failed to fetch user by index")]
+  FetchByIndexError(#[from] FetchModelByIndexError),
+  /// Indicates that an error occurred while updating the user.
+  #[error("Failed to update user")]
+  PatchError(#[from] PatchModelError),
+  /// Indicates that an error occurred while sending the email.
+  #[error("Failed to send login link email")]
+  MailError(#[from] SendMailError),
+  /// Indicates that an error occurred while counting signup links.
+  #[error("Failed to count signup links")]
+  LoginAttemptError(#[from] LoginAttemptError),
+}
+
+/// Signs and checks the signup links sent to addresses without an account.
+#[derive(Clone)]
+pub(crate) struct LinkSigner {
+  secret: Arc<[u8]>,
+}
+
+impl fmt::Debug for LinkSigner {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("LinkSigner").finish_non_exhaustive()
+  }
+}
+
+impl LinkSigner {
+  pub(crate) fn new(secret: &[u8]) -> Self {
+    Self {
+      secret: secret.into(),
+    }
+  }
+
+  /// A signer with a random secret. Signup links it signs won't work after
+  /// a restart.
+  pub(crate) fn new_random() -> Self {
+    let mut secret = [0; 32];
+    rand::rng().fill_bytes(&mut secret);
+    Self::new(&secret)
+  }
+
+  fn mac(&self, message: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(&self.secret)
+      .expect("hmac accepts keys of any length");
+    mac.update(message.as_bytes());
+    mac
+  }
+
+  /// Signs a signup token of the form `<email>.<expiry>.<signature>`, with
+  /// the email base64url-encoded.
+  fn sign_up_token(&self, email: &EmailAddress) -> String {
+    let email = base64::prelude::BASE64_URL_SAFE_NO_PAD.encode(email.as_ref());
+    let expires = (chrono::Utc::now() + MAGIC_LINK_TTL).timestamp();
+    let message = format!("{email}.{expires}");
+    let signature = base64::prelude::BASE64_URL_SAFE_NO_PAD
+      .encode(self.mac(&message).finalize().into_bytes());
+    format!("{message}.{signature}")
+  }
+
+  /// The address a signup token was issued to, if it's genuine and hasn't
+  /// expired.
+  fn check_sign_up_token(&self, token: &str) -> Option<EmailAddress> {
+    let (message, signature) = token.rsplit_once('.')?;
+    let (email, expires) = message.split_once('.')?;
+    let signature = base64::prelude::BASE64_URL_SAFE_NO_PAD
+      .decode(signature)
+      .ok()?;
+    self.mac(message).verify_slice(&signature).ok()?;
+    if expires.parse::<i64>().ok()? < chrono::Utc::now().timestamp() {
+      return None;
+    }
+    let email = base64::prelude::BASE64_URL_SAFE_NO_PAD.decode(email).ok()?;
+    EmailAddress::try_new(String::from_utf8(email).ok()?).ok()
+  }
+}
+
+impl AuthDomainService {
+  /// Sets the secret that signs the signup links sent to addresses without
+  /// an account. Without one, a random secret is used.
+  #[must_use]
+  pub fn with_link_secret(mut self, secret: &[u8]) -> Self {
+    self.link_signer = LinkSigner::new(secret);
+    self
+  }
+
+  /// Email a login link to the given address. If there's no account with the
+  /// address, opening the link creates one without a password.
+  ///
+  /// Succeeds without sending anything if the user has a second factor,
+  /// which a link would bypass, or if a link was sent moments ago. Either
+  /// way, callers can't learn which addresses have accounts.
+  pub async fn request_magic_link(
+    &self,
+    email: EmailAddress,
+  ) -> Result<(), RequestMagicLinkError> {
+    self.request_magic_link_from(email, None).await
+  }
+
+  /// Email a login link, as with
+  /// [`request_magic_link`](Self::request_magic_link), for a client with the
+  /// given address. Signup links for addresses without an account are
+  /// counted against the address and the client, and silently not sent once
+  /// either has asked for too many.
+  #[instrument(skip(self))]
+  pub async fn request_magic_link_from(
+    &self,
+    email: EmailAddress,
+    client: Option<IpAddr>,
+  ) -> Result<(), RequestMagicLinkError> {
+    let (email, name, token) =
+      match self.user_repo.fetch_user_by_email(email.clone()).await? {
+        Some(user) if user.has_two_factor() => {
+          tracing::info!("login link requested for user with a second factor");
+          return Ok(());
+        }
+        Some(mut user) => {
+          if cooldown_remaining(user.magic_link.as_ref(), MAGIC_LINK_COOLDOWN)
+            .is_some()
+          {
+            tracing::info!("login link requested again too soon; ignoring");
+            return Ok(());
+          }
+          let (token, link) = generate_token(user.id, MAGIC_LINK_TTL);
+          user.magic_link = Some(link);
+          let user = self.user_repo.update_user(user).await?;
+          (user.email, Some(user.name), token)
+        }
+        None => {
+          if !self.begin_sign_up_link(&email, client).await? {
+            tracing::info!("signup link requested too often; ignoring");
+            return Ok(());
+          }
+          let token = self.link_signer.sign_up_token(&email);
+          (email, None, token)
+        }
+      };
+
+    let template = LogInLink {
+      name,
+      url: format!(
+        "{base_url}{MAGIC_LINK_PATH}{token}",
+        base_url = self.base_url.0
+      ),
+    };
+    self.mailer.send(email, &template).await?;
+
+    Ok(())
+  }
+
+  /// Checks the token from a login link, using it up. Signup links create
+  /// the account they were sent for, and only work while it doesn't exist.
+  pub(crate) async fn authenticate_magic_link(
+    &self,
+    token: &str,
+  ) -> Result<Option<User>, AuthenticationError> {
+    if let Some(email) = self.link_signer.check_sign_up_token(token) {
+      if self
+        .user_repo
+        .fetch_user_by_email(email.clone())
+        .await?
+        .is_some()
+      {
+        return Ok(None);
+      }
+      return self.create_magic_link_user(email).await.map(Some);
+    }
+
+    let Some((id, secret)) = parse_token(token) else {
+      return Ok(None);
+    };
+    let Some(mut user) = self.user_repo.fetch_user_by_id(id).await? else {
+      return Ok(None);
+    };
+    if !matches!(
+      check_token(user.magic_link.as_ref(), secret),
+      TokenCheck::Valid
+    ) {
+      return Ok(None);
+    }
+    // a second factor added after the link was sent still counts
+    if user.has_two_factor() {
+      return Ok(None);
+    }
+
+    user.magic_link = None;
+    // the link was delivered to the user's inbox, which proves they own it
+    user.email_verified = true;
+    user.email_verification = None;
+    Ok(Some(self.user_repo.update_user(user).await?))
+  }
+
+  /// Creates a passwordless account for the address a signup link was sent
+  /// to.
+  async fn create_magic_link_user(
+    &self,
+    email: EmailAddress,
+  ) -> Result<User, AuthenticationError> {
+    let req = UserCreateRequest {
+      name:  default_name(&email),
+      email: email.clone(),
+      auth:  UserAuthCredentials::MagicLink { email },
+    };
+    let mut user = self
+      .user_repo
+      .create_user(req)
+      .await
+      .into_diagnostic()
+      .map_err(AuthenticationError::CreateError)?;
+
+    user.email_verified = true;
+    Ok(self.user_repo.update_user(user).await?)
+  }
+}
@@ -0,0 +1,856 @@
+//! Logging in with `OpenID` Connect providers, e.g. "Sign in with Google".
+//!
+//! The site uses the authorization code flow with PKCE.
+//! [`begin_oidc_login`](AuthDomainService::begin_oidc_login) returns the URL
+//! to send the browser to, and a [`PendingOidcLogin`] that the caller keeps
+//! server-side until the provider redirects back. The redirect is then
+//! checked by [`user_authenticate`](AuthDomainService::user_authenticate),
+//! which logs into the account with the provider's verified email address,
+//! creating it if there isn't one.
+
+use std::fmt;
+
+use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+use chrono::TimeDelta;
+use miette::IntoDiagnostic;
+use models::{
+  EmailAddress, HumanName, OidcIdentity, PendingOidcLogin, User,
+  UserAuthCredentials, UserCreateRequest,
+};
+use p256::ecdsa::{signature::Verifier, Signature, VerifyingKey};
+use rand::RngCore;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tracing::instrument;
+
+use crate::{default_name, AuthDomainService, AuthenticationError};
+
+/// The path under which each provider's login and redirect URLs live, e.g.
+/// `/auth/oidc/google/callback`.
+pub const OIDC_PATH: &str = "/auth/oidc/";
+
+/// How long the user has to finish logging in at the provider.
+const PENDING_OIDC_LOGIN_TTL: TimeDelta = TimeDelta::minutes(10);
+
+/// How far past its expiry an ID token is still accepted, for clock skew.
+const ID_TOKEN_LEEWAY_SECONDS: i64 = 60;
+
+/// An `OpenID` Connect provider that users can log in with.
+#[derive(Clone)]
+pub struct OidcProviderConfig {
+  /// A short ID for the provider, used in URLs, e.g. `google`.
+  pub id:            String,
+  /// The name shown to users, e.g. `Google`.
+  pub name:          String,
+  /// The provider's issuer URL, e.g. `https://accounts.google.com`.
+  pub issuer:        String,
+  /// The client ID the provider issued to the site.
+  pub client_id:     String,
+  /// The client secret the provider issued to the site.
+  pub client_secret: String,
+}
+
+impl fmt::Debug for OidcProviderConfig {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("OidcProviderConfig")
+      .field("id", &self.id)
+      .field("name", &self.name)
+      .field("issuer", &self.issuer)
+      .field("client_id", &self.client_id)
+      .finish_non_exhaustive()
+  }
+}
+
+/// Where to send the browser to log in with a provider.
+#[derive(Clone, Debug)]
+pub struct OidcAuthorization {
+  /// The provider's authorization URL, with the request's parameters.
+  pub url:     String,
+  /// The login to keep server-side until the provider redirects back.
+  pub pending: PendingOidcLogin,
+}
+
+/// An error that occurs when talking to an `OpenID` Connect provider.
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum OidcError {
+  /// Indicates that no provider is configured with the given ID.
+  #[error("Unknown login provider: \"{0}\"")]
+  UnknownProvider(String),
+  /// Indicates that the provider couldn't be reached.
+  #[error("Failed to reach the login provider")]
+  Request(#[from] reqwest::Error),
+  /// Indicates that the provider's answer didn't follow the protocol.
+  #[error("The login provider gave an unexpected answer: {0}")]
+  Protocol(&'static str),
+  /// Indicates that the provider refused the authorization code, e.g.
+  /// because it was already used.
+  #[error("The login provider rejected the authorization code")]
+  CodeRejected,
+  /// Indicates that the ID token didn't check out.
+  #[error("The login provider's ID token is invalid: {0}")]
+  InvalidIdToken(&'static str),
+}
+
+/// The parts of a provider's discovery document that we use.
+#[derive(Deserialize)]
+struct ProviderMetadata {
+  issuer:                 String,
+  authorization_endpoint: String,
+  token_endpoint:         String,
+  jwks_uri:               String,
+}
+
+/// A provider's signing keys.
+#[derive(Deserialize)]
+struct JsonWebKeySet {
+  keys: Vec<JsonWebKey>,
+}
+
+/// A signing key, either RSA (`n` and `e`) or P-256 (`x` and `y`).
+#[derive(Deserialize)]
+struct JsonWebKey {
+  kty: String,
+  kid: Option<String>,
+  crv: Option<String>,
+  n:   Option<String>,
+  e:   Option<String>,
+  x:   Option<String>,
+  y:   Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+  id_token: String,
+}
+
+#[derive(Deserialize)]
+struct IdTokenHeader {
+  alg: String,
+  kid: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Audience {
+  One(String),
+  Many(Vec<String>),
+}
+
+impl Audience {
+  fn contains(&self, client_id: &str) -> bool {
+    match self {
+      Self::One(aud) => aud == client_id,
+      Self::Many(auds) => auds.iter().any(|aud| aud == client_id),
+    }
+  }
+}
+
+/// The claims of an ID token that we use.
+#[derive(Deserialize)]
+struct IdTokenClaims {
+  iss:            String,
+  aud:            Audience,
+  exp:            i64,
+  nonce:          Option<String>,
+  sub:            String,
+  email:          Option<String>,
+  // some providers send `"true"` rather than `true`
+  email_verified: Option<serde_json::Value>,
+  name:           Option<String>,
+}
+
+impl IdTokenClaims {
+  /// The user's email address, if the provider vouches for it.
+  fn verified_email(&self) -> Option<EmailAddress> {
+    let verified = match &self.email_verified {
+      Some(serde_json::Value::Bool(verified)) => *verified,
+      Some(serde_json::Value::String(verified)) => verified == "true",
+      _ => false,
+    };
+    if !verified {
+      return None;
+    }
+    EmailAddress::try_new(self.email.clone()?).ok()
+  }
+}
+
+fn random_string() -> String {
+  let mut bytes = [0_u8; 32];
+  rand::rng().fill_bytes(&mut bytes);
+  BASE64_URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn pkce_challenge(code_verifier: &str) -> String {
+  BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+/// Issuers are compared without trailing slashes, which providers are
+/// inconsistent about.
+fn same_issuer(a: &str, b: &str) -> bool {
+  a.trim_end_matches('/') == b.trim_end_matches('/')
+}
+
+fn decode_part<T: serde::de::DeserializeOwned>(
+  part: &str,
+  what: &'static str,
+) -> Result<T, OidcError> {
+  BASE64_URL_SAFE_NO_PAD
+    .decode(part)
+    .ok()
+    .and_then(|json| serde_json::from_slice(&json).ok())
+    .ok_or(OidcError::InvalidIdToken(what))
+}
+
+/// Checks the signature of a JWS with one of the provider's keys.
+fn verify_signature(
+  alg: &str,
+  key: &JsonWebKey,
+  message: &[u8],
+  signature: &[u8],
+) -> Result<(), OidcError> {
+  let field = |value: &Option<String>| {
+    value
+      .as_deref()
+      .and_then(|v| BASE64_URL_SAFE_NO_PAD.decode(v).ok())
+      .ok_or(OidcError::InvalidIdToken("malformed signing key"))
+  };
+
+  let valid = match (alg, key.kty.as_str()) {
+    ("RS256", "RSA") => ring::signature::RsaPublicKeyComponents {
+      n: field(&key.n)?,
+      e: field(&key.e)?,
+    }
+    .verify(
+      &ring::signature::RSA_PKCS1_2048_8192_SHA256,
+      message,
+      signature,
+    )
+    .is_ok(),
+    ("ES256", "EC") if key.crv.as_deref() == Some("P-256") => {
+      let mut point = vec![0x04];
+      point.extend(field(&key.x)?);
+      point.extend(field(&key.y)?);
+      let key = VerifyingKey::from_sec1_bytes(&point)
+        .map_err(|_| OidcError::InvalidIdToken("malformed signing key"))?;
+      Signature::from_slice(signature)
+        .is_ok_and(|signature| key.verify(message, &signature).is_ok())
+    }
+    _ => {
+      return Err(OidcError::InvalidIdToken("unsupported signing algorithm"))
+    }
+  };
+
+  if valid {
+    Ok(())
+  } else {
+    Err(OidcError::InvalidIdToken("bad signature"))
+  }
+}
+
+/// Checks an ID token's signature and claims. `now` is a Unix timestamp.
+fn verify_id_token(
+  token: &str,
+  keys: &JsonWebKeySet,
+  issuer: &str,
+  client_id: &str,
+  nonce: &str,
+  now: i64,
+) -> Result<IdTokenClaims, OidcError> {
+  let (signing_input, signature) = token
+    .rsplit_once('.')
+    .ok_or(OidcError::InvalidIdToken("not a JWS"))?;
+  let (header, payload) = signing_input
+    .split_once('.')
+    .ok_or(OidcError::InvalidIdToken("not a JWS"))?;
+  let header: IdTokenHeader = decode_part(header, "malformed header")?;
+  let signature = BASE64_URL_SAFE_NO_PAD
+    .decode(signature)
+    .map_err(|_| OidcError::InvalidIdToken("malformed signature"))?;
+
+  let key = keys
+    .keys
+    .iter()
+    .find(|k| header.kid.is_none() || k.kid == header.kid)
+    .ok_or(OidcError::InvalidIdToken("unknown signing key"))?;
+  verify_signature(&header.alg, key, signing_input.as_bytes(), &signature)?;
+
+  let claims: IdTokenClaims = decode_part(payload, "malformed claims")?;
+  if !same_issuer(&claims.iss, issuer) {
+    return Err(OidcError::InvalidIdToken("wrong issuer"));
+  }
+  if !claims.aud.contains(client_id) {
+    return Err(OidcError::InvalidIdToken("wrong audience"));
+  }
+  if claims.exp + ID_TOKEN_LEEWAY_SECONDS < now {
+    return Err(OidcError::InvalidIdToken("expired"));
+  }
+  if claims.nonce.as_deref() != Some(nonce) {
+    return Err(OidcError::InvalidIdToken("wrong nonce"));
+  }
+
+  Ok(claims)
+}
+
+impl AuthDomainService {
+  /// Sets the `OpenID` Connect providers that users can log in with.
+  #[must_use]
+  pub fn with_oidc_providers(
+    mut self,
+    providers: Vec<OidcProviderConfig>,
+  ) -> Self {
+    self.oidc_providers = providers.into();
+    self
+  }
+
+  /// The `OpenID` Connect providers that users can log in with.
+  #[must_use]
+  pub fn oidc_providers(&self) -> &[OidcProviderConfig] { &self.oidc_providers }
+
+  fn oidc_provider(&self, id: &str) -> Option<&OidcProviderConfig> {
+    self.oidc_providers.iter().find(|p| p.id == id)
+  }
+
+  /// The URL the provider redirects the browser back to.
+  fn oidc_redirect_uri(&self, provider: &OidcProviderConfig) -> String {
+    format!(
+      "{base_url}{OIDC_PATH}{id}/callback",
+      base_url = self.base_url.0,
+      id = provider.id
+    )
+  }
+
+  async fn fetch_provider_metadata(
+    &self,
+    provider: &OidcProviderConfig,
+  ) -> Result<ProviderMetadata, OidcError> {
+    let url = format!(
+      "{}/.well-known/openid-configuration",
+      provider.issuer.trim_end_matches('/')
+    );
+    let metadata: ProviderMetadata = self
+      .http
+      .get(url)
+      .send()
+      .await?
+      .error_for_status()?
+      .json()
+      .await?;
+    if !same_issuer(&metadata.issuer, &provider.issuer) {
+      return Err(OidcError::Protocol(
+        "discovery document is for another issuer",
+      ));
+    }
+    Ok(metadata)
+  }
+
+  /// Start logging in with an `OpenID` Connect provider.
+  #[instrument(skip(self))]
+  pub async fn begin_oidc_login(
+    &self,
+    provider_id: &str,
+  ) -> Result<OidcAuthorization, OidcError> {
+    let provider = self
+      .oidc_provider(provider_id)
+      .ok_or_else(|| OidcError::UnknownProvider(provider_id.to_owned()))?;
+    let metadata = self.fetch_provider_metadata(provider).await?;
+
+    let pending = PendingOidcLogin {
+      provider:      provider.id.clone(),
+      state:         random_string(),
+      nonce:         random_string(),
+      code_verifier: random_string(),
+      expires_at:    chrono::Utc::now() + PENDING_OIDC_LOGIN_TTL,
+    };
+    let url =
+      reqwest::Url::parse_with_params(&metadata.authorization_endpoint, &[
+        ("response_type", "code"),
+        ("client_id", provider.client_id.as_str()),
+        ("redirect_uri", self.oidc_redirect_uri(provider).as_str()),
+        ("scope", "openid email profile"),
+        ("state", pending.state.as_str()),
+        ("nonce", pending.nonce.as_str()),
+        (
+          "code_challenge",
+          pkce_challenge(&pending.code_verifier).as_str(),
+        ),
+        ("code_challenge_method", "S256"),
+      ])
+      .map_err(|_| OidcError::Protocol("invalid authorization endpoint"))?;
+
+    Ok(OidcAuthorization {
+      url: url.into(),
+      pending,
+    })
+  }
+
+  /// Trades the redirect's authorization code for a checked ID token.
+  async fn exchange_oidc_code(
+    &self,
+    provider: &OidcProviderConfig,
+    pending: &PendingOidcLogin,
+    code: &str,
+  ) -> Result<IdTokenClaims, OidcError> {
+    let metadata = self.fetch_provider_metadata(provider).await?;
+
+    let response = self
+      .http
+      .post(&metadata.token_endpoint)
+      .form(&[
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", self.oidc_redirect_uri(provider).as_str()),
+        ("client_id", provider.client_id.as_str()),
+        ("client_secret", provider.client_secret.as_str()),
+        ("code_verifier", pending.code_verifier.as_str()),
+      ])
+      .send()
+      .await?;
+    if response.status().is_client_error() {
+      return Err(OidcError::CodeRejected);
+    }
+    let response: TokenResponse = response.error_for_status()?.json().await?;
+
+    let keys: JsonWebKeySet = self
+      .http
+      .get(&metadata.jwks_uri)
+      .send()
+      .await?
+      .error_for_status()?
+      .json()
+      .await?;
+
+    verify_id_token(
+      &response.id_token,
+      &keys,
+      &metadata.issuer,
+      &provider.client_id,
+      &pending.nonce,
+      chrono::Utc::now().timestamp(),
+    )
+  }
+
+  /// Checks an `OpenID` Connect provider's redirect, logging into the account
+  /// with the provider's verified email address. Unknown addresses get a new
+  /// account without a password. Accounts with a second factor are refused,
+  /// since the provider can't check it; their owners log in with their
+  /// password and code instead.
+  pub(crate) async fn authenticate_oidc(
+    &self,
+    pending: &PendingOidcLogin,
+    state: &str,
+    code: &str,
+  ) -> Result<Option<User>, AuthenticationError> {
+    if pending.state != state || pending.expires_at < chrono::Utc::now() {
+      return Ok(None);
+    }
+    let Some(provider) = self.oidc_provider(&pending.provider) else {
+      return Ok(None);
+    };
+
+    let claims = match self.exchange_oidc_code(provider, pending, code).await {
+      Ok(claims) => claims,
+      Err(e @ (OidcError::CodeRejected | OidcError::InvalidIdToken(_))) => {
+        tracing::warn!("rejected login with {}: {e}", provider.id);
+        return Ok(None);
+      }
+      Err(e) => return Err(e.into()),
+    };
+    let Some(email) = claims.verified_email() else {
+      tracing::warn!(
+        "rejected login with {}: no verified email address",
+        provider.id
+      );
+      return Ok(None);
+    };
+    let identity = OidcIdentity {
+      issuer:    claims.iss.clone(),
+      subject:   claims.sub.clone(),
+      linked_at: chrono::Utc::now(),
+    };
+
+    match self.user_repo.fetch_user_by_email(email.clone()).await? {
+      Some(user) => self.link_oidc_identity(user, identity).await,
+      None => self
+        .create_oidc_user(email, claims.name.as_deref(), identity)
+        .await
+        .map(Some),
+    }
+  }
+
+  /// Links a provider identity to the account with its email address.
+  async fn link_oidc_identity(
+    &self,
+    mut user: User,
+    identity: OidcIdentity,
+  ) -> Result<Option<User>, AuthenticationError> {
+    // an unverified signup's second factor is dropped below with the rest of
+    // its credentials, so it doesn't keep out the address's owner
+    if user.email_verified && user.has_two_factor() {
+      tracing::info!(
+        "rejected login for user {} with {}: the user has a second factor",
+        user.id,
+        identity.issuer
+      );
+      return Ok(None);
+    }
+
+    if let Some(linked) = user
+      .oidc_identities
+      .iter()
+      .find(|i| i.issuer == identity.issuer)
+    {
+      if linked.subject == identity.subject {
+        return Ok(Some(user));
+      }
+      // the address now belongs to someone else at the provider
+      tracing::warn!(
+        "rejected login for user {}: another identity at {} is linked",
+        user.id,
+        identity.issuer
+      );
+      return Ok(None);
+    }
+
+    if !user.email_verified {
+      // nobody proved they own the address, so whoever signed up may not be
+      // its owner; the provider's word wins and their credentials go
+      user.auth = UserAuthCredentials::Oidc {
+        email: user.email.clone(),
+      };
+      user.totp = None;
+      user.passkeys.clear();
+      user.password_reset = None;
+      user.magic_link = None;
+      user.email_verification = None;
+      user.email_verified = true;
+    }
+    user.oidc_identities.push(identity);
+    Ok(Some(self.user_repo.update_user(user).await?))
+  }
+
+  /// Creates an account for a provider identity with an unknown address.
+  async fn create_oidc_user(
+    &self,
+    email: EmailAddress,
+    name: Option<&str>,
+    identity: OidcIdentity,
+  ) -> Result<User, AuthenticationError> {
+    let name = name
+      .and_then(|n| HumanName::try_new(n).ok())
+      .unwrap_or_else(|| default_name(&email));
+
+    let req = UserCreateRequest {
+      name,
+      email: email.clone(),
+      auth: UserAuthCredentials::Oidc { email },
+    };
+    let mut user = self
+      .user_repo
+      .create_user(req)
+      .await
+      .into_diagnostic()
+      .map_err(AuthenticationError::CreateError)?;
+
+    user.email_verified = true;
+    user.oidc_identities.push(identity);
+    Ok(self.user_repo.update_user(user).await?)
+  }
+}
+
+/// An `OpenID` Connect provider served from a local port, for tests.
+#[cfg(test)]
+pub(crate) mod mock_provider {
+  use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+  };
+
+  use axum::{
+    extract::State,
+    http::StatusCode,
+    routing::{get, post},
+    Form, Json, Router,
+  };
+
+  use super::*;
+
+  /// The client ID the mock issues to the site.
+  const CLIENT_ID: &str = "picturepro";
+  /// The client secret the mock issues to the site.
+  const CLIENT_SECRET: &str = "mock-secret";
+  /// The ID of the mock's signing key.
+  const KEY_ID: &str = "mock-key";
+
+  /// A throwaway 2048-bit RSA signing key, as base64 PKCS#8.
+  const SIGNING_KEY: &[&str] = &[
+    "MIIEvQIBADANBgkqhkiG9w0BAQEFAASCBKcwggSjAgEAAoIBAQDGyhh4/VIHy2la",
+    "tm7xC0VJzp2Hp0la11QWWjTZuR34yYjVJIo/i9YUaWoGjwjLHXRx/4CUCSnEJQmm",
+    "+ZD+BtW4f5A+1804YsTO5nE/NNE32+yXp++Omsv2iz9nTqLyLzCCC+/pJ44gxR58",
+    "bTR6xTnWLz8Hu4H18tv8ey10nUGvVXyh2YkSUNOeBaDGkEA34Hag9DhVeBwX3p0w",
+    "TGpQmDWLb+ZqSapOJf+yJ9xWHCnoM+hnBKBc92kiBurSdK5EwV6Qr05fyqVVtp8d",
+    "px1qFbsgKG7LK6mOVAjHPNt0A48OQHPFTv8+H7oU0VZijZSGVWia5zfpeHt3Z6rt",
+    "ttsmRXAVAgMBAAECgf9GtZw88QVbwGuh7/y8uR7NSOeJj3PGWGT9QNrwlw8SMZXn",
+    "FDdPDeqj8zg35rhojyEiRM9v2MrMKM0fWaZQtYKGVE4KSooxD/ij7WPPThlL+5Fb",
+    "Ob4yxSqwj4IVVGc8tnUeGpVDl/zfAISZf7MjXUI1Ye44Lv5BnQJF+1wYkzwnKP0S",
+    "T1jtCMPfTb/iRlwUtQ/hIBw6dbOx4dYVsxe/FwL1mYXAkF0tRQJkHzd8uDagcoHf",
+    "V0NZIRu42SKCcABxRSqyFbr5o08hnvDqB0Se+Acw2GqwV6Cu0Im8gc0oK073qMQh",
+    "4VG74wR3w2rCFWNW+pRKZsZf01lK1RiS8A5y970CgYEA+iOztgZH7TJpRGVkZvQO",
+    "h5m0HWpCDvBnU2My2cWPkpwxKYeEme9XyQcO8qygd1oSCivOZ33u8HCihhe+j617",
+    "flPITzs3Ebg9uudDXlTEbY/XqYPRxbLJ6Or7ZvtWshmYra3AibmaPL2gHmdsv5iC",
+    "Lz3FVM+J66xXqrAd7penpW8CgYEAy3Jnb9FJk7Tv+XTIK+w5uI90nLFh3YYBQN0k",
+    "kP41yWNC1eE/wO8gqqFxQEsDvOl5aJ7NJBtaVcGdnlXaw0Mw/r9N5x7bBQ6iAXZe",
+    "dqJTJDkYMwlpsqGk2Y/h+iTuAQiYgcIU5jQIXGWcCBXrWLj/U0VrDXmz4GS/2go3",
+    "pvnN6LsCgYEAmmRc8RYRYSuDY+79OLqdGT9at+k1GslxzTtmUJorUv/LLGn0A5uC",
+    "YJdVt52rcd+LUPHW9XP6fjfXaNLRERewKLoH4jeXdv10RJ+nqlrB3yown/W4DcGb",
+    "9ILNZ7PswAMihNjBBYEk3Sbh5MJLkjrM5+HFoeJzq551geown2rF1EsCgYEAytKr",
+    "PFaxu4fPFirOxLskcFLUNsLAhlBqP3g4VDSa+sLyhrehD39WWcXJWgdvGo7Icvye",
+    "FzkNviDgOlHtXm1GEzI1QA0K3OfRqxOO6Ua7DIm/DmJ79ZIg/qTq7K6UeINRR0tt",
+    "+nI1Q6go/gacMj98jE/QggaqxTKr8XvuL5ErLrECgYEApU3PqdzzPE8nQAPQKOTe",
+    "6zUS0HgwEDDGfPpeyW+12pLckHQRZS3VUIRT5Kne659+161ekcm9NBqqnxzrR7iv",
+    "aCc6nvdTjf/HMOcpVVuN5Tq+dngf/bIwGE6iqcHZGXinlM6SEuGUDDSq1F1jLugI",
+    "3bx0rktzhew5sMFByg9rtsA=",
+  ];
+  /// The signing key's modulus, as base64url.
+  const SIGNING_KEY_N: &[&str] = &[
+    "xsoYeP1SB8tpWrZu8QtFSc6dh6dJWtdUFlo02bkd-MmI1SSKP4vWFGlqBo8Iyx10",
+    "cf-AlAkpxCUJpvmQ_gbVuH-QPtfNOGLEzuZxPzTRN9vsl6fvjprL9os_Z06i8i8w",
+    "ggvv6SeOIMUefG00esU51i8_B7uB9fLb_HstdJ1Br1V8odmJElDTngWgxpBAN-B2",
+    "oPQ4VXgcF96dMExqUJg1i2_makmqTiX_sifcVhwp6DPoZwSgXPdpIgbq0nSuRMFe",
+    "kK9OX8qlVbafHacdahW7IChuyyupjlQIxzzbdAOPDkBzxU7_Ph-6FNFWYo2UhlVo",
+    "muc36Xh7d2eq7bbbJkVwFQ",
+  ];
+
+  /// A code the mock handed out, and what it trades for.
+  struct Grant {
+    claims:         serde_json::Value,
+    code_challenge: String,
+    redirect_uri:   String,
+  }
+
+  struct MockState {
+    issuer: String,
+    grants: Mutex<HashMap<String, Grant>>,
+  }
+
+  /// A running mock provider.
+  pub(crate) struct MockProvider {
+    state: Arc<MockState>,
+  }
+
+  /// Signs claims as an RS256 JWS with the mock's key.
+  pub(super) fn sign_id_token(claims: &serde_json::Value) -> String {
+    use base64::prelude::BASE64_STANDARD;
+
+    let key = BASE64_STANDARD.decode(SIGNING_KEY.concat()).unwrap();
+    let key = ring::signature::RsaKeyPair::from_pkcs8(&key).unwrap();
+
+    let header = serde_json::json!({ "alg": "RS256", "kid": KEY_ID });
+    let signing_input = format!(
+      "{}.{}",
+      BASE64_URL_SAFE_NO_PAD.encode(header.to_string()),
+      BASE64_URL_SAFE_NO_PAD.encode(claims.to_string()),
+    );
+    let modulus = BASE64_URL_SAFE_NO_PAD
+      .decode(SIGNING_KEY_N.concat())
+      .unwrap();
+    let mut signature = vec![0; modulus.len()];
+    key
+      .sign(
+        &ring::signature::RSA_PKCS1_SHA256,
+        &ring::rand::SystemRandom::new(),
+        signing_input.as_bytes(),
+        &mut signature,
+      )
+      .unwrap();
+    format!(
+      "{signing_input}.{}",
+      BASE64_URL_SAFE_NO_PAD.encode(signature)
+    )
+  }
+
+  /// The mock's signing keys, as a JWKS document.
+  pub(super) fn jwks() -> serde_json::Value {
+    serde_json::json!({
+      "keys": [{
+        "kty": "RSA",
+        "kid": KEY_ID,
+        "alg": "RS256",
+        "use": "sig",
+        "n": SIGNING_KEY_N.concat(),
+        "e": "AQAB",
+      }],
+    })
+  }
+
+  async fn discovery(
+    State(state): State<Arc<MockState>>,
+  ) -> Json<serde_json::Value> {
+    let issuer = &state.issuer;
+    Json(serde_json::json!({
+      "issuer": issuer,
+      "authorization_endpoint": format!("{issuer}/authorize"),
+      "token_endpoint": format!("{issuer}/token"),
+      "jwks_uri": format!("{issuer}/jwks"),
+    }))
+  }
+
+  async fn token(
+    State(state): State<Arc<MockState>>,
+    Form(form): Form<HashMap<String, String>>,
+  ) -> Result<Json<serde_json::Value>, StatusCode> {
+    let field = |name: &str| form.get(name).map_or("", String::as_str);
+    if field("client_id") != CLIENT_ID
+      || field("client_secret") != CLIENT_SECRET
+    {
+      return Err(StatusCode::UNAUTHORIZED);
+    }
+    // codes are single-use
+    let grant = state
+      .grants
+      .lock()
+      .unwrap()
+      .remove(field("code"))
+      .ok_or(StatusCode::BAD_REQUEST)?;
+    if pkce_challenge(field("code_verifier")) != grant.code_challenge
+      || field("redirect_uri") != grant.redirect_uri
+    {
+      return Err(StatusCode::BAD_REQUEST);
+    }
+
+    Ok(Json(serde_json::json!({
+      "access_token": random_string(),
+      "token_type": "Bearer",
+      "id_token": sign_id_token(&grant.claims),
+    })))
+  }
+
+  impl MockProvider {
+    /// Starts a provider on a free local port.
+    pub(crate) async fn start() -> Self {
+      let listener =
+        tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+      let state = Arc::new(MockState {
+        issuer: format!("http://{}", listener.local_addr().unwrap()),
+        grants: Mutex::default(),
+      });
+      let app = Router::new()
+        .route("/.well-known/openid-configuration", get(discovery))
+        .route("/jwks", get(|| async { Json(jwks()) }))
+        .route("/token", post(token))
+        .with_state(state.clone());
+      tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+      Self { state }
+    }
+
+    /// The site's configuration for the mock, with the ID `mock`.
+    pub(crate) fn config(&self) -> OidcProviderConfig {
+      OidcProviderConfig {
+        id:            "mock".to_owned(),
+        name:          "Mock".to_owned(),
+        issuer:        self.state.issuer.clone(),
+        client_id:     CLIENT_ID.to_owned(),
+        client_secret: CLIENT_SECRET.to_owned(),
+      }
+    }
+
+    /// Plays the user logging in at the provider's authorization URL,
+    /// returning the `state` and `code` of the redirect back. The ID token
+    /// will carry `claims`, plus the standard ones.
+    pub(crate) fn authorize(
+      &self,
+      url: &str,
+      mut claims: serde_json::Value,
+    ) -> (String, String) {
+      let url = reqwest::Url::parse(url).unwrap();
+      let params = url.query_pairs().into_owned().collect::<HashMap<_, _>>();
+      assert_eq!(params["client_id"], CLIENT_ID);
+      assert_eq!(params["code_challenge_method"], "S256");
+
+      claims["iss"] = self.state.issuer.clone().into();
+      claims["aud"] = CLIENT_ID.into();
+      claims["nonce"] = params["nonce"].clone().into();
+      claims["exp"] = (chrono::Utc::now().timestamp() + 300).into();
+
+      let code = random_string();
+      self
+        .state
+        .grants
+        .lock()
+        .unwrap()
+        .insert(code.clone(), Grant {
+          claims,
+          code_challenge: params["code_challenge"].clone(),
+          redirect_uri: params["redirect_uri"].clone(),
+        });
+      (params["state"].clone(), code)
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{mock_provider::*, *};
+
+  #[test]
+  fn id_token_checks() {
+    let keys: JsonWebKeySet = serde_json::from_value(jwks()).unwrap();
+    let now = 1_700_000_000;
+    let claims = serde_json::json!({
+      "iss": "https://issuer.test",
+      "aud": ["other-client", "picturepro"],
+      "exp": now + 300,
+      "nonce": "nonce",
+      "sub": "1234",
+      "email": "test@example.com",
+      "email_verified": "true",
+    });
+    let check = |token: &str| {
+      verify_id_token(
+        token,
+        &keys,
+        "https://issuer.test/",
+        "picturepro",
+        "nonce",
+        now,
+      )
+    };
+
+    let claims_ok = check(&sign_id_token(&claims)).unwrap();
+    assert_eq!(claims_ok.sub, "1234");
+    assert!(claims_ok.verified_email().is_some());
+
+    for (field, value, reason) in [
+      (
+        "iss",
+        serde_json::json!("https://evil.test"),
+        "wrong issuer",
+      ),
+      ("aud", serde_json::json!("other-client"), "wrong audience"),
+      ("exp", serde_json::json!(now - 600), "expired"),
+      ("nonce", serde_json::json!("replayed"), "wrong nonce"),
+    ] {
+      let mut bad = claims.clone();
+      bad[field] = value;
+      let result = check(&sign_id_token(&bad));
+      assert!(
+        matches!(result, Err(OidcError::InvalidIdToken(r)) if r == reason),
+        "{field}"
+      );
+    }
+
+    // a valid signature over other claims
+    let token = sign_id_token(&claims);
+    let (header, rest) = token.split_once('.').unwrap();
+    let (_, signature) = rest.split_once('.').unwrap();
+    let mut forged = claims.clone();
+    forged["sub"] = "5678".into();
+    let forged = format!(
+      "{header}.{}.{signature}",
+      BASE64_URL_SAFE_NO_PAD.encode(forged.to_string())
+    );
+    assert!(matches!(
+      check(&forged),
+      Err(OidcError::InvalidIdToken("bad signature"))
+    ));
+  }
+}
@@ -0,0 +1,472 @@
+//! Registering passkeys, and logging in with them.
+//!
+//! Each ceremony has two halves. The `begin_*` half returns a
+//! [`PasskeyCeremony`]: the options to hand to the browser's
+//! `navigator.credentials`, and a [`PasskeyChallenge`] that the caller keeps
+//! server-side (e.g. in the session) until the browser answers. The `finish_*`
+//! half, or [`user_authenticate`](AuthDomainService::user_authenticate) for
+//! logins, checks the answer against that challenge. Confirmations before
+//! sensitive account changes are checked as a
+//! [`Reauthentication`](crate::Reauthentication).
+
+use std::str::FromStr;
+
+use chrono::TimeDelta;
+use models::{
+  PasskeyAssertion, PasskeyAttestation, PasskeyChallenge, User,
+  UserAuthCredentials, UserRecordId,
+};
+use rand::RngCore;
+use repos::{FetchModelError, LoginAttemptError, PatchModelError};
+use tracing::instrument;
+
+pub use crate::webauthn::PasskeyVerificationError;
+use crate::{
+  verify_password,
+  webauthn::{
+    decode, encode, verify_assertion, verify_registration, RelyingParty,
+    COSE_ALG_ES256,
+  },
+  AuthDomainService, AuthenticationError,
+};
+
+/// How long the browser has to answer a passkey challenge.
+const PASSKEY_CHALLENGE_TTL: TimeDelta = TimeDelta::minutes(5);
+
+/// The name of the site shown by passkey prompts.
+const RELYING_PARTY_NAME: &str = "PicturePro";
+
+/// The first half of a passkey ceremony.
+#[derive(Clone, Debug)]
+pub struct PasskeyCeremony {
+  /// The challenge to keep server-side until the browser answers.
+  pub challenge:    PasskeyChallenge,
+  /// The options for `navigator.credentials`, as JSON with binary fields
+  /// base64url-encoded.
+  pub options_json: String,
+}
+
+/// An error that occurs when starting passkey registration.
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum BeginPasskeyRegistrationError {
+  /// Indicates that the user doesn't exist.
+  #[error("The user doesn't exist")]
+  UserNotFound,
+  /// Indicates that an error occurred while fetching the user.
+  #[error("Failed to fetch user")]
+  FetchError(#[from] FetchModelError),
+}
+
+/// An error that occurs when finishing passkey registration.
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum FinishPasskeyRegistrationError {
+  /// Indicates that the user doesn't exist.
+  #[error("The user doesn't exist")]
+  UserNotFound,
+  /// Indicates that the challenge was issued to someone else, or for a
+  /// login.
+  #[error("The passkey challenge was issued for something else")]
+  WrongChallenge,
+  /// Indicates that the challenge has expired.
+  #[error("The passkey challenge has expired")]
+  Expired,
+  /// Indicates that the passkey is already registered.
+  #[error("The passkey is already registered")]
+  AlreadyRegistered,
+  /// Indicates that the browser's answer didn't check out.
+  #[error("Failed to verify passkey: {0}")]
+  Verification(#[from] PasskeyVerificationError),
+  /// Indicates that an error occurred while fetching the user.
+  #[error("Failed to fetch user")]
+  FetchError(#[from] FetchModelError),
+  /// Indicates that an error occurred while updating the user.
+  #[error("Failed to update user")]
+  PatchError(#[from] PatchModelError),
+}
+
+/// An error that occurs when starting to confirm it's a user with one of
+/// their passkeys.
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum BeginPasskeyReauthenticationError {
+  /// Indicates that the user doesn't exist.
+  #[error("The user doesn't exist")]
+  UserNotFound,
+  /// Indicates that the user has no passkeys to confirm with.
+  #[error("You have no passkeys")]
+  NoPasskeys,
+  /// Indicates that an error occurred while fetching the user.
+  #[error("Failed to fetch user")]
+  FetchError(#[from] FetchModelError),
+}
+
+/// An error that occurs when removing a passkey.
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum RemovePasskeyError {
+  /// Indicates that the user doesn't exist.
+  #[error("The user doesn't exist")]
+  UserNotFound,
+  /// Indicates that the user has no such passkey.
+  #[error("The passkey doesn't exist")]
+  PasskeyNotFound,
+  /// Indicates that the passkey is the only way the user can log in.
+  #[error("Can't remove the only passkey of an account without a password")]
+  LastCredential,
+  /// Indicates that an error occurred while fetching the user.
+  #[error("Failed to fetch user")]
+  FetchError(#[from] FetchModelError),
+  /// Indicates that an error occurred while updating the user.
+  #[error("Failed to update user")]
+  PatchError(#[from] PatchModelError),
+}
+
+/// An error that occurs when removing a user's password.
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum RemovePasswordError {
+  /// Indicates that the user doesn't exist.
+  #[error("The user doesn't exist")]
+  UserNotFound,
+  /// Indicates that the current password was wrong.
+  #[error("The current password is incorrect")]
+  IncorrectPassword,
+  /// Indicates that the user has no passkey to log in with instead.
+  #[error("Register a passkey before removing your password")]
+  NoPasskeys,
+  /// Indicates that there were too many recent password attempts against the
+  /// account.
+  #[error("Too many password attempts; try again in {retry_after} seconds")]
+  Throttled {
+    /// How many seconds to wait before trying again.
+    retry_after: u64,
+  },
+  /// Indicates than an error occurred while verifying the password.
+  #[error("Failed to verify password")]
+  PasswordHashing(miette::Report),
+  /// Indicates that an error occurred while counting password attempts.
+  #[error("Failed to count password attempts")]
+  LoginAttemptError(#[from] LoginAttemptError),
+  /// Indicates that an error occurred while fetching the user.
+  #[error("Failed to fetch user")]
+  FetchError(#[from] FetchModelError),
+  /// Indicates that an error occurred while updating the user.
+  #[error("Failed to update user")]
+  PatchError(#[from] PatchModelError),
+}
+
+fn new_challenge(user: Option<UserRecordId>) -> PasskeyChallenge {
+  let mut challenge = vec![0_u8; 32];
+  rand::rng().fill_bytes(&mut challenge);
+  PasskeyChallenge {
+    challenge,
+    user,
+    expires_at: chrono::Utc::now() + PASSKEY_CHALLENGE_TTL,
+  }
+}
+
+fn is_expired(challenge: &PasskeyChallenge) -> bool {
+  challenge.expires_at < chrono::Utc::now()
+}
+
+/// The user handle stored with a user's passkeys: their ID.
+fn user_handle(id: UserRecordId) -> String { encode(id.to_string().as_bytes()) }
+
+impl AuthDomainService {
+  fn relying_party(&self) -> RelyingParty {
+    RelyingParty::from_base_url(&self.base_url)
+  }
+
+  /// Start registering a passkey for a [`User`].
+  #[instrument(skip(self))]
+  pub async fn begin_passkey_registration(
+    &self,
+    id: UserRecordId,
+  ) -> Result<PasskeyCeremony, BeginPasskeyRegistrationError> {
+    let user = self
+      .user_repo
+      .fetch_user_by_id(id)
+      .await?
+      .ok_or(BeginPasskeyRegistrationError::UserNotFound)?;
+
+    let rp = self.relying_party();
+    let challenge = new_challenge(Some(user.id));
+    let exclude_credentials = user
+      .passkeys
+      .iter()
+      .map(|p| {
+        serde_json::json!({
+          "type": "public-key",
+          "id": encode(&p.credential_id),
+        })
+      })
+      .collect::<Vec<_>>();
+    let options = serde_json::json!({
+      "rp": { "id": rp.id, "name": RELYING_PARTY_NAME },
+      "user": {
+        "id": user_handle(user.id),
+        "name": user.email.as_ref(),
+        "displayName": user.name.as_ref(),
+      },
+      "challenge": encode(&challenge.challenge),
+      "pubKeyCredParams": [{ "type": "public-key", "alg": COSE_ALG_ES256 }],
+      "timeout": PASSKEY_CHALLENGE_TTL.num_milliseconds(),
+      "attestation": "none",
+      "authenticatorSelection": {
+        "residentKey": "required",
+        "userVerification": "required",
+      },
+      "excludeCredentials": exclude_credentials,
+    });
+
+    Ok(PasskeyCeremony {
+      challenge,
+      options_json: options.to_string(),
+    })
+  }
+
+  /// Finish registering a passkey for a [`User`], with the browser's answer
+  /// to the challenge from
+  /// [`begin_passkey_registration`](Self::begin_passkey_registration).
+  #[instrument(skip(self, challenge, attestation))]
+  pub async fn finish_passkey_registration(
+    &self,
+    id: UserRecordId,
+    challenge: &PasskeyChallenge,
+    attestation: &PasskeyAttestation,
+  ) -> Result<User, FinishPasskeyRegistrationError> {
+    if challenge.user != Some(id) {
+      return Err(FinishPasskeyRegistrationError::WrongChallenge);
+    }
+    if is_expired(challenge) {
+      return Err(FinishPasskeyRegistrationError::Expired);
+    }
+    let mut user = self
+      .user_repo
+      .fetch_user_by_id(id)
+      .await?
+      .ok_or(FinishPasskeyRegistrationError::UserNotFound)?;
+
+    let passkey = verify_registration(
+      &self.relying_party(),
+      &challenge.challenge,
+      attestation,
+    )?;
+    if user
+      .passkeys
+      .iter()
+      .any(|p| p.credential_id == passkey.credential_id)
+    {
+      return Err(FinishPasskeyRegistrationError::AlreadyRegistered);
+    }
+
+    user.passkeys.push(passkey);
+    Ok(self.user_repo.update_user(user).await?)
+  }
+
+  /// Start logging in with a passkey. Any of the site's passkeys can answer,
+  /// since they carry their user's handle.
+  #[must_use]
+  pub fn begin_passkey_login(&self) -> PasskeyCeremony {
+    let challenge = new_challenge(None);
+    let options = serde_json::json!({
+      "rpId": self.relying_party().id,
+      "challenge": encode(&challenge.challenge),
+      "timeout": PASSKEY_CHALLENGE_TTL.num_milliseconds(),
+      "userVerification": "required",
+    });
+
+    PasskeyCeremony {
+      challenge,
+      options_json: options.to_string(),
+    }
+  }
+
+  /// Start confirming it's a [`User`] with one of their passkeys, before a
+  /// sensitive change to their account. Only their own passkeys can answer.
+  #[instrument(skip(self))]
+  pub async fn begin_passkey_reauthentication(
+    &self,
+    id: UserRecordId,
+  ) -> Result<PasskeyCeremony, BeginPasskeyReauthenticationError> {
+    let user = self
+      .user_repo
+      .fetch_user_by_id(id)
+      .await?
+      .ok_or(BeginPasskeyReauthenticationError::UserNotFound)?;
+    if user.passkeys.is_empty() {
+      return Err(BeginPasskeyReauthenticationError::NoPasskeys);
+    }
+
+    let challenge = new_challenge(Some(user.id));
+    let allow_credentials = user
+      .passkeys
+      .iter()
+      .map(|p| {
+        serde_json::json!({
+          "type": "public-key",
+          "id": encode(&p.credential_id),
+        })
+      })
+      .collect::<Vec<_>>();
+    let options = serde_json::json!({
+      "rpId": self.relying_party().id,
+      "challenge": encode(&challenge.challenge),
+      "timeout": PASSKEY_CHALLENGE_TTL.num_milliseconds(),
+      "userVerification": "required",
+      "allowCredentials": allow_credentials,
+    });
+
+    Ok(PasskeyCeremony {
+      challenge,
+      options_json: options.to_string(),
+    })
+  }
+
+  /// Checks a passkey assertion made to confirm it's `user`, recording the
+  /// passkey's use on `user`. Returns whether it checked out.
+  pub(crate) fn reauthenticate_passkey(
+    &self,
+    user: &mut User,
+    challenge: &PasskeyChallenge,
+    assertion: &PasskeyAssertion,
+  ) -> bool {
+    if challenge.user != Some(user.id) || is_expired(challenge) {
+      return false;
+    }
+    let Some(passkey) = user
+      .passkeys
+      .iter_mut()
+      .find(|p| encode(&p.credential_id) == assertion.credential_id)
+    else {
+      return false;
+    };
+
+    match verify_assertion(
+      &self.relying_party(),
+      &challenge.challenge,
+      assertion,
+      passkey,
+    ) {
+      Ok(sign_count) => {
+        passkey.sign_count = sign_count;
+        passkey.last_used_at = Some(chrono::Utc::now());
+        true
+      }
+      Err(e) => {
+        tracing::warn!(
+          "rejected passkey confirmation for user {}: {e}",
+          user.id
+        );
+        false
+      }
+    }
+  }
+
+  /// Checks a passkey assertion, recording the passkey's use. Passkeys
+  /// verify the user themselves, so no second factor is asked for.
+  pub(crate) async fn authenticate_passkey(
+    &self,
+    challenge: &PasskeyChallenge,
+    assertion: &PasskeyAssertion,
+  ) -> Result<Option<User>, AuthenticationError> {
+    if challenge.user.is_some() || is_expired(challenge) {
+      return Ok(None);
+    }
+    let Some(user_id) = assertion
+      .user_handle
+      .as_deref()
+      .and_then(|h| decode(h, "user handle").ok())
+      .and_then(|h| String::from_utf8(h).ok())
+      .and_then(|h| UserRecordId::from_str(&h).ok())
+    else {
+      return Ok(None);
+    };
+    let Some(mut user) = self.user_repo.fetch_user_by_id(user_id).await? else {
+      return Ok(None);
+    };
+    let Some(passkey) = user
+      .passkeys
+      .iter_mut()
+      .find(|p| encode(&p.credential_id) == assertion.credential_id)
+    else {
+      return Ok(None);
+    };
+
+    match verify_assertion(
+      &self.relying_party(),
+      &challenge.challenge,
+      assertion,
+      passkey,
+    ) {
+      Ok(sign_count) => {
+        passkey.sign_count = sign_count;
+        passkey.last_used_at = Some(chrono::Utc::now());
+      }
+      Err(e) => {
+        tracing::warn!("rejected passkey login for user {}: {e}", user.id);
+        return Ok(None);
+      }
+    }
+
+    Ok(Some(self.user_repo.update_user(user).await?))
+  }
+
+  /// Remove one of a [`User`]'s passkeys, by its base64url credential ID.
+  #[instrument(skip(self))]
+  pub async fn remove_passkey(
+    &self,
+    id: UserRecordId,
+    credential_id: &str,
+  ) -> Result<User, RemovePasskeyError> {
+    let mut user = self
+      .user_repo
+      .fetch_user_by_id(id)
+      .await?
+      .ok_or(RemovePasskeyError::UserNotFound)?;
+    let index = user
+      .passkeys
+      .iter()
+      .position(|p| encode(&p.credential_id) == credential_id)
+      .ok_or(RemovePasskeyError::PasskeyNotFound)?;
+    if user.passkeys.len() == 1
+      && matches!(user.auth, UserAuthCredentials::Passkey { .. })
+    {
+      return Err(RemovePasskeyError::LastCredential);
+    }
+
+    user.passkeys.remove(index);
+    Ok(self.user_repo.update_user(user).await?)
+  }
+
+  /// Remove a [`User`]'s password, after checking it, so they can only log
+  /// in with their passkeys. The user's sessions end because their
+  /// credentials changed.
+  #[instrument(skip(self, current_password))]
+  pub async fn remove_password(
+    &self,
+    id: UserRecordId,
+    current_password: &str,
+  ) -> Result<User, RemovePasswordError> {
+    let mut user = self
+      .user_repo
+      .fetch_user_by_id(id)
+      .await?
+      .ok_or(RemovePasswordError::UserNotFound)?;
+    if user.passkeys.is_empty() {
+      return Err(RemovePasswordError::NoPasskeys);
+    }
+    let attempt = self.begin_login_attempt(&user.email, None).await?;
+    let correct = verify_password(&user, current_password);
+    self
+      .finish_login_attempt(attempt, matches!(correct, Ok(true)))
+      .await;
+    if !correct.map_err(RemovePasswordError::PasswordHashing)? {
+      return Err(RemovePasswordError::IncorrectPassword);
+    }
+
+    user.auth = UserAuthCredentials::Passkey {
+      email: user.email.clone(),
+    };
+    // a TOTP code only ever accompanied the password
+    user.totp = None;
+    Ok(self.user_repo.update_user(user).await?)
+  }
+}
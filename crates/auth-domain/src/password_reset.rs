@@ -0,0 +1,167 @@
+//! Recovering accounts with emailed, single-use reset links.
+
+use chrono::TimeDelta;
+use mailer::{templates::ResetPassword, SendMailError};
+use models::{EmailAddress, User, UserAuthCredentials};
+use repos::{
+  FetchModelByIndexError, FetchModelError, LoginAttemptSubject, PatchModelError,
+};
+use tracing::instrument;
+
+use crate::{
+  hash_password,
+  token::{
+    check_token, cooldown_remaining, generate_token, parse_token, TokenCheck,
+  },
+  AuthDomainService,
+};
+
+/// The path that password reset links point to.
+pub const RESET_PASSWORD_PATH: &str = "/reset-password/";
+
+/// The path of the page that sends password reset links.
+pub const FORGOT_PASSWORD_PATH: &str = "/forgot-password";
+
+/// How long a password reset link stays valid.
+const RESET_TOKEN_TTL: TimeDelta = TimeDelta::hours(1);
+
+/// How long a user must wait before another reset email is sent.
+const RESET_REQUEST_COOLDOWN: TimeDelta = TimeDelta::minutes(1);
+
+/// An error that occurs when requesting a password reset.
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum RequestPasswordResetError {
+  /// Indicates that an error occurred while fetching the user.
+  #[error("Failed to fetch user by index")]
+  FetchByIndexError(#[from] FetchModelByIndexError),
+  /// Indicates that an error occurred while updating the user.
+  #[error("Failed to update user")]
+  PatchError(#[from] PatchModelError),
+  /// Indicates that an error occurred while sending the email.
+  #[error("Failed to send password reset email")]
+  MailError(#[from] SendMailError),
+}
+
+/// An error that occurs when resetting a password.
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum ResetPasswordError {
+  /// Indicates that the token is malformed, unknown, or already used.
+  #[error("The password reset link is invalid")]
+  InvalidToken,
+  /// Indicates that the token has expired.
+  #[error("The password reset link has expired")]
+  Expired,
+  /// Indicates than an error occurred while hashing the password.
+  #[error("Failed to hash password")]
+  PasswordHashing(miette::Report),
+  /// Indicates that an error occurred while fetching the user.
+  #[error("Failed to fetch user")]
+  FetchError(#[from] FetchModelError),
+  /// Indicates that an error occurred while updating the user.
+  #[error("Failed to update user")]
+  PatchError(#[from] PatchModelError),
+}
+
+impl AuthDomainService {
+  /// Email a password reset link to the [`User`] with the given address. Any
+  /// earlier link stops working.
+  ///
+  /// Succeeds without sending anything if there's no such user, or if a link
+  /// was sent moments ago, so callers can't learn which addresses have
+  /// accounts.
+  #[instrument(skip(self))]
+  pub async fn request_password_reset(
+    &self,
+    email: EmailAddress,
+  ) -> Result<(), RequestPasswordResetError> {
+    let Some(mut user) = self.user_repo.fetch_user_by_email(email).await?
+    else {
+      tracing::info!("password reset requested for unknown email");
+      return Ok(());
+    };
+    if cooldown_remaining(user.password_reset.as_ref(), RESET_REQUEST_COOLDOWN)
+      .is_some()
+    {
+      tracing::info!("password reset requested again too soon; ignoring");
+      return Ok(());
+    }
+
+    let (token, reset) = generate_token(user.id, RESET_TOKEN_TTL);
+    user.password_reset = Some(reset);
+    let user = self.user_repo.update_user(user).await?;
+
+    let template = ResetPassword {
+      name: user.name.clone(),
+      url:  format!(
+        "{base_url}{RESET_PASSWORD_PATH}{token}",
+        base_url = self.base_url.0
+      ),
+    };
+    self.mailer.send(user.email, &template).await?;
+
+    Ok(())
+  }
+
+  /// Set a new password for a [`User`] with the token from a reset link. This
+  /// changes the user's auth hash, which ends all of their sessions.
+  #[instrument(skip(self, token, new_password))]
+  pub async fn reset_password(
+    &self,
+    token: &str,
+    new_password: &str,
+  ) -> Result<User, ResetPasswordError> {
+    let (id, secret) =
+      parse_token(token).ok_or(ResetPasswordError::InvalidToken)?;
+
+    // checked up front for a precise error, and again when the token is used
+    // up, in case another reset used it in the meantime
+    let user = self
+      .user_repo
+      .fetch_user_by_id(id)
+      .await?
+      .ok_or(ResetPasswordError::InvalidToken)?;
+    match check_token(user.password_reset.as_ref(), secret) {
+      TokenCheck::Valid => (),
+      TokenCheck::Expired => return Err(ResetPasswordError::Expired),
+      TokenCheck::Invalid => return Err(ResetPasswordError::InvalidToken),
+    }
+
+    let password_hash = hash_password(new_password)
+      .map_err(ResetPasswordError::PasswordHashing)?;
+    let secret = secret.to_owned();
+    let user = self
+      .user_repo
+      .update_user_with(
+        id,
+        Box::new(move |user| {
+          if !matches!(
+            check_token(user.password_reset.as_ref(), &secret),
+            TokenCheck::Valid
+          ) {
+            return false;
+          }
+          // passkey-only accounts get a password back this way too
+          user.auth = UserAuthCredentials::EmailAndPassword {
+            email: user.email.clone(),
+            password_hash,
+          };
+          user.password_reset = None;
+          // the link was delivered to the user's inbox, which proves they own
+          // it
+          user.email_verified = true;
+          user.email_verification = None;
+          true
+        }),
+      )
+      .await?
+      .ok_or(ResetPasswordError::InvalidToken)?;
+
+    // whoever was locking the account out no longer knows the password
+    let account = LoginAttemptSubject::Account(user.email.clone());
+    if let Err(e) = self.clear_login_attempts(&account).await {
+      tracing::warn!("failed to clear login attempts: {e}");
+    }
+
+    Ok(user)
+  }
+}
@@ -0,0 +1,214 @@
+//! Confirming it's really the user, before changes that take over or end
+//! their account.
+//!
+//! Users with a password give it again. Users without one, like accounts
+//! created by logging in with a provider or a login link, answer a fresh
+//! passkey challenge or open a single-use confirmation link emailed to them.
+
+use std::fmt;
+
+use chrono::TimeDelta;
+use mailer::{templates::ConfirmIdentity, SendMailError};
+use models::{PasskeyAssertion, PasskeyChallenge, User, UserRecordId};
+use repos::{FetchModelError, LoginAttemptError, PatchModelError};
+use tracing::instrument;
+
+use crate::{
+  token::{
+    check_token, cooldown_remaining, generate_token, parse_token, TokenCheck,
+  },
+  verify_password, AuthDomainService,
+};
+
+/// The path that confirmation links point to.
+pub const REAUTHENTICATION_PATH: &str = "/settings/confirm/";
+
+/// How long a confirmation link stays valid.
+const REAUTHENTICATION_TTL: TimeDelta = TimeDelta::minutes(15);
+
+/// How long a user must wait before another confirmation link is sent.
+const REAUTHENTICATION_COOLDOWN: TimeDelta = TimeDelta::minutes(1);
+
+/// Proof that it's really the user, given before a sensitive change to their
+/// account.
+#[derive(Clone)]
+pub enum Reauthentication {
+  /// The user's current password.
+  Password(String),
+  /// A passkey's answer to a challenge from
+  /// [`begin_passkey_reauthentication`](AuthDomainService::begin_passkey_reauthentication).
+  Passkey {
+    /// The challenge the server issued, from server-side storage.
+    challenge: PasskeyChallenge,
+    /// The browser's answer to the challenge.
+    assertion: PasskeyAssertion,
+  },
+  /// The token from a link sent by
+  /// [`request_reauthentication_link`](AuthDomainService::request_reauthentication_link).
+  EmailLink(String),
+}
+
+impl fmt::Debug for Reauthentication {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    // none of the variants' contents belong in logs
+    f.write_str(match self {
+      Self::Password(_) => "Password",
+      Self::Passkey { .. } => "Passkey",
+      Self::EmailLink(_) => "EmailLink",
+    })
+  }
+}
+
+/// An error that occurs when checking a [`Reauthentication`].
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum ReauthenticationError {
+  /// Indicates that the password was wrong, or that the user has none.
+  #[error("The current password is incorrect")]
+  IncorrectPassword,
+  /// Indicates that the passkey's answer didn't check out.
+  #[error("The passkey wasn't accepted")]
+  PasskeyRejected,
+  /// Indicates that the confirmation link is wrong or was already used.
+  #[error("The confirmation link is invalid or was already used")]
+  InvalidLink,
+  /// Indicates that the confirmation link has expired.
+  #[error("The confirmation link has expired")]
+  ExpiredLink,
+  /// Indicates that there were too many recent password attempts against the
+  /// account.
+  #[error("Too many password attempts; try again in {retry_after} seconds")]
+  Throttled {
+    /// How many seconds to wait before trying again.
+    retry_after: u64,
+  },
+  /// Indicates than an error occurred while verifying the password.
+  #[error("Failed to verify password")]
+  PasswordHashing(miette::Report),
+  /// Indicates that an error occurred while counting password attempts.
+  #[error("Failed to count password attempts")]
+  LoginAttemptError(#[from] LoginAttemptError),
+  /// Indicates that an error occurred while recording the confirmation.
+  #[error("Failed to update user")]
+  PatchError(#[from] PatchModelError),
+}
+
+impl ReauthenticationError {
+  /// Whether the proof was turned down, rather than failing to be checked.
+  #[must_use]
+  pub fn is_rejection(&self) -> bool {
+    !matches!(
+      self,
+      Self::PasswordHashing(_)
+        | Self::LoginAttemptError(_)
+        | Self::PatchError(_)
+    )
+  }
+}
+
+/// An error that occurs when requesting a confirmation link.
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum RequestReauthenticationLinkError {
+  /// Indicates that the user doesn't exist.
+  #[error("The user doesn't exist")]
+  UserNotFound,
+  /// Indicates that a confirmation link was sent too recently.
+  #[error("A confirmation link was sent recently; try again in {0} seconds")]
+  Throttled(u64),
+  /// Indicates that an error occurred while fetching the user.
+  #[error("Failed to fetch user")]
+  FetchError(#[from] FetchModelError),
+  /// Indicates that an error occurred while updating the user.
+  #[error("Failed to update user")]
+  PatchError(#[from] PatchModelError),
+  /// Indicates that an error occurred while sending the email.
+  #[error("Failed to send confirmation email")]
+  MailError(#[from] SendMailError),
+}
+
+impl AuthDomainService {
+  /// Email a [`User`] a link confirming it's them, for when they have no
+  /// password to confirm a sensitive change with. Opening the link leads
+  /// back to their account settings, where the change is finished.
+  #[instrument(skip(self))]
+  pub async fn request_reauthentication_link(
+    &self,
+    id: UserRecordId,
+  ) -> Result<(), RequestReauthenticationLinkError> {
+    let mut user = self
+      .user_repo
+      .fetch_user_by_id(id)
+      .await?
+      .ok_or(RequestReauthenticationLinkError::UserNotFound)?;
+    if let Some(wait) = cooldown_remaining(
+      user.reauthentication.as_ref(),
+      REAUTHENTICATION_COOLDOWN,
+    ) {
+      return Err(RequestReauthenticationLinkError::Throttled(wait));
+    }
+
+    let (token, link) = generate_token(user.id, REAUTHENTICATION_TTL);
+    user.reauthentication = Some(link);
+    let user = self.user_repo.update_user(user).await?;
+
+    let template = ConfirmIdentity {
+      name: user.name,
+      url:  format!(
+        "{base_url}{REAUTHENTICATION_PATH}{token}",
+        base_url = self.base_url.0
+      ),
+    };
+    self.mailer.send(user.email, &template).await?;
+
+    Ok(())
+  }
+
+  /// Checks that `reauth` proves it's `user`. Passwords are throttled like
+  /// password logins. Passkey uses and confirmation links are recorded before
+  /// returning the updated user, so neither can be replayed even if the change
+  /// they confirm fails.
+  pub(crate) async fn reauthenticate(
+    &self,
+    mut user: User,
+    reauth: &Reauthentication,
+  ) -> Result<User, ReauthenticationError> {
+    match reauth {
+      Reauthentication::Password(password) => {
+        // throttled like password logins, so a hijacked session can't be
+        // used to guess the password
+        let attempt = self.begin_login_attempt(&user.email, None).await?;
+        let correct = verify_password(&user, password);
+        self
+          .finish_login_attempt(attempt, matches!(correct, Ok(true)))
+          .await;
+        if !correct.map_err(ReauthenticationError::PasswordHashing)? {
+          return Err(ReauthenticationError::IncorrectPassword);
+        }
+        return Ok(user);
+      }
+      Reauthentication::Passkey {
+        challenge,
+        assertion,
+      } => {
+        if !self.reauthenticate_passkey(&mut user, challenge, assertion) {
+          return Err(ReauthenticationError::PasskeyRejected);
+        }
+      }
+      Reauthentication::EmailLink(token) => {
+        let secret = parse_token(token)
+          .filter(|(id, _)| *id == user.id)
+          .map(|(_, secret)| secret)
+          .ok_or(ReauthenticationError::InvalidLink)?;
+        match check_token(user.reauthentication.as_ref(), secret) {
+          TokenCheck::Valid => user.reauthentication = None,
+          TokenCheck::Expired => {
+            return Err(ReauthenticationError::ExpiredLink)
+          }
+          TokenCheck::Invalid => {
+            return Err(ReauthenticationError::InvalidLink)
+          }
+        }
+      }
+    }
+    Ok(self.user_repo.update_user(user).await?)
+  }
+}
@@ -0,0 +1,385 @@
+//! Slowing down password guessing.
+//!
+//! Every password login counts against the email address it names and, when
+//! it's known, the client it came from. Past a few free attempts, each has to
+//! wait exponentially longer between attempts, and enough attempts against
+//! one address lock it for a while and tell its owner. A successful login
+//! clears the address's count. Throttled attempts are refused before any
+//! password is hashed, so they cost next to nothing. Passwords given again to
+//! confirm a sensitive change count the same way.
+//!
+//! Signup links emailed to addresses without an account are counted apart
+//! from logins, but backed off the same way, so that they can't be used to
+//! flood someone's inbox.
+
+use std::net::IpAddr;
+
+use chrono::TimeDelta;
+use mailer::templates::AccountLocked;
+use models::{EmailAddress, LoginAttempts, Timestamp};
+use repos::{
+  LoginAttemptError, LoginAttemptSubject, LoginAttemptUpdate,
+  UpdatedLoginAttempts,
+};
+
+use crate::{
+  AuthDomainService, AuthenticationError, ReauthenticationError,
+  RemovePasswordError, FORGOT_PASSWORD_PATH,
+};
+
+/// How long without an attempt before a count starts over.
+const ATTEMPT_WINDOW: TimeDelta = TimeDelta::days(1);
+
+/// How many attempts an address gets before backing off.
+const FREE_ACCOUNT_ATTEMPTS: u32 = 5;
+
+/// How many attempts a client gets before backing off. Clients may be shared,
+/// e.g. behind a NAT, so they get more.
+const FREE_CLIENT_ATTEMPTS: u32 = 20;
+
+/// Every this many attempts, an address is locked.
+const LOCKOUT_ATTEMPTS: u32 = 10;
+
+/// How long an address stays locked.
+const LOCKOUT_DURATION: TimeDelta = TimeDelta::minutes(15);
+
+/// The longest wait between attempts outside of a lockout.
+const MAX_BACKOFF: TimeDelta = TimeDelta::minutes(15);
+
+/// How long an address must wait before another signup link is sent to it.
+const SIGN_UP_LINK_COOLDOWN: TimeDelta = TimeDelta::minutes(1);
+
+/// How many signup links an address gets before backing off beyond the
+/// cooldown.
+const FREE_SIGN_UP_LINKS: u32 = 3;
+
+/// How many signup links a client gets before backing off.
+const FREE_CLIENT_SIGN_UP_LINKS: u32 = 10;
+
+/// How long a subject must wait before its next attempt, if at all.
+fn retry_after(
+  attempts: &LoginAttempts,
+  free_attempts: u32,
+  now: Timestamp,
+) -> Option<TimeDelta> {
+  let locked = attempts.locked_until.map(|until| until - now);
+  let backoff = attempts
+    .last_at
+    .filter(|_| attempts.count >= free_attempts)
+    .map(|last_at| {
+      let doublings = (attempts.count - free_attempts).min(20);
+      last_at + TimeDelta::seconds(1 << doublings).min(MAX_BACKOFF) - now
+    });
+  locked
+    .into_iter()
+    .chain(backoff)
+    .max()
+    .filter(|wait| *wait > TimeDelta::zero())
+}
+
+/// Counts an attempt made at `now`, locking the subject every
+/// `lockout_attempts` attempts if given.
+fn count_attempt(
+  mut attempts: LoginAttempts,
+  now: Timestamp,
+  lockout_attempts: Option<u32>,
+) -> LoginAttempts {
+  if attempts
+    .last_at
+    .is_some_and(|last_at| now - last_at > ATTEMPT_WINDOW)
+  {
+    attempts = LoginAttempts::default();
+  }
+  attempts.locked_until = attempts.locked_until.filter(|until| *until > now);
+  attempts.count += 1;
+  attempts.last_at = Some(now);
+  if lockout_attempts.is_some_and(|n| attempts.count.is_multiple_of(n)) {
+    attempts.locked_until = Some(now + LOCKOUT_DURATION);
+  }
+  attempts
+}
+
+/// Counts an attempt made at `now`, unless the subject must wait first. The
+/// check and the count are one update, so that guesses made in parallel can't
+/// all pass the check before any of them is counted.
+fn count_unless_throttled(
+  free_attempts: u32,
+  lockout_attempts: Option<u32>,
+  now: Timestamp,
+) -> LoginAttemptUpdate {
+  Box::new(move |attempts| {
+    retry_after(attempts, free_attempts, now)
+      .is_none()
+      .then(|| count_attempt(attempts.clone(), now, lockout_attempts))
+  })
+}
+
+/// Counts a signup link sent at `now`, unless one was sent within the
+/// cooldown or the address must back off.
+fn count_sign_up_link(now: Timestamp) -> LoginAttemptUpdate {
+  Box::new(move |attempts| {
+    let cooling_down = attempts
+      .last_at
+      .is_some_and(|last_at| now - last_at < SIGN_UP_LINK_COOLDOWN);
+    (!cooling_down && retry_after(attempts, FREE_SIGN_UP_LINKS, now).is_none())
+      .then(|| count_attempt(attempts.clone(), now, None))
+  })
+}
+
+/// Why a password login wasn't let through.
+#[derive(Debug)]
+pub(crate) enum BeginLoginAttemptError {
+  /// There were too many recent attempts; the number of seconds to wait.
+  Throttled(u64),
+  /// The attempt couldn't be counted.
+  Count(LoginAttemptError),
+}
+
+impl BeginLoginAttemptError {
+  /// The error for an attempt refused while `attempts` stood.
+  fn throttled(
+    attempts: &LoginAttempts,
+    free_attempts: u32,
+    now: Timestamp,
+  ) -> Self {
+    let wait = retry_after(attempts, free_attempts, now).unwrap_or_default();
+    Self::Throttled(wait.num_seconds().max(1).unsigned_abs())
+  }
+}
+
+impl From<LoginAttemptError> for BeginLoginAttemptError {
+  fn from(e: LoginAttemptError) -> Self { Self::Count(e) }
+}
+
+/// Implements `From<BeginLoginAttemptError>` for errors with `Throttled` and
+/// `LoginAttemptError` variants.
+macro_rules! impl_from_begin_login_attempt_error {
+  ($($error:ty),* $(,)?) => {$(
+    impl From<BeginLoginAttemptError> for $error {
+      fn from(e: BeginLoginAttemptError) -> Self {
+        match e {
+          BeginLoginAttemptError::Throttled(retry_after) => {
+            Self::Throttled { retry_after }
+          }
+          BeginLoginAttemptError::Count(e) => Self::LoginAttemptError(e),
+        }
+      }
+    }
+  )*};
+}
+
+impl_from_begin_login_attempt_error!(
+  AuthenticationError,
+  ReauthenticationError,
+  RemovePasswordError,
+);
+
+/// A password login that was let through, and counted.
+pub(crate) struct LoginAttempt {
+  account: LoginAttemptSubject,
+  /// Whether counting the attempt locked the address.
+  locked:  bool,
+}
+
+impl AuthDomainService {
+  /// Checks that a password login may go ahead, and counts it. Attempts are
+  /// counted before the password is checked, so that guesses made in
+  /// parallel are all counted.
+  pub(crate) async fn begin_login_attempt(
+    &self,
+    email: &EmailAddress,
+    client: Option<IpAddr>,
+  ) -> Result<LoginAttempt, BeginLoginAttemptError> {
+    let now = chrono::Utc::now();
+    let account = LoginAttemptSubject::Account(email.clone());
+
+    if let Some(client) = client.map(LoginAttemptSubject::Client) {
+      let update = count_unless_throttled(FREE_CLIENT_ATTEMPTS, None, now);
+      if let UpdatedLoginAttempts::Declined(attempts) = self
+        .login_attempt_repo
+        .update_login_attempts(&client, update)
+        .await?
+      {
+        return Err(BeginLoginAttemptError::throttled(
+          &attempts,
+          FREE_CLIENT_ATTEMPTS,
+          now,
+        ));
+      }
+    }
+    let update = count_unless_throttled(
+      FREE_ACCOUNT_ATTEMPTS,
+      Some(LOCKOUT_ATTEMPTS),
+      now,
+    );
+    let attempts = match self
+      .login_attempt_repo
+      .update_login_attempts(&account, update)
+      .await?
+    {
+      UpdatedLoginAttempts::Applied(attempts) => attempts,
+      UpdatedLoginAttempts::Declined(attempts) => {
+        return Err(BeginLoginAttemptError::throttled(
+          &attempts,
+          FREE_ACCOUNT_ATTEMPTS,
+          now,
+        ));
+      }
+    };
+
+    Ok(LoginAttempt {
+      account,
+      locked: attempts.locked_until.is_some_and(|until| until > now),
+    })
+  }
+
+  /// Settles a counted password login: a success clears the address's
+  /// count, and a failure that locked the address tells its owner.
+  pub(crate) async fn finish_login_attempt(
+    &self,
+    attempt: LoginAttempt,
+    succeeded: bool,
+  ) {
+    if succeeded {
+      if let Err(e) = self.clear_login_attempts(&attempt.account).await {
+        tracing::warn!("failed to clear login attempts: {e}");
+      }
+      return;
+    }
+    if !attempt.locked {
+      return;
+    }
+
+    let LoginAttemptSubject::Account(email) = attempt.account else {
+      return;
+    };
+    tracing::warn!("locked password logins after repeated failures");
+    match self.user_repo.fetch_user_by_email(email).await {
+      Ok(Some(user)) => {
+        let template = AccountLocked {
+          name:    user.name,
+          minutes: LOCKOUT_DURATION.num_minutes(),
+          url:     format!(
+            "{base_url}{FORGOT_PASSWORD_PATH}",
+            base_url = self.base_url.0
+          ),
+        };
+        if let Err(e) = self.mailer.send(user.email, &template).await {
+          tracing::warn!("failed to send lockout email: {e}");
+        }
+      }
+      // nobody to tell
+      Ok(None) => (),
+      Err(e) => tracing::warn!("failed to fetch locked user: {e}"),
+    }
+  }
+
+  /// Checks that a signup link may be emailed to an address without an
+  /// account, and counts it against the address and, when it's known, the
+  /// client. Returns whether the link may be sent.
+  pub(crate) async fn begin_sign_up_link(
+    &self,
+    email: &EmailAddress,
+    client: Option<IpAddr>,
+  ) -> Result<bool, LoginAttemptError> {
+    let now = chrono::Utc::now();
+
+    if let Some(client) = client.map(LoginAttemptSubject::SignUpLinkClient) {
+      let update = count_unless_throttled(FREE_CLIENT_SIGN_UP_LINKS, None, now);
+      if let UpdatedLoginAttempts::Declined(_) = self
+        .login_attempt_repo
+        .update_login_attempts(&client, update)
+        .await?
+      {
+        return Ok(false);
+      }
+    }
+    let address = LoginAttemptSubject::SignUpLink(email.clone());
+    let updated = self
+      .login_attempt_repo
+      .update_login_attempts(&address, count_sign_up_link(now))
+      .await?;
+
+    Ok(matches!(updated, UpdatedLoginAttempts::Applied(_)))
+  }
+
+  /// Forgets the password login attempts against an address, lifting any
+  /// lockout.
+  pub(crate) async fn clear_login_attempts(
+    &self,
+    account: &LoginAttemptSubject,
+  ) -> Result<(), LoginAttemptError> {
+    self
+      .login_attempt_repo
+      .update_login_attempts(
+        account,
+        Box::new(|_| Some(LoginAttempts::default())),
+      )
+      .await
+      .map(|_| ())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn backoff_doubles_after_free_attempts() {
+    let now = chrono::Utc::now();
+    let mut attempts = LoginAttempts::default();
+    for _ in 0..FREE_ACCOUNT_ATTEMPTS {
+      assert_eq!(retry_after(&attempts, FREE_ACCOUNT_ATTEMPTS, now), None);
+      attempts = count_attempt(attempts, now, None);
+    }
+    assert_eq!(
+      retry_after(&attempts, FREE_ACCOUNT_ATTEMPTS, now),
+      Some(TimeDelta::seconds(1))
+    );
+    attempts = count_attempt(attempts, now, None);
+    assert_eq!(
+      retry_after(&attempts, FREE_ACCOUNT_ATTEMPTS, now),
+      Some(TimeDelta::seconds(2))
+    );
+
+    for _ in 0..40 {
+      attempts = count_attempt(attempts, now, None);
+    }
+    assert_eq!(
+      retry_after(&attempts, FREE_ACCOUNT_ATTEMPTS, now),
+      Some(MAX_BACKOFF)
+    );
+
+    // counts lapse after a quiet day
+    let later = now + ATTEMPT_WINDOW + TimeDelta::seconds(1);
+    let attempts = count_attempt(attempts, later, None);
+    assert_eq!(attempts.count, 1);
+  }
+
+  #[test]
+  fn sign_up_links_cool_down_then_back_off() {
+    let mut at = chrono::Utc::now();
+    let mut attempts = count_sign_up_link(at)(&LoginAttempts::default())
+      .expect("the first link is sent");
+
+    // nothing more within the cooldown
+    let soon = at + SIGN_UP_LINK_COOLDOWN - TimeDelta::seconds(1);
+    assert_eq!(count_sign_up_link(soon)(&attempts), None);
+
+    // links every cooldown get spaced out further and further
+    let mut refused = false;
+    for _ in 0..20 {
+      at += SIGN_UP_LINK_COOLDOWN;
+      match count_sign_up_link(at)(&attempts) {
+        Some(counted) => attempts = counted,
+        None => refused = true,
+      }
+    }
+    assert!(refused);
+    let last_at = attempts.last_at.unwrap();
+    assert!(
+      retry_after(&attempts, FREE_SIGN_UP_LINKS, last_at).unwrap()
+        > SIGN_UP_LINK_COOLDOWN
+    );
+  }
+}
@@ -0,0 +1,75 @@
+//! Single-use tokens that are emailed to users.
+
+use std::str::FromStr;
+
+use base64::Engine;
+use chrono::TimeDelta;
+use models::{EmailToken, UserRecordId};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+fn hash_secret(secret: &str) -> Vec<u8> {
+  Sha256::digest(secret.as_bytes()).to_vec()
+}
+
+/// Generates a token of the form `<user id>.<secret>` that expires after
+/// `ttl`, and the stored [`EmailToken`] that accepts it.
+pub(crate) fn generate_token(
+  user: UserRecordId,
+  ttl: TimeDelta,
+) -> (String, EmailToken) {
+  let mut secret = [0_u8; 32];
+  rand::rng().fill_bytes(&mut secret);
+  let secret = base64::prelude::BASE64_URL_SAFE_NO_PAD.encode(secret);
+
+  let now = chrono::Utc::now();
+  let stored = EmailToken {
+    secret_hash: hash_secret(&secret),
+    expires_at:  now + ttl,
+    sent_at:     now,
+  };
+  (format!("{user}.{secret}"), stored)
+}
+
+/// Splits a token into the user it was issued to and its secret.
+pub(crate) fn parse_token(token: &str) -> Option<(UserRecordId, &str)> {
+  let (id, secret) = token.split_once('.')?;
+  Some((UserRecordId::from_str(id).ok()?, secret))
+}
+
+/// How a token compares to the one stored for its user.
+pub(crate) enum TokenCheck {
+  /// The token matches and hasn't expired.
+  Valid,
+  /// The token matches but has expired.
+  Expired,
+  /// There's no stored token, or it doesn't match.
+  Invalid,
+}
+
+/// Checks a token's secret against the one stored for its user.
+pub(crate) fn check_token(
+  stored: Option<&EmailToken>,
+  secret: &str,
+) -> TokenCheck {
+  match stored {
+    Some(stored) if stored.secret_hash == hash_secret(secret) => {
+      if stored.expires_at < chrono::Utc::now() {
+        TokenCheck::Expired
+      } else {
+        TokenCheck::Valid
+      }
+    }
+    _ => TokenCheck::Invalid,
+  }
+}
+
+/// How much longer a user must wait before another token is sent, if a
+/// previous one was sent less than `cooldown` ago.
+pub(crate) fn cooldown_remaining(
+  stored: Option<&EmailToken>,
+  cooldown: TimeDelta,
+) -> Option<u64> {
+  let wait = stored?.sent_at + cooldown - chrono::Utc::now();
+  (wait > TimeDelta::zero()).then(|| wait.num_seconds().max(1).unsigned_abs())
+}
@@ -0,0 +1,230 @@
+//! Enrolling in, and logging in with, a TOTP second factor.
+
+use miette::IntoDiagnostic;
+use models::{TotpEnrollment, User, UserRecordId};
+use qr::QrCodeGenerator;
+use repos::{FetchModelError, PatchModelError};
+use tracing::instrument;
+
+use crate::{
+  totp::{
+    base32_encode, current_step, generate_recovery_codes, generate_secret,
+    hash_recovery_code, otpauth_uri, verify_code,
+  },
+  AuthDomainService, Reauthentication, ReauthenticationError,
+};
+
+/// What a user needs to add their account to an authenticator app.
+#[derive(Clone, Debug)]
+pub struct TotpEnrollmentStart {
+  /// The shared secret, base32-encoded for manual entry.
+  pub secret:      String,
+  /// The `otpauth://` URI that the QR code holds.
+  pub otpauth_uri: String,
+  /// The QR code, as an SVG document.
+  pub qr_code_svg: String,
+}
+
+/// An error that occurs when starting TOTP enrollment.
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum BeginTotpEnrollmentError {
+  /// Indicates that the user doesn't exist.
+  #[error("The user doesn't exist")]
+  UserNotFound,
+  /// Indicates that the user already has a confirmed second factor.
+  #[error("Two-factor authentication is already enabled")]
+  AlreadyEnabled,
+  /// Indicates that an error occurred while generating the QR code.
+  #[error("Failed to generate QR code")]
+  QrCodeError(miette::Report),
+  /// Indicates that an error occurred while fetching the user.
+  #[error("Failed to fetch user")]
+  FetchError(#[from] FetchModelError),
+  /// Indicates that an error occurred while updating the user.
+  #[error("Failed to update user")]
+  PatchError(#[from] PatchModelError),
+}
+
+/// An error that occurs when confirming TOTP enrollment.
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum ConfirmTotpEnrollmentError {
+  /// Indicates that the user doesn't exist.
+  #[error("The user doesn't exist")]
+  UserNotFound,
+  /// Indicates that enrollment hasn't been started.
+  #[error("Two-factor enrollment hasn't been started")]
+  NotStarted,
+  /// Indicates that the user already has a confirmed second factor.
+  #[error("Two-factor authentication is already enabled")]
+  AlreadyEnabled,
+  /// Indicates that the code doesn't match.
+  #[error("The code is incorrect")]
+  InvalidCode,
+  /// Indicates that an error occurred while fetching the user.
+  #[error("Failed to fetch user")]
+  FetchError(#[from] FetchModelError),
+  /// Indicates that an error occurred while updating the user.
+  #[error("Failed to update user")]
+  PatchError(#[from] PatchModelError),
+}
+
+/// An error that occurs when turning off two-factor authentication.
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum DisableTwoFactorError {
+  /// Indicates that the user doesn't exist.
+  #[error("The user doesn't exist")]
+  UserNotFound,
+  /// Indicates that it couldn't be confirmed that it's the user.
+  #[error("Failed to confirm it's the user: {0}")]
+  Reauthentication(#[from] ReauthenticationError),
+  /// Indicates that an error occurred while fetching the user.
+  #[error("Failed to fetch user")]
+  FetchError(#[from] FetchModelError),
+  /// Indicates that an error occurred while updating the user.
+  #[error("Failed to update user")]
+  PatchError(#[from] PatchModelError),
+}
+
+impl AuthDomainService {
+  /// Start enrolling a [`User`] in TOTP, replacing any unconfirmed
+  /// enrollment. The second factor isn't enforced until it's confirmed with
+  /// [`confirm_totp_enrollment`](Self::confirm_totp_enrollment).
+  #[instrument(skip(self))]
+  pub async fn begin_totp_enrollment(
+    &self,
+    id: UserRecordId,
+  ) -> Result<TotpEnrollmentStart, BeginTotpEnrollmentError> {
+    let mut user = self
+      .user_repo
+      .fetch_user_by_id(id)
+      .await?
+      .ok_or(BeginTotpEnrollmentError::UserNotFound)?;
+    if user.has_two_factor() {
+      return Err(BeginTotpEnrollmentError::AlreadyEnabled);
+    }
+
+    let secret = generate_secret();
+    let uri = otpauth_uri(&secret, user.email.as_ref());
+    let qr_code_svg = QrCodeGenerator::new()
+      .generate_otpauth_link(&uri)
+      .into_diagnostic()
+      .map_err(BeginTotpEnrollmentError::QrCodeError)?;
+    let start = TotpEnrollmentStart {
+      secret: base32_encode(&secret),
+      otpauth_uri: uri,
+      qr_code_svg,
+    };
+
+    user.totp = Some(TotpEnrollment {
+      secret,
+      confirmed_at: None,
+      last_used_step: 0,
+      recovery_code_hashes: Vec::new(),
+    });
+    self.user_repo.update_user(user).await?;
+
+    Ok(start)
+  }
+
+  /// Confirm a [`User`]'s TOTP enrollment with a code from their app.
+  /// Returns their recovery codes, which are only stored hashed, so this is
+  /// the only time they can be shown.
+  #[instrument(skip(self, code))]
+  pub async fn confirm_totp_enrollment(
+    &self,
+    id: UserRecordId,
+    code: &str,
+  ) -> Result<Vec<String>, ConfirmTotpEnrollmentError> {
+    let mut user = self
+      .user_repo
+      .fetch_user_by_id(id)
+      .await?
+      .ok_or(ConfirmTotpEnrollmentError::UserNotFound)?;
+    let totp = user
+      .totp
+      .as_mut()
+      .ok_or(ConfirmTotpEnrollmentError::NotStarted)?;
+    if totp.is_confirmed() {
+      return Err(ConfirmTotpEnrollmentError::AlreadyEnabled);
+    }
+    let step = verify_code(&totp.secret, code, current_step(), 0)
+      .ok_or(ConfirmTotpEnrollmentError::InvalidCode)?;
+
+    let (codes, hashes) = generate_recovery_codes();
+    totp.confirmed_at = Some(chrono::Utc::now());
+    totp.last_used_step = step;
+    totp.recovery_code_hashes = hashes;
+    self.user_repo.update_user(user).await?;
+
+    Ok(codes)
+  }
+
+  /// Turn off a [`User`]'s second factor, after confirming it's them.
+  #[instrument(skip(self, reauth))]
+  pub async fn disable_two_factor(
+    &self,
+    id: UserRecordId,
+    reauth: &Reauthentication,
+  ) -> Result<User, DisableTwoFactorError> {
+    let user = self
+      .user_repo
+      .fetch_user_by_id(id)
+      .await?
+      .ok_or(DisableTwoFactorError::UserNotFound)?;
+    let mut user = self.reauthenticate(user, reauth).await?;
+
+    user.totp = None;
+    Ok(self.user_repo.update_user(user).await?)
+  }
+
+  /// Checks a second factor code for a [`User`] whose password was correct.
+  /// Accepts a TOTP code that hasn't been used yet, or an unused recovery
+  /// code, and records the use so neither can be replayed.
+  ///
+  /// The code is checked against the stored user and used up in the same
+  /// write, so concurrent logins can't both use it.
+  pub(crate) async fn check_second_factor(
+    &self,
+    user: User,
+    code: &str,
+  ) -> Result<Option<User>, PatchModelError> {
+    if !user.has_two_factor() {
+      return Ok(Some(user));
+    }
+
+    let code = code.to_owned();
+    let step = current_step();
+    self
+      .user_repo
+      .update_user_with(
+        user.id,
+        Box::new(move |stored| {
+          let Some(totp) = stored.totp.as_mut().filter(|t| t.is_confirmed())
+          else {
+            return false;
+          };
+
+          if let Some(step) =
+            verify_code(&totp.secret, &code, step, totp.last_used_step)
+          {
+            totp.last_used_step = step;
+          } else {
+            let hash = hash_recovery_code(&code);
+            let Some(index) =
+              totp.recovery_code_hashes.iter().position(|h| *h == hash)
+            else {
+              return false;
+            };
+            totp.recovery_code_hashes.swap_remove(index);
+            tracing::info!(
+              "user {} logged in with a recovery code; {} left",
+              stored.id,
+              totp.recovery_code_hashes.len()
+            );
+          }
+          true
+        }),
+      )
+      .await
+  }
+}
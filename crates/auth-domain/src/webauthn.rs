@@ -0,0 +1,456 @@
+//! A minimal Web Authentication relying party: just enough to register and
+//! verify ES256 passkeys, without checking attestation statements.
+
+use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+use ciborium::value::Value as Cbor;
+use models::{BaseUrl, Passkey, PasskeyAssertion, PasskeyAttestation};
+use p256::ecdsa::{signature::Verifier, Signature, VerifyingKey};
+use sha2::{Digest, Sha256};
+
+/// The COSE algorithm identifier for ES256.
+pub(crate) const COSE_ALG_ES256: i64 = -7;
+
+/// Authenticator data flag: the user was present.
+const FLAG_USER_PRESENT: u8 = 0x01;
+/// Authenticator data flag: the user was verified, e.g. by biometrics.
+const FLAG_USER_VERIFIED: u8 = 0x04;
+/// Authenticator data flag: attested credential data is included.
+const FLAG_ATTESTED_CREDENTIAL: u8 = 0x40;
+
+/// An error that occurs when checking a browser's answer to a passkey
+/// challenge.
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum PasskeyVerificationError {
+  /// Indicates that part of the response couldn't be decoded.
+  #[error("Malformed passkey response: {0}")]
+  Malformed(&'static str),
+  /// Indicates that the response is for a different kind of ceremony.
+  #[error("Passkey response is for the wrong ceremony")]
+  WrongCeremony,
+  /// Indicates that the response answers a different challenge.
+  #[error("Passkey response answers a different challenge")]
+  ChallengeMismatch,
+  /// Indicates that the response came from a different origin.
+  #[error("Passkey response came from another origin: {0}")]
+  OriginMismatch(String),
+  /// Indicates that the credential is scoped to a different site.
+  #[error("Passkey is for a different relying party")]
+  RelyingPartyMismatch,
+  /// Indicates that the authenticator didn't verify the user.
+  #[error("Authenticator didn't verify the user")]
+  UserNotVerified,
+  /// Indicates that the credential uses a key type we don't support.
+  #[error("Passkey uses an unsupported key type")]
+  UnsupportedKey,
+  /// Indicates that the signature doesn't match the stored public key.
+  #[error("Passkey signature is invalid")]
+  InvalidSignature,
+  /// Indicates that the signature counter went backwards, which suggests a
+  /// cloned authenticator.
+  #[error("Passkey signature counter went backwards")]
+  CounterRegressed,
+}
+
+type Result<T> = std::result::Result<T, PasskeyVerificationError>;
+
+/// Encodes bytes as unpadded base64url.
+pub(crate) fn encode(data: &[u8]) -> String {
+  BASE64_URL_SAFE_NO_PAD.encode(data)
+}
+
+/// Decodes unpadded base64url, naming the field in the error.
+pub(crate) fn decode(data: &str, what: &'static str) -> Result<Vec<u8>> {
+  BASE64_URL_SAFE_NO_PAD
+    .decode(data)
+    .map_err(|_| PasskeyVerificationError::Malformed(what))
+}
+
+/// The site that passkeys are scoped to.
+#[derive(Clone, Debug)]
+pub(crate) struct RelyingParty {
+  /// The RP ID: the host name of the site.
+  pub id:     String,
+  /// The origin that ceremonies must come from.
+  pub origin: String,
+}
+
+impl RelyingParty {
+  /// Derives the relying party from the site's base URL.
+  pub(crate) fn from_base_url(base_url: &BaseUrl) -> Self {
+    let origin = base_url.0.trim_end_matches('/');
+    let origin = origin
+      .find("://")
+      .and_then(|i| origin[i + 3..].find('/').map(|j| &origin[..i + 3 + j]))
+      .unwrap_or(origin)
+      .to_string();
+    let host = origin.split_once("://").map_or(&*origin, |(_, h)| h);
+    let id = host.rsplit_once(':').map_or(host, |(h, _)| h).to_string();
+    Self { id, origin }
+  }
+}
+
+/// Checks the client data JSON that the authenticator signed over.
+fn check_client_data(
+  rp: &RelyingParty,
+  client_data_json: &[u8],
+  ceremony: &str,
+  challenge: &[u8],
+) -> Result<()> {
+  let client_data: serde_json::Value = serde_json::from_slice(client_data_json)
+    .map_err(|_| PasskeyVerificationError::Malformed("client data"))?;
+  if client_data["type"].as_str() != Some(ceremony) {
+    return Err(PasskeyVerificationError::WrongCeremony);
+  }
+  if client_data["challenge"].as_str() != Some(&encode(challenge)) {
+    return Err(PasskeyVerificationError::ChallengeMismatch);
+  }
+  let origin = client_data["origin"].as_str().unwrap_or_default();
+  if origin != rp.origin {
+    return Err(PasskeyVerificationError::OriginMismatch(origin.to_string()));
+  }
+  Ok(())
+}
+
+/// The fixed-size start of authenticator data.
+struct AuthenticatorData<'a> {
+  flags:      u8,
+  sign_count: u32,
+  /// Whatever follows the fixed-size part.
+  rest:       &'a [u8],
+}
+
+/// Parses authenticator data, and checks its RP ID hash and flags.
+fn parse_authenticator_data<'a>(
+  rp: &RelyingParty,
+  data: &'a [u8],
+) -> Result<AuthenticatorData<'a>> {
+  if data.len() < 37 {
+    return Err(PasskeyVerificationError::Malformed("authenticator data"));
+  }
+  if data[..32] != *Sha256::digest(rp.id.as_bytes()) {
+    return Err(PasskeyVerificationError::RelyingPartyMismatch);
+  }
+  let flags = data[32];
+  if flags & FLAG_USER_PRESENT == 0 || flags & FLAG_USER_VERIFIED == 0 {
+    return Err(PasskeyVerificationError::UserNotVerified);
+  }
+  Ok(AuthenticatorData {
+    flags,
+    sign_count: u32::from_be_bytes([data[33], data[34], data[35], data[36]]),
+    rest: &data[37..],
+  })
+}
+
+fn cbor_map_get<'a>(map: &'a [(Cbor, Cbor)], key: &Cbor) -> Option<&'a Cbor> {
+  map.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+}
+
+/// Converts a COSE EC2 P-256 key into an uncompressed SEC1 point.
+fn cose_key_to_sec1(cose_key: &Cbor) -> Result<Vec<u8>> {
+  let int = |i: i64| Cbor::Integer(i.into());
+  let map = cose_key
+    .as_map()
+    .ok_or(PasskeyVerificationError::Malformed("credential public key"))?;
+
+  // kty: EC2, alg: ES256, crv: P-256
+  let kty = cbor_map_get(map, &int(1)).and_then(Cbor::as_integer);
+  let alg = cbor_map_get(map, &int(3)).and_then(Cbor::as_integer);
+  let crv = cbor_map_get(map, &int(-1)).and_then(Cbor::as_integer);
+  if kty != Some(2.into())
+    || alg != Some(COSE_ALG_ES256.into())
+    || crv != Some(1.into())
+  {
+    return Err(PasskeyVerificationError::UnsupportedKey);
+  }
+
+  let coordinate = |key: i64| {
+    cbor_map_get(map, &int(key))
+      .and_then(Cbor::as_bytes)
+      .filter(|c| c.len() == 32)
+      .ok_or(PasskeyVerificationError::Malformed("credential public key"))
+  };
+  let mut point = vec![0x04];
+  point.extend_from_slice(coordinate(-2)?);
+  point.extend_from_slice(coordinate(-3)?);
+  VerifyingKey::from_sec1_bytes(&point)
+    .map_err(|_| PasskeyVerificationError::UnsupportedKey)?;
+  Ok(point)
+}
+
+/// Verifies a browser's answer to a registration challenge, returning the
+/// new [`Passkey`].
+pub(crate) fn verify_registration(
+  rp: &RelyingParty,
+  challenge: &[u8],
+  attestation: &PasskeyAttestation,
+) -> Result<Passkey> {
+  let client_data_json = decode(&attestation.client_data_json, "client data")?;
+  check_client_data(rp, &client_data_json, "webauthn.create", challenge)?;
+
+  let attestation_object =
+    decode(&attestation.attestation_object, "attestation object")?;
+  let attestation_object: Cbor =
+    ciborium::de::from_reader(attestation_object.as_slice())
+      .map_err(|_| PasskeyVerificationError::Malformed("attestation object"))?;
+  let auth_data = attestation_object
+    .as_map()
+    .and_then(|m| cbor_map_get(m, &Cbor::Text("authData".to_string())))
+    .and_then(Cbor::as_bytes)
+    .ok_or(PasskeyVerificationError::Malformed("attestation object"))?;
+
+  let auth_data = parse_authenticator_data(rp, auth_data)?;
+  if auth_data.flags & FLAG_ATTESTED_CREDENTIAL == 0 {
+    return Err(PasskeyVerificationError::Malformed("attested credential"));
+  }
+  // AAGUID (16), credential ID length (2), credential ID, public key
+  let rest = auth_data.rest;
+  if rest.len() < 18 {
+    return Err(PasskeyVerificationError::Malformed("attested credential"));
+  }
+  let id_len = usize::from(u16::from_be_bytes([rest[16], rest[17]]));
+  let credential_id = rest
+    .get(18..18 + id_len)
+    .ok_or(PasskeyVerificationError::Malformed("attested credential"))?;
+  if credential_id != decode(&attestation.credential_id, "credential id")? {
+    return Err(PasskeyVerificationError::Malformed("credential id"));
+  }
+  let cose_key: Cbor = ciborium::de::from_reader(&rest[18 + id_len..])
+    .map_err(|_| {
+      PasskeyVerificationError::Malformed("credential public key")
+    })?;
+
+  Ok(Passkey {
+    credential_id: credential_id.to_vec(),
+    public_key:    cose_key_to_sec1(&cose_key)?,
+    sign_count:    auth_data.sign_count,
+    created_at:    chrono::Utc::now(),
+    last_used_at:  None,
+  })
+}
+
+/// Verifies a browser's answer to a login challenge against the stored
+/// [`Passkey`], returning the authenticator's new signature counter.
+pub(crate) fn verify_assertion(
+  rp: &RelyingParty,
+  challenge: &[u8],
+  assertion: &PasskeyAssertion,
+  passkey: &Passkey,
+) -> Result<u32> {
+  let client_data_json = decode(&assertion.client_data_json, "client data")?;
+  check_client_data(rp, &client_data_json, "webauthn.get", challenge)?;
+
+  let auth_data_bytes =
+    decode(&assertion.authenticator_data, "authenticator data")?;
+  let auth_data = parse_authenticator_data(rp, &auth_data_bytes)?;
+
+  let key = VerifyingKey::from_sec1_bytes(&passkey.public_key)
+    .map_err(|_| PasskeyVerificationError::UnsupportedKey)?;
+  let signature =
+    Signature::from_der(&decode(&assertion.signature, "signature")?)
+      .map_err(|_| PasskeyVerificationError::Malformed("signature"))?;
+  let mut signed = auth_data_bytes.clone();
+  signed.extend_from_slice(&Sha256::digest(&client_data_json));
+  key
+    .verify(&signed, &signature)
+    .map_err(|_| PasskeyVerificationError::InvalidSignature)?;
+
+  // authenticators without counters always report zero
+  if (auth_data.sign_count != 0 || passkey.sign_count != 0)
+    && auth_data.sign_count <= passkey.sign_count
+  {
+    return Err(PasskeyVerificationError::CounterRegressed);
+  }
+  Ok(auth_data.sign_count)
+}
+
+/// A software authenticator, for exercising ceremonies in tests.
+#[cfg(test)]
+pub(crate) mod soft_authenticator {
+  use p256::ecdsa::{signature::Signer, SigningKey};
+  use rand::RngCore;
+
+  use super::*;
+
+  /// A single software passkey.
+  pub(crate) struct SoftAuthenticator {
+    key:           SigningKey,
+    credential_id: Vec<u8>,
+    user_handle:   Vec<u8>,
+    sign_count:    u32,
+    origin:        String,
+    rp_id:         String,
+  }
+
+  fn client_data(ceremony: &str, challenge: &[u8], origin: &str) -> Vec<u8> {
+    serde_json::json!({
+      "type": ceremony,
+      "challenge": encode(challenge),
+      "origin": origin,
+    })
+    .to_string()
+    .into_bytes()
+  }
+
+  impl SoftAuthenticator {
+    /// Creates a passkey for the site at `base_url`.
+    pub(crate) fn new(base_url: &BaseUrl) -> Self {
+      let rp = RelyingParty::from_base_url(base_url);
+      let mut secret = [0_u8; 32];
+      rand::rng().fill_bytes(&mut secret);
+      let mut credential_id = vec![0_u8; 16];
+      rand::rng().fill_bytes(&mut credential_id);
+      Self {
+        key: SigningKey::from_slice(&secret).expect("secret is a valid key"),
+        credential_id,
+        user_handle: Vec::new(),
+        sign_count: 0,
+        origin: rp.origin,
+        rp_id: rp.id,
+      }
+    }
+
+    fn auth_data(&self, flags: u8) -> Vec<u8> {
+      let mut data = Sha256::digest(self.rp_id.as_bytes()).to_vec();
+      data.push(flags);
+      data.extend_from_slice(&self.sign_count.to_be_bytes());
+      data
+    }
+
+    /// Answers a registration challenge for the given user handle.
+    pub(crate) fn register(
+      &mut self,
+      challenge: &[u8],
+      user_handle: &[u8],
+    ) -> PasskeyAttestation {
+      self.user_handle = user_handle.to_vec();
+      self.sign_count += 1;
+
+      let point = self.key.verifying_key().to_encoded_point(false);
+      let int = |i: i64| Cbor::Integer(i.into());
+      let cose_key = Cbor::Map(vec![
+        (int(1), int(2)),
+        (int(3), int(COSE_ALG_ES256)),
+        (int(-1), int(1)),
+        (int(-2), Cbor::Bytes(point.x().unwrap().to_vec())),
+        (int(-3), Cbor::Bytes(point.y().unwrap().to_vec())),
+      ]);
+
+      let mut auth_data = self.auth_data(
+        FLAG_USER_PRESENT | FLAG_USER_VERIFIED | FLAG_ATTESTED_CREDENTIAL,
+      );
+      auth_data.extend_from_slice(&[0; 16]);
+      auth_data.extend_from_slice(
+        &u16::try_from(self.credential_id.len())
+          .unwrap()
+          .to_be_bytes(),
+      );
+      auth_data.extend_from_slice(&self.credential_id);
+      ciborium::ser::into_writer(&cose_key, &mut auth_data).unwrap();
+
+      let attestation_object = Cbor::Map(vec![
+        (
+          Cbor::Text("fmt".to_string()),
+          Cbor::Text("none".to_string()),
+        ),
+        (Cbor::Text("attStmt".to_string()), Cbor::Map(Vec::new())),
+        (Cbor::Text("authData".to_string()), Cbor::Bytes(auth_data)),
+      ]);
+      let mut attestation_bytes = Vec::new();
+      ciborium::ser::into_writer(&attestation_object, &mut attestation_bytes)
+        .unwrap();
+
+      PasskeyAttestation {
+        credential_id:      encode(&self.credential_id),
+        client_data_json:   encode(&client_data(
+          "webauthn.create",
+          challenge,
+          &self.origin,
+        )),
+        attestation_object: encode(&attestation_bytes),
+      }
+    }
+
+    /// Answers a login challenge.
+    pub(crate) fn authenticate(
+      &mut self,
+      challenge: &[u8],
+    ) -> PasskeyAssertion {
+      self.sign_count += 1;
+      let auth_data = self.auth_data(FLAG_USER_PRESENT | FLAG_USER_VERIFIED);
+      let client_data_json =
+        client_data("webauthn.get", challenge, &self.origin);
+
+      let mut signed = auth_data.clone();
+      signed.extend_from_slice(&Sha256::digest(&client_data_json));
+      let signature: Signature = self.key.sign(&signed);
+
+      PasskeyAssertion {
+        credential_id:      encode(&self.credential_id),
+        client_data_json:   encode(&client_data_json),
+        authenticator_data: encode(&auth_data),
+        signature:          encode(signature.to_der().as_bytes()),
+        user_handle:        Some(encode(&self.user_handle)),
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{soft_authenticator::SoftAuthenticator, *};
+
+  fn base_url() -> BaseUrl { BaseUrl("https://picturepro.example:8443".into()) }
+
+  #[test]
+  fn relying_party_comes_from_base_url() {
+    let rp = RelyingParty::from_base_url(&BaseUrl(
+      "http://localhost:3000/".to_string(),
+    ));
+    assert_eq!(rp.id, "localhost");
+    assert_eq!(rp.origin, "http://localhost:3000");
+  }
+
+  #[test]
+  fn registered_passkeys_verify_assertions() {
+    let rp = RelyingParty::from_base_url(&base_url());
+    let mut authenticator = SoftAuthenticator::new(&base_url());
+
+    let attestation = authenticator.register(b"register", b"user");
+    let mut passkey =
+      verify_registration(&rp, b"register", &attestation).unwrap();
+
+    let assertion = authenticator.authenticate(b"login");
+    passkey.sign_count =
+      verify_assertion(&rp, b"login", &assertion, &passkey).unwrap();
+    assert!(matches!(
+      verify_assertion(&rp, b"other", &assertion, &passkey),
+      Err(PasskeyVerificationError::ChallengeMismatch)
+    ));
+    // replaying an old assertion trips the counter check
+    assert!(matches!(
+      verify_assertion(&rp, b"login", &assertion, &passkey),
+      Err(PasskeyVerificationError::CounterRegressed)
+    ));
+
+    let other =
+      SoftAuthenticator::new(&base_url()).register(b"register", b"user");
+    let other = verify_registration(&rp, b"register", &other).unwrap();
+    let assertion = authenticator.authenticate(b"login");
+    assert!(matches!(
+      verify_assertion(&rp, b"login", &assertion, &other),
+      Err(PasskeyVerificationError::InvalidSignature)
+    ));
+  }
+
+  #[test]
+  fn registration_rejects_other_origins() {
+    let rp = RelyingParty::from_base_url(&base_url());
+    let mut authenticator =
+      SoftAuthenticator::new(&BaseUrl("https://evil.example".to_string()));
+    let attestation = authenticator.register(b"register", b"user");
+    assert!(matches!(
+      verify_registration(&rp, b"register", &attestation),
+      Err(PasskeyVerificationError::OriginMismatch(_))
+    ));
+  }
+}
@@ -29,5 +29,6 @@ radix_icon!(
   MagnifyingGlassIcon,
   "crates/lsc/src/radix-icons/magnifying-glass.svg"
 );
+radix_icon!(Pencil1Icon, "crates/lsc/src/radix-icons/pencil-1.svg");
 radix_icon!(TrashIcon, "crates/lsc/src/radix-icons/trash.svg");
 radix_icon!(UploadIcon, "crates/lsc/src/radix-icons/upload.svg");
@@ -0,0 +1,31 @@
+use std::path::PathBuf;
+
+use models::Ulid;
+
+use crate::{MailTransport, Message, SendMailError};
+
+/// Writes each message to its own `.eml` file in a directory, for local
+/// development. The files open in any mail client.
+#[derive(Clone, Debug)]
+pub struct FileTransport {
+  dir: PathBuf,
+}
+
+impl FileTransport {
+  /// Create a new [`FileTransport`] that writes into `dir`, creating it if
+  /// needed.
+  #[must_use]
+  pub fn new(dir: impl Into<PathBuf>) -> Self { Self { dir: dir.into() } }
+}
+
+#[async_trait::async_trait]
+impl MailTransport for FileTransport {
+  async fn send(&self, message: &Message) -> Result<(), SendMailError> {
+    let id = Ulid::new();
+    let path = self.dir.join(format!("{id}.eml"));
+    tokio::fs::create_dir_all(&self.dir).await?;
+    tokio::fs::write(&path, message.to_rfc5322(id)).await?;
+    tracing::info!("wrote mail to {} at {}", message.to, path.display());
+    Ok(())
+  }
+}
@@ -0,0 +1,176 @@
+//! Sending templated emails through a pluggable transport.
+
+mod file;
+mod memory;
+mod smtp;
+pub mod templates;
+
+use std::{fmt, sync::Arc};
+
+use base64::Engine;
+use models::{EmailAddress, Ulid};
+
+pub use self::{file::*, memory::*, smtp::*};
+
+/// An error that occurs when sending an email.
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum SendMailError {
+  /// An I/O error occurred while handing the message to the transport.
+  #[error("Failed to send mail: {0}")]
+  IoError(#[from] std::io::Error),
+  /// The SMTP server rejected a command.
+  #[error("SMTP server rejected `{command}`: {reply}")]
+  SmtpRejected {
+    /// The command that was rejected.
+    command: &'static str,
+    /// The server's reply.
+    reply:   String,
+  },
+}
+
+/// A message rendered from a template, ready to be sent.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Message {
+  /// The sender's address.
+  pub from:    EmailAddress,
+  /// The recipient's address.
+  pub to:      EmailAddress,
+  /// The subject line.
+  pub subject: String,
+  /// The plain-text body.
+  pub body:    String,
+}
+
+impl Message {
+  /// Formats the message as an RFC 5322 document with CRLF line endings,
+  /// suitable for an `.eml` file or an SMTP `DATA` command.
+  #[must_use]
+  pub fn to_rfc5322(&self, message_id: Ulid) -> String {
+    let mut out = String::new();
+    for (name, value) in [
+      ("From", format!("PicturePro <{}>", self.from)),
+      ("To", format!("<{}>", self.to)),
+      ("Subject", encode_header_value(&self.subject)),
+      ("Date", chrono::Utc::now().to_rfc2822()),
+      ("Message-ID", format!("<{message_id}@picturepro>")),
+      ("MIME-Version", "1.0".to_owned()),
+      ("Content-Type", "text/plain; charset=utf-8".to_owned()),
+      ("Content-Transfer-Encoding", "8bit".to_owned()),
+    ] {
+      out.push_str(name);
+      out.push_str(": ");
+      out.push_str(&value);
+      out.push_str("\r\n");
+    }
+    out.push_str("\r\n");
+    for line in self.body.lines() {
+      out.push_str(line);
+      out.push_str("\r\n");
+    }
+    out
+  }
+}
+
+/// Strips line breaks from a header value, so it can't inject headers, and
+/// encodes it as an RFC 2047 encoded-word if it isn't plain ASCII.
+fn encode_header_value(value: &str) -> String {
+  let value = value.replace(['\r', '\n'], " ");
+  if value.is_ascii() {
+    value
+  } else {
+    format!(
+      "=?UTF-8?B?{}?=",
+      base64::prelude::BASE64_STANDARD.encode(value)
+    )
+  }
+}
+
+/// A message that can be rendered for a recipient.
+pub trait EmailTemplate {
+  /// The subject line.
+  fn subject(&self) -> String;
+  /// The plain-text body.
+  fn body(&self) -> String;
+}
+
+/// Delivers rendered [`Message`]s.
+#[async_trait::async_trait]
+pub trait MailTransport: fmt::Debug + Send + Sync + 'static {
+  /// Delivers a message.
+  async fn send(&self, message: &Message) -> Result<(), SendMailError>;
+}
+
+/// Renders [`EmailTemplate`]s and sends them from a fixed address through a
+/// [`MailTransport`].
+#[derive(Clone, Debug)]
+pub struct Mailer {
+  from:      EmailAddress,
+  transport: Arc<dyn MailTransport>,
+}
+
+impl Mailer {
+  /// Create a new [`Mailer`].
+  #[must_use]
+  pub fn new(from: EmailAddress, transport: impl MailTransport) -> Self {
+    Self {
+      from,
+      transport: Arc::new(transport),
+    }
+  }
+
+  /// Render a template and send it to `to`.
+  #[tracing::instrument(skip(self, template))]
+  pub async fn send(
+    &self,
+    to: EmailAddress,
+    template: &impl EmailTemplate,
+  ) -> Result<(), SendMailError> {
+    let message = Message {
+      from: self.from.clone(),
+      to,
+      subject: template.subject(),
+      body: template.body(),
+    };
+    self.transport.send(&message).await
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn message(subject: &str, body: &str) -> Message {
+    Message {
+      from:    EmailAddress::try_new("noreply@example.com").unwrap(),
+      to:      EmailAddress::try_new("user@example.com").unwrap(),
+      subject: subject.to_owned(),
+      body:    body.to_owned(),
+    }
+  }
+
+  #[test]
+  fn formats_headers_and_crlf_body() {
+    let eml = message("Hello", "line one\nline two").to_rfc5322(Ulid::new());
+
+    assert!(eml.starts_with("From: PicturePro <noreply@example.com>\r\n"));
+    assert!(eml.contains("\r\nTo: <user@example.com>\r\n"));
+    assert!(eml.contains("\r\nSubject: Hello\r\n"));
+    assert!(eml.ends_with("\r\n\r\nline one\r\nline two\r\n"));
+  }
+
+  #[test]
+  fn subject_cannot_inject_headers() {
+    let eml =
+      message("Hi\r\nBcc: evil@example.com", "").to_rfc5322(Ulid::new());
+
+    assert!(eml.contains("\r\nSubject: Hi  Bcc: evil@example.com\r\n"));
+    assert!(!eml.contains("\r\nBcc:"));
+  }
+
+  #[test]
+  fn encodes_non_ascii_subject() {
+    let eml = message("Café", "").to_rfc5322(Ulid::new());
+
+    assert!(eml.contains("\r\nSubject: =?UTF-8?B?Q2Fmw6k=?=\r\n"));
+  }
+}
@@ -0,0 +1,37 @@
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::{MailTransport, Message, SendMailError};
+
+/// Keeps sent messages in memory, for tests. Clones share the same outbox.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryTransport {
+  sent: Arc<Mutex<Vec<Message>>>,
+}
+
+impl MemoryTransport {
+  /// Create a new, empty [`MemoryTransport`].
+  #[must_use]
+  pub fn new() -> Self { Self::default() }
+
+  /// The messages sent so far, oldest first.
+  #[must_use]
+  pub fn sent(&self) -> Vec<Message> {
+    self
+      .sent
+      .lock()
+      .unwrap_or_else(PoisonError::into_inner)
+      .clone()
+  }
+}
+
+#[async_trait::async_trait]
+impl MailTransport for MemoryTransport {
+  async fn send(&self, message: &Message) -> Result<(), SendMailError> {
+    self
+      .sent
+      .lock()
+      .unwrap_or_else(PoisonError::into_inner)
+      .push(message.clone());
+    Ok(())
+  }
+}
@@ -0,0 +1,182 @@
+use models::Ulid;
+use tokio::{
+  io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+  net::TcpStream,
+};
+
+use crate::{MailTransport, Message, SendMailError};
+
+/// Speaks plain SMTP to a local sink such as Mailpit or `MailHog`, for
+/// development. There's no TLS or authentication, so this isn't for
+/// delivering real mail.
+#[derive(Clone, Debug)]
+pub struct SmtpSinkTransport {
+  addr: String,
+}
+
+impl SmtpSinkTransport {
+  /// Create a new [`SmtpSinkTransport`] that connects to `addr`, e.g.
+  /// `localhost:1025`.
+  #[must_use]
+  pub fn new(addr: impl Into<String>) -> Self { Self { addr: addr.into() } }
+}
+
+/// One SMTP connection, reading replies line by line.
+struct Session {
+  reader: BufReader<TcpStream>,
+}
+
+impl Session {
+  /// Reads a (possibly multi-line) reply and checks its code.
+  async fn expect(
+    &mut self,
+    command: &'static str,
+    codes: &[&str],
+  ) -> Result<(), SendMailError> {
+    let mut reply = String::new();
+    loop {
+      let mut line = String::new();
+      if self.reader.read_line(&mut line).await? == 0 {
+        return Err(SendMailError::IoError(
+          std::io::ErrorKind::UnexpectedEof.into(),
+        ));
+      }
+      reply.push_str(&line);
+      // `250-` continues a reply, `250 ` ends it
+      if line.as_bytes().get(3) != Some(&b'-') {
+        break;
+      }
+    }
+
+    if codes.iter().any(|code| reply.starts_with(code)) {
+      Ok(())
+    } else {
+      Err(SendMailError::SmtpRejected {
+        command,
+        reply: reply.trim_end().to_owned(),
+      })
+    }
+  }
+
+  async fn command(
+    &mut self,
+    command: &'static str,
+    line: &str,
+    codes: &[&str],
+  ) -> Result<(), SendMailError> {
+    self.reader.get_mut().write_all(line.as_bytes()).await?;
+    self.reader.get_mut().write_all(b"\r\n").await?;
+    self.expect(command, codes).await
+  }
+}
+
+/// Escapes lines that start with a `.`, so the message can't end the `DATA`
+/// section early.
+fn dot_stuff(data: &str) -> String {
+  let mut out = String::with_capacity(data.len());
+  for line in data.split_inclusive("\r\n") {
+    if line.starts_with('.') {
+      out.push('.');
+    }
+    out.push_str(line);
+  }
+  out
+}
+
+#[async_trait::async_trait]
+impl MailTransport for SmtpSinkTransport {
+  async fn send(&self, message: &Message) -> Result<(), SendMailError> {
+    let stream = TcpStream::connect(&self.addr).await?;
+    let mut session = Session {
+      reader: BufReader::new(stream),
+    };
+
+    session.expect("CONNECT", &["220"]).await?;
+    session.command("EHLO", "EHLO localhost", &["250"]).await?;
+    session
+      .command("MAIL FROM", &format!("MAIL FROM:<{}>", message.from), &[
+        "250",
+      ])
+      .await?;
+    session
+      .command("RCPT TO", &format!("RCPT TO:<{}>", message.to), &[
+        "250", "251",
+      ])
+      .await?;
+    session.command("DATA", "DATA", &["354"]).await?;
+    let data = dot_stuff(&message.to_rfc5322(Ulid::new()));
+    session.reader.get_mut().write_all(data.as_bytes()).await?;
+    session.command("DATA", ".", &["250"]).await?;
+    session.command("QUIT", "QUIT", &["221"]).await?;
+
+    tracing::info!("sent mail to {} via {}", message.to, self.addr);
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use models::EmailAddress;
+  use tokio::net::TcpListener;
+
+  use super::*;
+
+  /// Accepts one connection, plays the server side of an SMTP session, and
+  /// returns everything the client sent.
+  async fn sink(listener: TcpListener) -> String {
+    let (stream, _) = listener.accept().await.unwrap();
+    let mut reader = BufReader::new(stream);
+    let mut received = String::new();
+    reader.get_mut().write_all(b"220 sink\r\n").await.unwrap();
+
+    let mut in_data = false;
+    loop {
+      let mut line = String::new();
+      if reader.read_line(&mut line).await.unwrap() == 0 {
+        break;
+      }
+      received.push_str(&line);
+      let reply: &[u8] = if in_data {
+        if line != ".\r\n" {
+          continue;
+        }
+        in_data = false;
+        b"250 queued\r\n"
+      } else if line.starts_with("EHLO") {
+        b"250-sink\r\n250 8BITMIME\r\n"
+      } else if line.starts_with("DATA") {
+        in_data = true;
+        b"354 go ahead\r\n"
+      } else if line.starts_with("QUIT") {
+        reader.get_mut().write_all(b"221 bye\r\n").await.unwrap();
+        break;
+      } else {
+        b"250 ok\r\n"
+      };
+      reader.get_mut().write_all(reply).await.unwrap();
+    }
+    received
+  }
+
+  #[tokio::test]
+  async fn delivers_message_to_sink() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let sink = tokio::spawn(sink(listener));
+
+    SmtpSinkTransport::new(addr)
+      .send(&Message {
+        from:    EmailAddress::try_new("noreply@example.com").unwrap(),
+        to:      EmailAddress::try_new("user@example.com").unwrap(),
+        subject: "Hello".to_owned(),
+        body:    "hi\n.hidden".to_owned(),
+      })
+      .await
+      .unwrap();
+
+    let received = sink.await.unwrap();
+    assert!(received.contains("MAIL FROM:<noreply@example.com>\r\n"));
+    assert!(received.contains("RCPT TO:<user@example.com>\r\n"));
+    assert!(received.contains("\r\nhi\r\n..hidden\r\n.\r\nQUIT\r\n"));
+  }
+}
@@ -0,0 +1,141 @@
+//! The messages the platform sends.
+
+use models::HumanName;
+
+use crate::EmailTemplate;
+
+/// Asks a user to confirm that they own their email address.
+#[derive(Clone, Debug)]
+pub struct VerifyEmail {
+  /// The user's name.
+  pub name: HumanName,
+  /// The link that verifies the address.
+  pub url:  String,
+}
+
+impl EmailTemplate for VerifyEmail {
+  fn subject(&self) -> String { "Verify your PicturePro email".to_owned() }
+
+  fn body(&self) -> String {
+    format!(
+      "Hi {name},\n\nPlease confirm this is your email address by opening \
+       this link:\n\n{url}\n\nThe link expires in 24 hours. If you didn't \
+       sign up for PicturePro, you can ignore this email.\n\n- PicturePro\n",
+      name = self.name,
+      url = self.url,
+    )
+  }
+}
+
+/// Sends a user a link to choose a new password.
+#[derive(Clone, Debug)]
+pub struct ResetPassword {
+  /// The user's name.
+  pub name: HumanName,
+  /// The link that resets the password.
+  pub url:  String,
+}
+
+impl EmailTemplate for ResetPassword {
+  fn subject(&self) -> String { "Reset your PicturePro password".to_owned() }
+
+  fn body(&self) -> String {
+    format!(
+      "Hi {name},\n\nSomeone asked to reset the password for your PicturePro \
+       account. To choose a new password, open this link:\n\n{url}\n\nThe \
+       link expires in 1 hour and can only be used once. Resetting your \
+       password logs you out everywhere. If you didn't ask for this, you can \
+       ignore this email.\n\n- PicturePro\n",
+      name = self.name,
+      url = self.url,
+    )
+  }
+}
+
+/// Sends a link that logs the user in, or creates their account if the
+/// address is new.
+#[derive(Clone, Debug)]
+pub struct LogInLink {
+  /// The user's name, if they have an account.
+  pub name: Option<HumanName>,
+  /// The link that logs the user in.
+  pub url:  String,
+}
+
+impl EmailTemplate for LogInLink {
+  fn subject(&self) -> String { "Your PicturePro login link".to_owned() }
+
+  fn body(&self) -> String {
+    let greeting = match &self.name {
+      Some(name) => format!("Hi {name},"),
+      None => "Hi,".to_owned(),
+    };
+    format!(
+      "{greeting}\n\nTo log in to PicturePro, open this link:\n\n{url}\n\nThe \
+       link expires in 15 minutes and can only be used once. If you don't \
+       have an account yet, opening it creates one. If you didn't ask for \
+       this, you can ignore this email.\n\n- PicturePro\n",
+      url = self.url,
+    )
+  }
+}
+
+/// Sends a logged-in user a link confirming it's them, before a change to
+/// their account that they have no password to confirm.
+#[derive(Clone, Debug)]
+pub struct ConfirmIdentity {
+  /// The user's name.
+  pub name: HumanName,
+  /// The link that confirms the change.
+  pub url:  String,
+}
+
+impl EmailTemplate for ConfirmIdentity {
+  fn subject(&self) -> String {
+    "Confirm your PicturePro account change".to_owned()
+  }
+
+  fn body(&self) -> String {
+    format!(
+      "Hi {name},\n\nSomeone logged in to your PicturePro account asked to \
+       change its email, password, or two-factor settings, or to delete it. \
+       To confirm it's you, open this link and finish the change \
+       there:\n\n{url}\n\nThe link expires in 15 minutes and can only be used \
+       once. If you didn't ask for this, someone else may be logged in to \
+       your account; log out everywhere from your account settings.\n\n- \
+       PicturePro\n",
+      name = self.name,
+      url = self.url,
+    )
+  }
+}
+
+/// Tells a user that their account was locked after repeated failed logins.
+#[derive(Clone, Debug)]
+pub struct AccountLocked {
+  /// The user's name.
+  pub name:    HumanName,
+  /// How many minutes the lockout lasts.
+  pub minutes: i64,
+  /// The page to reset the password from.
+  pub url:     String,
+}
+
+impl EmailTemplate for AccountLocked {
+  fn subject(&self) -> String {
+    "Your PicturePro account was temporarily locked".to_owned()
+  }
+
+  fn body(&self) -> String {
+    format!(
+      "Hi {name},\n\nThere were too many failed attempts to log in to your \
+       PicturePro account, so logging in with your password is blocked for \
+       the next {minutes} minutes.\n\nIf this was you, wait and try again. If \
+       it wasn't, someone may be guessing your password; you can choose a new \
+       one here:\n\n{url}\n\n- PicturePro\n",
+      name = self.name,
+      minutes = self.minutes,
+      url = self.url,
+    )
+  }
+}
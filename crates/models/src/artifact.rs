@@ -3,7 +3,10 @@ use std::{fmt, path::PathBuf};
 use model::{Model, RecordId};
 use serde::{Deserialize, Serialize};
 
-use crate::{CompressionStatus, EitherSlug, StrictSlug, Ulid, UserRecordId};
+use crate::{
+  CompressionStatus, EitherSlug, StrictSlug, Timestamp, Ulid, UserRecordId,
+  Versioned,
+};
 
 /// The table name for [`Artifact`] records.
 pub const ARTIFACT_TABLE_NAME: &str = "artifact";
@@ -18,12 +21,39 @@ pub struct Artifact {
   pub id:               ArtifactRecordId,
   /// The artifact's path.
   pub path:             ArtifactPath,
-  /// The artifact's originator.
+  /// The user who uploaded the artifact.
   pub originator:       UserRecordId,
   /// The artifact's compression status.
   pub comp_status:      CompressionStatus,
   /// The artifact's stated mime-type.
   pub stated_mime_type: Option<ArtifactMimeType>,
+  /// The artifact's encryption parameters, if it is encrypted at rest.
+  #[serde(default)]
+  pub encryption:       Option<ArtifactEncryption>,
+  /// When the artifact was created.
+  #[serde(default)]
+  pub created_at:       Timestamp,
+  /// When the artifact was last updated.
+  #[serde(default)]
+  pub updated_at:       Timestamp,
+  /// The schema version the artifact was written at.
+  #[serde(default)]
+  pub schema_version:   u32,
+}
+
+/// The envelope encryption parameters of an [`Artifact`].
+///
+/// The artifact's data is encrypted with its own data key, which is stored
+/// here wrapped (encrypted) by a master key. Rotating the master key only
+/// requires re-wrapping the data key, not rewriting the data.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ArtifactEncryption {
+  /// The ID of the master key that wraps the data key.
+  pub master_key_id: String,
+  /// The data key, wrapped by the master key.
+  pub wrapped_key:   Vec<u8>,
+  /// The nonce prefix used to encrypt the data stream.
+  pub stream_nonce:  Vec<u8>,
 }
 
 /// The object storage path for an [`Artifact`].
@@ -66,6 +96,29 @@ impl ArtifactMimeType {
   /// Converts the [`ArtifactMimeType`] into a [`String`].
   #[must_use]
   pub fn into_inner(self) -> String { self.0 }
+
+  /// Whether the format is already entropy-coded, such that general-purpose
+  /// compression won't meaningfully shrink it.
+  #[must_use]
+  pub fn is_precompressed(&self) -> bool {
+    let essence = self.0.split(';').next().unwrap_or_default().trim();
+    let essence = essence.to_ascii_lowercase();
+    matches!(
+      essence.as_str(),
+      "image/jpeg"
+        | "image/jpg"
+        | "image/png"
+        | "image/webp"
+        | "image/avif"
+        | "image/heic"
+        | "image/heif"
+        | "image/jxl"
+        | "application/zip"
+        | "application/gzip"
+        | "application/zstd"
+    ) || essence.starts_with("video/")
+      || essence.starts_with("audio/")
+  }
 }
 
 impl AsRef<str> for ArtifactMimeType {
@@ -99,16 +152,23 @@ pub struct ArtifactCreateRequest {
   pub comp_status:      CompressionStatus,
   /// The artifact's stated mime-type.
   pub stated_mime_type: Option<ArtifactMimeType>,
+  /// The artifact's encryption parameters, if it is encrypted at rest.
+  pub encryption:       Option<ArtifactEncryption>,
 }
 
 impl From<ArtifactCreateRequest> for Artifact {
   fn from(input: ArtifactCreateRequest) -> Self {
+    let now = chrono::Utc::now();
     Self {
       id:               ArtifactRecordId::new(),
       path:             input.path,
       originator:       input.originator,
       comp_status:      input.comp_status,
       stated_mime_type: input.stated_mime_type,
+      encryption:       input.encryption,
+      created_at:       now,
+      updated_at:       now,
+      schema_version:   Self::SCHEMA_VERSION,
     }
   }
 }
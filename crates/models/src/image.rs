@@ -1,7 +1,9 @@
 use model::{Model, RecordId};
 use serde::{Deserialize, Serialize};
 
-use crate::{ArtifactRecordId, EitherSlug, LaxSlug};
+use crate::{
+  ArtifactRecordId, EitherSlug, LaxSlug, Timestamp, UserRecordId, Versioned,
+};
 
 /// The table name for [`Image`] records.
 pub const IMAGE_TABLE_NAME: &str = "image";
@@ -13,11 +15,24 @@ pub type ImageRecordId = RecordId<Image>;
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Image {
   /// The image's ID.
-  pub id:       ImageRecordId,
+  pub id:             ImageRecordId,
   /// The [`Artifact`](crate::Artifact) backing the image.
-  pub artifact: ArtifactRecordId,
+  pub artifact:       ArtifactRecordId,
   /// The image's metadata.
-  pub meta:     ImageMetadata,
+  pub meta:           ImageMetadata,
+  /// When the image was created.
+  #[serde(default)]
+  pub created_at:     Timestamp,
+  /// When the image was last updated.
+  #[serde(default)]
+  pub updated_at:     Timestamp,
+  /// The schema version the image was written at.
+  #[serde(default)]
+  pub schema_version: u32,
+  /// The user who uploaded the image, if it was stored after uploaders were
+  /// recorded.
+  #[serde(default)]
+  pub uploader:       Option<UserRecordId>,
 }
 
 /// The metadata of an [`Image`].
@@ -65,14 +80,21 @@ pub struct ImageCreateRequest {
   pub artifact: ArtifactRecordId,
   /// The image's metadata.
   pub meta:     ImageMetadata,
+  /// The user who uploaded the image.
+  pub uploader: UserRecordId,
 }
 
 impl From<ImageCreateRequest> for Image {
   fn from(value: ImageCreateRequest) -> Self {
+    let now = chrono::Utc::now();
     Self {
-      id:       ImageRecordId::default(),
-      artifact: value.artifact,
-      meta:     value.meta,
+      id:             ImageRecordId::default(),
+      artifact:       value.artifact,
+      meta:           value.meta,
+      created_at:     now,
+      updated_at:     now,
+      schema_version: Self::SCHEMA_VERSION,
+      uploader:       Some(value.uploader),
     }
   }
 }
@@ -6,12 +6,16 @@ mod photo;
 mod photo_group;
 mod user;
 
-mod price;
+mod license;
+mod money;
+mod schema;
 mod state;
+mod timestamp;
 
 pub use dvf::{slugger::*, *};
 pub use model::*;
 
 pub use self::{
-  artifact::*, image::*, photo::*, photo_group::*, price::*, state::*, user::*,
+  artifact::*, image::*, license::*, money::*, photo::*, photo_group::*,
+  schema::*, state::*, timestamp::*, user::*,
 };
@@ -0,0 +1,76 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Money;
+
+/// A tier of usage rights that photos can be licensed under.
+#[derive(
+  Clone,
+  Copy,
+  Debug,
+  PartialEq,
+  Eq,
+  PartialOrd,
+  Ord,
+  Hash,
+  Serialize,
+  Deserialize,
+)]
+pub enum LicenseTier {
+  /// Personal, non-commercial use in digital form.
+  PersonalDigital,
+  /// Use in commercial work, such as advertising or products.
+  Commercial,
+  /// The right to make and sell prints.
+  PrintRights,
+}
+
+impl LicenseTier {
+  /// All license tiers, from least to most permissive.
+  pub const ALL: &'static [LicenseTier] =
+    &[Self::PersonalDigital, Self::Commercial, Self::PrintRights];
+
+  /// The tier's display name.
+  #[must_use]
+  pub const fn name(self) -> &'static str {
+    match self {
+      Self::PersonalDigital => "Personal Digital",
+      Self::Commercial => "Commercial",
+      Self::PrintRights => "Print Rights",
+    }
+  }
+
+  /// A short description of what the tier allows.
+  #[must_use]
+  pub const fn description(self) -> &'static str {
+    match self {
+      Self::PersonalDigital => {
+        "Share and keep the photos for personal, non-commercial use."
+      }
+      Self::Commercial => {
+        "Use the photos in commercial work, like marketing or products."
+      }
+      Self::PrintRights => "Make and sell prints of the photos.",
+    }
+  }
+}
+
+impl fmt::Display for LicenseTier {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.name())
+  }
+}
+
+/// The prices a [`PhotoGroup`](crate::PhotoGroup) is offered at under a
+/// [`LicenseTier`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LicenseOffer {
+  /// The license tier.
+  pub tier:        LicenseTier,
+  /// The price of the license for every photo in the group.
+  pub group_price: Money,
+  /// The default price of the license for a single photo, if single photos
+  /// are sold under this tier.
+  pub photo_price: Option<Money>,
+}
@@ -0,0 +1,583 @@
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+/// An ISO 4217 currency.
+#[derive(
+  Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+#[serde(into = "&'static str", try_from = "String")]
+pub enum Currency {
+  /// United States dollar.
+  #[default]
+  Usd,
+  /// Euro.
+  Eur,
+  /// Pound sterling.
+  Gbp,
+  /// Canadian dollar.
+  Cad,
+  /// Australian dollar.
+  Aud,
+  /// New Zealand dollar.
+  Nzd,
+  /// Swiss franc.
+  Chf,
+  /// Swedish krona.
+  Sek,
+  /// Norwegian krone.
+  Nok,
+  /// Danish krone.
+  Dkk,
+  /// Mexican peso.
+  Mxn,
+  /// Brazilian real.
+  Brl,
+  /// Indian rupee.
+  Inr,
+  /// Japanese yen.
+  Jpy,
+  /// South Korean won.
+  Krw,
+  /// Kuwaiti dinar.
+  Kwd,
+}
+
+impl Currency {
+  /// All supported currencies.
+  pub const ALL: &'static [Currency] = &[
+    Self::Usd,
+    Self::Eur,
+    Self::Gbp,
+    Self::Cad,
+    Self::Aud,
+    Self::Nzd,
+    Self::Chf,
+    Self::Sek,
+    Self::Nok,
+    Self::Dkk,
+    Self::Mxn,
+    Self::Brl,
+    Self::Inr,
+    Self::Jpy,
+    Self::Krw,
+    Self::Kwd,
+  ];
+
+  /// The currency's ISO 4217 alphabetic code.
+  #[must_use]
+  pub const fn code(self) -> &'static str {
+    match self {
+      Self::Usd => "USD",
+      Self::Eur => "EUR",
+      Self::Gbp => "GBP",
+      Self::Cad => "CAD",
+      Self::Aud => "AUD",
+      Self::Nzd => "NZD",
+      Self::Chf => "CHF",
+      Self::Sek => "SEK",
+      Self::Nok => "NOK",
+      Self::Dkk => "DKK",
+      Self::Mxn => "MXN",
+      Self::Brl => "BRL",
+      Self::Inr => "INR",
+      Self::Jpy => "JPY",
+      Self::Krw => "KRW",
+      Self::Kwd => "KWD",
+    }
+  }
+
+  /// The number of decimal digits in the currency's minor unit.
+  #[must_use]
+  pub const fn exponent(self) -> u32 {
+    match self {
+      Self::Jpy | Self::Krw => 0,
+      Self::Kwd => 3,
+      _ => 2,
+    }
+  }
+
+  /// The currency's symbol, if it has an unambiguous one.
+  #[must_use]
+  pub const fn symbol(self) -> Option<&'static str> {
+    match self {
+      Self::Usd => Some("$"),
+      Self::Eur => Some("€"),
+      Self::Gbp => Some("£"),
+      Self::Inr => Some("₹"),
+      Self::Jpy => Some("¥"),
+      Self::Krw => Some("₩"),
+      Self::Brl => Some("R$"),
+      _ => None,
+    }
+  }
+
+  /// The number of minor units in one major unit.
+  #[must_use]
+  pub const fn minor_units_per_major(self) -> u64 {
+    10_u64.pow(self.exponent())
+  }
+}
+
+impl fmt::Display for Currency {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.code())
+  }
+}
+
+/// An error that occurs when parsing a [`Currency`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnknownCurrencyError(String);
+
+impl fmt::Display for UnknownCurrencyError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "unknown currency code: {:?}", self.0)
+  }
+}
+
+impl std::error::Error for UnknownCurrencyError {}
+
+impl FromStr for Currency {
+  type Err = UnknownCurrencyError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    Self::ALL
+      .iter()
+      .find(|c| c.code().eq_ignore_ascii_case(s.trim()))
+      .copied()
+      .ok_or_else(|| UnknownCurrencyError(s.to_owned()))
+  }
+}
+
+impl From<Currency> for &'static str {
+  fn from(currency: Currency) -> Self { currency.code() }
+}
+
+impl TryFrom<String> for Currency {
+  type Error = UnknownCurrencyError;
+
+  fn try_from(value: String) -> Result<Self, Self::Error> { value.parse() }
+}
+
+/// An exact amount of money, as an integer number of minor units of a
+/// currency.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub struct Money {
+  minor_units: u64,
+  currency:    Currency,
+}
+
+/// Deserializes both the current form and the legacy form from before prices
+/// carried a currency, `{ cents }`, which is read as USD.
+impl<'de> Deserialize<'de> for Money {
+  fn deserialize<D: serde::Deserializer<'de>>(
+    deserializer: D,
+  ) -> Result<Self, D::Error> {
+    use serde::de::{self, MapAccess, SeqAccess, Visitor};
+
+    struct MoneyVisitor;
+
+    impl<'de> Visitor<'de> for MoneyVisitor {
+      type Value = Money;
+
+      fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an amount of money")
+      }
+
+      fn visit_seq<A: SeqAccess<'de>>(
+        self,
+        mut seq: A,
+      ) -> Result<Money, A::Error> {
+        let minor_units = seq
+          .next_element()?
+          .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        // the legacy form has no currency
+        let currency = seq.next_element()?.unwrap_or(Currency::Usd);
+        Ok(Money::from_minor_units(minor_units, currency))
+      }
+
+      fn visit_map<A: MapAccess<'de>>(
+        self,
+        mut map: A,
+      ) -> Result<Money, A::Error> {
+        let mut minor_units = None;
+        let mut currency = None;
+        while let Some(key) = map.next_key::<String>()? {
+          match key.as_str() {
+            "minor_units" | "cents" => minor_units = Some(map.next_value()?),
+            "currency" => currency = Some(map.next_value()?),
+            _ => {
+              map.next_value::<de::IgnoredAny>()?;
+            }
+          }
+        }
+        let minor_units =
+          minor_units.ok_or_else(|| de::Error::missing_field("minor_units"))?;
+        Ok(Money::from_minor_units(
+          minor_units,
+          currency.unwrap_or(Currency::Usd),
+        ))
+      }
+    }
+
+    deserializer.deserialize_struct(
+      "Money",
+      &["minor_units", "currency"],
+      MoneyVisitor,
+    )
+  }
+}
+
+/// An error that occurs when parsing [`Money`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseMoneyError {
+  /// The input was empty.
+  Empty,
+  /// The amount was negative.
+  Negative,
+  /// The amount wasn't a decimal number.
+  Invalid,
+  /// The amount had more decimal places than the currency allows.
+  TooPrecise(Currency),
+  /// The amount was too large to represent.
+  Overflow,
+  /// The currency code wasn't recognized.
+  UnknownCurrency(UnknownCurrencyError),
+}
+
+impl fmt::Display for ParseMoneyError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Empty => write!(f, "amount is empty"),
+      Self::Negative => write!(f, "amount is negative"),
+      Self::Invalid => write!(f, "amount is not a decimal number"),
+      Self::TooPrecise(currency) => write!(
+        f,
+        "{currency} amounts have at most {} decimal places",
+        currency.exponent()
+      ),
+      Self::Overflow => write!(f, "amount is too large"),
+      Self::UnknownCurrency(e) => write!(f, "{e}"),
+    }
+  }
+}
+
+impl std::error::Error for ParseMoneyError {}
+
+impl Money {
+  /// Creates a new amount from a number of minor units.
+  #[must_use]
+  pub const fn from_minor_units(minor_units: u64, currency: Currency) -> Self {
+    Self {
+      minor_units,
+      currency,
+    }
+  }
+
+  /// Creates a new amount of zero.
+  #[must_use]
+  pub const fn zero(currency: Currency) -> Self {
+    Self::from_minor_units(0, currency)
+  }
+
+  /// The amount, in minor units of the currency.
+  #[must_use]
+  pub const fn minor_units(&self) -> u64 { self.minor_units }
+
+  /// The amount's currency.
+  #[must_use]
+  pub const fn currency(&self) -> Currency { self.currency }
+
+  /// Parses a decimal amount in major units of the given currency, e.g.
+  /// `"0.29"` or `"1,250.00"`. A leading currency symbol is accepted. The
+  /// conversion is exact; input with more precision than the currency
+  /// supports is rejected rather than rounded.
+  pub fn parse(
+    input: &str,
+    currency: Currency,
+  ) -> Result<Self, ParseMoneyError> {
+    let mut input = input.trim();
+    if let Some(rest) = currency.symbol().and_then(|s| input.strip_prefix(s)) {
+      input = rest.trim_start();
+    }
+    if input.is_empty() {
+      return Err(ParseMoneyError::Empty);
+    }
+    if input.starts_with('-') {
+      return Err(ParseMoneyError::Negative);
+    }
+    let input = input.strip_prefix('+').unwrap_or(input);
+
+    let (whole, fraction) = match input.split_once('.') {
+      Some((whole, fraction)) => (whole, fraction),
+      None => (input, ""),
+    };
+    // allow thousands separators in the whole part only
+    let whole = whole.replace(',', "");
+    if (whole.is_empty() && fraction.is_empty())
+      || !whole.chars().all(|c| c.is_ascii_digit())
+      || !fraction.chars().all(|c| c.is_ascii_digit())
+    {
+      return Err(ParseMoneyError::Invalid);
+    }
+
+    // trailing zeros beyond the currency's precision don't lose anything
+    let fraction = fraction.trim_end_matches('0');
+    let exponent = currency.exponent() as usize;
+    if fraction.len() > exponent {
+      return Err(ParseMoneyError::TooPrecise(currency));
+    }
+
+    let whole = if whole.is_empty() {
+      0
+    } else {
+      whole
+        .parse::<u64>()
+        .map_err(|_| ParseMoneyError::Overflow)?
+    };
+    let fraction = if fraction.is_empty() {
+      0
+    } else {
+      format!("{fraction:0<exponent$}")
+        .parse::<u64>()
+        .map_err(|_| ParseMoneyError::Invalid)?
+    };
+
+    whole
+      .checked_mul(currency.minor_units_per_major())
+      .and_then(|m| m.checked_add(fraction))
+      .map(|m| Self::from_minor_units(m, currency))
+      .ok_or(ParseMoneyError::Overflow)
+  }
+
+  /// Renders the amount as a plain decimal number in major units, without a
+  /// symbol or separators, e.g. `"1250.00"`. This round-trips through
+  /// [`Money::parse`].
+  #[must_use]
+  pub fn to_decimal_string(self) -> String {
+    let (whole, fraction) = self.split();
+    match fraction {
+      Some(fraction) => format!("{whole}.{fraction}"),
+      None => whole.to_string(),
+    }
+  }
+
+  /// Formats the amount for display in the given BCP 47 locale, e.g.
+  /// `"$1,250.00"` for `en-US` or `"1.250,00 €"` for `de-DE`. Unsupported
+  /// locales fall back to English conventions.
+  #[must_use]
+  pub fn format_locale(&self, locale: &str) -> String {
+    let conventions = LocaleConventions::for_locale(locale);
+    let (whole, fraction) = self.split();
+
+    let mut amount = group_digits(&whole.to_string(), conventions.group);
+    if let Some(fraction) = fraction {
+      amount.push(conventions.decimal);
+      amount.push_str(&fraction);
+    }
+
+    match (self.currency.symbol(), conventions.symbol_after) {
+      (Some(symbol), false) => format!("{symbol}{amount}"),
+      (Some(symbol), true) => format!("{amount}\u{a0}{symbol}"),
+      (None, false) => format!("{}\u{a0}{amount}", self.currency.code()),
+      (None, true) => format!("{amount}\u{a0}{}", self.currency.code()),
+    }
+  }
+
+  /// Splits the amount into its whole part and zero-padded fractional digits.
+  fn split(&self) -> (u64, Option<String>) {
+    let per_major = self.currency.minor_units_per_major();
+    let whole = self.minor_units / per_major;
+    let exponent = self.currency.exponent() as usize;
+    let fraction = (exponent > 0)
+      .then(|| format!("{:0exponent$}", self.minor_units % per_major));
+    (whole, fraction)
+  }
+}
+
+/// Compares amounts of the same currency. Amounts in different currencies
+/// are incomparable.
+impl PartialOrd for Money {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    (self.currency == other.currency)
+      .then(|| self.minor_units.cmp(&other.minor_units))
+  }
+}
+
+/// Parses an amount followed by a currency code, e.g. `"12.50 EUR"`.
+impl FromStr for Money {
+  type Err = ParseMoneyError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let (amount, code) =
+      s.trim().rsplit_once(' ').ok_or(ParseMoneyError::Invalid)?;
+    let currency = code.parse().map_err(ParseMoneyError::UnknownCurrency)?;
+    Self::parse(amount, currency)
+  }
+}
+
+// uses the display impl
+impl fmt::Debug for Money {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_tuple("Money").field(&self.to_string()).finish()
+  }
+}
+
+/// Formats the amount with English conventions.
+impl fmt::Display for Money {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(&self.format_locale("en"))
+  }
+}
+
+/// The locale that amounts are formatted in for a request, as negotiated
+/// from its `Accept-Language` header. Defaults to English.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Locale(pub String);
+
+impl Locale {
+  /// Picks the most preferred language of an `Accept-Language` header value,
+  /// e.g. `"de-DE"` from `"en;q=0.8, de-DE"`. Wildcards, malformed entries
+  /// and refused languages are skipped; ties go to the earlier language.
+  #[must_use]
+  pub fn from_accept_language(header: &str) -> Option<Self> {
+    let mut best: Option<(&str, f32)> = None;
+    for entry in header.split(',') {
+      let mut params = entry.split(';');
+      let tag = params.next().unwrap_or_default().trim();
+      if tag.is_empty()
+        || !tag.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+      {
+        continue;
+      }
+      let quality = match params.find_map(|p| p.trim().strip_prefix("q=")) {
+        Some(q) => match q.trim().parse::<f32>() {
+          Ok(q) => q,
+          Err(_) => continue,
+        },
+        None => 1.0,
+      };
+      if quality > 0.0 && best.is_none_or(|(_, b)| quality > b) {
+        best = Some((tag, quality));
+      }
+    }
+    best.map(|(tag, _)| Self(tag.to_owned()))
+  }
+}
+
+impl Default for Locale {
+  fn default() -> Self { Self("en".to_owned()) }
+}
+
+/// The number formatting conventions of a locale.
+struct LocaleConventions {
+  decimal:      char,
+  group:        char,
+  symbol_after: bool,
+}
+
+impl LocaleConventions {
+  fn for_locale(locale: &str) -> Self {
+    let locale = locale.to_ascii_lowercase().replace('_', "-");
+    let language = locale.split('-').next().unwrap_or_default();
+    match (language, locale.as_str()) {
+      (_, "de-ch") => Self {
+        decimal:      '.',
+        group:        '\u{2019}',
+        symbol_after: false,
+      },
+      ("de" | "es" | "it" | "nl" | "pt" | "da" | "id" | "tr", _) => Self {
+        decimal:      ',',
+        group:        '.',
+        symbol_after: true,
+      },
+      ("fr" | "sv" | "nb" | "no" | "fi" | "pl" | "cs" | "ru" | "uk", _) => {
+        Self {
+          decimal:      ',',
+          group:        '\u{a0}',
+          symbol_after: true,
+        }
+      }
+      _ => Self {
+        decimal:      '.',
+        group:        ',',
+        symbol_after: false,
+      },
+    }
+  }
+}
+
+/// Inserts a group separator every three digits from the right.
+fn group_digits(digits: &str, separator: char) -> String {
+  let mut output = String::with_capacity(digits.len() + digits.len() / 3);
+  for (i, c) in digits.chars().enumerate() {
+    if i > 0 && (digits.len() - i).is_multiple_of(3) {
+      output.push(separator);
+    }
+    output.push(c);
+  }
+  output
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parse_is_exact() {
+    let usd = |s| Money::parse(s, Currency::Usd);
+    assert_eq!(usd("0.29").unwrap().minor_units(), 29);
+    assert_eq!(usd("$1,250.5").unwrap().minor_units(), 125_050);
+    assert_eq!(usd("0.290").unwrap().minor_units(), 29);
+    assert_eq!(
+      usd("0.295"),
+      Err(ParseMoneyError::TooPrecise(Currency::Usd))
+    );
+    assert_eq!(usd("-1"), Err(ParseMoneyError::Negative));
+    assert_eq!(usd(""), Err(ParseMoneyError::Empty));
+    assert_eq!(usd("1.2.3"), Err(ParseMoneyError::Invalid));
+    assert_eq!(
+      Money::parse("1.5", Currency::Jpy),
+      Err(ParseMoneyError::TooPrecise(Currency::Jpy))
+    );
+  }
+
+  #[test]
+  fn decimal_string_round_trips() {
+    for currency in Currency::ALL {
+      let money = Money::from_minor_units(123_456, *currency);
+      assert_eq!(
+        Money::parse(&money.to_decimal_string(), *currency),
+        Ok(money)
+      );
+    }
+  }
+
+  #[test]
+  fn locale_is_the_most_preferred_language() {
+    let locale = |h| Locale::from_accept_language(h).map(|l| l.0);
+    assert_eq!(locale("de-DE"), Some("de-DE".to_owned()));
+    assert_eq!(
+      locale("en;q=0.8, fr-CH, de;q=0.9"),
+      Some("fr-CH".to_owned())
+    );
+    assert_eq!(locale("*, nl;q=0.5"), Some("nl".to_owned()));
+    assert_eq!(locale("sv;q=0, da;q=x"), None);
+    assert_eq!(locale(""), None);
+  }
+
+  #[test]
+  fn formats_by_locale() {
+    let money = Money::from_minor_units(123_456_780, Currency::Eur);
+    assert_eq!(money.format_locale("en-US"), "€1,234,567.80");
+    assert_eq!(money.format_locale("de-DE"), "1.234.567,80\u{a0}€");
+    assert_eq!(
+      Money::from_minor_units(1_500, Currency::Kwd).to_string(),
+      "KWD\u{a0}1.500"
+    );
+    assert_eq!(
+      "12.50 EUR".parse::<Money>(),
+      Ok(Money::from_minor_units(1_250, Currency::Eur))
+    );
+  }
+}
@@ -1,7 +1,12 @@
+use std::collections::BTreeMap;
+
 use model::{Model, RecordId};
 use serde::{Deserialize, Serialize};
 
-use crate::ImageRecordId;
+use crate::{
+  EitherSlug, ImageRecordId, LicenseOffer, LicenseTier, Money, StrictSlug,
+  Timestamp, UserRecordId, Versioned,
+};
 
 /// The table name for [`Photo`] records.
 pub const PHOTO_TABLE_NAME: &str = "photo";
@@ -11,11 +16,43 @@ pub type PhotoRecordId = RecordId<Photo>;
 
 /// The domain model for a photo stored on the platform.
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(from = "StoredPhoto")]
 pub struct Photo {
   /// The photo's ID.
-  pub id:        PhotoRecordId,
+  pub id:              PhotoRecordId,
   /// The photo's artifacts.
-  pub artifacts: PhotoImages,
+  pub artifacts:       PhotoImages,
+  /// Prices for this photo that override its group's default photo price,
+  /// by license tier.
+  #[serde(default)]
+  pub price_overrides: BTreeMap<LicenseTier, Money>,
+  /// When the photo was created.
+  #[serde(default)]
+  pub created_at:      Timestamp,
+  /// When the photo was last updated.
+  #[serde(default)]
+  pub updated_at:      Timestamp,
+  /// The schema version the photo was written at.
+  #[serde(default)]
+  pub schema_version:  u32,
+  /// The user who uploaded the photo, if it was stored after uploaders were
+  /// recorded.
+  #[serde(default)]
+  pub uploader:        Option<UserRecordId>,
+}
+
+impl Photo {
+  /// The price of this photo alone under a license offer, if it's sold
+  /// alone. Overrides in a different currency than the offer are ignored.
+  #[must_use]
+  pub fn price(&self, offer: &LicenseOffer) -> Option<Money> {
+    self
+      .price_overrides
+      .get(&offer.tier)
+      .filter(|p| p.currency() == offer.group_price.currency())
+      .copied()
+      .or(offer.photo_price)
+  }
 }
 
 /// The [`Image`](crate::Image)s for a [`Photo`].
@@ -28,7 +65,14 @@ pub struct PhotoImages {
 }
 
 impl Model for Photo {
-  const INDICES: &'static [(&'static str, model::SlugFieldGetter<Self>)] = &[];
+  const INDICES: &'static [(&'static str, model::SlugFieldGetter<Self>)] = &[
+    ("original", |photo| {
+      EitherSlug::Strict(StrictSlug::new(photo.artifacts.original.to_string()))
+    }),
+    ("thumbnail", |photo| {
+      EitherSlug::Strict(StrictSlug::new(photo.artifacts.thumbnail.to_string()))
+    }),
+  ];
   const TABLE_NAME: &'static str = PHOTO_TABLE_NAME;
   const UNIQUE_INDICES: &'static [(
     &'static str,
@@ -43,13 +87,83 @@ impl Model for Photo {
 pub struct PhotoCreateRequest {
   /// The photo's artifacts.
   pub artifacts: PhotoImages,
+  /// The user who uploaded the photo.
+  pub uploader:  UserRecordId,
 }
 
 impl From<PhotoCreateRequest> for Photo {
   fn from(input: PhotoCreateRequest) -> Self {
+    let now = chrono::Utc::now();
     Self {
-      id:        PhotoRecordId::default(),
-      artifacts: input.artifacts,
+      id:              PhotoRecordId::default(),
+      artifacts:       input.artifacts,
+      price_overrides: BTreeMap::new(),
+      created_at:      now,
+      updated_at:      now,
+      schema_version:  Self::SCHEMA_VERSION,
+      uploader:        Some(input.uploader),
+    }
+  }
+}
+
+/// The layouts a [`Photo`] has been stored in, newest first. A record is
+/// decoded as the first layout it fits, and keeps the schema version it was
+/// written at so that the migration runner re-writes it.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredPhoto {
+  Current(CurrentPhoto),
+  Timestamped(TimestampedPhoto),
+}
+
+/// The current layout. Fields added after the original layout default, so
+/// this also decodes photos from before timestamps.
+#[derive(Deserialize)]
+struct CurrentPhoto {
+  id:              PhotoRecordId,
+  artifacts:       PhotoImages,
+  #[serde(default)]
+  price_overrides: BTreeMap<LicenseTier, Money>,
+  #[serde(default)]
+  created_at:      Timestamp,
+  #[serde(default)]
+  updated_at:      Timestamp,
+  #[serde(default)]
+  schema_version:  u32,
+  #[serde(default)]
+  uploader:        Option<UserRecordId>,
+}
+
+/// The layout with timestamps, from before price overrides.
+#[derive(Deserialize)]
+struct TimestampedPhoto {
+  id:         PhotoRecordId,
+  artifacts:  PhotoImages,
+  created_at: Timestamp,
+  updated_at: Timestamp,
+}
+
+impl From<StoredPhoto> for Photo {
+  fn from(stored: StoredPhoto) -> Self {
+    match stored {
+      StoredPhoto::Current(photo) => Self {
+        id:              photo.id,
+        artifacts:       photo.artifacts,
+        price_overrides: photo.price_overrides,
+        created_at:      photo.created_at,
+        updated_at:      photo.updated_at,
+        schema_version:  photo.schema_version,
+        uploader:        photo.uploader,
+      },
+      StoredPhoto::Timestamped(photo) => Self {
+        id:              photo.id,
+        artifacts:       photo.artifacts,
+        price_overrides: BTreeMap::new(),
+        created_at:      photo.created_at,
+        updated_at:      photo.updated_at,
+        schema_version:  0,
+        uploader:        None,
+      },
     }
   }
 }
@@ -1,41 +1,242 @@
 mod query;
 
+use std::fmt;
+
 use model::{Model, RecordId};
 use serde::{Deserialize, Serialize};
 
 pub use self::query::*;
 use crate::{
-  price::UsdPriceNaive, EitherSlug, PhotoRecordId, StrictSlug, UserRecordId,
+  Currency, EitherSlug, ImageRecordId, LicenseOffer, LicenseTier, Money,
+  PhotoRecordId, StrictSlug, Timestamp, UserRecordId, Versioned,
 };
 
 /// The table name for [`PhotoGroup`] records.
 pub const PHOTO_GROUP_TABLE_NAME: &str = "photo_group";
 
-/// The minimum allowed price for the usage rights to a photo group.
-pub const PHOTO_GROUP_USAGE_RIGHTS_MINIMUM_PRICE: UsdPriceNaive =
-  UsdPriceNaive::new_from_f32(0.1);
+/// The minimum allowed price for the usage rights to a photo group, in the
+/// given currency: a tenth of its major unit, or one minor unit if that's
+/// larger.
+#[must_use]
+pub const fn photo_group_usage_rights_minimum_price(
+  currency: Currency,
+) -> Money {
+  let minor_units = currency.minor_units_per_major() / 10;
+  Money::from_minor_units(
+    if minor_units == 0 { 1 } else { minor_units },
+    currency,
+  )
+}
 
 /// An alias for [`RecordId<PhotoGroup>`].
 pub type PhotoGroupRecordId = RecordId<PhotoGroup>;
 
 /// The domain model for a photo group.
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(from = "StoredPhotoGroup")]
 pub struct PhotoGroup {
   /// The photo group's id.
-  pub id:     PhotoGroupRecordId,
+  pub id:             PhotoGroupRecordId,
   /// The photo group's vendor.
-  pub vendor: UserRecordId,
+  pub vendor:         UserRecordId,
   /// The photos included in the group.
-  pub photos: Vec<PhotoRecordId>,
+  pub photos:         Vec<PhotoRecordId>,
   /// The configuration for the group.
-  pub config: PhotoGroupConfig,
+  pub config:         PhotoGroupConfig,
+  /// The descriptive metadata for the group.
+  #[serde(default)]
+  pub meta:           PhotoGroupMetadata,
+  /// When the photo group was created.
+  #[serde(default)]
+  pub created_at:     Timestamp,
+  /// When the photo group was last updated.
+  #[serde(default)]
+  pub updated_at:     Timestamp,
+  /// When the photo group was archived, if it has been. Archived groups are
+  /// hidden from everyone but their vendor.
+  #[serde(default)]
+  pub archived_at:    Option<Timestamp>,
+  /// The schema version the photo group was written at.
+  #[serde(default)]
+  pub schema_version: u32,
+}
+
+impl PhotoGroup {
+  /// Whether the photo group has been archived.
+  #[must_use]
+  pub fn is_archived(&self) -> bool { self.archived_at.is_some() }
+}
+
+/// The maximum length of a [`PhotoGroup`]'s title or location, in characters.
+pub const PHOTO_GROUP_MAX_LABEL_LENGTH: usize = 120;
+/// The maximum length of a [`PhotoGroup`]'s description, in characters.
+pub const PHOTO_GROUP_MAX_DESCRIPTION_LENGTH: usize = 10_000;
+/// The maximum number of tags on a [`PhotoGroup`].
+pub const PHOTO_GROUP_MAX_TAGS: usize = 20;
+
+/// Descriptive metadata for a [`PhotoGroup`].
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PhotoGroupMetadata {
+  /// The group's title.
+  pub title:       Option<String>,
+  /// The group's description, as Markdown.
+  pub description: Option<String>,
+  /// The date of the event the photos were taken at.
+  pub event_date:  Option<chrono::NaiveDate>,
+  /// A label for where the photos were taken.
+  pub location:    Option<String>,
+  /// Free-form tags for the group.
+  pub tags:        Vec<String>,
+}
+
+impl PhotoGroupMetadata {
+  /// Trims whitespace, drops empty fields, truncates overlong fields, and
+  /// lowercases and deduplicates tags.
+  #[must_use]
+  pub fn normalize(self) -> Self {
+    fn clean(value: Option<String>, max_length: usize) -> Option<String> {
+      value
+        .map(|v| v.trim().chars().take(max_length).collect::<String>())
+        .filter(|v| !v.is_empty())
+    }
+
+    let mut tags = Vec::new();
+    for tag in self.tags {
+      let tag = tag.trim().to_lowercase();
+      if !tag.is_empty() && !tags.contains(&tag) {
+        tags.push(tag);
+      }
+    }
+    tags.truncate(PHOTO_GROUP_MAX_TAGS);
+
+    Self {
+      title: clean(self.title, PHOTO_GROUP_MAX_LABEL_LENGTH),
+      description: clean(self.description, PHOTO_GROUP_MAX_DESCRIPTION_LENGTH),
+      event_date: self.event_date,
+      location: clean(self.location, PHOTO_GROUP_MAX_LABEL_LENGTH),
+      tags,
+    }
+  }
 }
 
 /// Configuration for a [`PhotoGroup`].
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(from = "PhotoGroupConfigRepr", into = "PhotoGroupConfigRepr")]
 pub struct PhotoGroupConfig {
-  /// The USD price of usage rights for all photos in the group.
-  pub usage_rights_price: UsdPriceNaive,
+  /// The license tiers the group is offered under, in display order.
+  pub licenses: Vec<LicenseOffer>,
+}
+
+/// The serialized form of [`PhotoGroupConfig`]. Configs from before license
+/// tiers had a single `usage_rights_price`, which is read as a
+/// [`LicenseTier::PersonalDigital`] offer for the whole group.
+#[derive(Serialize, Deserialize)]
+struct PhotoGroupConfigRepr {
+  #[serde(default)]
+  usage_rights_price: Option<Money>,
+  #[serde(default)]
+  licenses:           Vec<LicenseOffer>,
+}
+
+impl From<PhotoGroupConfigRepr> for PhotoGroupConfig {
+  fn from(repr: PhotoGroupConfigRepr) -> Self {
+    let mut licenses = repr.licenses;
+    if let (true, Some(price)) = (licenses.is_empty(), repr.usage_rights_price)
+    {
+      licenses.push(LicenseOffer {
+        tier:        LicenseTier::PersonalDigital,
+        group_price: price,
+        photo_price: None,
+      });
+    }
+    Self { licenses }
+  }
+}
+
+impl From<PhotoGroupConfig> for PhotoGroupConfigRepr {
+  fn from(config: PhotoGroupConfig) -> Self {
+    Self {
+      usage_rights_price: None,
+      licenses:           config.licenses,
+    }
+  }
+}
+
+/// An error that occurs when a [`PhotoGroupConfig`] is invalid.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PhotoGroupConfigError {
+  /// The group isn't offered under any license.
+  NoLicenses,
+  /// A license tier was offered more than once.
+  DuplicateTier(LicenseTier),
+  /// The prices weren't all in the same currency.
+  MixedCurrencies,
+  /// A price was below the minimum.
+  PriceTooLow(LicenseTier),
+}
+
+impl fmt::Display for PhotoGroupConfigError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::NoLicenses => write!(f, "no licenses are offered"),
+      Self::DuplicateTier(tier) => {
+        write!(f, "{tier} is offered more than once")
+      }
+      Self::MixedCurrencies => write!(f, "prices are in different currencies"),
+      Self::PriceTooLow(tier) => {
+        write!(f, "a {tier} price is below the minimum")
+      }
+    }
+  }
+}
+
+impl std::error::Error for PhotoGroupConfigError {}
+
+impl PhotoGroupConfig {
+  /// The offer for a license tier, if the group is offered under it.
+  #[must_use]
+  pub fn offer(&self, tier: LicenseTier) -> Option<&LicenseOffer> {
+    self.licenses.iter().find(|o| o.tier == tier)
+  }
+
+  /// The currency the group is priced in.
+  #[must_use]
+  pub fn currency(&self) -> Option<Currency> {
+    self.licenses.first().map(|o| o.group_price.currency())
+  }
+
+  /// The lowest price for the whole group, across license tiers.
+  #[must_use]
+  pub fn lowest_group_price(&self) -> Option<Money> {
+    self
+      .licenses
+      .iter()
+      .map(|o| o.group_price)
+      .min_by_key(Money::minor_units)
+  }
+
+  /// Checks that at least one license is offered, each tier at most once,
+  /// and that every price is in one currency and above the minimum.
+  pub fn validate(&self) -> Result<(), PhotoGroupConfigError> {
+    let currency = self.currency().ok_or(PhotoGroupConfigError::NoLicenses)?;
+    let minimum = photo_group_usage_rights_minimum_price(currency);
+
+    for (i, offer) in self.licenses.iter().enumerate() {
+      if self.licenses[..i].iter().any(|o| o.tier == offer.tier) {
+        return Err(PhotoGroupConfigError::DuplicateTier(offer.tier));
+      }
+      for price in std::iter::once(offer.group_price).chain(offer.photo_price) {
+        if price.currency() != currency {
+          return Err(PhotoGroupConfigError::MixedCurrencies);
+        }
+        if price < minimum {
+          return Err(PhotoGroupConfigError::PriceTooLow(offer.tier));
+        }
+      }
+    }
+
+    Ok(())
+  }
 }
 
 impl Model for PhotoGroup {
@@ -52,6 +253,104 @@ impl Model for PhotoGroup {
   fn id(&self) -> PhotoGroupRecordId { self.id }
 }
 
+/// An edit to an existing [`PhotoGroup`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PhotoGroupEdit {
+  /// Replace the group's configuration.
+  SetConfig(PhotoGroupConfig),
+  /// Replace the group's descriptive metadata.
+  SetMetadata(PhotoGroupMetadata),
+  /// Append new photos to the group, created from the given
+  /// [`Image`](crate::Image)s.
+  AddImages(Vec<ImageRecordId>),
+  /// Remove a photo from the group.
+  RemovePhoto(PhotoRecordId),
+  /// Reorder the group's photos. Must contain exactly the group's current
+  /// photos.
+  ReorderPhotos(Vec<PhotoRecordId>),
+  /// Set or clear a photo's price override for a license tier.
+  SetPhotoPrice {
+    /// The photo to price.
+    photo: PhotoRecordId,
+    /// The license tier to price it under.
+    tier:  LicenseTier,
+    /// The price, or `None` to use the tier's default photo price.
+    price: Option<Money>,
+  },
+}
+
+/// The layouts a [`PhotoGroup`] has been stored in, newest first. A record
+/// is decoded as the first layout it fits, and keeps the schema version it
+/// was written at so that the migration runner re-writes it.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredPhotoGroup {
+  Current(CurrentPhotoGroup),
+  Timestamped(TimestampedPhotoGroup),
+}
+
+/// The current layout. Fields added after the original layout default, so
+/// this also decodes photo groups from before timestamps.
+#[derive(Deserialize)]
+struct CurrentPhotoGroup {
+  id:             PhotoGroupRecordId,
+  vendor:         UserRecordId,
+  photos:         Vec<PhotoRecordId>,
+  config:         PhotoGroupConfig,
+  #[serde(default)]
+  meta:           PhotoGroupMetadata,
+  #[serde(default)]
+  created_at:     Timestamp,
+  #[serde(default)]
+  updated_at:     Timestamp,
+  #[serde(default)]
+  archived_at:    Option<Timestamp>,
+  #[serde(default)]
+  schema_version: u32,
+}
+
+/// The layout with timestamps and, later, archival, from before metadata.
+#[derive(Deserialize)]
+struct TimestampedPhotoGroup {
+  id:          PhotoGroupRecordId,
+  vendor:      UserRecordId,
+  photos:      Vec<PhotoRecordId>,
+  config:      PhotoGroupConfig,
+  created_at:  Timestamp,
+  updated_at:  Timestamp,
+  #[serde(default)]
+  archived_at: Option<Timestamp>,
+}
+
+impl From<StoredPhotoGroup> for PhotoGroup {
+  fn from(stored: StoredPhotoGroup) -> Self {
+    match stored {
+      StoredPhotoGroup::Current(photo_group) => Self {
+        id:             photo_group.id,
+        vendor:         photo_group.vendor,
+        photos:         photo_group.photos,
+        config:         photo_group.config,
+        meta:           photo_group.meta,
+        created_at:     photo_group.created_at,
+        updated_at:     photo_group.updated_at,
+        archived_at:    photo_group.archived_at,
+        schema_version: photo_group.schema_version,
+      },
+      StoredPhotoGroup::Timestamped(photo_group) => Self {
+        id:             photo_group.id,
+        vendor:         photo_group.vendor,
+        photos:         photo_group.photos,
+        config:         photo_group.config,
+        meta:           PhotoGroupMetadata::default(),
+        created_at:     photo_group.created_at,
+        updated_at:     photo_group.updated_at,
+        archived_at:    photo_group.archived_at,
+        schema_version: 0,
+      },
+    }
+  }
+}
+
 /// A request to create a new [`PhotoGroup`].
 #[derive(Debug)]
 pub struct PhotoGroupCreateRequest {
@@ -61,15 +360,23 @@ pub struct PhotoGroupCreateRequest {
   pub photos: Vec<PhotoRecordId>,
   /// The configuration for the group.
   pub config: PhotoGroupConfig,
+  /// The descriptive metadata for the group.
+  pub meta:   PhotoGroupMetadata,
 }
 
 impl From<PhotoGroupCreateRequest> for PhotoGroup {
   fn from(input: PhotoGroupCreateRequest) -> Self {
+    let now = chrono::Utc::now();
     Self {
-      id:     PhotoGroupRecordId::default(),
-      vendor: input.vendor,
-      photos: input.photos,
-      config: input.config,
+      id:             PhotoGroupRecordId::default(),
+      vendor:         input.vendor,
+      photos:         input.photos,
+      config:         input.config,
+      meta:           input.meta,
+      created_at:     now,
+      updated_at:     now,
+      schema_version: Self::SCHEMA_VERSION,
+      archived_at:    None,
     }
   }
 }
@@ -1,13 +1,27 @@
 use serde::{Deserialize, Serialize};
 
 use super::*;
-use crate::PublicUser;
+use crate::{Image, Photo, PublicUser};
 
 /// A query containing all the data relating to a given `PhotoGroup`.
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct PhotoGroupFullQuery {
   /// The photo group being queried for.
   pub photo_group: PhotoGroup,
+  /// The photos in the group, in order.
+  pub photos:      Vec<Photo>,
+  /// The thumbnail images of the photos, in the same order as `photos`.
+  pub thumbnails:  Vec<Image>,
   /// The public user data of the photo group vendor.
   pub vendor_data: PublicUser,
 }
+
+impl PhotoGroupFullQuery {
+  /// Iterates over the photos in the group, in order, along with their
+  /// thumbnail images.
+  pub fn photos_with_thumbnails(
+    &self,
+  ) -> impl Iterator<Item = (&Photo, &Image)> {
+    self.photos.iter().zip(&self.thumbnails)
+  }
+}
@@ -0,0 +1,514 @@
+use model::Model;
+
+use crate::{Artifact, Image, Photo, PhotoGroup, Timestamped, User};
+
+/// A model whose stored records are tagged with the schema version they were
+/// written at.
+///
+/// Records are first decoded through the layouts the model has been stored
+/// in, so that records stored positionally before a field was inserted still
+/// decode; fields appended to a layout only need a serde default. They are
+/// then upcast one version at a time to [`Versioned::SCHEMA_VERSION`].
+/// Records written before versioning existed read as version 0.
+pub trait Versioned: Model {
+  /// The current schema version.
+  const SCHEMA_VERSION: u32;
+
+  /// The schema version the record was written at.
+  fn schema_version(&self) -> u32;
+  /// Sets the schema version of the record.
+  fn set_schema_version(&mut self, version: u32);
+  /// Upcasts the record from schema version `from` to `from + 1`.
+  fn upcast_from(&mut self, from: u32);
+
+  /// Whether the record was written at an older schema version.
+  fn is_outdated(&self) -> bool { self.schema_version() < Self::SCHEMA_VERSION }
+
+  /// Upcasts the record to the current schema version. Returns whether
+  /// anything changed.
+  fn upcast(&mut self) -> bool {
+    let from = self.schema_version();
+    for version in from..Self::SCHEMA_VERSION {
+      self.upcast_from(version);
+      self.set_schema_version(version + 1);
+    }
+    from < Self::SCHEMA_VERSION
+  }
+}
+
+macro_rules! impl_schema_version_accessors {
+  () => {
+    fn schema_version(&self) -> u32 { self.schema_version }
+
+    fn set_schema_version(&mut self, version: u32) {
+      self.schema_version = version;
+    }
+  };
+}
+
+impl Versioned for Artifact {
+  // 1: created and updated timestamps
+  const SCHEMA_VERSION: u32 = 1;
+
+  impl_schema_version_accessors!();
+
+  fn upcast_from(&mut self, from: u32) {
+    if from == 0 {
+      self.backfill_timestamps();
+    }
+  }
+}
+
+impl Versioned for Image {
+  // 1: created and updated timestamps
+  // 2: uploaders; images stored before read as having none
+  const SCHEMA_VERSION: u32 = 2;
+
+  impl_schema_version_accessors!();
+
+  fn upcast_from(&mut self, from: u32) {
+    if from == 0 {
+      self.backfill_timestamps();
+    }
+  }
+}
+
+impl Versioned for Photo {
+  // 1: created and updated timestamps
+  // 2: uploaders; photos stored before read as having none
+  // 3: indexed by original and thumbnail image; re-writing builds the entries
+  const SCHEMA_VERSION: u32 = 3;
+
+  impl_schema_version_accessors!();
+
+  fn upcast_from(&mut self, from: u32) {
+    if from == 0 {
+      self.backfill_timestamps();
+    }
+  }
+}
+
+impl Versioned for PhotoGroup {
+  // 1: created and updated timestamps
+  // 2: license offers priced in `Money`, replacing the USD-cents
+  //    `usage_rights_price`, which is converted as it's read; only groups
+  //    below this version are re-written, so the conversion is persisted once
+  const SCHEMA_VERSION: u32 = 2;
+
+  impl_schema_version_accessors!();
+
+  fn upcast_from(&mut self, from: u32) {
+    if from == 0 {
+      self.backfill_timestamps();
+    }
+  }
+}
+
+impl Versioned for User {
+  // 1: created and updated timestamps
+  // 2: email verification state; existing users start unverified
+  // 3: pending password resets
+  // 4: TOTP second factors
+  // 5: passkeys, and passkey-only accounts
+  // 6: OpenID Connect identities
+  // 7: login links, and accounts created by them
+  // 8: the fields of versions 2 to 7 moved after `schema_version`, where
+  //    later fields are appended; users in the older layouts are decoded
+  //    through them, and re-writing stores the new one
+  // 9: pending reauthentication links
+  const SCHEMA_VERSION: u32 = 9;
+
+  impl_schema_version_accessors!();
+
+  fn upcast_from(&mut self, from: u32) {
+    if from == 0 {
+      self.backfill_timestamps();
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::BTreeMap;
+
+  use serde::{de::DeserializeOwned, Serialize};
+
+  use super::*;
+  use crate::{
+    Currency, EmailAddress, EmailToken, HumanName, ImageRecordId, LicenseOffer,
+    LicenseTier, Money, OidcIdentity, Passkey, PasswordHash, PhotoGroupConfig,
+    PhotoGroupMetadata, PhotoGroupRecordId, PhotoImages, PhotoRecordId,
+    Timestamp, TotpEnrollment, UserAuthCredentials, UserRecordId,
+  };
+
+  /// Encodes a record positionally, as the database stores it, and decodes
+  /// it as `M`.
+  fn decode<M: DeserializeOwned>(record: &impl Serialize) -> M {
+    rmp_serde::from_slice(&rmp_serde::to_vec(record).unwrap()).unwrap()
+  }
+
+  fn images() -> PhotoImages {
+    PhotoImages {
+      original:  ImageRecordId::new(),
+      thumbnail: ImageRecordId::new(),
+    }
+  }
+
+  fn config() -> PhotoGroupConfig {
+    PhotoGroupConfig {
+      licenses: vec![LicenseOffer {
+        tier:        LicenseTier::PersonalDigital,
+        group_price: Money::from_minor_units(2500, Currency::Usd),
+        photo_price: None,
+      }],
+    }
+  }
+
+  #[test]
+  fn photos_decode_from_every_layout() {
+    #[derive(Serialize)]
+    struct Original {
+      id:        PhotoRecordId,
+      artifacts: PhotoImages,
+    }
+    #[derive(Serialize)]
+    struct Timestamped {
+      id:         PhotoRecordId,
+      artifacts:  PhotoImages,
+      created_at: Timestamp,
+      updated_at: Timestamp,
+    }
+
+    let (id, artifacts, now) =
+      (PhotoRecordId::new(), images(), chrono::Utc::now());
+
+    let photo: Photo = decode(&Original {
+      id,
+      artifacts: artifacts.clone(),
+    });
+    assert_eq!(photo.artifacts, artifacts);
+    assert_eq!(photo.schema_version, 0);
+
+    let mut photo: Photo = decode(&Timestamped {
+      id,
+      artifacts: artifacts.clone(),
+      created_at: now,
+      updated_at: now,
+    });
+    assert_eq!(photo.created_at, now);
+    assert!(photo.price_overrides.is_empty());
+    assert!(photo.upcast());
+
+    photo.price_overrides = BTreeMap::from([(
+      LicenseTier::PersonalDigital,
+      Money::from_minor_units(500, Currency::Usd),
+    )]);
+    assert_eq!(decode::<Photo>(&photo), photo);
+    let json = serde_json::to_string(&photo).unwrap();
+    assert_eq!(serde_json::from_str::<Photo>(&json).unwrap(), photo);
+  }
+
+  #[test]
+  fn photo_groups_decode_from_every_layout() {
+    #[derive(Serialize)]
+    struct Original {
+      id:     PhotoGroupRecordId,
+      vendor: UserRecordId,
+      photos: Vec<PhotoRecordId>,
+      config: PhotoGroupConfig,
+    }
+    #[derive(Serialize)]
+    struct Archivable {
+      id:          PhotoGroupRecordId,
+      vendor:      UserRecordId,
+      photos:      Vec<PhotoRecordId>,
+      config:      PhotoGroupConfig,
+      created_at:  Timestamp,
+      updated_at:  Timestamp,
+      archived_at: Option<Timestamp>,
+    }
+
+    let (id, vendor, photos) =
+      (PhotoGroupRecordId::new(), UserRecordId::new(), vec![
+        PhotoRecordId::new(),
+      ]);
+    let now = chrono::Utc::now();
+
+    let photo_group: PhotoGroup = decode(&Original {
+      id,
+      vendor,
+      photos: photos.clone(),
+      config: config(),
+    });
+    assert_eq!(photo_group.config, config());
+    assert_eq!(photo_group.schema_version, 0);
+
+    let mut photo_group: PhotoGroup = decode(&Archivable {
+      id,
+      vendor,
+      photos,
+      config: config(),
+      created_at: now,
+      updated_at: now,
+      archived_at: Some(now),
+    });
+    assert_eq!(photo_group.archived_at, Some(now));
+    assert_eq!(photo_group.meta, PhotoGroupMetadata::default());
+    assert!(photo_group.upcast());
+
+    photo_group.meta.title = Some("Regatta".to_owned());
+    assert_eq!(decode::<PhotoGroup>(&photo_group), photo_group);
+    let json = serde_json::to_string(&photo_group).unwrap();
+    assert_eq!(
+      serde_json::from_str::<PhotoGroup>(&json).unwrap(),
+      photo_group
+    );
+  }
+
+  /// The parts of a user record, in every field any layout has had.
+  struct UserParts {
+    id:              UserRecordId,
+    name:            HumanName,
+    email:           EmailAddress,
+    auth:            UserAuthCredentials,
+    now:             Timestamp,
+    token:           Option<EmailToken>,
+    totp:            Option<TotpEnrollment>,
+    passkeys:        Vec<Passkey>,
+    oidc_identities: Vec<OidcIdentity>,
+  }
+
+  fn user_parts() -> UserParts {
+    let email = EmailAddress::try_new("test@example.com").unwrap();
+    let now = chrono::Utc::now();
+    UserParts {
+      id: UserRecordId::new(),
+      name: HumanName::try_new("Test User").unwrap(),
+      auth: UserAuthCredentials::EmailAndPassword {
+        email:         email.clone(),
+        password_hash: PasswordHash("not a real hash".to_owned()),
+      },
+      email,
+      now,
+      token: Some(EmailToken {
+        secret_hash: vec![1],
+        expires_at:  now,
+        sent_at:     now,
+      }),
+      totp: Some(TotpEnrollment {
+        secret:               vec![2],
+        confirmed_at:         Some(now),
+        last_used_step:       3,
+        recovery_code_hashes: Vec::new(),
+      }),
+      passkeys: vec![Passkey {
+        credential_id: vec![4],
+        public_key:    vec![5],
+        sign_count:    6,
+        created_at:    now,
+        last_used_at:  None,
+      }],
+      oidc_identities: vec![OidcIdentity {
+        issuer:    "https://accounts.example.com".to_owned(),
+        subject:   "123".to_owned(),
+        linked_at: now,
+      }],
+    }
+  }
+
+  // layouts are written out as tuples, which encode like the structs did
+
+  #[test]
+  fn users_decode_from_layouts_up_to_password_resets() {
+    let UserParts {
+      id,
+      name,
+      email,
+      auth,
+      now,
+      token,
+      ..
+    } = user_parts();
+
+    let user: User = decode(&(id, name.clone(), email.clone(), auth.clone()));
+    assert_eq!(user.schema_version, 0);
+    assert!(!user.email_verified);
+
+    let user: User = decode(&(
+      id,
+      name.clone(),
+      email.clone(),
+      auth.clone(),
+      now,
+      now,
+      1_u32,
+    ));
+    assert_eq!((user.created_at, user.schema_version), (now, 1));
+
+    let user: User = decode(&(
+      id,
+      name.clone(),
+      email.clone(),
+      auth.clone(),
+      true,
+      token.clone(),
+      now,
+      now,
+      2_u32,
+    ));
+    assert!(user.email_verified);
+    assert_eq!(user.email_verification, token);
+    assert_eq!((user.created_at, user.schema_version), (now, 2));
+
+    let user: User = decode(&(
+      id,
+      name,
+      email,
+      auth,
+      true,
+      None::<EmailToken>,
+      token.clone(),
+      now,
+      now,
+      3_u32,
+    ));
+    assert_eq!(user.password_reset, token);
+    assert_eq!(user.schema_version, 3);
+  }
+
+  #[test]
+  fn users_decode_from_layouts_since_two_factor() {
+    let UserParts {
+      id,
+      name,
+      email,
+      auth,
+      now,
+      token,
+      totp,
+      passkeys,
+      oidc_identities,
+    } = user_parts();
+
+    let user: User = decode(&(
+      id,
+      name.clone(),
+      email.clone(),
+      auth.clone(),
+      true,
+      None::<EmailToken>,
+      None::<EmailToken>,
+      totp.clone(),
+      now,
+      now,
+      4_u32,
+    ));
+    assert_eq!(user.totp, totp);
+    assert_eq!(user.schema_version, 4);
+
+    let user: User = decode(&(
+      id,
+      name.clone(),
+      email.clone(),
+      auth.clone(),
+      true,
+      None::<EmailToken>,
+      None::<EmailToken>,
+      totp.clone(),
+      passkeys.clone(),
+      now,
+      now,
+      5_u32,
+    ));
+    assert_eq!(user.passkeys, passkeys);
+    assert_eq!(user.schema_version, 5);
+
+    let user: User = decode(&(
+      id,
+      name.clone(),
+      email.clone(),
+      auth.clone(),
+      true,
+      None::<EmailToken>,
+      None::<EmailToken>,
+      totp.clone(),
+      passkeys.clone(),
+      oidc_identities.clone(),
+      now,
+      now,
+      6_u32,
+    ));
+    assert_eq!(user.oidc_identities, oidc_identities);
+    assert_eq!(user.schema_version, 6);
+
+    let mut user: User = decode(&(
+      id,
+      name,
+      email,
+      auth,
+      true,
+      token.clone(),
+      None::<EmailToken>,
+      token.clone(),
+      totp.clone(),
+      passkeys.clone(),
+      oidc_identities.clone(),
+      now,
+      now,
+      7_u32,
+    ));
+    assert_eq!(user.email_verification, token);
+    assert_eq!(user.password_reset, None);
+    assert_eq!(user.magic_link, token);
+    assert_eq!(user.totp, totp);
+    assert_eq!(user.passkeys, passkeys);
+    assert_eq!(user.oidc_identities, oidc_identities);
+    assert_eq!((user.created_at, user.schema_version), (now, 7));
+    assert!(user.upcast());
+
+    assert_eq!(decode::<User>(&user), user);
+    let json = serde_json::to_string(&user).unwrap();
+    assert_eq!(serde_json::from_str::<User>(&json).unwrap(), user);
+  }
+
+  #[test]
+  fn users_decode_without_appended_fields() {
+    let UserParts {
+      id,
+      name,
+      email,
+      auth,
+      now,
+      token,
+      totp,
+      passkeys,
+      oidc_identities,
+    } = user_parts();
+
+    let mut user: User = decode(&(
+      id,
+      name,
+      email,
+      auth,
+      now,
+      now,
+      8_u32,
+      true,
+      token.clone(),
+      None::<EmailToken>,
+      token.clone(),
+      totp,
+      passkeys,
+      oidc_identities,
+    ));
+    assert_eq!(user.magic_link, token);
+    assert_eq!(user.reauthentication, None);
+    assert_eq!(user.schema_version, 8);
+    assert!(user.upcast());
+
+    user.reauthentication = token;
+    assert_eq!(decode::<User>(&user), user);
+    let json = serde_json::to_string(&user).unwrap();
+    assert_eq!(serde_json::from_str::<User>(&json).unwrap(), user);
+  }
+}
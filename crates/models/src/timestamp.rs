@@ -0,0 +1,60 @@
+use std::str::FromStr;
+
+use model::{Model, RecordId};
+
+use crate::{Artifact, Image, Photo, PhotoGroup, Ulid, User};
+
+/// A point in time, in UTC.
+pub type Timestamp = chrono::DateTime<chrono::Utc>;
+
+/// Returns the creation time encoded in a [`RecordId`]'s ULID.
+#[must_use]
+pub fn record_id_timestamp<M: Model>(id: RecordId<M>) -> Timestamp {
+  Ulid::from_str(&id.to_string())
+    .map(|ulid| Timestamp::from(ulid.datetime()))
+    .unwrap_or_default()
+}
+
+/// A model which records when it was created and last updated.
+pub trait Timestamped: Model {
+  /// When the model was created.
+  fn created_at(&self) -> Timestamp;
+  /// When the model was last updated.
+  fn updated_at(&self) -> Timestamp;
+  /// Marks the model as updated now.
+  fn touch(&mut self);
+  /// Fills in timestamps missing from records written before they existed,
+  /// using the creation time encoded in the model's ID. Returns whether
+  /// anything changed.
+  fn backfill_timestamps(&mut self) -> bool;
+}
+
+macro_rules! impl_timestamped {
+  ($($model:ty),* $(,)?) => {
+    $(
+      impl Timestamped for $model {
+        fn created_at(&self) -> Timestamp { self.created_at }
+
+        fn updated_at(&self) -> Timestamp { self.updated_at }
+
+        fn touch(&mut self) { self.updated_at = chrono::Utc::now(); }
+
+        fn backfill_timestamps(&mut self) -> bool {
+          let missing = Timestamp::default();
+          let mut changed = false;
+          if self.created_at == missing {
+            self.created_at = record_id_timestamp(self.id);
+            changed = true;
+          }
+          if self.updated_at == missing {
+            self.updated_at = self.created_at;
+            changed = true;
+          }
+          changed
+        }
+      }
+    )*
+  };
+}
+
+impl_timestamped!(Artifact, Image, Photo, PhotoGroup, User);
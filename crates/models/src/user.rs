@@ -1,10 +1,16 @@
-use std::hash::{self, Hash, Hasher};
+use std::{
+  hash::{self, Hash, Hasher},
+  str::FromStr,
+  sync::LazyLock,
+};
 
 use model::{Model, RecordId};
 use serde::{Deserialize, Serialize};
 
 pub use self::bridge::*;
-use crate::{EitherSlug, EmailAddress, HumanName, LaxSlug};
+use crate::{
+  EitherSlug, EmailAddress, HumanName, LaxSlug, Timestamp, Versioned,
+};
 
 /// The table name for [`User`] records.
 pub const USER_TABLE_NAME: &str = "user";
@@ -14,25 +20,207 @@ pub type UserRecordId = RecordId<User>;
 
 /// The domain model for a user on the platform.
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(from = "StoredUser")]
 pub struct User {
   /// The user's ID.
-  pub id:    UserRecordId,
+  pub id:                 UserRecordId,
   /// The user's name.
-  pub name:  HumanName,
+  pub name:               HumanName,
   /// The user's email address.
-  pub email: EmailAddress,
+  pub email:              EmailAddress,
   /// The user's authentication secrets.
-  pub auth:  UserAuthCredentials,
+  pub auth:               UserAuthCredentials,
+  /// When the user was created.
+  #[serde(default)]
+  pub created_at:         Timestamp,
+  /// When the user was last updated.
+  #[serde(default)]
+  pub updated_at:         Timestamp,
+  /// The schema version the user was written at.
+  #[serde(default)]
+  pub schema_version:     u32,
+  /// Whether the user has proven they own their email address.
+  #[serde(default)]
+  pub email_verified:     bool,
+  /// The outstanding email verification, if one has been sent.
+  #[serde(default)]
+  pub email_verification: Option<EmailToken>,
+  /// The outstanding password reset, if one has been requested.
+  #[serde(default)]
+  pub password_reset:     Option<EmailToken>,
+  /// The outstanding login link, if one has been requested.
+  #[serde(default)]
+  pub magic_link:         Option<EmailToken>,
+  /// The user's TOTP second factor, if they've started enrolling one.
+  #[serde(default)]
+  pub totp:               Option<TotpEnrollment>,
+  /// The passkeys the user has registered.
+  #[serde(default)]
+  pub passkeys:           Vec<Passkey>,
+  /// The user's identities at `OpenID` Connect providers.
+  #[serde(default)]
+  pub oidc_identities:    Vec<OidcIdentity>,
+  /// The outstanding link confirming it's the user before a sensitive
+  /// account change, if one has been requested.
+  #[serde(default)]
+  pub reauthentication:   Option<EmailToken>,
 }
 
 impl User {
-  /// Returns the hash of the user's authentication secrets.
+  /// Returns the hash of the user's authentication secrets: their password
+  /// or other primary credential, their passkeys, and their second factor
+  /// once it's confirmed. Sessions end when it changes.
+  ///
+  /// Passkey signature counters and the last TOTP step used change with
+  /// every login, so they're left out.
   #[must_use]
   pub fn auth_hash(&self) -> u64 {
     let mut hasher = hash::DefaultHasher::new();
     self.auth.hash(&mut hasher);
+    for passkey in &self.passkeys {
+      passkey.credential_id.hash(&mut hasher);
+    }
+    if let Some(totp) = self.totp.as_ref().filter(|t| t.is_confirmed()) {
+      totp.secret.hash(&mut hasher);
+      totp.confirmed_at.hash(&mut hasher);
+      totp.recovery_code_hashes.hash(&mut hasher);
+    }
     hasher.finish()
   }
+
+  /// Whether the user has a confirmed second factor, and so has to give a
+  /// code when logging in.
+  #[must_use]
+  pub fn has_two_factor(&self) -> bool {
+    self.totp.as_ref().is_some_and(TotpEnrollment::is_confirmed)
+  }
+}
+
+/// A single-use token emailed to a [`User`], e.g. to verify their address or
+/// reset their password. Only a hash of the token's secret is stored.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EmailToken {
+  /// The SHA-256 hash of the token's secret.
+  pub secret_hash: Vec<u8>,
+  /// When the token stops being accepted.
+  pub expires_at:  Timestamp,
+  /// When the token was last sent.
+  pub sent_at:     Timestamp,
+}
+
+/// Recent login attempts from one client, or against one email address,
+/// counted to slow down password guessing.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct LoginAttempts {
+  /// The attempts since the last successful login, or since the count
+  /// lapsed.
+  pub count:        u32,
+  /// When the last attempt was made.
+  pub last_at:      Option<Timestamp>,
+  /// When the current lockout ends, if there is one.
+  pub locked_until: Option<Timestamp>,
+}
+
+/// A [`User`]'s RFC 6238 TOTP second factor.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TotpEnrollment {
+  /// The secret shared with the user's authenticator app.
+  pub secret:               Vec<u8>,
+  /// When the user proved their app works by giving a code. Until then the
+  /// enrollment isn't enforced at login.
+  pub confirmed_at:         Option<Timestamp>,
+  /// The last time step a code was accepted for, so codes can't be replayed.
+  pub last_used_step:       u64,
+  /// The SHA-256 hashes of the unused recovery codes.
+  pub recovery_code_hashes: Vec<Vec<u8>>,
+}
+
+impl TotpEnrollment {
+  /// Whether the enrollment has been confirmed.
+  #[must_use]
+  pub fn is_confirmed(&self) -> bool { self.confirmed_at.is_some() }
+}
+
+/// A passkey registered to a [`User`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Passkey {
+  /// The credential ID the authenticator chose.
+  pub credential_id: Vec<u8>,
+  /// The credential's ES256 public key, as an uncompressed SEC1 point.
+  pub public_key:    Vec<u8>,
+  /// The authenticator's signature counter when the passkey was last used.
+  pub sign_count:    u32,
+  /// When the passkey was registered.
+  pub created_at:    Timestamp,
+  /// When the passkey was last used to log in.
+  pub last_used_at:  Option<Timestamp>,
+}
+
+/// A passkey challenge issued by the server, kept server-side (e.g. in the
+/// session) until the browser answers it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PasskeyChallenge {
+  /// The random challenge bytes.
+  pub challenge:  Vec<u8>,
+  /// The user registering a passkey, or `None` for a login.
+  pub user:       Option<UserRecordId>,
+  /// When the challenge stops being accepted.
+  pub expires_at: Timestamp,
+}
+
+/// A browser's answer to a passkey registration challenge. Binary fields are
+/// base64url-encoded, as in the Web Authentication JSON format.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PasskeyAttestation {
+  /// The new credential's ID.
+  pub credential_id:      String,
+  /// The client data JSON the authenticator signed over.
+  pub client_data_json:   String,
+  /// The CBOR attestation object.
+  pub attestation_object: String,
+}
+
+/// A browser's answer to a passkey login challenge. Binary fields are
+/// base64url-encoded, as in the Web Authentication JSON format.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PasskeyAssertion {
+  /// The ID of the credential used.
+  pub credential_id:      String,
+  /// The client data JSON the authenticator signed over.
+  pub client_data_json:   String,
+  /// The authenticator data the authenticator signed over.
+  pub authenticator_data: String,
+  /// The DER-encoded ECDSA signature.
+  pub signature:          String,
+  /// The user handle stored with the credential.
+  pub user_handle:        Option<String>,
+}
+
+/// A [`User`]'s identity at an `OpenID` Connect provider.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct OidcIdentity {
+  /// The provider's issuer URL.
+  pub issuer:    String,
+  /// The provider's stable identifier for the user.
+  pub subject:   String,
+  /// When the identity was linked to the user.
+  pub linked_at: Timestamp,
+}
+
+/// An `OpenID` Connect login in progress, kept server-side (e.g. in the
+/// session) until the provider redirects the browser back.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PendingOidcLogin {
+  /// The ID of the configured provider.
+  pub provider:      String,
+  /// The `state` parameter the provider has to echo back.
+  pub state:         String,
+  /// The nonce the provider has to put in the ID token.
+  pub nonce:         String,
+  /// The PKCE code verifier.
+  pub code_verifier: String,
+  /// When the login stops being accepted.
+  pub expires_at:    Timestamp,
 }
 
 /// A password hash.
@@ -49,6 +237,37 @@ pub enum UserSubmittedAuthCredentials {
     /// The password used.
     password: String,
   },
+  /// Email and password, plus a code from the user's second factor: either
+  /// a TOTP code or a recovery code.
+  EmailPasswordAndCode {
+    /// The email used.
+    email:    EmailAddress,
+    /// The password used.
+    password: String,
+    /// The second factor code used.
+    code:     String,
+  },
+  /// A passkey assertion.
+  Passkey {
+    /// The challenge the server issued, from server-side storage.
+    challenge: PasskeyChallenge,
+    /// The browser's answer to the challenge.
+    assertion: PasskeyAssertion,
+  },
+  /// The token from an emailed login link.
+  MagicLink {
+    /// The token from the link.
+    token: String,
+  },
+  /// An `OpenID` Connect provider's redirect back to the site.
+  Oidc {
+    /// The login the redirect answers, from server-side storage.
+    pending: PendingOidcLogin,
+    /// The `state` parameter of the redirect.
+    state:   String,
+    /// The authorization code of the redirect.
+    code:    String,
+  },
 }
 
 /// The authentication method for a [`User`].
@@ -61,6 +280,26 @@ pub enum UserAuthCredentials {
     /// The hash of the password used.
     password_hash: PasswordHash,
   },
+  /// Passkeys only, with no password. The passkeys themselves are in
+  /// [`User::passkeys`]; setting a password through a reset link switches
+  /// the user back to [`EmailAndPassword`](Self::EmailAndPassword).
+  Passkey {
+    /// The email used.
+    email: EmailAddress,
+  },
+  /// `OpenID` Connect providers only, for accounts created by logging in with
+  /// one. The identities are in [`User::oidc_identities`]; as with
+  /// [`Passkey`](Self::Passkey), a reset link sets a password.
+  Oidc {
+    /// The email used.
+    email: EmailAddress,
+  },
+  /// Emailed login links only, for accounts created by opening one. As with
+  /// [`Passkey`](Self::Passkey), a reset link sets a password.
+  MagicLink {
+    /// The email used.
+    email: EmailAddress,
+  },
 }
 
 impl Model for User {
@@ -74,6 +313,322 @@ impl Model for User {
   fn id(&self) -> UserRecordId { self.id }
 }
 
+/// The layouts a [`User`] has been stored in, newest first. A record is
+/// decoded as the first layout it fits, and keeps the schema version it was
+/// written at so that the migration runner re-writes it.
+///
+/// Versions 2 to 7 each inserted their fields before the timestamps, so
+/// every one of them is its own layout, told apart by its length.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredUser {
+  Current(CurrentUser),
+  LoginLinks(LoginLinkUser),
+  Oidc(OidcUser),
+  Passkeys(PasskeyUser),
+  TwoFactor(TotpUser),
+  PasswordResets(PasswordResetUser),
+  Verification(VerificationUser),
+}
+
+/// The current layout, where fields are appended after `schema_version`.
+/// Fields added after the original layout default, so this also decodes
+/// users from before timestamps and from version 1.
+#[derive(Deserialize)]
+struct CurrentUser {
+  id:                 UserRecordId,
+  name:               HumanName,
+  email:              EmailAddress,
+  auth:               UserAuthCredentials,
+  #[serde(default)]
+  created_at:         Timestamp,
+  #[serde(default)]
+  updated_at:         Timestamp,
+  #[serde(default)]
+  schema_version:     u32,
+  #[serde(default)]
+  email_verified:     bool,
+  #[serde(default)]
+  email_verification: Option<EmailToken>,
+  #[serde(default)]
+  password_reset:     Option<EmailToken>,
+  #[serde(default)]
+  magic_link:         Option<EmailToken>,
+  #[serde(default)]
+  totp:               Option<TotpEnrollment>,
+  #[serde(default)]
+  passkeys:           Vec<Passkey>,
+  #[serde(default)]
+  oidc_identities:    Vec<OidcIdentity>,
+  #[serde(default)]
+  reauthentication:   Option<EmailToken>,
+}
+
+/// The version 7 layout, with login links.
+#[derive(Deserialize)]
+struct LoginLinkUser {
+  id:                 UserRecordId,
+  name:               HumanName,
+  email:              EmailAddress,
+  auth:               UserAuthCredentials,
+  email_verified:     bool,
+  email_verification: Option<EmailToken>,
+  password_reset:     Option<EmailToken>,
+  magic_link:         Option<EmailToken>,
+  totp:               Option<TotpEnrollment>,
+  passkeys:           Vec<Passkey>,
+  oidc_identities:    Vec<OidcIdentity>,
+  created_at:         Timestamp,
+  updated_at:         Timestamp,
+  schema_version:     u32,
+}
+
+/// The version 6 layout, with `OpenID` Connect identities.
+#[derive(Deserialize)]
+struct OidcUser {
+  id:                 UserRecordId,
+  name:               HumanName,
+  email:              EmailAddress,
+  auth:               UserAuthCredentials,
+  email_verified:     bool,
+  email_verification: Option<EmailToken>,
+  password_reset:     Option<EmailToken>,
+  totp:               Option<TotpEnrollment>,
+  passkeys:           Vec<Passkey>,
+  oidc_identities:    Vec<OidcIdentity>,
+  created_at:         Timestamp,
+  updated_at:         Timestamp,
+  schema_version:     u32,
+}
+
+/// The version 5 layout, with passkeys.
+#[derive(Deserialize)]
+struct PasskeyUser {
+  id:                 UserRecordId,
+  name:               HumanName,
+  email:              EmailAddress,
+  auth:               UserAuthCredentials,
+  email_verified:     bool,
+  email_verification: Option<EmailToken>,
+  password_reset:     Option<EmailToken>,
+  totp:               Option<TotpEnrollment>,
+  passkeys:           Vec<Passkey>,
+  created_at:         Timestamp,
+  updated_at:         Timestamp,
+  schema_version:     u32,
+}
+
+/// The version 4 layout, with TOTP second factors.
+#[derive(Deserialize)]
+struct TotpUser {
+  id:                 UserRecordId,
+  name:               HumanName,
+  email:              EmailAddress,
+  auth:               UserAuthCredentials,
+  email_verified:     bool,
+  email_verification: Option<EmailToken>,
+  password_reset:     Option<EmailToken>,
+  totp:               Option<TotpEnrollment>,
+  created_at:         Timestamp,
+  updated_at:         Timestamp,
+  schema_version:     u32,
+}
+
+/// The version 3 layout, with password resets.
+#[derive(Deserialize)]
+struct PasswordResetUser {
+  id:                 UserRecordId,
+  name:               HumanName,
+  email:              EmailAddress,
+  auth:               UserAuthCredentials,
+  email_verified:     bool,
+  email_verification: Option<EmailToken>,
+  password_reset:     Option<EmailToken>,
+  created_at:         Timestamp,
+  updated_at:         Timestamp,
+  schema_version:     u32,
+}
+
+/// The version 2 layout, with email verification.
+#[derive(Deserialize)]
+struct VerificationUser {
+  id:                 UserRecordId,
+  name:               HumanName,
+  email:              EmailAddress,
+  auth:               UserAuthCredentials,
+  email_verified:     bool,
+  email_verification: Option<EmailToken>,
+  created_at:         Timestamp,
+  updated_at:         Timestamp,
+  schema_version:     u32,
+}
+
+impl From<StoredUser> for User {
+  fn from(stored: StoredUser) -> Self {
+    match stored {
+      StoredUser::Current(user) => user.into(),
+      StoredUser::LoginLinks(user) => user.into(),
+      StoredUser::Oidc(user) => LoginLinkUser::from(user).into(),
+      StoredUser::Passkeys(user) => {
+        LoginLinkUser::from(OidcUser::from(user)).into()
+      }
+      StoredUser::TwoFactor(user) => {
+        LoginLinkUser::from(OidcUser::from(PasskeyUser::from(user))).into()
+      }
+      StoredUser::PasswordResets(user) => LoginLinkUser::from(OidcUser::from(
+        PasskeyUser::from(TotpUser::from(user)),
+      ))
+      .into(),
+      StoredUser::Verification(user) => LoginLinkUser::from(OidcUser::from(
+        PasskeyUser::from(TotpUser::from(PasswordResetUser::from(user))),
+      ))
+      .into(),
+    }
+  }
+}
+
+impl From<CurrentUser> for User {
+  fn from(user: CurrentUser) -> Self {
+    Self {
+      id:                 user.id,
+      name:               user.name,
+      email:              user.email,
+      auth:               user.auth,
+      created_at:         user.created_at,
+      updated_at:         user.updated_at,
+      schema_version:     user.schema_version,
+      email_verified:     user.email_verified,
+      email_verification: user.email_verification,
+      password_reset:     user.password_reset,
+      magic_link:         user.magic_link,
+      totp:               user.totp,
+      passkeys:           user.passkeys,
+      oidc_identities:    user.oidc_identities,
+      reauthentication:   user.reauthentication,
+    }
+  }
+}
+
+// each older layout converts into the one after it, with the fields it
+// lacks left empty
+
+impl From<LoginLinkUser> for User {
+  fn from(user: LoginLinkUser) -> Self {
+    Self {
+      id:                 user.id,
+      name:               user.name,
+      email:              user.email,
+      auth:               user.auth,
+      created_at:         user.created_at,
+      updated_at:         user.updated_at,
+      schema_version:     user.schema_version,
+      email_verified:     user.email_verified,
+      email_verification: user.email_verification,
+      password_reset:     user.password_reset,
+      magic_link:         user.magic_link,
+      totp:               user.totp,
+      passkeys:           user.passkeys,
+      oidc_identities:    user.oidc_identities,
+      reauthentication:   None,
+    }
+  }
+}
+
+impl From<OidcUser> for LoginLinkUser {
+  fn from(user: OidcUser) -> Self {
+    Self {
+      id:                 user.id,
+      name:               user.name,
+      email:              user.email,
+      auth:               user.auth,
+      email_verified:     user.email_verified,
+      email_verification: user.email_verification,
+      password_reset:     user.password_reset,
+      magic_link:         None,
+      totp:               user.totp,
+      passkeys:           user.passkeys,
+      oidc_identities:    user.oidc_identities,
+      created_at:         user.created_at,
+      updated_at:         user.updated_at,
+      schema_version:     user.schema_version,
+    }
+  }
+}
+
+impl From<PasskeyUser> for OidcUser {
+  fn from(user: PasskeyUser) -> Self {
+    Self {
+      id:                 user.id,
+      name:               user.name,
+      email:              user.email,
+      auth:               user.auth,
+      email_verified:     user.email_verified,
+      email_verification: user.email_verification,
+      password_reset:     user.password_reset,
+      totp:               user.totp,
+      passkeys:           user.passkeys,
+      oidc_identities:    Vec::new(),
+      created_at:         user.created_at,
+      updated_at:         user.updated_at,
+      schema_version:     user.schema_version,
+    }
+  }
+}
+
+impl From<TotpUser> for PasskeyUser {
+  fn from(user: TotpUser) -> Self {
+    Self {
+      id:                 user.id,
+      name:               user.name,
+      email:              user.email,
+      auth:               user.auth,
+      email_verified:     user.email_verified,
+      email_verification: user.email_verification,
+      password_reset:     user.password_reset,
+      totp:               user.totp,
+      passkeys:           Vec::new(),
+      created_at:         user.created_at,
+      updated_at:         user.updated_at,
+      schema_version:     user.schema_version,
+    }
+  }
+}
+
+impl From<PasswordResetUser> for TotpUser {
+  fn from(user: PasswordResetUser) -> Self {
+    Self {
+      id:                 user.id,
+      name:               user.name,
+      email:              user.email,
+      auth:               user.auth,
+      email_verified:     user.email_verified,
+      email_verification: user.email_verification,
+      password_reset:     user.password_reset,
+      totp:               None,
+      created_at:         user.created_at,
+      updated_at:         user.updated_at,
+      schema_version:     user.schema_version,
+    }
+  }
+}
+
+impl From<VerificationUser> for PasswordResetUser {
+  fn from(user: VerificationUser) -> Self {
+    Self {
+      id:                 user.id,
+      name:               user.name,
+      email:              user.email,
+      auth:               user.auth,
+      email_verified:     user.email_verified,
+      email_verification: user.email_verification,
+      password_reset:     None,
+      created_at:         user.created_at,
+      updated_at:         user.updated_at,
+      schema_version:     user.schema_version,
+    }
+  }
+}
+
 /// A request to create a new [`User`].
 #[derive(Debug)]
 pub struct UserCreateRequest {
@@ -87,11 +642,23 @@ pub struct UserCreateRequest {
 
 impl From<UserCreateRequest> for User {
   fn from(req: UserCreateRequest) -> Self {
+    let now = chrono::Utc::now();
     Self {
-      id:    UserRecordId::new(),
-      name:  req.name,
-      email: req.email,
-      auth:  req.auth,
+      id:                 UserRecordId::new(),
+      name:               req.name,
+      email:              req.email,
+      auth:               req.auth,
+      created_at:         now,
+      updated_at:         now,
+      schema_version:     Self::SCHEMA_VERSION,
+      email_verified:     false,
+      email_verification: None,
+      password_reset:     None,
+      magic_link:         None,
+      totp:               None,
+      passkeys:           Vec::new(),
+      oidc_identities:    Vec::new(),
+      reauthentication:   None,
     }
   }
 }
@@ -105,6 +672,15 @@ pub struct PublicUser {
   pub name: HumanName,
 }
 
+/// Stands in for the vendor of a [`PhotoGroup`](crate::PhotoGroup) whose
+/// vendor deleted their account. No stored user has its id.
+pub static DELETED_USER: LazyLock<PublicUser> = LazyLock::new(|| PublicUser {
+  id:   UserRecordId::from_str("00000000000000000000000000")
+    .expect("nil ULID is a valid record id"),
+  name: HumanName::try_new("Former PicturePro user")
+    .expect("placeholder name is a valid human name"),
+});
+
 impl From<User> for PublicUser {
   fn from(user: User) -> Self {
     Self {
@@ -114,6 +690,60 @@ impl From<User> for PublicUser {
   }
 }
 
+/// A private view of a [`User`], only shown to the user themselves.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AccountDetails {
+  /// The user's name.
+  pub name:               HumanName,
+  /// The user's email address.
+  pub email:              EmailAddress,
+  /// Whether the user has proven they own their email address.
+  pub email_verified:     bool,
+  /// Whether the user logs in with a second factor.
+  pub two_factor_enabled: bool,
+  /// Whether the user has a password, rather than only passkeys.
+  pub has_password:       bool,
+  /// The passkeys the user has registered.
+  pub passkeys:           Vec<PasskeyDetails>,
+}
+
+/// The displayable parts of a [`Passkey`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PasskeyDetails {
+  /// The credential ID the authenticator chose.
+  pub credential_id: Vec<u8>,
+  /// When the passkey was registered.
+  pub created_at:    Timestamp,
+  /// When the passkey was last used to log in.
+  pub last_used_at:  Option<Timestamp>,
+}
+
+impl From<Passkey> for PasskeyDetails {
+  fn from(passkey: Passkey) -> Self {
+    Self {
+      credential_id: passkey.credential_id,
+      created_at:    passkey.created_at,
+      last_used_at:  passkey.last_used_at,
+    }
+  }
+}
+
+impl From<User> for AccountDetails {
+  fn from(user: User) -> Self {
+    Self {
+      two_factor_enabled: user.has_two_factor(),
+      has_password:       matches!(
+        user.auth,
+        UserAuthCredentials::EmailAndPassword { .. }
+      ),
+      passkeys:           user.passkeys.into_iter().map(Into::into).collect(),
+      name:               user.name,
+      email:              user.email,
+      email_verified:     user.email_verified,
+    }
+  }
+}
+
 /// An auth-centric view of a [`User`], able to be sent to the client.
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct AuthUser {
@@ -137,12 +767,48 @@ impl From<User> for AuthUser {
   }
 }
 
+/// Where and when a login session was used, kept in the session's data.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SessionActivity {
+  /// Identifies the session to its user, e.g. for revoking it. This isn't
+  /// the session ID, which would let anyone who sees it take over the
+  /// session.
+  pub handle:        String,
+  /// The `User-Agent` of the browser that last used the session.
+  pub user_agent:    Option<String>,
+  /// The address the session was last used from.
+  pub ip:            Option<String>,
+  /// When the session was first used while logged in.
+  pub first_seen_at: Timestamp,
+  /// When the session was last used.
+  pub last_seen_at:  Timestamp,
+}
+
+impl SessionActivity {
+  /// The session data key that [`SessionActivity`] is stored under.
+  pub const SESSION_KEY: &'static str = "picturepro.activity";
+}
+
+/// A session that a user is logged into, as shown to them.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ActiveSession {
+  /// Where and when the session was used, if it's been used since that
+  /// started being tracked.
+  pub activity: Option<SessionActivity>,
+  /// Whether this is the session making the request.
+  pub current:  bool,
+}
+
 /// Types in this module are for repackaging data to transfer from the high-deps
 /// side (server code) to the low-deps side (client code)
 mod bridge {
   /// The status of user authentication.
   #[derive(Debug, Clone)]
   pub struct AuthStatus(pub Option<super::AuthUser>);
+
+  /// The address of the client making the request, if it's known.
+  #[derive(Debug, Clone, Copy)]
+  pub struct ClientIp(pub Option<std::net::IpAddr>);
 }
 
 #[cfg(feature = "auth")]
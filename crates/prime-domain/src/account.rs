@@ -0,0 +1,395 @@
+//! Exporting a user's data, and cleaning up their photo groups when they
+//! delete their account.
+//!
+//! An export is an uncompressed tar file, since most of it is already
+//! compressed images. It holds the user's record (minus credentials and
+//! token hashes) as `user.json`, their photo groups and photos as
+//! `photo_groups.json` and `photos.json`, and the original file of each photo
+//! under `originals/`.
+//!
+//! Originals are streamed into the archive as they're read, so an export
+//! never holds more than a few chunks of one in memory.
+
+use std::io::{self, Read, Write};
+
+use bytes::Bytes;
+use futures::StreamExt;
+use models::{
+  ArtifactMimeType, ArtifactRecordId, CompressionStatus, Photo, PhotoGroup,
+  PhotoGroupRecordId, UserRecordId, DELETED_USER,
+};
+use repos::{FetchModelByIndexError, FetchModelError, PatchModelError};
+use tokio::{sync::mpsc, task::JoinHandle};
+use tracing::instrument;
+
+use crate::{DeletePhotoGroupError, PrimeDomainService};
+
+/// User fields left out of exports, because they're secrets.
+const REDACTED_USER_FIELDS: &[&str] = &[
+  "auth",
+  "email_verification",
+  "password_reset",
+  "magic_link",
+  "totp",
+  "reauthentication",
+];
+
+/// An error that occurs when exporting a user's data.
+#[derive(Debug, thiserror::Error)]
+pub enum ExportUserDataError {
+  /// The user doesn't exist.
+  #[error("User not found")]
+  UserNotFound,
+  /// An error occurred while fetching a model.
+  #[error("Failed to fetch model: {0}")]
+  FetchError(#[from] FetchModelError),
+  /// An error occurred while fetching the user's photo groups.
+  #[error("Failed to fetch photo groups by user: {0}")]
+  FetchByIndexError(#[from] FetchModelByIndexError),
+  /// A photo's original image or artifact is missing.
+  #[error("Original artifact of photo is missing: {0}")]
+  MissingArtifact(ArtifactRecordId),
+  /// An error occurred while reading an original artifact.
+  #[error("Failed to read artifact {0}: {1}")]
+  ReadArtifactError(ArtifactRecordId, repos::ReadArtifactError),
+  /// An error occurred while streaming an original artifact's data.
+  #[error("Failed to stream data of artifact {0}: {1}")]
+  StreamArtifactDataError(ArtifactRecordId, io::Error),
+  /// An error occurred while serializing a model.
+  #[error("Failed to serialize {0}: {1}")]
+  SerializeError(&'static str, serde_json::Error),
+  /// An error occurred while writing the archive.
+  #[error("Failed to write export archive: {0}")]
+  IoError(#[from] io::Error),
+}
+
+/// An error that occurs when cleaning up a deleted user's photo groups.
+#[derive(Debug, thiserror::Error)]
+pub enum DeleteUserContentError {
+  /// An error occurred while fetching the user's photo groups.
+  #[error("Failed to fetch photo groups by user: {0}")]
+  FetchByIndexError(#[from] FetchModelByIndexError),
+  /// An error occurred while deleting a photo group.
+  #[error("Failed to delete photo group {0}: {1}")]
+  DeleteError(PhotoGroupRecordId, DeletePhotoGroupError),
+  /// An error occurred while anonymizing a photo group.
+  #[error("Failed to anonymize photo group {0}: {1}")]
+  PatchError(PhotoGroupRecordId, PatchModelError),
+}
+
+/// What happened to a deleted user's photo groups.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UserContentDeletionReport {
+  /// Groups that were never offered for sale, deleted with their photos.
+  pub deleted:    Vec<PhotoGroupRecordId>,
+  /// Groups that may have been sold, kept with the vendor anonymized.
+  pub anonymized: Vec<PhotoGroupRecordId>,
+}
+
+/// Picks a file extension for an original from its stated mime type.
+fn extension_for(mime_type: Option<&ArtifactMimeType>) -> &'static str {
+  let essence = mime_type
+    .map(|mt| mt.as_ref().split(';').next().unwrap_or_default().trim())
+    .unwrap_or_default();
+  match essence {
+    "image/jpeg" => "jpg",
+    "image/png" => "png",
+    "image/webp" => "webp",
+    "image/gif" => "gif",
+    "image/avif" => "avif",
+    "image/heic" => "heic",
+    "image/tiff" => "tiff",
+    _ => "bin",
+  }
+}
+
+/// How many chunks of the archive may wait for the task writing it.
+const EXPORT_QUEUE_DEPTH: usize = 8;
+
+/// A piece of an export archive, sent to the task writing it.
+enum ExportChunk {
+  /// Starts an entry of `size` bytes, whose data follows as [`Data`]
+  /// chunks.
+  ///
+  /// [`Data`]: ExportChunk::Data
+  Entry { path: String, size: u64 },
+  /// The next non-empty piece of the current entry's data.
+  Data(Bytes),
+}
+
+/// Reads the data of the current entry off the queue, checking that it's
+/// exactly as long as its header says.
+struct EntryReader<'a> {
+  queue:     &'a mut mpsc::Receiver<ExportChunk>,
+  remaining: u64,
+  current:   Bytes,
+}
+
+impl Read for EntryReader<'_> {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    while self.current.is_empty() {
+      if self.remaining == 0 {
+        return Ok(0);
+      }
+      let Some(ExportChunk::Data(data)) = self.queue.blocking_recv() else {
+        return Err(io::Error::new(
+          io::ErrorKind::UnexpectedEof,
+          "entry data is shorter than its header says",
+        ));
+      };
+      self.remaining = self
+        .remaining
+        .checked_sub(data.len() as u64)
+        .ok_or_else(|| {
+          io::Error::new(
+            io::ErrorKind::InvalidData,
+            "entry data is longer than its header says",
+          )
+        })?;
+      self.current = data;
+    }
+    let len = buf.len().min(self.current.len());
+    buf[..len].copy_from_slice(&self.current.split_to(len));
+    Ok(len)
+  }
+}
+
+/// Writes an export archive on a blocking task, so that neither the tar
+/// encoding nor a slow `writer` stalls the runtime. Entries are handed over
+/// through a bounded queue, which keeps reads from outpacing the writer.
+struct ExportTask<W> {
+  chunks: mpsc::Sender<ExportChunk>,
+  handle: JoinHandle<io::Result<W>>,
+}
+
+impl<W: Write + Send + 'static> ExportTask<W> {
+  fn spawn(writer: W) -> Self {
+    let (chunks, mut queue) = mpsc::channel::<ExportChunk>(EXPORT_QUEUE_DEPTH);
+    let handle = tokio::task::spawn_blocking(move || {
+      let mtime =
+        u64::try_from(chrono::Utc::now().timestamp()).unwrap_or_default();
+      let mut builder = tar::Builder::new(writer);
+      while let Some(chunk) = queue.blocking_recv() {
+        let ExportChunk::Entry { path, size } = chunk else {
+          return Err(io::Error::other("export data sent outside of an entry"));
+        };
+        let mut header = tar::Header::new_gnu();
+        header.set_size(size);
+        header.set_mode(0o644);
+        header.set_mtime(mtime);
+        header.set_cksum();
+        builder.append_data(&mut header, path, EntryReader {
+          queue:     &mut queue,
+          remaining: size,
+          current:   Bytes::new(),
+        })?;
+      }
+      builder.into_inner()
+    });
+    Self { chunks, handle }
+  }
+
+  async fn send(
+    &mut self,
+    chunk: ExportChunk,
+  ) -> Result<(), ExportUserDataError> {
+    if self.chunks.send(chunk).await.is_ok() {
+      return Ok(());
+    }
+    // the writer only stops taking chunks when it has failed
+    let error = match (&mut self.handle).await {
+      Ok(Err(e)) => e,
+      Ok(Ok(_)) => io::Error::other("export writer stopped early"),
+      Err(e) => io::Error::other(e),
+    };
+    Err(ExportUserDataError::IoError(error))
+  }
+
+  async fn append(
+    &mut self,
+    path: String,
+    data: Vec<u8>,
+  ) -> Result<(), ExportUserDataError> {
+    self
+      .send(ExportChunk::Entry {
+        path,
+        size: data.len() as u64,
+      })
+      .await?;
+    if !data.is_empty() {
+      self.send(ExportChunk::Data(Bytes::from(data))).await?;
+    }
+    Ok(())
+  }
+
+  /// Wait for every queued chunk to be written, then finish the archive.
+  async fn finish(self) -> Result<W, ExportUserDataError> {
+    drop(self.chunks);
+    match self.handle.await {
+      Ok(result) => Ok(result?),
+      Err(e) => Err(ExportUserDataError::IoError(io::Error::other(e))),
+    }
+  }
+}
+
+impl PrimeDomainService {
+  /// Write an archive of everything a [`User`](models::User) has stored to
+  /// `writer`: their account record, photo groups, photos, and original
+  /// uploads.
+  ///
+  /// `writer` is written from a blocking task, so it may block.
+  #[instrument(skip(self, writer))]
+  pub async fn write_user_data_export<W: Write + Send + 'static>(
+    &self,
+    user_id: UserRecordId,
+    writer: W,
+  ) -> Result<W, ExportUserDataError> {
+    let user = self
+      .user_repo
+      .fetch_user_by_id(user_id)
+      .await?
+      .ok_or(ExportUserDataError::UserNotFound)?;
+    let mut user = serde_json::to_value(&user)
+      .map_err(|e| ExportUserDataError::SerializeError("user", e))?;
+    if let Some(fields) = user.as_object_mut() {
+      for field in REDACTED_USER_FIELDS {
+        fields.remove(*field);
+      }
+    }
+
+    let photo_groups = self
+      .photo_group_repo
+      .fetch_photo_groups_by_user(user_id)
+      .await?;
+    let mut photos = Vec::<Photo>::new();
+    for photo_group in &photo_groups {
+      for photo_id in &photo_group.photos {
+        if photos.iter().any(|p| p.id == *photo_id) {
+          continue;
+        }
+        if let Some(photo) = self.fetch_photo(*photo_id).await? {
+          photos.push(photo);
+        }
+      }
+    }
+
+    let mut export = ExportTask::spawn(writer);
+    for (path, name, value) in [
+      ("user.json", "user", serde_json::to_vec_pretty(&user)),
+      (
+        "photo_groups.json",
+        "photo groups",
+        serde_json::to_vec_pretty::<[PhotoGroup]>(&photo_groups),
+      ),
+      (
+        "photos.json",
+        "photos",
+        serde_json::to_vec_pretty::<[Photo]>(&photos),
+      ),
+    ] {
+      let value =
+        value.map_err(|e| ExportUserDataError::SerializeError(name, e))?;
+      export.append(path.to_owned(), value).await?;
+    }
+
+    for photo in &photos {
+      self.export_original(&mut export, photo).await?;
+    }
+
+    export.finish().await
+  }
+
+  /// Stream the original upload of `photo` into the export, uncompressed.
+  async fn export_original<W: Write + Send + 'static>(
+    &self,
+    export: &mut ExportTask<W>,
+    photo: &Photo,
+  ) -> Result<(), ExportUserDataError> {
+    let image = self.fetch_image(photo.artifacts.original).await?.ok_or(
+      ExportUserDataError::FetchError(FetchModelError::Db(miette::miette!(
+        "image {} not found: original of photo {}",
+        photo.artifacts.original,
+        photo.id
+      ))),
+    )?;
+    // tar headers come before the data, so the size has to be known up
+    // front; the stored size before compression is exactly that
+    let size = match self
+      .fetch_artifact(image.artifact)
+      .await?
+      .ok_or(ExportUserDataError::MissingArtifact(image.artifact))?
+      .comp_status
+    {
+      CompressionStatus::Compressed {
+        uncompressed_size, ..
+      } => uncompressed_size,
+      CompressionStatus::Uncompressed { size } => size,
+    };
+    let (data, mime_type) = self
+      .read_artifact_by_id(image.artifact)
+      .await
+      .map_err(|e| ExportUserDataError::ReadArtifactError(image.artifact, e))?
+      .ok_or(ExportUserDataError::MissingArtifact(image.artifact))?;
+
+    export
+      .send(ExportChunk::Entry {
+        path: format!(
+          "originals/{}.{}",
+          photo.id,
+          extension_for(mime_type.as_ref())
+        ),
+        size: size.into_inner(),
+      })
+      .await?;
+    let mut data = std::pin::pin!(data.adapt_to_no_comp());
+    while let Some(chunk) = data.next().await {
+      let chunk = chunk.map_err(|e| {
+        ExportUserDataError::StreamArtifactDataError(image.artifact, e)
+      })?;
+      if !chunk.is_empty() {
+        export.send(ExportChunk::Data(chunk)).await?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Clean up the photo groups of a [`User`](models::User) who is deleting
+  /// their account.
+  ///
+  /// Sales aren't recorded yet, so any group that offers a license is
+  /// treated as sold: it's kept, with its vendor replaced by
+  /// [`DELETED_USER`], so buyers keep access and nobody can edit it. Groups
+  /// that offer no license are deleted with their photos.
+  #[instrument(skip(self))]
+  pub async fn delete_user_content(
+    &self,
+    user_id: UserRecordId,
+  ) -> Result<UserContentDeletionReport, DeleteUserContentError> {
+    let mut report = UserContentDeletionReport::default();
+
+    for mut photo_group in self
+      .photo_group_repo
+      .fetch_photo_groups_by_user(user_id)
+      .await?
+    {
+      let id = photo_group.id;
+      if photo_group.config.licenses.is_empty() {
+        self
+          .delete_photo_group(user_id, id)
+          .await
+          .map_err(|e| DeleteUserContentError::DeleteError(id, e))?;
+        report.deleted.push(id);
+      } else {
+        photo_group.vendor = DELETED_USER.id;
+        self
+          .photo_group_repo
+          .update_photo_group(photo_group)
+          .await
+          .map_err(|e| DeleteUserContentError::PatchError(id, e))?;
+        report.anonymized.push(id);
+      }
+    }
+
+    Ok(report)
+  }
+}
@@ -0,0 +1,692 @@
+//! Portable backups of all stored models and artifact data.
+//!
+//! A backup archive is a zstd-compressed tar file. Its first entry is a
+//! [`BackupManifest`], followed by one JSON-lines file of records per table
+//! and then the stored data of each artifact, byte-for-byte as it sits in
+//! storage (compressed, and sealed if the artifact is encrypted). Artifact
+//! data is split into numbered entries of at most [`ARTIFACT_CHUNK_SIZE`]
+//! bytes, so that it can be archived as it streams out of storage. Encrypted
+//! artifacts can only be read after a restore if the master key that wrapped
+//! their data keys is still in the keyring.
+//!
+//! Incremental backups hold only the records updated, and the artifact data
+//! created, since the snapshot of the backup they build on. Every manifest
+//! lists the ids of all records live at its snapshot, so restoring a full
+//! backup followed by its incrementals also removes deleted records.
+
+use std::{
+  collections::{BTreeMap, BTreeSet},
+  io::{self, BufRead, BufReader, Read, Write},
+};
+
+use bytes::Bytes;
+use futures::StreamExt;
+use models::{
+  Artifact, ArtifactPath, ArtifactRecordId, Image, Model, Photo, PhotoGroup,
+  Timestamp, Timestamped, User,
+};
+use repos::{
+  belt::{Belt, DEFAULT_CHUNK_SIZE},
+  ArtifactRepositoryLike, CreateArtifactError, ImageRepositoryLike,
+  PhotoGroupRepositoryLike, PhotoRepositoryLike, ReadArtifactError,
+  RestoreModelError, SnapshotError, SnapshotRepositoryLike, UserRepositoryLike,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::{sync::mpsc, task::JoinHandle};
+use tracing::instrument;
+
+use crate::PrimeDomainService;
+
+/// The version of the backup archive format written by this build.
+///
+/// Version 1 archives stored each artifact's data in a single entry; they
+/// can still be restored.
+pub const BACKUP_FORMAT_VERSION: u32 = 2;
+
+/// The most artifact data held in one archive entry.
+pub const ARTIFACT_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// How many entries may wait for the archive writer before reading more
+/// artifact data waits for it to catch up.
+const ARCHIVE_QUEUE_DEPTH: usize = 8;
+
+const MANIFEST_ENTRY: &str = "manifest.json";
+const TABLES_DIR: &str = "tables/";
+const TABLE_EXTENSION: &str = ".jsonl";
+const ARTIFACT_DATA_DIR: &str = "artifacts/";
+
+/// The kind of backup to take.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BackupKind {
+  /// Every record and the data of every artifact.
+  Full,
+  /// Only what changed since an earlier backup.
+  Incremental {
+    /// The snapshot time of the backup this one builds on.
+    base_snapshot_at: Timestamp,
+  },
+}
+
+/// Describes the contents of a backup archive.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupManifest {
+  /// The archive format version.
+  pub format_version:   u32,
+  /// When the snapshot was taken. Changes made after this are left to the
+  /// next backup.
+  pub snapshot_at:      Timestamp,
+  /// The snapshot time of the backup this one builds on, if it's
+  /// incremental.
+  pub base_snapshot_at: Option<Timestamp>,
+  /// The ids of all records live at the snapshot, by table.
+  pub live_ids:         BTreeMap<String, BTreeSet<String>>,
+  /// The number of records in the archive, by table.
+  pub records:          BTreeMap<String, usize>,
+  /// The number of artifacts whose data is in the archive.
+  pub artifact_data:    usize,
+}
+
+impl BackupManifest {
+  /// Whether the backup builds on an earlier one.
+  #[must_use]
+  pub fn is_incremental(&self) -> bool { self.base_snapshot_at.is_some() }
+}
+
+/// The outcome of restoring a chain of backups.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RestoreReport {
+  /// The number of archives restored.
+  pub archives:      usize,
+  /// The number of records written, by table.
+  pub records:       BTreeMap<String, usize>,
+  /// The number of artifacts whose data was written.
+  pub artifact_data: usize,
+  /// The number of records deleted because they were gone by the last
+  /// snapshot.
+  pub pruned:        usize,
+}
+
+/// An error that occurs when writing a backup.
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum WriteBackupError {
+  /// An error that occurs when taking the snapshot.
+  #[error(transparent)]
+  SnapshotError(SnapshotError),
+  /// An error that occurs when serializing a model.
+  #[error("Failed to serialize {0} model: {1}")]
+  SerializeError(&'static str, serde_json::Error),
+  /// An error that occurs when reading the stored data of an [`Artifact`].
+  #[error("Failed to read stored data of Artifact {0}: {1}")]
+  ReadArtifactDataError(ArtifactRecordId, ReadArtifactError),
+  /// An error that occurs when streaming the stored data of an
+  /// [`Artifact`].
+  #[error("Failed to stream stored data of Artifact {0}: {1}")]
+  StreamArtifactDataError(ArtifactRecordId, io::Error),
+  /// An error that occurs when writing the archive.
+  #[error("Failed to write backup archive: {0}")]
+  IoError(io::Error),
+}
+
+/// An error that occurs when restoring a chain of backups.
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum RestoreBackupError {
+  /// No archives were given.
+  #[error("No backup archives were given")]
+  NoArchives,
+  /// The store already holds records, so a full backup can't be restored
+  /// into it.
+  #[error("The store is not empty; restore into an empty store")]
+  StoreNotEmpty,
+  /// The archive doesn't start with a manifest.
+  #[error("Backup archive {0} has no manifest")]
+  MissingManifest(usize),
+  /// The archive was written in an unknown format.
+  #[error("Backup archive {0} has unsupported format version {1}")]
+  UnsupportedFormat(usize, u32),
+  /// The first archive is incremental.
+  #[error("The first backup archive must be a full backup")]
+  FirstNotFull,
+  /// An archive doesn't build on the one before it.
+  #[error(
+    "Backup archive {index} builds on the snapshot at {expected:?}, but the \
+     previous archive was taken at {found}"
+  )]
+  BrokenChain {
+    /// The index of the archive in the chain.
+    index:    usize,
+    /// The snapshot time the archive builds on.
+    expected: Option<Timestamp>,
+    /// The snapshot time of the previous archive.
+    found:    Timestamp,
+  },
+  /// The archive holds an entry this build doesn't understand.
+  #[error("Backup archive {0} has unexpected entry `{1}`")]
+  UnexpectedEntry(usize, String),
+  /// An error that occurs when deserializing the manifest or a model.
+  #[error("Failed to deserialize `{0}`: {1}")]
+  DeserializeError(String, serde_json::Error),
+  /// An error that occurs when checking whether the store is empty.
+  #[error(transparent)]
+  SnapshotError(SnapshotError),
+  /// An error that occurs when writing or deleting a model.
+  #[error(transparent)]
+  RestoreModelError(RestoreModelError),
+  /// An error that occurs when writing the stored data of an [`Artifact`].
+  #[error("Failed to write stored data of artifact path {0}: {1}")]
+  WriteArtifactDataError(String, CreateArtifactError),
+  /// An error that occurs when reading the archive.
+  #[error("Failed to read backup archive: {0}")]
+  IoError(io::Error),
+}
+
+impl From<RestoreModelError> for RestoreBackupError {
+  fn from(e: RestoreModelError) -> Self { Self::RestoreModelError(e) }
+}
+
+/// Writes entries to a zstd-compressed tar archive.
+struct ArchiveWriter<W: Write> {
+  builder: tar::Builder<zstd::Encoder<'static, W>>,
+  mtime:   u64,
+}
+
+impl<W: Write> ArchiveWriter<W> {
+  fn new(writer: W, snapshot_at: Timestamp) -> io::Result<Self> {
+    Ok(Self {
+      builder: tar::Builder::new(zstd::Encoder::new(writer, 3)?),
+      mtime:   u64::try_from(snapshot_at.timestamp()).unwrap_or_default(),
+    })
+  }
+
+  fn append(&mut self, path: &str, data: &[u8]) -> io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(self.mtime);
+    header.set_cksum();
+    self.builder.append_data(&mut header, path, data)
+  }
+
+  fn finish(self) -> io::Result<W> { self.builder.into_inner()?.finish() }
+}
+
+/// Runs an [`ArchiveWriter`] on a blocking thread, so that compressing and
+/// writing the archive doesn't hold up the async runtime. Entries are handed
+/// over through a bounded queue.
+struct ArchiveTask<W> {
+  entries: mpsc::Sender<(String, Bytes)>,
+  handle:  JoinHandle<io::Result<W>>,
+}
+
+impl<W: Write + Send + 'static> ArchiveTask<W> {
+  fn spawn(writer: W, snapshot_at: Timestamp) -> Self {
+    let (entries, mut queue) =
+      mpsc::channel::<(String, Bytes)>(ARCHIVE_QUEUE_DEPTH);
+    let handle = tokio::task::spawn_blocking(move || {
+      let mut archive = ArchiveWriter::new(writer, snapshot_at)?;
+      while let Some((path, data)) = queue.blocking_recv() {
+        archive.append(&path, &data)?;
+      }
+      archive.finish()
+    });
+    Self { entries, handle }
+  }
+
+  async fn append(
+    &mut self,
+    path: String,
+    data: Bytes,
+  ) -> Result<(), WriteBackupError> {
+    if self.entries.send((path, data)).await.is_ok() {
+      return Ok(());
+    }
+    // the writer only stops taking entries when it has failed
+    let error = match (&mut self.handle).await {
+      Ok(Err(e)) => e,
+      Ok(Ok(_)) => io::Error::other("archive writer stopped early"),
+      Err(e) => io::Error::other(e),
+    };
+    Err(WriteBackupError::IoError(error))
+  }
+
+  async fn append_table<M: Model + Serialize>(
+    &mut self,
+    models: &[&M],
+  ) -> Result<(), WriteBackupError> {
+    let mut lines = Vec::new();
+    for model in models {
+      serde_json::to_writer(&mut lines, model)
+        .map_err(|e| WriteBackupError::SerializeError(M::TABLE_NAME, e))?;
+      lines.push(b'\n');
+    }
+    self
+      .append(table_entry(M::TABLE_NAME), Bytes::from(lines))
+      .await
+  }
+
+  /// Wait for every queued entry to be written, then finish the archive.
+  async fn finish(self) -> Result<W, WriteBackupError> {
+    drop(self.entries);
+    match self.handle.await {
+      Ok(result) => result.map_err(WriteBackupError::IoError),
+      Err(e) => Err(WriteBackupError::IoError(io::Error::other(e))),
+    }
+  }
+}
+
+fn table_entry(table: &str) -> String {
+  format!("{TABLES_DIR}{table}{TABLE_EXTENSION}")
+}
+
+fn artifact_chunk_entry(path: &ArtifactPath, index: usize) -> String {
+  format!("{ARTIFACT_DATA_DIR}{path}/{index:06}")
+}
+
+/// Keeps the models changed since `base`, or all of them for a full backup.
+fn changed_since<M: Timestamped>(
+  models: &[M],
+  base: Option<Timestamp>,
+) -> Vec<&M> {
+  models
+    .iter()
+    .filter(|m| base.is_none_or(|base| m.updated_at() > base))
+    .collect()
+}
+
+/// The data of one artifact, read from the archive entries so far.
+struct PendingArtifactData {
+  name:   String,
+  path:   Option<ArtifactPath>,
+  chunks: Vec<Bytes>,
+}
+
+fn entry_path<R: Read>(
+  index: usize,
+  entry: &tar::Entry<'_, R>,
+) -> Result<String, RestoreBackupError> {
+  let path = entry.path().map_err(RestoreBackupError::IoError)?;
+  path.to_str().map(ToOwned::to_owned).ok_or_else(|| {
+    RestoreBackupError::UnexpectedEntry(index, path.display().to_string())
+  })
+}
+
+fn deserialize_lines<M: DeserializeOwned>(
+  entry: impl Read,
+  entry_path: &str,
+) -> Result<Vec<M>, RestoreBackupError> {
+  BufReader::new(entry)
+    .lines()
+    .filter(|line| line.as_ref().is_ok_and(|l| !l.trim().is_empty()))
+    .map(|line| {
+      let line = line.map_err(RestoreBackupError::IoError)?;
+      serde_json::from_str(&line).map_err(|e| {
+        RestoreBackupError::DeserializeError(entry_path.to_owned(), e)
+      })
+    })
+    .collect()
+}
+
+/// Checks that `manifest` can be restored after `previous`, the manifest of
+/// the archive restored before it.
+fn check_chain(
+  index: usize,
+  manifest: &BackupManifest,
+  previous: Option<&BackupManifest>,
+) -> Result<(), RestoreBackupError> {
+  match previous {
+    None if manifest.is_incremental() => {
+      return Err(RestoreBackupError::FirstNotFull);
+    }
+    Some(previous)
+      if manifest.base_snapshot_at != Some(previous.snapshot_at) =>
+    {
+      return Err(RestoreBackupError::BrokenChain {
+        index,
+        expected: manifest.base_snapshot_at,
+        found: previous.snapshot_at,
+      });
+    }
+    _ => (),
+  }
+  Ok(())
+}
+
+impl PrimeDomainService {
+  /// Write a backup archive of all stored models and artifact data to
+  /// `writer`. Returns the archive's manifest and the writer.
+  ///
+  /// The server keeps running while the backup is taken. Records are read in
+  /// a single read transaction, and anything changed after it is picked up
+  /// by the next incremental backup. The archive is compressed and written
+  /// on a blocking thread, with artifact data streamed into it.
+  #[instrument(skip(self, writer))]
+  pub async fn write_backup<W: Write + Send + 'static>(
+    &self,
+    writer: W,
+    kind: BackupKind,
+  ) -> Result<(BackupManifest, W), WriteBackupError> {
+    let snapshot_at = chrono::Utc::now();
+    let snapshot = self
+      .snapshot_repo
+      .snapshot()
+      .await
+      .map_err(WriteBackupError::SnapshotError)?;
+
+    let base = match kind {
+      BackupKind::Full => None,
+      BackupKind::Incremental { base_snapshot_at } => Some(base_snapshot_at),
+    };
+    let users = changed_since(&snapshot.users, base);
+    let photo_groups = changed_since(&snapshot.photo_groups, base);
+    let photos = changed_since(&snapshot.photos, base);
+    let images = changed_since(&snapshot.images, base);
+    let artifacts = changed_since(&snapshot.artifacts, base);
+    // artifact data is never rewritten, so only new artifacts need theirs
+    let new_artifacts = snapshot
+      .artifacts
+      .iter()
+      .filter(|a| base.is_none_or(|base| a.created_at() > base))
+      .collect::<Vec<_>>();
+
+    let manifest = BackupManifest {
+      format_version: BACKUP_FORMAT_VERSION,
+      snapshot_at,
+      base_snapshot_at: base,
+      live_ids: snapshot.live_ids(),
+      records: BTreeMap::from([
+        (User::TABLE_NAME.to_owned(), users.len()),
+        (PhotoGroup::TABLE_NAME.to_owned(), photo_groups.len()),
+        (Photo::TABLE_NAME.to_owned(), photos.len()),
+        (Image::TABLE_NAME.to_owned(), images.len()),
+        (Artifact::TABLE_NAME.to_owned(), artifacts.len()),
+      ]),
+      artifact_data: new_artifacts.len(),
+    };
+
+    let mut archive = ArchiveTask::spawn(writer, snapshot_at);
+    let manifest_json = serde_json::to_vec_pretty(&manifest)
+      .map_err(|e| WriteBackupError::SerializeError("manifest", e))?;
+    archive
+      .append(MANIFEST_ENTRY.to_owned(), Bytes::from(manifest_json))
+      .await?;
+
+    archive.append_table(&users).await?;
+    archive.append_table(&photo_groups).await?;
+    archive.append_table(&photos).await?;
+    archive.append_table(&images).await?;
+    archive.append_table(&artifacts).await?;
+
+    for artifact in new_artifacts {
+      self.archive_artifact_data(&mut archive, artifact).await?;
+    }
+
+    let writer = archive.finish().await?;
+    Ok((manifest, writer))
+  }
+
+  /// Stream the stored data of `artifact` into the archive, one entry per
+  /// [`ARTIFACT_CHUNK_SIZE`] bytes. Empty data still gets one, empty, entry.
+  async fn archive_artifact_data<W: Write + Send + 'static>(
+    &self,
+    archive: &mut ArchiveTask<W>,
+    artifact: &Artifact,
+  ) -> Result<(), WriteBackupError> {
+    let data = self
+      .artifact_repo
+      .read_stored_artifact_data(&artifact.path)
+      .await
+      .map_err(|e| WriteBackupError::ReadArtifactDataError(artifact.id, e))?;
+    let mut data = std::pin::pin!(data);
+
+    let mut index = 0;
+    while let Some(chunk) = data.next().await {
+      let mut chunk = chunk.map_err(|e| {
+        WriteBackupError::StreamArtifactDataError(artifact.id, e)
+      })?;
+      while !chunk.is_empty() {
+        let entry = chunk.split_to(chunk.len().min(ARTIFACT_CHUNK_SIZE));
+        archive
+          .append(artifact_chunk_entry(&artifact.path, index), entry)
+          .await?;
+        index += 1;
+      }
+    }
+    if index == 0 {
+      archive
+        .append(artifact_chunk_entry(&artifact.path, 0), Bytes::new())
+        .await?;
+    }
+    Ok(())
+  }
+
+  /// Restore a full backup followed by any number of incremental backups
+  /// built on it, in order, into an empty store.
+  ///
+  /// Records are written with their index entries. Records missing from the
+  /// last archive's snapshot are deleted at the end.
+  #[instrument(skip(self, archives))]
+  pub async fn restore_backup<R: Read>(
+    &self,
+    archives: impl IntoIterator<Item = R>,
+  ) -> Result<RestoreReport, RestoreBackupError> {
+    if !self
+      .snapshot_repo
+      .snapshot()
+      .await
+      .map_err(RestoreBackupError::SnapshotError)?
+      .is_empty()
+    {
+      return Err(RestoreBackupError::StoreNotEmpty);
+    }
+
+    let mut report = RestoreReport::default();
+    let mut artifact_paths = BTreeMap::<String, ArtifactPath>::new();
+    let mut previous: Option<BackupManifest> = None;
+
+    for (index, reader) in archives.into_iter().enumerate() {
+      let decoder =
+        zstd::Decoder::new(reader).map_err(RestoreBackupError::IoError)?;
+      let mut archive = tar::Archive::new(decoder);
+      let mut entries =
+        archive.entries().map_err(RestoreBackupError::IoError)?;
+
+      let entry = entries
+        .next()
+        .ok_or(RestoreBackupError::MissingManifest(index))?
+        .map_err(RestoreBackupError::IoError)?;
+      if entry_path(index, &entry)? != MANIFEST_ENTRY {
+        return Err(RestoreBackupError::MissingManifest(index));
+      }
+      let manifest: BackupManifest =
+        serde_json::from_reader(entry).map_err(|e| {
+          RestoreBackupError::DeserializeError(MANIFEST_ENTRY.to_owned(), e)
+        })?;
+      if manifest.format_version > BACKUP_FORMAT_VERSION {
+        return Err(RestoreBackupError::UnsupportedFormat(
+          index,
+          manifest.format_version,
+        ));
+      }
+      check_chain(index, &manifest, previous.as_ref())?;
+
+      // the chunks of one artifact's data are consecutive entries
+      let mut pending: Option<PendingArtifactData> = None;
+      for entry in entries {
+        let mut entry = entry.map_err(RestoreBackupError::IoError)?;
+        let path = entry_path(index, &entry)?;
+
+        let Some(name) = path.strip_prefix(ARTIFACT_DATA_DIR) else {
+          if let Some(data) = pending.take() {
+            report.artifact_data += self.restore_artifact_data(data).await?;
+          }
+          let (table, count) = self
+            .restore_table(index, path, entry, &mut artifact_paths)
+            .await?;
+          *report.records.entry(table).or_default() += count;
+          continue;
+        };
+
+        // version 1 archives hold the whole data in one unnumbered entry
+        let (name, chunk) = match name.split_once('/') {
+          Some((name, chunk)) => (name, Some(chunk)),
+          None => (name, None),
+        };
+        let chunk = chunk
+          .map(|c| {
+            c.parse::<usize>().map_err(|_| {
+              RestoreBackupError::UnexpectedEntry(index, path.clone())
+            })
+          })
+          .transpose()?;
+        let mut data = Vec::new();
+        entry
+          .read_to_end(&mut data)
+          .map_err(RestoreBackupError::IoError)?;
+
+        if let Some(pending) = pending.as_mut().filter(|p| {
+          p.name == name && chunk.is_some_and(|c| c == p.chunks.len())
+        }) {
+          pending.chunks.push(Bytes::from(data));
+          continue;
+        }
+        if let Some(data) = pending.take() {
+          report.artifact_data += self.restore_artifact_data(data).await?;
+        }
+        if chunk.is_some_and(|c| c != 0) {
+          return Err(RestoreBackupError::UnexpectedEntry(index, path));
+        }
+        pending = Some(PendingArtifactData {
+          name:   name.to_owned(),
+          path:   artifact_paths.get(name).copied(),
+          chunks: vec![Bytes::from(data)],
+        });
+      }
+      if let Some(data) = pending.take() {
+        report.artifact_data += self.restore_artifact_data(data).await?;
+      }
+
+      report.archives += 1;
+      previous = Some(manifest);
+    }
+
+    let Some(last) = previous else {
+      return Err(RestoreBackupError::NoArchives);
+    };
+    report.pruned = self.prune_to_snapshot(&last).await?;
+
+    Ok(report)
+  }
+
+  /// Write the data of one artifact. Returns the number of artifacts whose
+  /// data was written.
+  async fn restore_artifact_data(
+    &self,
+    data: PendingArtifactData,
+  ) -> Result<usize, RestoreBackupError> {
+    let Some(artifact_path) = data.path else {
+      tracing::warn!("skipping data of unknown artifact path {}", data.name);
+      return Ok(0);
+    };
+    let chunks = Belt::from_stream(
+      futures::stream::iter(data.chunks.into_iter().map(Ok)),
+      Some(DEFAULT_CHUNK_SIZE),
+    );
+    self
+      .artifact_repo
+      .write_stored_artifact_data(&artifact_path, chunks)
+      .await
+      .map_err(|e| RestoreBackupError::WriteArtifactDataError(data.name, e))?;
+    Ok(1)
+  }
+
+  /// Restore the records of one table entry. Returns the table name and the
+  /// number of records written.
+  async fn restore_table(
+    &self,
+    index: usize,
+    path: String,
+    entry: impl Read,
+    artifact_paths: &mut BTreeMap<String, ArtifactPath>,
+  ) -> Result<(String, usize), RestoreBackupError> {
+    let Some(table) = path
+      .strip_prefix(TABLES_DIR)
+      .and_then(|p| p.strip_suffix(TABLE_EXTENSION))
+      .map(ToOwned::to_owned)
+    else {
+      return Err(RestoreBackupError::UnexpectedEntry(index, path));
+    };
+
+    let count = if table == User::TABLE_NAME {
+      let users = deserialize_lines::<User>(entry, &path)?;
+      let count = users.len();
+      for user in users {
+        self.user_repo.restore_user(user).await?;
+      }
+      count
+    } else if table == PhotoGroup::TABLE_NAME {
+      let photo_groups = deserialize_lines::<PhotoGroup>(entry, &path)?;
+      let count = photo_groups.len();
+      for photo_group in photo_groups {
+        self
+          .photo_group_repo
+          .restore_photo_group(photo_group)
+          .await?;
+      }
+      count
+    } else if table == Photo::TABLE_NAME {
+      let photos = deserialize_lines::<Photo>(entry, &path)?;
+      let count = photos.len();
+      for photo in photos {
+        self.photo_repo.restore_photo(photo).await?;
+      }
+      count
+    } else if table == Image::TABLE_NAME {
+      let images = deserialize_lines::<Image>(entry, &path)?;
+      let count = images.len();
+      for image in images {
+        self.image_repo.restore_image(image).await?;
+      }
+      count
+    } else if table == Artifact::TABLE_NAME {
+      let artifacts = deserialize_lines::<Artifact>(entry, &path)?;
+      let count = artifacts.len();
+      for artifact in artifacts {
+        artifact_paths.insert(artifact.path.to_string(), artifact.path);
+        self.artifact_repo.restore_artifact(artifact).await?;
+      }
+      count
+    } else {
+      return Err(RestoreBackupError::UnexpectedEntry(index, path));
+    };
+    Ok((table, count))
+  }
+
+  /// Delete every record missing from the snapshot of `manifest`. Returns
+  /// the number of records deleted.
+  async fn prune_to_snapshot(
+    &self,
+    manifest: &BackupManifest,
+  ) -> Result<usize, RestoreBackupError> {
+    let mut pruned = 0;
+    let live =
+      |table: &str| manifest.live_ids.get(table).cloned().unwrap_or_default();
+    pruned += self.user_repo.prune_users(&live(User::TABLE_NAME)).await?;
+    pruned += self
+      .photo_group_repo
+      .prune_photo_groups(&live(PhotoGroup::TABLE_NAME))
+      .await?;
+    pruned += self
+      .photo_repo
+      .prune_photos(&live(Photo::TABLE_NAME))
+      .await?;
+    pruned += self
+      .image_repo
+      .prune_images(&live(Image::TABLE_NAME))
+      .await?;
+    pruned += self
+      .artifact_repo
+      .prune_artifacts(&live(Artifact::TABLE_NAME))
+      .await?;
+
+    Ok(pruned)
+  }
+}
@@ -3,6 +3,15 @@
 
 #![feature(iterator_try_collect)]
 
+mod account;
+mod backup;
+mod signed_url;
+#[cfg(test)]
+mod tests;
+mod thumbnail;
+
+use std::{collections::BTreeSet, sync::Arc, time::Duration};
+
 pub use hex;
 use hex::health::{self, HealthAware};
 pub use imaging;
@@ -10,30 +19,46 @@ use imaging::{ImageCreateError, ImageProcessor};
 use miette::{miette, Context, IntoDiagnostic, Result};
 pub use models;
 use models::{
-  Artifact, ArtifactMimeType, ArtifactRecordId, BaseUrl, Image,
-  ImageCreateRequest, ImageRecordId, Photo, PhotoCreateRequest, PhotoGroup,
-  PhotoGroupConfig, PhotoGroupCreateRequest, PhotoGroupFullQuery,
-  PhotoGroupRecordId, PhotoImages, PhotoRecordId, UserRecordId,
+  photo_group_usage_rights_minimum_price, Artifact, ArtifactMimeType,
+  ArtifactRecordId, BaseUrl, EmailAddress, Image, ImageCreateRequest,
+  ImageRecordId, LicenseTier, Photo, PhotoCreateRequest, PhotoGroup,
+  PhotoGroupConfig, PhotoGroupConfigError, PhotoGroupCreateRequest,
+  PhotoGroupEdit, PhotoGroupFullQuery, PhotoGroupMetadata, PhotoGroupRecordId,
+  PhotoImages, PhotoRecordId, UserRecordId, DELETED_USER,
 };
 use qr::QrCodeGenerator;
 pub use repos;
 use repos::{
-  belt::Belt, ArtifactRepository, CreateArtifactError, CreateModelError,
-  FetchModelByIndexError, FetchModelError, ImageRepository,
-  PhotoGroupRepository, PhotoRepository, ReadArtifactError, UserRepository,
+  belt::Belt, ArtifactRepositoryLike, CreateArtifactError, CreateModelError,
+  DeleteArtifactError, DeleteModelError, FetchModelByIndexError,
+  FetchModelError, ImageRepositoryLike, MigrateModelsError, MigrationOptions,
+  MigrationReport, PatchModelError, PhotoGroupRepositoryLike,
+  PhotoRepositoryLike, ReadArtifactError, RewrapArtifactKeysError,
+  SnapshotRepositoryLike, UnitOfWork, UserRepositoryLike,
 };
 use tracing::instrument;
 
+use self::thumbnail::ThumbnailCache;
+pub use self::{
+  account::*, backup::*, signed_url::*, thumbnail::ReadPhotoThumbnailError,
+};
+
+/// How long a signed download URL for a photo original stays valid.
+const ORIGINAL_DOWNLOAD_URL_TTL: Duration = Duration::from_secs(60 * 60);
+
 /// The prime domain service.
 #[derive(Debug, Clone)]
 pub struct PrimeDomainService {
-  artifact_repo:    ArtifactRepository,
+  artifact_repo:    Arc<dyn ArtifactRepositoryLike>,
   image_processor:  ImageProcessor,
-  image_repo:       ImageRepository,
-  photo_group_repo: PhotoGroupRepository,
-  photo_repo:       PhotoRepository,
+  image_repo:       Arc<dyn ImageRepositoryLike>,
+  photo_group_repo: Arc<dyn PhotoGroupRepositoryLike>,
+  photo_repo:       Arc<dyn PhotoRepositoryLike>,
   qr_generator:     QrCodeGenerator,
-  user_repo:        UserRepository,
+  snapshot_repo:    Arc<dyn SnapshotRepositoryLike>,
+  thumbnail_cache:  ThumbnailCache,
+  url_signer:       ArtifactUrlSigner,
+  user_repo:        Arc<dyn UserRepositoryLike>,
 }
 
 #[async_trait::async_trait]
@@ -63,6 +88,9 @@ pub enum CreatePhotoGroupFromImagesError {
   /// Failed to create a photo.
   #[error("failed to create a photo: {0}")]
   PhotoCreatingFailed(CreateModelError),
+  /// The photo group config was invalid.
+  #[error("invalid config: {0}")]
+  InvalidConfig(PhotoGroupConfigError),
   /// An internal error occurred.
   #[error("an internal error occurred")]
   InternalError,
@@ -74,6 +102,9 @@ pub enum CreateImageFromArtifactError {
   /// The artifact didn't exist.
   #[error("missing artifact: {0}")]
   MissingArtifact(ArtifactRecordId),
+  /// Failed to fetch the artifact.
+  #[error("failed to fetch artifact: {0}")]
+  FetchArtifactError(FetchModelError),
   /// Failed to read from an artifact.
   #[error("failed to read from artifact: {0}")]
   ReadArtifactError(ReadArtifactError),
@@ -85,25 +116,147 @@ pub enum CreateImageFromArtifactError {
   CreateImageError(CreateModelError),
 }
 
+/// The possible errors of [`PrimeDomainService::edit_photo_group()`].
+#[derive(Debug, thiserror::Error)]
+pub enum EditPhotoGroupError {
+  /// The photo group didn't exist.
+  #[error("photo group not found")]
+  NotFound,
+  /// The requester isn't the photo group's vendor.
+  #[error("unauthorized")]
+  Unauthorized,
+  /// The new config was invalid.
+  #[error("invalid config: {0}")]
+  InvalidConfig(PhotoGroupConfigError),
+  /// A photo price override was for a tier the group isn't offered under.
+  #[error("{0} isn't offered for this photo group")]
+  TierNotOffered(LicenseTier),
+  /// A photo price override was in the wrong currency or below the minimum.
+  #[error("invalid price for photo {0}")]
+  InvalidPhotoPrice(PhotoRecordId),
+  /// An image to add didn't exist.
+  #[error("missing image: {0}")]
+  MissingImage(ImageRecordId),
+  /// A photo to remove wasn't in the group.
+  #[error("photo not in group: {0}")]
+  PhotoNotInGroup(PhotoRecordId),
+  /// A new photo order didn't contain exactly the group's photos.
+  #[error("new photo order doesn't match the group's photos")]
+  InvalidOrder,
+  /// The edits would leave the group without photos.
+  #[error("a photo group must contain at least one photo")]
+  EmptyGroup,
+  /// Failed to create photos for added images.
+  #[error("failed to create photos: {0}")]
+  PhotoCreationFailed(CreatePhotoGroupFromImagesError),
+  /// The photo group was changed by someone else in the meantime.
+  #[error("photo group was changed in the meantime; try again")]
+  Conflict,
+  /// Failed to fetch the photo group.
+  #[error("failed to fetch photo group: {0}")]
+  FetchError(FetchModelError),
+  /// Failed to update the photo group.
+  #[error("failed to update photo group: {0}")]
+  PatchError(PatchModelError),
+}
+
+/// The possible errors of [`PrimeDomainService::set_photo_group_archived()`].
+#[derive(Debug, thiserror::Error)]
+pub enum ArchivePhotoGroupError {
+  /// The photo group didn't exist.
+  #[error("photo group not found")]
+  NotFound,
+  /// The requester isn't the photo group's vendor.
+  #[error("unauthorized")]
+  Unauthorized,
+  /// The photo group was changed by someone else in the meantime.
+  #[error("photo group was changed in the meantime; try again")]
+  Conflict,
+  /// Failed to fetch the photo group.
+  #[error("failed to fetch photo group: {0}")]
+  FetchError(FetchModelError),
+  /// Failed to update the photo group.
+  #[error("failed to update photo group: {0}")]
+  PatchError(PatchModelError),
+}
+
+/// The possible errors of [`PrimeDomainService::delete_photo_group()`].
+#[derive(Debug, thiserror::Error)]
+pub enum DeletePhotoGroupError {
+  /// The photo group didn't exist.
+  #[error("photo group not found")]
+  NotFound,
+  /// The requester isn't the photo group's vendor.
+  #[error("unauthorized")]
+  Unauthorized,
+  /// The photo group offers a license, so it may have been sold.
+  #[error("photo group is offered for sale; archive it instead")]
+  Licensed,
+  /// Failed to fetch a model.
+  #[error("failed to fetch model: {0}")]
+  FetchError(FetchModelError),
+  /// Failed to fetch models by index.
+  #[error("failed to fetch models by index: {0}")]
+  FetchByIndexError(FetchModelByIndexError),
+  /// Failed to delete a model.
+  #[error("failed to delete model: {0}")]
+  DeleteError(DeleteModelError),
+  /// Failed to delete an artifact.
+  #[error("failed to delete artifact: {0}")]
+  DeleteArtifactError(DeleteArtifactError),
+}
+
+/// The possible errors of
+/// [`PrimeDomainService::photo_original_download_url()`].
+#[derive(Debug, thiserror::Error)]
+pub enum PhotoDownloadUrlError {
+  /// The photo group didn't exist, or didn't contain the photo.
+  #[error("photo not found")]
+  NotFound,
+  /// The requester isn't allowed to download the photo.
+  #[error("unauthorized")]
+  Unauthorized,
+  /// No user has the buyer's email address.
+  #[error("buyer not found")]
+  BuyerNotFound,
+  /// Failed to fetch a model.
+  #[error("failed to fetch model: {0}")]
+  FetchError(FetchModelError),
+  /// Failed to fetch the buyer by email.
+  #[error("failed to fetch buyer: {0}")]
+  FetchBuyerError(FetchModelByIndexError),
+  /// The URL's expiry couldn't be computed.
+  #[error(transparent)]
+  ClockError(ClockError),
+  /// An internal error occurred.
+  #[error("an internal error occurred")]
+  InternalError,
+}
+
 impl PrimeDomainService {
   /// Create a new [`PrimeDomainService`].
   #[must_use]
   pub fn new(
-    artifact_repo: ArtifactRepository,
+    artifact_repo: impl ArtifactRepositoryLike,
     image_processor: ImageProcessor,
-    image_repo: ImageRepository,
-    photo_repo: PhotoRepository,
-    photo_group_repo: PhotoGroupRepository,
-    user_repo: UserRepository,
+    image_repo: impl ImageRepositoryLike,
+    photo_repo: impl PhotoRepositoryLike,
+    photo_group_repo: impl PhotoGroupRepositoryLike,
+    user_repo: impl UserRepositoryLike,
+    snapshot_repo: impl SnapshotRepositoryLike,
+    url_signer: ArtifactUrlSigner,
   ) -> Self {
     Self {
-      artifact_repo,
+      artifact_repo: Arc::new(artifact_repo),
       image_processor,
-      image_repo,
-      photo_repo,
-      photo_group_repo,
-      user_repo,
+      image_repo: Arc::new(image_repo),
+      photo_repo: Arc::new(photo_repo),
+      photo_group_repo: Arc::new(photo_group_repo),
+      user_repo: Arc::new(user_repo),
+      snapshot_repo: Arc::new(snapshot_repo),
+      url_signer,
       qr_generator: QrCodeGenerator::new(),
+      thumbnail_cache: self::thumbnail::new_thumbnail_cache(),
     }
   }
 
@@ -121,12 +274,18 @@ impl PrimeDomainService {
       .await
   }
 
-  /// Create an [`Image`] from an [`Artifact`].
+  /// Create an [`Image`] from an [`Artifact`]. The image is uploaded by
+  /// whoever uploaded the artifact.
   #[instrument(skip(self))]
   pub async fn create_image_from_artifact(
     &self,
     artifact_id: ArtifactRecordId,
   ) -> Result<Image, CreateImageFromArtifactError> {
+    let artifact = self
+      .fetch_artifact(artifact_id)
+      .await
+      .map_err(CreateImageFromArtifactError::FetchArtifactError)?
+      .ok_or(CreateImageFromArtifactError::MissingArtifact(artifact_id))?;
     let (data, _) = self
       .read_artifact_by_id(artifact_id)
       .await
@@ -151,6 +310,7 @@ impl PrimeDomainService {
     let image_cr = ImageCreateRequest {
       artifact: artifact_id,
       meta:     image_meta,
+      uploader: artifact.originator,
     };
 
     let image = self
@@ -162,32 +322,12 @@ impl PrimeDomainService {
     Ok(image)
   }
 
-  /// Create a [`Photo`].
-  #[instrument(skip(self))]
-  async fn create_photo(
-    &self,
-    input: PhotoCreateRequest,
-  ) -> Result<Photo, CreateModelError> {
-    self.photo_repo.create_photo(input).await
-  }
-
-  /// Create a [`PhotoGroup`].
-  #[instrument(skip(self))]
-  async fn create_photo_group(
-    &self,
-    input: PhotoGroupCreateRequest,
-  ) -> Result<PhotoGroup, CreateModelError> {
-    self.photo_group_repo.create_photo_group(input).await
-  }
-
-  /// Create a [`PhotoGroup`] from a set of [`Artifact`]s.
-  #[instrument(skip(self))]
-  pub async fn create_photo_group_from_images(
+  /// Fetch the [`Image`]s that [`Photo`]s are to be created from, failing if
+  /// any of them doesn't exist.
+  async fn fetch_images_for_photos(
     &self,
     image_ids: Vec<ImageRecordId>,
-    config: PhotoGroupConfig,
-    user: UserRecordId,
-  ) -> Result<PhotoGroupRecordId, CreatePhotoGroupFromImagesError> {
+  ) -> Result<Vec<Image>, CreatePhotoGroupFromImagesError> {
     let artifacts = futures::future::join_all(image_ids.into_iter().map(|i| {
       tokio::spawn({
         let pd = self.clone();
@@ -199,7 +339,7 @@ impl PrimeDomainService {
     // we keep the ID with the value the whole way.
     // here we first get rid of the join errors, then the fetch errors, and then
     // we throw if the artifact doesn't exist.
-    let images = artifacts
+    artifacts
       .into_iter()
       .try_collect::<Vec<_>>()
       .map_err(|e| {
@@ -212,31 +352,89 @@ impl PrimeDomainService {
       .map_err(CreatePhotoGroupFromImagesError::ImageFetchingFailed)?
       .into_iter()
       .map(|(ar, a)| a.ok_or(CreatePhotoGroupFromImagesError::MissingImage(ar)))
-      .try_collect::<Vec<_>>()?;
+      .try_collect::<Vec<_>>()
+  }
 
-    let mut photos = Vec::with_capacity(images.len());
-    for image in images {
-      let photo_images = PhotoImages {
+  /// The request for a [`Photo`] of `image`, uploaded by `uploader`.
+  fn photo_create_request(
+    image: &Image,
+    uploader: UserRecordId,
+  ) -> PhotoCreateRequest {
+    PhotoCreateRequest {
+      artifacts: PhotoImages {
         original:  image.id,
         thumbnail: image.id,
-      };
-      let photo_create_request = PhotoCreateRequest {
-        artifacts: photo_images,
-      };
-      let photo = self
-        .create_photo(photo_create_request)
+      },
+      uploader,
+    }
+  }
+
+  /// Create a [`Photo`] uploaded by `uploader` for each of a set of
+  /// [`Image`]s, as part of a [`UnitOfWork`].
+  async fn create_photos_from_images(
+    &self,
+    uow: &mut UnitOfWork,
+    image_ids: Vec<ImageRecordId>,
+    uploader: UserRecordId,
+  ) -> Result<Vec<PhotoRecordId>, CreatePhotoGroupFromImagesError> {
+    let images = self.fetch_images_for_photos(image_ids).await?;
+
+    let mut photos = Vec::with_capacity(images.len());
+    for image in images {
+      let photo = uow
+        .create_photo(
+          &self.photo_repo,
+          Self::photo_create_request(&image, uploader),
+        )
         .await
         .map_err(CreatePhotoGroupFromImagesError::PhotoCreatingFailed)?;
       photos.push(photo.id);
     }
 
+    Ok(photos)
+  }
+
+  /// Undo the writes of a failed [`UnitOfWork`]. Writes that can't be undone
+  /// are logged and left behind.
+  async fn roll_back(uow: UnitOfWork) {
+    if let Err(e) = uow.rollback().await {
+      tracing::error!("failed to roll back unit of work: {e}");
+    }
+  }
+
+  /// Create a [`PhotoGroup`] from a set of [`Artifact`]s.
+  ///
+  /// The group and all of its photos are written in one store transaction,
+  /// so either all of them are created or none are, and a failed creation
+  /// can be retried without leaving duplicates.
+  #[instrument(skip(self))]
+  pub async fn create_photo_group_from_images(
+    &self,
+    image_ids: Vec<ImageRecordId>,
+    config: PhotoGroupConfig,
+    meta: PhotoGroupMetadata,
+    user: UserRecordId,
+  ) -> Result<PhotoGroupRecordId, CreatePhotoGroupFromImagesError> {
+    config
+      .validate()
+      .map_err(CreatePhotoGroupFromImagesError::InvalidConfig)?;
+
+    let photos = self
+      .fetch_images_for_photos(image_ids)
+      .await?
+      .iter()
+      .map(|image| Photo::from(Self::photo_create_request(image, user)))
+      .collect::<Vec<_>>();
+
     let photo_group_create_request = PhotoGroupCreateRequest {
       vendor: user,
-      photos,
+      photos: photos.iter().map(|p| p.id).collect(),
       config,
+      meta: meta.normalize(),
     };
     let photo_group = self
-      .create_photo_group(photo_group_create_request)
+      .photo_group_repo
+      .create_photo_group_with_photos(photo_group_create_request, photos)
       .await
       .map_err(|e| {
         tracing::error!("failed to create photo group: {e}");
@@ -246,6 +444,330 @@ impl PrimeDomainService {
     Ok(photo_group.id)
   }
 
+  /// Apply a set of [`PhotoGroupEdit`]s to a [`PhotoGroup`], in order. Only
+  /// the group's vendor may edit it.
+  ///
+  /// Every edit is checked before anything is written. Photos created for
+  /// added images, and photo price changes, are undone if the edit fails,
+  /// including when the group was changed by someone else in the meantime.
+  /// Once the edit is saved, removed photos are deleted along with any images
+  /// and artifacts no other photo references.
+  #[instrument(skip(self))]
+  pub async fn edit_photo_group(
+    &self,
+    requester: UserRecordId,
+    id: PhotoGroupRecordId,
+    edits: Vec<PhotoGroupEdit>,
+  ) -> Result<PhotoGroup, EditPhotoGroupError> {
+    let mut uow = UnitOfWork::new();
+    match self
+      .edit_photo_group_in(&mut uow, requester, id, edits)
+      .await
+    {
+      Ok((photo_group, removed)) => {
+        uow.commit();
+        // removed photos belonged only to this group
+        if let Err(e) = self.delete_unreferenced_photos(removed).await {
+          tracing::warn!(
+            "failed to clean up photos removed from photo group {id}: {e}"
+          );
+        }
+        Ok(photo_group)
+      }
+      Err(e) => {
+        Self::roll_back(uow).await;
+        Err(e)
+      }
+    }
+  }
+
+  /// Apply a set of [`PhotoGroupEdit`]s as part of a [`UnitOfWork`].
+  /// Returns the edited group and the photos removed from it.
+  async fn edit_photo_group_in(
+    &self,
+    uow: &mut UnitOfWork,
+    requester: UserRecordId,
+    id: PhotoGroupRecordId,
+    edits: Vec<PhotoGroupEdit>,
+  ) -> Result<(PhotoGroup, BTreeSet<PhotoRecordId>), EditPhotoGroupError> {
+    let original = self
+      .photo_group_repo
+      .fetch_photo_group_by_id(id)
+      .await
+      .map_err(EditPhotoGroupError::FetchError)?
+      .ok_or(EditPhotoGroupError::NotFound)?;
+    if original.vendor != requester {
+      return Err(EditPhotoGroupError::Unauthorized);
+    }
+    let mut photo_group = original.clone();
+
+    // check every edit against the group before writing anything. added
+    // images get placeholder photo ids until their photos are created
+    let mut added = Vec::new();
+    let mut price_edits = Vec::new();
+    for edit in edits {
+      match edit {
+        PhotoGroupEdit::SetConfig(config) => {
+          config
+            .validate()
+            .map_err(EditPhotoGroupError::InvalidConfig)?;
+          photo_group.config = config;
+        }
+        PhotoGroupEdit::SetMetadata(meta) => {
+          photo_group.meta = meta.normalize();
+        }
+        PhotoGroupEdit::AddImages(image_ids) => {
+          for image in image_ids {
+            self
+              .fetch_image(image)
+              .await
+              .map_err(EditPhotoGroupError::FetchError)?
+              .ok_or(EditPhotoGroupError::MissingImage(image))?;
+            let placeholder = PhotoRecordId::new();
+            photo_group.photos.push(placeholder);
+            added.push((placeholder, image));
+          }
+        }
+        PhotoGroupEdit::RemovePhoto(photo) => {
+          let Some(index) = photo_group.photos.iter().position(|p| *p == photo)
+          else {
+            return Err(EditPhotoGroupError::PhotoNotInGroup(photo));
+          };
+          photo_group.photos.remove(index);
+        }
+        PhotoGroupEdit::ReorderPhotos(order) => {
+          let mut current = photo_group.photos.clone();
+          let mut requested = order.clone();
+          current.sort_unstable();
+          requested.sort_unstable();
+          if current != requested {
+            return Err(EditPhotoGroupError::InvalidOrder);
+          }
+          photo_group.photos = order;
+        }
+        PhotoGroupEdit::SetPhotoPrice { photo, tier, price } => {
+          if !photo_group.photos.contains(&photo) {
+            return Err(EditPhotoGroupError::PhotoNotInGroup(photo));
+          }
+          let offer = photo_group
+            .config
+            .offer(tier)
+            .ok_or(EditPhotoGroupError::TierNotOffered(tier))?;
+          if let Some(price) = price {
+            let currency = offer.group_price.currency();
+            if price.currency() != currency
+              || price < photo_group_usage_rights_minimum_price(currency)
+            {
+              return Err(EditPhotoGroupError::InvalidPhotoPrice(photo));
+            }
+          }
+          price_edits.push((photo, tier, price));
+        }
+      }
+    }
+
+    if photo_group.photos.is_empty() {
+      return Err(EditPhotoGroupError::EmptyGroup);
+    }
+
+    // the edits are valid, so the remaining writes can only fail in storage,
+    // and the unit undoes them if they do
+    let (placeholders, image_ids): (Vec<_>, Vec<_>) = added.into_iter().unzip();
+    let photos = self
+      .create_photos_from_images(uow, image_ids, requester)
+      .await
+      .map_err(|e| match e {
+        CreatePhotoGroupFromImagesError::MissingImage(i) => {
+          EditPhotoGroupError::MissingImage(i)
+        }
+        e => EditPhotoGroupError::PhotoCreationFailed(e),
+      })?;
+    for (placeholder, photo) in placeholders.into_iter().zip(photos) {
+      if let Some(slot) =
+        photo_group.photos.iter_mut().find(|p| **p == placeholder)
+      {
+        *slot = photo;
+      }
+    }
+
+    for (photo, tier, price) in price_edits {
+      if !photo_group.photos.contains(&photo) {
+        continue;
+      }
+      let photo = self
+        .photo_repo
+        .fetch_photo_by_id(photo)
+        .await
+        .map_err(EditPhotoGroupError::FetchError)?
+        .ok_or(EditPhotoGroupError::PhotoNotInGroup(photo))?;
+      uow
+        .update_photo(&self.photo_repo, photo, |photo| {
+          match price {
+            Some(price) => photo.price_overrides.insert(tier, price),
+            None => photo.price_overrides.remove(&tier),
+          };
+        })
+        .await
+        .map_err(EditPhotoGroupError::PatchError)?;
+    }
+
+    let removed = original
+      .photos
+      .iter()
+      .filter(|p| !photo_group.photos.contains(p))
+      .copied()
+      .collect();
+    // the group is only written if nobody else wrote it since it was fetched,
+    // or one of the writes would be lost
+    let photo_group = self
+      .photo_group_repo
+      .replace_photo_group(&original, photo_group)
+      .await
+      .map_err(EditPhotoGroupError::PatchError)?
+      .ok_or(EditPhotoGroupError::Conflict)?;
+
+    Ok((photo_group, removed))
+  }
+
+  /// Archive or unarchive a [`PhotoGroup`]. Archived groups are hidden from
+  /// everyone but their vendor, but none of their data is removed. Only the
+  /// group's vendor may archive it.
+  #[instrument(skip(self))]
+  pub async fn set_photo_group_archived(
+    &self,
+    requester: UserRecordId,
+    id: PhotoGroupRecordId,
+    archived: bool,
+  ) -> Result<PhotoGroup, ArchivePhotoGroupError> {
+    let original = self
+      .photo_group_repo
+      .fetch_photo_group_by_id(id)
+      .await
+      .map_err(ArchivePhotoGroupError::FetchError)?
+      .ok_or(ArchivePhotoGroupError::NotFound)?;
+    if original.vendor != requester {
+      return Err(ArchivePhotoGroupError::Unauthorized);
+    }
+    if original.is_archived() == archived {
+      return Ok(original);
+    }
+
+    let mut photo_group = original.clone();
+    photo_group.archived_at = archived.then(chrono::Utc::now);
+    self
+      .photo_group_repo
+      .replace_photo_group(&original, photo_group)
+      .await
+      .map_err(ArchivePhotoGroupError::PatchError)?
+      .ok_or(ArchivePhotoGroupError::Conflict)
+  }
+
+  /// Permanently delete a [`PhotoGroup`], along with its [`Photo`]s and any
+  /// [`Image`]s and [`Artifact`]s that no other photo references. Only the
+  /// group's vendor may delete it.
+  ///
+  /// Unlike archiving, this can't be undone. Sales aren't recorded yet, so a
+  /// group that offers a license is treated as sold and refused; it should be
+  /// archived instead, so buyers keep access to their photos.
+  #[instrument(skip(self))]
+  pub async fn delete_photo_group(
+    &self,
+    requester: UserRecordId,
+    id: PhotoGroupRecordId,
+  ) -> Result<(), DeletePhotoGroupError> {
+    let photo_group = self
+      .photo_group_repo
+      .fetch_photo_group_by_id(id)
+      .await
+      .map_err(DeletePhotoGroupError::FetchError)?
+      .ok_or(DeletePhotoGroupError::NotFound)?;
+    if photo_group.vendor != requester {
+      return Err(DeletePhotoGroupError::Unauthorized);
+    }
+    if !photo_group.config.licenses.is_empty() {
+      return Err(DeletePhotoGroupError::Licensed);
+    }
+
+    self
+      .photo_group_repo
+      .delete_photo_group(id)
+      .await
+      .map_err(DeletePhotoGroupError::DeleteError)?;
+    // photos are created for the group they're added to, so no other group
+    // references them
+    self
+      .delete_unreferenced_photos(photo_group.photos.into_iter().collect())
+      .await
+  }
+
+  /// Delete [`Photo`]s that no [`PhotoGroup`] references any more, along with
+  /// any [`Image`]s and [`Artifact`]s that no other photo references.
+  async fn delete_unreferenced_photos(
+    &self,
+    deleted_photos: BTreeSet<PhotoRecordId>,
+  ) -> Result<(), DeletePhotoGroupError> {
+    let mut candidate_images = BTreeSet::new();
+    for photo in &deleted_photos {
+      let Some(photo) = self
+        .photo_repo
+        .fetch_photo_by_id(*photo)
+        .await
+        .map_err(DeletePhotoGroupError::FetchError)?
+      else {
+        continue;
+      };
+      candidate_images
+        .extend([photo.artifacts.original, photo.artifacts.thumbnail]);
+    }
+
+    // images referenced by other photos are kept
+    let mut deleted_images = Vec::new();
+    for image in candidate_images {
+      let shared = self
+        .photo_repo
+        .fetch_photos_by_image(image)
+        .await
+        .map_err(DeletePhotoGroupError::FetchByIndexError)?
+        .iter()
+        .any(|p| !deleted_photos.contains(&p.id));
+      if !shared {
+        deleted_images.push(image);
+      }
+    }
+
+    // delete from the top down, so that a failure part-way through leaves
+    // orphaned records rather than dangling references
+    for photo in deleted_photos {
+      self
+        .photo_repo
+        .delete_photo(photo)
+        .await
+        .map_err(DeletePhotoGroupError::DeleteError)?;
+      self.thumbnail_cache.invalidate(&photo);
+    }
+    for image in deleted_images {
+      let Some(image) = self
+        .fetch_image(image)
+        .await
+        .map_err(DeletePhotoGroupError::FetchError)?
+      else {
+        continue;
+      };
+      self
+        .image_repo
+        .delete_image(image.id)
+        .await
+        .map_err(DeletePhotoGroupError::DeleteError)?;
+      self
+        .artifact_repo
+        .delete_artifact(image.artifact)
+        .await
+        .map_err(DeletePhotoGroupError::DeleteArtifactError)?;
+    }
+
+    Ok(())
+  }
+
   /// Fetch an [`Artifact`].
   #[instrument(skip(self))]
   pub async fn fetch_artifact(
@@ -264,6 +786,30 @@ impl PrimeDomainService {
     self.artifact_repo.read_artifact_by_id(id).await
   }
 
+  /// Re-wrap [`Artifact`] data keys with the current master key.
+  #[instrument(skip(self))]
+  pub async fn rewrap_artifact_keys(
+    &self,
+  ) -> Result<usize, RewrapArtifactKeysError> {
+    self.artifact_repo.rewrap_artifact_keys().await
+  }
+
+  /// Upcast all stored models written at older schema versions and
+  /// re-write them. Returns a report for each model.
+  #[instrument(skip(self))]
+  pub async fn migrate_models(
+    &self,
+    options: MigrationOptions,
+  ) -> Result<Vec<MigrationReport>, MigrateModelsError> {
+    Ok(vec![
+      self.artifact_repo.migrate_artifacts(options).await?,
+      self.image_repo.migrate_images(options).await?,
+      self.photo_repo.migrate_photos(options).await?,
+      self.photo_group_repo.migrate_photo_groups(options).await?,
+      self.user_repo.migrate_users(options).await?,
+    ])
+  }
+
   /// Fetch a [`Image`].
   #[instrument(skip(self))]
   pub async fn fetch_image(
@@ -282,7 +828,9 @@ impl PrimeDomainService {
     self.photo_repo.fetch_photo_by_id(id).await
   }
 
-  /// Fetch a [`PhotoGroup`].
+  /// Fetch a [`PhotoGroup`], along with its vendor, its [`Photo`]s and their
+  /// thumbnail [`Image`]s. The photos and images are each fetched in one
+  /// concurrent batch.
   #[instrument(skip(self))]
   pub async fn fetch_photo_group(
     &self,
@@ -293,23 +841,84 @@ impl PrimeDomainService {
     else {
       return Ok(None);
     };
-    let vendor_data = self
-      .user_repo
-      .fetch_user_by_id(photo_group.vendor)
-      .await?
-      .ok_or(FetchModelError::Db(miette!(
-        "user (vendor) {vendor} not found: listed in photo_group {photo_group}",
-        vendor = photo_group.vendor,
-        photo_group = photo_group.id
-      )))?
-      .into();
+
+    let vendor_fut = self.user_repo.fetch_user_by_id(photo_group.vendor);
+    let photos_fut = futures::future::try_join_all(
+      photo_group
+        .photos
+        .iter()
+        .map(|p| self.photo_repo.fetch_photo_by_id(*p)),
+    );
+    let (vendor, photos) = futures::try_join!(vendor_fut, photos_fut)?;
+
+    let vendor_data = match vendor {
+      Some(vendor) => vendor.into(),
+      // the vendor deleted their account, and the group was kept for buyers
+      None if photo_group.vendor == DELETED_USER.id => DELETED_USER.clone(),
+      None => {
+        return Err(FetchModelError::Db(miette!(
+          "user (vendor) {vendor} not found: listed in photo_group \
+           {photo_group}",
+          vendor = photo_group.vendor,
+          photo_group = photo_group.id
+        )))
+      }
+    };
+    let photos = photo_group
+      .photos
+      .iter()
+      .zip(photos)
+      .map(|(photo_id, photo)| {
+        photo.ok_or(FetchModelError::Db(miette!(
+          "photo {photo_id} not found: listed in photo_group {photo_group}",
+          photo_group = photo_group.id
+        )))
+      })
+      .collect::<Result<Vec<_>, _>>()?;
+
+    let thumbnails = futures::future::try_join_all(
+      photos
+        .iter()
+        .map(|p| self.image_repo.fetch_image_by_id(p.artifacts.thumbnail)),
+    )
+    .await?
+    .into_iter()
+    .zip(&photos)
+    .map(|(image, photo)| {
+      image.ok_or(FetchModelError::Db(miette!(
+        "image {image} not found: thumbnail of photo {photo}",
+        image = photo.artifacts.thumbnail,
+        photo = photo.id
+      )))
+    })
+    .collect::<Result<Vec<_>, _>>()?;
 
     Ok(Some(PhotoGroupFullQuery {
       photo_group,
+      photos,
+      thumbnails,
       vendor_data,
     }))
   }
 
+  /// Fetch the thumbnail [`Image`] of a [`Photo`].
+  #[instrument(skip(self))]
+  pub async fn fetch_thumbnail_image_for_photo(
+    &self,
+    id: PhotoRecordId,
+  ) -> Result<Option<Image>, FetchModelError> {
+    let Some(photo) = self.photo_repo.fetch_photo_by_id(id).await? else {
+      return Ok(None);
+    };
+    let image_id = photo.artifacts.thumbnail;
+    let image = self.image_repo.fetch_image_by_id(image_id).await?.ok_or(
+      FetchModelError::Db(miette!(
+        "image {image_id} not found: thumbnail of photo {id}"
+      )),
+    )?;
+    Ok(Some(image))
+  }
+
   /// Fetch [`PhotoGroup`]s by user.
   #[instrument(skip(self))]
   pub async fn fetch_photo_groups_by_user(
@@ -335,4 +944,106 @@ impl PrimeDomainService {
       .into_diagnostic()
       .context("failed to generate photo group qr code")
   }
+
+  /// Create a signed URL for downloading an [`Artifact`], valid for `ttl` and
+  /// optionally bound to a user.
+  #[instrument(skip(self))]
+  pub fn sign_artifact_download_url(
+    &self,
+    base_url: &BaseUrl,
+    artifact: ArtifactRecordId,
+    ttl: Duration,
+    user: Option<UserRecordId>,
+  ) -> Result<String, ClockError> {
+    let claims = ArtifactDownloadClaims {
+      artifact,
+      expires: expiry_from_now(ttl)?,
+      user,
+    };
+    Ok(self.url_signer.signed_url(base_url, &claims))
+  }
+
+  /// Verify the claims and signature of a signed artifact download URL.
+  #[instrument(skip(self))]
+  pub fn verify_artifact_download(
+    &self,
+    claims: &ArtifactDownloadClaims,
+    signature: &str,
+    requester: Option<UserRecordId>,
+  ) -> Result<(), VerifyArtifactDownloadError> {
+    self.url_signer.verify(claims, signature, requester)
+  }
+
+  /// Create a signed URL for downloading the original of a [`Photo`] in a
+  /// [`PhotoGroup`]. Only the group's vendor may request one. The URL is bound
+  /// to the user with the buyer's email if one is given, so only they can use
+  /// it; otherwise anyone with the URL can, without a session.
+  #[instrument(skip(self))]
+  pub async fn photo_original_download_url(
+    &self,
+    base_url: &BaseUrl,
+    requester: UserRecordId,
+    photo_group: PhotoGroupRecordId,
+    photo: PhotoRecordId,
+    buyer: Option<EmailAddress>,
+  ) -> Result<String, PhotoDownloadUrlError> {
+    let photo_group = self
+      .photo_group_repo
+      .fetch_photo_group_by_id(photo_group)
+      .await
+      .map_err(PhotoDownloadUrlError::FetchError)?
+      .ok_or(PhotoDownloadUrlError::NotFound)?;
+    if !photo_group.photos.contains(&photo) {
+      return Err(PhotoDownloadUrlError::NotFound);
+    }
+    if photo_group.vendor != requester {
+      return Err(PhotoDownloadUrlError::Unauthorized);
+    }
+
+    let photo = self
+      .fetch_photo(photo)
+      .await
+      .map_err(PhotoDownloadUrlError::FetchError)?
+      .ok_or_else(|| {
+        tracing::error!(
+          "photo {photo} missing (referenced by photo group {pg})",
+          pg = photo_group.id
+        );
+        PhotoDownloadUrlError::InternalError
+      })?;
+    let image_id = photo.artifacts.original;
+    let image = self
+      .fetch_image(image_id)
+      .await
+      .map_err(PhotoDownloadUrlError::FetchError)?
+      .ok_or_else(|| {
+        tracing::error!(
+          "image {image_id} missing (referenced by photo {photo})",
+          photo = photo.id
+        );
+        PhotoDownloadUrlError::InternalError
+      })?;
+
+    let buyer = match buyer {
+      Some(email) => Some(
+        self
+          .user_repo
+          .fetch_user_by_email(email)
+          .await
+          .map_err(PhotoDownloadUrlError::FetchBuyerError)?
+          .ok_or(PhotoDownloadUrlError::BuyerNotFound)?
+          .id,
+      ),
+      None => None,
+    };
+
+    self
+      .sign_artifact_download_url(
+        base_url,
+        image.artifact,
+        ORIGINAL_DOWNLOAD_URL_TTL,
+        buyer,
+      )
+      .map_err(PhotoDownloadUrlError::ClockError)
+  }
 }
@@ -0,0 +1,297 @@
+//! Signed, expiring URLs for downloading artifacts without a session.
+
+use std::{
+  fmt,
+  sync::Arc,
+  time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use models::{ArtifactRecordId, BaseUrl, UserRecordId};
+use rand::RngCore;
+use sha2::Sha256;
+
+/// The path prefix of signed artifact download URLs.
+pub const ARTIFACT_DOWNLOAD_PATH: &str = "/api/download/";
+
+/// The claims carried by a signed artifact download URL.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArtifactDownloadClaims {
+  /// The artifact to download.
+  pub artifact: ArtifactRecordId,
+  /// The expiry time, in seconds since the Unix epoch.
+  pub expires:  u64,
+  /// The user the URL is bound to, if any.
+  pub user:     Option<UserRecordId>,
+}
+
+impl ArtifactDownloadClaims {
+  /// The canonical message that is signed for these claims.
+  fn message(&self) -> String {
+    format!(
+      "{artifact}:{expires}:{user}",
+      artifact = self.artifact,
+      expires = self.expires,
+      user = self.user.map(|u| u.to_string()).unwrap_or_default(),
+    )
+  }
+
+  /// The number of seconds left until the claims expire.
+  pub fn remaining_secs(&self) -> Result<u64, ClockError> {
+    Ok(self.expires.saturating_sub(unix_now()?))
+  }
+}
+
+/// The system clock is set before the Unix epoch, so expiry times can't be
+/// computed or checked.
+#[derive(Debug, thiserror::Error, PartialEq)]
+#[error("system clock is set before the unix epoch")]
+pub struct ClockError;
+
+/// The possible errors of verifying a signed artifact download URL.
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum VerifyArtifactDownloadError {
+  /// The signature didn't match the claims.
+  #[error("invalid signature")]
+  InvalidSignature,
+  /// The URL has expired.
+  #[error("download url expired")]
+  Expired,
+  /// The URL is bound to a different user than the requester.
+  #[error("download url is bound to another user")]
+  WrongUser,
+  /// The expiry couldn't be checked against the system clock.
+  #[error(transparent)]
+  Clock(#[from] ClockError),
+}
+
+/// Signs and verifies artifact download URLs with HMAC-SHA256.
+#[derive(Clone)]
+pub struct ArtifactUrlSigner {
+  secret: Arc<[u8]>,
+}
+
+impl fmt::Debug for ArtifactUrlSigner {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("ArtifactUrlSigner").finish_non_exhaustive()
+  }
+}
+
+impl ArtifactUrlSigner {
+  /// Creates a new [`ArtifactUrlSigner`] from a secret.
+  #[must_use]
+  pub fn new(secret: &[u8]) -> Self {
+    Self {
+      secret: secret.into(),
+    }
+  }
+
+  /// Creates a new [`ArtifactUrlSigner`] with a random secret. URLs signed
+  /// with it won't verify after a restart.
+  #[must_use]
+  pub fn new_random() -> Self {
+    let mut secret = [0; 32];
+    rand::rng().fill_bytes(&mut secret);
+    Self::new(&secret)
+  }
+
+  fn mac(&self, claims: &ArtifactDownloadClaims) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(&self.secret)
+      .expect("hmac accepts keys of any length");
+    mac.update(claims.message().as_bytes());
+    mac
+  }
+
+  /// Signs a set of claims, producing a URL-safe signature.
+  #[must_use]
+  pub fn sign(&self, claims: &ArtifactDownloadClaims) -> String {
+    base64::prelude::BASE64_URL_SAFE_NO_PAD
+      .encode(self.mac(claims).finalize().into_bytes())
+  }
+
+  /// Builds a full signed download URL for a set of claims.
+  #[must_use]
+  pub fn signed_url(
+    &self,
+    base_url: &BaseUrl,
+    claims: &ArtifactDownloadClaims,
+  ) -> String {
+    let user = claims
+      .user
+      .map(|user| format!("&user={user}"))
+      .unwrap_or_default();
+    format!(
+      "{base_url}{ARTIFACT_DOWNLOAD_PATH}{artifact}?expires={expires}{user}&\
+       signature={signature}",
+      base_url = base_url.0,
+      artifact = claims.artifact,
+      expires = claims.expires,
+      signature = self.sign(claims),
+    )
+  }
+
+  /// Verifies a signature against a set of claims, checking expiry and the
+  /// user binding against the requesting user.
+  pub fn verify(
+    &self,
+    claims: &ArtifactDownloadClaims,
+    signature: &str,
+    requester: Option<UserRecordId>,
+  ) -> Result<(), VerifyArtifactDownloadError> {
+    let signature =
+      base64::prelude::BASE64_URL_SAFE_NO_PAD
+        .decode(signature)
+        .map_err(|_| VerifyArtifactDownloadError::InvalidSignature)?;
+    self
+      .mac(claims)
+      .verify_slice(&signature)
+      .map_err(|_| VerifyArtifactDownloadError::InvalidSignature)?;
+
+    if claims.expires <= unix_now()? {
+      return Err(VerifyArtifactDownloadError::Expired);
+    }
+    if claims.user.is_some() && claims.user != requester {
+      return Err(VerifyArtifactDownloadError::WrongUser);
+    }
+
+    Ok(())
+  }
+}
+
+/// Computes an expiry timestamp `ttl` from now.
+pub fn expiry_from_now(ttl: Duration) -> Result<u64, ClockError> {
+  Ok(unix_now()?.saturating_add(ttl.as_secs()))
+}
+
+fn unix_now() -> Result<u64, ClockError> {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_secs())
+    .map_err(|_| ClockError)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn signer() -> ArtifactUrlSigner { ArtifactUrlSigner::new(b"test secret") }
+
+  fn claims(user: Option<UserRecordId>) -> ArtifactDownloadClaims {
+    ArtifactDownloadClaims {
+      artifact: ArtifactRecordId::new(),
+      expires: expiry_from_now(Duration::from_secs(30)).unwrap(),
+      user,
+    }
+  }
+
+  #[test]
+  fn unbound_urls_verify_without_a_session() {
+    let signer = signer();
+    let claims = claims(None);
+    let signature = signer.sign(&claims);
+
+    assert_eq!(signer.verify(&claims, &signature, None), Ok(()));
+    assert_eq!(
+      signer.verify(&claims, &signature, Some(UserRecordId::new())),
+      Ok(())
+    );
+  }
+
+  #[test]
+  fn bound_urls_only_verify_for_their_user() {
+    let signer = signer();
+    let buyer = UserRecordId::new();
+    let claims = claims(Some(buyer));
+    let signature = signer.sign(&claims);
+
+    assert_eq!(signer.verify(&claims, &signature, Some(buyer)), Ok(()));
+    assert_eq!(
+      signer.verify(&claims, &signature, Some(UserRecordId::new())),
+      Err(VerifyArtifactDownloadError::WrongUser)
+    );
+    assert_eq!(
+      signer.verify(&claims, &signature, None),
+      Err(VerifyArtifactDownloadError::WrongUser)
+    );
+  }
+
+  #[test]
+  fn changed_claims_are_rejected() {
+    let signer = signer();
+    let buyer = UserRecordId::new();
+    let original = claims(Some(buyer));
+    let signature = signer.sign(&original);
+
+    let unbound = ArtifactDownloadClaims {
+      user: None,
+      ..original.clone()
+    };
+    let extended = ArtifactDownloadClaims {
+      expires: original.expires + 60,
+      ..original.clone()
+    };
+    let other_artifact = ArtifactDownloadClaims {
+      artifact: ArtifactRecordId::new(),
+      ..original.clone()
+    };
+    for claims in [unbound, extended, other_artifact] {
+      assert_eq!(
+        signer.verify(&claims, &signature, Some(buyer)),
+        Err(VerifyArtifactDownloadError::InvalidSignature)
+      );
+    }
+    assert_eq!(
+      ArtifactUrlSigner::new(b"other secret").verify(
+        &original,
+        &signature,
+        Some(buyer)
+      ),
+      Err(VerifyArtifactDownloadError::InvalidSignature)
+    );
+    assert_eq!(
+      signer.verify(&original, "not base64!", Some(buyer)),
+      Err(VerifyArtifactDownloadError::InvalidSignature)
+    );
+  }
+
+  #[test]
+  fn expired_urls_are_rejected() {
+    let signer = signer();
+    let claims = ArtifactDownloadClaims {
+      expires: unix_now().unwrap(),
+      ..claims(None)
+    };
+    let signature = signer.sign(&claims);
+
+    assert_eq!(
+      signer.verify(&claims, &signature, None),
+      Err(VerifyArtifactDownloadError::Expired)
+    );
+    assert_eq!(claims.remaining_secs(), Ok(0));
+  }
+
+  #[test]
+  fn signed_urls_carry_the_claims() {
+    let signer = signer();
+    let buyer = UserRecordId::new();
+    let base_url = BaseUrl("https://picturepro.test".to_owned());
+
+    let unbound = claims(None);
+    let url = signer.signed_url(&base_url, &unbound);
+    assert_eq!(
+      url,
+      format!(
+        "https://picturepro.test/api/download/{artifact}?expires={expires}\
+         &signature={signature}",
+        artifact = unbound.artifact,
+        expires = unbound.expires,
+        signature = signer.sign(&unbound),
+      )
+    );
+
+    let bound = claims(Some(buyer));
+    let url = signer.signed_url(&base_url, &bound);
+    assert!(url.contains(&format!("&user={buyer}&")));
+  }
+}
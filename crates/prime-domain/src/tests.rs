@@ -0,0 +1,1034 @@
+use std::{collections::BTreeMap, io::Read};
+
+use models::{
+  Currency, EmailAddress, HumanName, ImageCreateRequest, ImageMetadata,
+  ImageTinyPreview, LicenseOffer, LicenseTier, Model, Money, PasswordHash,
+  UserAuthCredentials, UserCreateRequest,
+};
+use repos::fake::{
+  FakeArtifactRepository, FakeImageRepository, FakePhotoGroupRepository,
+  FakePhotoRepository, FakeSnapshotRepository, FakeUserRepository,
+};
+
+use super::*;
+
+struct Harness {
+  service:      PrimeDomainService,
+  artifacts:    FakeArtifactRepository,
+  images:       FakeImageRepository,
+  photos:       FakePhotoRepository,
+  photo_groups: FakePhotoGroupRepository,
+  users:        FakeUserRepository,
+}
+
+impl Harness {
+  fn new() -> Self {
+    let artifacts = FakeArtifactRepository::new();
+    let images = FakeImageRepository::new();
+    let photos = FakePhotoRepository::new();
+    let photo_groups = FakePhotoGroupRepository::with_photos(&photos);
+    let users = FakeUserRepository::new();
+    let snapshots = FakeSnapshotRepository::new(
+      &artifacts,
+      &images,
+      &photos,
+      &photo_groups,
+      &users,
+    );
+    let service = PrimeDomainService::new(
+      artifacts.clone(),
+      ImageProcessor::new(),
+      images.clone(),
+      photos.clone(),
+      photo_groups.clone(),
+      users.clone(),
+      snapshots,
+      ArtifactUrlSigner::new_random(),
+    );
+    Self {
+      service,
+      artifacts,
+      images,
+      photos,
+      photo_groups,
+      users,
+    }
+  }
+
+  async fn user(&self, email: &str) -> UserRecordId {
+    let email = EmailAddress::try_new(email).unwrap();
+    self
+      .users
+      .create_user(UserCreateRequest {
+        name:  HumanName::try_new("Test User").unwrap(),
+        email: email.clone(),
+        auth:  UserAuthCredentials::EmailAndPassword {
+          email,
+          password_hash: PasswordHash("not a real hash".to_owned()),
+        },
+      })
+      .await
+      .unwrap()
+      .id
+  }
+
+  async fn image(&self, vendor: UserRecordId) -> ImageRecordId {
+    let data = Belt::from_stream(
+      futures::stream::once(async { Ok(bytes::Bytes::from_static(b"image")) }),
+      None,
+    );
+    let artifact = self
+      .artifacts
+      .create_artifact(data, vendor, None)
+      .await
+      .unwrap();
+    self
+      .images
+      .create_image(ImageCreateRequest {
+        artifact: artifact.id,
+        meta:     ImageMetadata {
+          width:        4,
+          height:       3,
+          tiny_preview: ImageTinyPreview {
+            width:  4,
+            height: 3,
+            data:   Vec::new(),
+          },
+        },
+        uploader: vendor,
+      })
+      .await
+      .unwrap()
+      .id
+  }
+
+  async fn images(
+    &self,
+    vendor: UserRecordId,
+    count: usize,
+  ) -> Vec<ImageRecordId> {
+    let mut images = Vec::with_capacity(count);
+    for _ in 0..count {
+      images.push(self.image(vendor).await);
+    }
+    images
+  }
+
+  /// Removes every license offer from a group, as for groups stored before
+  /// licenses were required.
+  async fn withdraw_licenses(&self, id: PhotoGroupRecordId) {
+    let mut photo_group = self
+      .photo_groups
+      .fetch_photo_group_by_id(id)
+      .await
+      .unwrap()
+      .unwrap();
+    photo_group.config.licenses.clear();
+    self
+      .photo_groups
+      .update_photo_group(photo_group)
+      .await
+      .unwrap();
+  }
+}
+
+fn config() -> PhotoGroupConfig {
+  PhotoGroupConfig {
+    licenses: vec![LicenseOffer {
+      tier:        LicenseTier::PersonalDigital,
+      group_price: Money::from_minor_units(2500, Currency::Usd),
+      photo_price: None,
+    }],
+  }
+}
+
+#[tokio::test]
+async fn create_photo_group_creates_a_photo_per_image() {
+  let h = Harness::new();
+  let vendor = h.user("vendor@example.com").await;
+  let images = h.images(vendor, 3).await;
+
+  let id = h
+    .service
+    .create_photo_group_from_images(
+      images.clone(),
+      config(),
+      PhotoGroupMetadata::default(),
+      vendor,
+    )
+    .await
+    .unwrap();
+
+  let query = h.service.fetch_photo_group(id).await.unwrap().unwrap();
+  assert_eq!(query.photo_group.vendor, vendor);
+  assert_eq!(query.photo_group.config, config());
+  assert_eq!(
+    query
+      .photos
+      .iter()
+      .map(|p| p.artifacts.original)
+      .collect::<Vec<_>>(),
+    images,
+  );
+  assert_eq!(
+    query.photos.iter().map(|p| p.id).collect::<Vec<_>>(),
+    query.photo_group.photos,
+  );
+  assert_eq!(
+    query
+      .photos_with_thumbnails()
+      .map(|(p, t)| (p.artifacts.thumbnail, t.id))
+      .collect::<Vec<_>>(),
+    images.iter().map(|i| (*i, *i)).collect::<Vec<_>>(),
+  );
+}
+
+#[tokio::test]
+async fn create_photo_group_rejects_invalid_config() {
+  let h = Harness::new();
+  let vendor = h.user("vendor@example.com").await;
+  let images = h.images(vendor, 1).await;
+
+  let result = h
+    .service
+    .create_photo_group_from_images(
+      images,
+      PhotoGroupConfig { licenses: vec![] },
+      PhotoGroupMetadata::default(),
+      vendor,
+    )
+    .await;
+
+  assert!(matches!(
+    result,
+    Err(CreatePhotoGroupFromImagesError::InvalidConfig(
+      PhotoGroupConfigError::NoLicenses
+    ))
+  ));
+  assert!(h.photos.enumerate_photos().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn create_photo_group_rejects_missing_image() {
+  let h = Harness::new();
+  let vendor = h.user("vendor@example.com").await;
+  let mut images = h.images(vendor, 2).await;
+  let missing = ImageRecordId::new();
+  images.push(missing);
+
+  let result = h
+    .service
+    .create_photo_group_from_images(
+      images,
+      config(),
+      PhotoGroupMetadata::default(),
+      vendor,
+    )
+    .await;
+
+  assert!(matches!(
+    result,
+    Err(CreatePhotoGroupFromImagesError::MissingImage(id)) if id == missing
+  ));
+  // images are all fetched before any photo is created
+  assert!(h.photos.enumerate_photos().await.unwrap().is_empty());
+  assert!(h
+    .photo_groups
+    .enumerate_photo_groups()
+    .await
+    .unwrap()
+    .is_empty());
+}
+
+#[tokio::test]
+async fn create_photo_group_reports_failed_image_fetch() {
+  let h = Harness::new();
+  let vendor = h.user("vendor@example.com").await;
+  let images = h.images(vendor, 2).await;
+  h.images.faults().fail("fetch_image_by_id");
+
+  let result = h
+    .service
+    .create_photo_group_from_images(
+      images,
+      config(),
+      PhotoGroupMetadata::default(),
+      vendor,
+    )
+    .await;
+
+  assert!(matches!(
+    result,
+    Err(CreatePhotoGroupFromImagesError::ImageFetchingFailed(_))
+  ));
+  assert!(h.photos.enumerate_photos().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn create_photo_group_leaves_nothing_behind_on_failure() {
+  let h = Harness::new();
+  let vendor = h.user("vendor@example.com").await;
+  let images = h.images(vendor, 2).await;
+  h.photo_groups
+    .faults()
+    .fail("create_photo_group_with_photos");
+
+  let result = h
+    .service
+    .create_photo_group_from_images(
+      images,
+      config(),
+      PhotoGroupMetadata::default(),
+      vendor,
+    )
+    .await;
+
+  assert!(matches!(
+    result,
+    Err(CreatePhotoGroupFromImagesError::InternalError)
+  ));
+  assert!(h.photos.enumerate_photos().await.unwrap().is_empty());
+  assert!(h
+    .photo_groups
+    .enumerate_photo_groups()
+    .await
+    .unwrap()
+    .is_empty());
+}
+
+#[tokio::test]
+async fn create_photo_group_retry_does_not_duplicate_photos() {
+  let h = Harness::new();
+  let vendor = h.user("vendor@example.com").await;
+  let images = h.images(vendor, 3).await;
+  h.photo_groups
+    .faults()
+    .fail("create_photo_group_with_photos");
+
+  let result = h
+    .service
+    .create_photo_group_from_images(
+      images.clone(),
+      config(),
+      PhotoGroupMetadata::default(),
+      vendor,
+    )
+    .await;
+  assert!(result.is_err());
+
+  h.photo_groups
+    .faults()
+    .heal("create_photo_group_with_photos");
+  let id = h
+    .service
+    .create_photo_group_from_images(
+      images,
+      config(),
+      PhotoGroupMetadata::default(),
+      vendor,
+    )
+    .await
+    .unwrap();
+
+  let query = h.service.fetch_photo_group(id).await.unwrap().unwrap();
+  assert_eq!(h.photos.enumerate_photos().await.unwrap().len(), 3);
+  assert_eq!(query.photos.len(), 3);
+}
+
+#[tokio::test]
+async fn edit_photo_group_requires_vendor() {
+  let h = Harness::new();
+  let vendor = h.user("vendor@example.com").await;
+  let other = h.user("other@example.com").await;
+  let images = h.images(vendor, 1).await;
+  let id = h
+    .service
+    .create_photo_group_from_images(
+      images,
+      config(),
+      PhotoGroupMetadata::default(),
+      vendor,
+    )
+    .await
+    .unwrap();
+
+  let result = h
+    .service
+    .edit_photo_group(other, id, vec![PhotoGroupEdit::SetMetadata(
+      PhotoGroupMetadata::default(),
+    )])
+    .await;
+
+  assert!(matches!(result, Err(EditPhotoGroupError::Unauthorized)));
+}
+
+#[tokio::test]
+async fn edit_photo_group_rolls_back_added_photos() {
+  let h = Harness::new();
+  let vendor = h.user("vendor@example.com").await;
+  let images = h.images(vendor, 1).await;
+  let id = h
+    .service
+    .create_photo_group_from_images(
+      images,
+      config(),
+      PhotoGroupMetadata::default(),
+      vendor,
+    )
+    .await
+    .unwrap();
+  let added = h.images(vendor, 2).await;
+  h.photo_groups.faults().fail("replace_photo_group");
+
+  let result = h
+    .service
+    .edit_photo_group(vendor, id, vec![PhotoGroupEdit::AddImages(added)])
+    .await;
+
+  assert!(matches!(result, Err(EditPhotoGroupError::PatchError(_))));
+  assert_eq!(h.photos.enumerate_photos().await.unwrap().len(), 1);
+  let query = h.service.fetch_photo_group(id).await.unwrap().unwrap();
+  assert_eq!(query.photos.len(), 1);
+}
+
+#[tokio::test]
+async fn edit_photo_group_checks_every_edit_before_creating_photos() {
+  let h = Harness::new();
+  let vendor = h.user("vendor@example.com").await;
+  let images = h.images(vendor, 1).await;
+  let id = h
+    .service
+    .create_photo_group_from_images(
+      images,
+      config(),
+      PhotoGroupMetadata::default(),
+      vendor,
+    )
+    .await
+    .unwrap();
+  let added = h.images(vendor, 2).await;
+  // photos created by mistake couldn't be rolled back
+  h.photos.faults().fail("delete_photo");
+
+  let missing = PhotoRecordId::new();
+  let result = h
+    .service
+    .edit_photo_group(vendor, id, vec![
+      PhotoGroupEdit::AddImages(added),
+      PhotoGroupEdit::RemovePhoto(missing),
+    ])
+    .await;
+
+  assert!(
+    matches!(result, Err(EditPhotoGroupError::PhotoNotInGroup(p)) if p == missing)
+  );
+  assert_eq!(h.photos.enumerate_photos().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn edit_photo_group_rolls_back_photo_prices() {
+  let h = Harness::new();
+  let vendor = h.user("vendor@example.com").await;
+  let images = h.images(vendor, 1).await;
+  let id = h
+    .service
+    .create_photo_group_from_images(
+      images,
+      config(),
+      PhotoGroupMetadata::default(),
+      vendor,
+    )
+    .await
+    .unwrap();
+  let photo = h
+    .service
+    .fetch_photo_group(id)
+    .await
+    .unwrap()
+    .unwrap()
+    .photos[0]
+    .clone();
+  h.photo_groups.faults().fail("replace_photo_group");
+
+  let result = h
+    .service
+    .edit_photo_group(vendor, id, vec![PhotoGroupEdit::SetPhotoPrice {
+      photo: photo.id,
+      tier:  LicenseTier::PersonalDigital,
+      price: Some(Money::from_minor_units(500, Currency::Usd)),
+    }])
+    .await;
+
+  assert!(matches!(result, Err(EditPhotoGroupError::PatchError(_))));
+  let stored = h.service.fetch_photo(photo.id).await.unwrap().unwrap();
+  assert_eq!(stored, photo);
+}
+
+#[tokio::test]
+async fn edit_photo_group_deletes_removed_photos() {
+  let h = Harness::new();
+  let vendor = h.user("vendor@example.com").await;
+  let images = h.images(vendor, 2).await;
+  let id = h
+    .service
+    .create_photo_group_from_images(
+      images,
+      config(),
+      PhotoGroupMetadata::default(),
+      vendor,
+    )
+    .await
+    .unwrap();
+  let query = h.service.fetch_photo_group(id).await.unwrap().unwrap();
+  let removed = query.photos[0].clone();
+
+  h.service
+    .edit_photo_group(vendor, id, vec![PhotoGroupEdit::RemovePhoto(removed.id)])
+    .await
+    .unwrap();
+
+  assert!(h.service.fetch_photo(removed.id).await.unwrap().is_none());
+  let images = h.images.enumerate_images().await.unwrap();
+  assert_eq!(images.len(), 1);
+  assert_ne!(images[0].id, removed.artifacts.original);
+  assert_eq!(h.artifacts.enumerate_artifacts().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn edit_photo_group_refuses_concurrent_edits() {
+  let h = Harness::new();
+  let vendor = h.user("vendor@example.com").await;
+  let id = h
+    .service
+    .create_photo_group_from_images(
+      h.images(vendor, 1).await,
+      config(),
+      PhotoGroupMetadata::default(),
+      vendor,
+    )
+    .await
+    .unwrap();
+  let (first, second) = (h.image(vendor).await, h.image(vendor).await);
+
+  // both edits fetch the group before either writes it
+  let results = tokio::join!(
+    h.service
+      .edit_photo_group(vendor, id, vec![PhotoGroupEdit::AddImages(vec![
+        first
+      ])]),
+    h.service
+      .edit_photo_group(vendor, id, vec![PhotoGroupEdit::AddImages(vec![
+        second
+      ])]),
+  );
+
+  let results = [results.0, results.1];
+  assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 1);
+  assert!(results
+    .iter()
+    .any(|r| matches!(r, Err(EditPhotoGroupError::Conflict))));
+  // the losing edit's photo is rolled back
+  let query = h.service.fetch_photo_group(id).await.unwrap().unwrap();
+  assert_eq!(query.photos.len(), 2);
+  assert_eq!(h.photos.enumerate_photos().await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn delete_photo_group_removes_unshared_records() {
+  let h = Harness::new();
+  let vendor = h.user("vendor@example.com").await;
+  let images = h.images(vendor, 2).await;
+  let id = h
+    .service
+    .create_photo_group_from_images(
+      images,
+      config(),
+      PhotoGroupMetadata::default(),
+      vendor,
+    )
+    .await
+    .unwrap();
+  h.withdraw_licenses(id).await;
+  let artifacts = h.artifacts.enumerate_artifacts().await.unwrap();
+
+  h.service.delete_photo_group(vendor, id).await.unwrap();
+
+  assert!(h.service.fetch_photo_group(id).await.unwrap().is_none());
+  assert!(h.photos.enumerate_photos().await.unwrap().is_empty());
+  assert!(h.images.enumerate_images().await.unwrap().is_empty());
+  assert!(h.artifacts.enumerate_artifacts().await.unwrap().is_empty());
+  for artifact in artifacts {
+    assert!(h
+      .artifacts
+      .read_stored_artifact_data(&artifact.path)
+      .await
+      .is_err());
+  }
+}
+
+#[tokio::test]
+async fn delete_photo_group_keeps_images_of_other_photos() {
+  let h = Harness::new();
+  let vendor = h.user("vendor@example.com").await;
+  let shared = h.image(vendor).await;
+  let deleted = h
+    .service
+    .create_photo_group_from_images(
+      vec![shared, h.image(vendor).await],
+      config(),
+      PhotoGroupMetadata::default(),
+      vendor,
+    )
+    .await
+    .unwrap();
+  let kept = h
+    .service
+    .create_photo_group_from_images(
+      vec![shared],
+      config(),
+      PhotoGroupMetadata::default(),
+      vendor,
+    )
+    .await
+    .unwrap();
+  h.withdraw_licenses(deleted).await;
+
+  h.service.delete_photo_group(vendor, deleted).await.unwrap();
+
+  let query = h.service.fetch_photo_group(kept).await.unwrap().unwrap();
+  assert_eq!(query.photos.len(), 1);
+  assert_eq!(query.photos[0].artifacts.original, shared);
+  let images = h.images.enumerate_images().await.unwrap();
+  assert_eq!(images.len(), 1);
+  assert_eq!(images[0].id, shared);
+  assert_eq!(h.artifacts.enumerate_artifacts().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn delete_photo_group_refuses_groups_for_sale() {
+  let h = Harness::new();
+  let vendor = h.user("vendor@example.com").await;
+  let images = h.images(vendor, 1).await;
+  let id = h
+    .service
+    .create_photo_group_from_images(
+      images,
+      config(),
+      PhotoGroupMetadata::default(),
+      vendor,
+    )
+    .await
+    .unwrap();
+
+  let result = h.service.delete_photo_group(vendor, id).await;
+
+  assert!(matches!(result, Err(DeletePhotoGroupError::Licensed)));
+  assert!(h.service.fetch_photo_group(id).await.unwrap().is_some());
+  assert_eq!(h.images.enumerate_images().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn delete_photo_group_stops_at_first_failure() {
+  let h = Harness::new();
+  let vendor = h.user("vendor@example.com").await;
+  let images = h.images(vendor, 2).await;
+  let id = h
+    .service
+    .create_photo_group_from_images(
+      images,
+      config(),
+      PhotoGroupMetadata::default(),
+      vendor,
+    )
+    .await
+    .unwrap();
+  h.withdraw_licenses(id).await;
+  h.images.faults().fail("delete_image");
+
+  let result = h.service.delete_photo_group(vendor, id).await;
+
+  assert!(matches!(result, Err(DeletePhotoGroupError::DeleteError(_))));
+  // deletion goes top down, so the group and its photos are already gone
+  // and only unreferenced images and artifacts are left behind
+  assert!(h.service.fetch_photo_group(id).await.unwrap().is_none());
+  assert!(h.photos.enumerate_photos().await.unwrap().is_empty());
+  assert_eq!(h.images.enumerate_images().await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn delete_user_content_keeps_licensed_groups_anonymized() {
+  let h = Harness::new();
+  let vendor = h.user("vendor@example.com").await;
+  let licensed = h
+    .service
+    .create_photo_group_from_images(
+      h.images(vendor, 1).await,
+      config(),
+      PhotoGroupMetadata::default(),
+      vendor,
+    )
+    .await
+    .unwrap();
+  let unlicensed = h
+    .service
+    .create_photo_group_from_images(
+      h.images(vendor, 1).await,
+      config(),
+      PhotoGroupMetadata::default(),
+      vendor,
+    )
+    .await
+    .unwrap();
+  h.withdraw_licenses(unlicensed).await;
+
+  let report = h.service.delete_user_content(vendor).await.unwrap();
+
+  assert_eq!(report.deleted, vec![unlicensed]);
+  assert_eq!(report.anonymized, vec![licensed]);
+  assert!(h
+    .service
+    .fetch_photo_group(unlicensed)
+    .await
+    .unwrap()
+    .is_none());
+  let query = h
+    .service
+    .fetch_photo_group(licensed)
+    .await
+    .unwrap()
+    .unwrap();
+  assert_eq!(query.photo_group.vendor, DELETED_USER.id);
+  assert_eq!(query.vendor_data, *DELETED_USER);
+  assert_eq!(query.photos.len(), 1);
+}
+
+#[tokio::test]
+async fn user_data_export_streams_originals_and_redacts_credentials() {
+  let h = Harness::new();
+  let vendor = h.user("vendor@example.com").await;
+  let id = h
+    .service
+    .create_photo_group_from_images(
+      h.images(vendor, 2).await,
+      config(),
+      PhotoGroupMetadata::default(),
+      vendor,
+    )
+    .await
+    .unwrap();
+
+  let archive = h
+    .service
+    .write_user_data_export(vendor, Vec::new())
+    .await
+    .unwrap();
+
+  let mut entries = BTreeMap::new();
+  for entry in tar::Archive::new(archive.as_slice()).entries().unwrap() {
+    let mut entry = entry.unwrap();
+    let path = entry.path().unwrap().to_string_lossy().into_owned();
+    let mut data = Vec::new();
+    entry.read_to_end(&mut data).unwrap();
+    entries.insert(path, data);
+  }
+  let user: serde_json::Value =
+    serde_json::from_slice(&entries["user.json"]).unwrap();
+  assert!(user.get("auth").is_none());
+  assert_eq!(user["email"], "vendor@example.com");
+  let photo_groups: Vec<PhotoGroup> =
+    serde_json::from_slice(&entries["photo_groups.json"]).unwrap();
+  assert_eq!(photo_groups.len(), 1);
+  assert_eq!(photo_groups[0].id, id);
+  let originals = entries
+    .iter()
+    .filter(|(path, _)| path.starts_with("originals/"))
+    .map(|(_, data)| data.as_slice())
+    .collect::<Vec<_>>();
+  assert_eq!(originals, vec![b"image".as_slice(); 2]);
+}
+
+#[tokio::test]
+async fn read_photo_thumbnail_is_cached_until_deleted() {
+  let h = Harness::new();
+  let vendor = h.user("vendor@example.com").await;
+  let images = h.images(vendor, 1).await;
+  let id = h
+    .service
+    .create_photo_group_from_images(
+      images,
+      config(),
+      PhotoGroupMetadata::default(),
+      vendor,
+    )
+    .await
+    .unwrap();
+  let photo = h
+    .service
+    .fetch_photo_group(id)
+    .await
+    .unwrap()
+    .unwrap()
+    .photos[0]
+    .id;
+
+  for _ in 0..2 {
+    let (data, _) = h
+      .service
+      .read_photo_thumbnail(photo)
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(data.collect().await.unwrap().as_ref(), b"image");
+  }
+  let stats = h.service.thumbnail_cache_metrics().snapshot();
+  assert_eq!((stats.hits, stats.misses), (1, 1));
+
+  h.withdraw_licenses(id).await;
+  h.service.delete_photo_group(vendor, id).await.unwrap();
+  assert!(h
+    .service
+    .read_photo_thumbnail(photo)
+    .await
+    .unwrap()
+    .is_none());
+  assert_eq!(h.service.thumbnail_cache_metrics().snapshot().entries, 0);
+}
+
+/// Parses the claims and signature back out of a signed download URL.
+fn download_url_claims(url: &str) -> (ArtifactDownloadClaims, String) {
+  let (path, query) = url.split_once('?').unwrap();
+  let artifact = path.rsplit('/').next().unwrap().parse().unwrap();
+  let params = query
+    .split('&')
+    .map(|p| p.split_once('=').unwrap())
+    .collect::<std::collections::HashMap<_, _>>();
+  let claims = ArtifactDownloadClaims {
+    artifact,
+    expires: params["expires"].parse().unwrap(),
+    user: params.get("user").map(|u| u.parse().unwrap()),
+  };
+  (claims, params["signature"].to_owned())
+}
+
+#[tokio::test]
+async fn photo_original_download_url_binds_to_the_buyer_if_given() {
+  let h = Harness::new();
+  let vendor = h.user("vendor@example.com").await;
+  let buyer = h.user("buyer@example.com").await;
+  let images = h.images(vendor, 1).await;
+  let id = h
+    .service
+    .create_photo_group_from_images(
+      images.clone(),
+      config(),
+      PhotoGroupMetadata::default(),
+      vendor,
+    )
+    .await
+    .unwrap();
+  let photo = h
+    .service
+    .fetch_photo_group(id)
+    .await
+    .unwrap()
+    .unwrap()
+    .photos[0]
+    .id;
+  let base_url = BaseUrl("https://picturepro.test".to_owned());
+  let original = h
+    .service
+    .fetch_image(images[0])
+    .await
+    .unwrap()
+    .unwrap()
+    .artifact;
+
+  let url = h
+    .service
+    .photo_original_download_url(&base_url, vendor, id, photo, None)
+    .await
+    .unwrap();
+  let (claims, signature) = download_url_claims(&url);
+  assert_eq!(claims.artifact, original);
+  assert_eq!(claims.user, None);
+  assert_eq!(
+    h.service
+      .verify_artifact_download(&claims, &signature, None),
+    Ok(())
+  );
+
+  let buyer_email = EmailAddress::try_new("buyer@example.com").unwrap();
+  let url = h
+    .service
+    .photo_original_download_url(
+      &base_url,
+      vendor,
+      id,
+      photo,
+      Some(buyer_email.clone()),
+    )
+    .await
+    .unwrap();
+  let (claims, signature) = download_url_claims(&url);
+  assert_eq!(claims.user, Some(buyer));
+  assert_eq!(
+    h.service
+      .verify_artifact_download(&claims, &signature, Some(buyer)),
+    Ok(())
+  );
+  assert_eq!(
+    h.service
+      .verify_artifact_download(&claims, &signature, Some(vendor)),
+    Err(VerifyArtifactDownloadError::WrongUser)
+  );
+
+  let unknown = EmailAddress::try_new("nobody@example.com").unwrap();
+  assert!(matches!(
+    h.service
+      .photo_original_download_url(&base_url, vendor, id, photo, Some(unknown))
+      .await,
+    Err(PhotoDownloadUrlError::BuyerNotFound)
+  ));
+  assert!(matches!(
+    h.service
+      .photo_original_download_url(&base_url, buyer, id, photo, None)
+      .await,
+    Err(PhotoDownloadUrlError::Unauthorized)
+  ));
+}
+
+async fn stored_data(h: &Harness, image: ImageRecordId) -> bytes::Bytes {
+  let image = h.images.fetch_image_by_id(image).await.unwrap().unwrap();
+  let artifact = h
+    .artifacts
+    .fetch_artifact_by_id(image.artifact)
+    .await
+    .unwrap()
+    .unwrap();
+  h.artifacts
+    .read_stored_artifact_data(&artifact.path)
+    .await
+    .unwrap()
+    .collect()
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn backups_restore_as_a_full_and_incremental_chain() {
+  let h = Harness::new();
+  let vendor = h.user("vendor@example.com").await;
+  let kept = h.image(vendor).await;
+  let deleted = h.image(vendor).await;
+  // large enough to be split over several archive entries
+  let large_data = bytes::Bytes::from(vec![7; ARTIFACT_CHUNK_SIZE * 2 + 5]);
+  let large = h
+    .artifacts
+    .create_artifact(
+      Belt::from_stream(
+        futures::stream::once({
+          let data = large_data.clone();
+          async move { Ok(data) }
+        }),
+        None,
+      ),
+      vendor,
+      None,
+    )
+    .await
+    .unwrap();
+
+  let (full, full_archive) = h
+    .service
+    .write_backup(Vec::new(), BackupKind::Full)
+    .await
+    .unwrap();
+  assert!(!full.is_incremental());
+  assert_eq!(full.artifact_data, 3);
+
+  let deleted_artifact = h
+    .images
+    .fetch_image_by_id(deleted)
+    .await
+    .unwrap()
+    .unwrap()
+    .artifact;
+  let deleted_path = h
+    .artifacts
+    .fetch_artifact_by_id(deleted_artifact)
+    .await
+    .unwrap()
+    .unwrap()
+    .path;
+  h.images.delete_image(deleted).await.unwrap();
+  h.artifacts.delete_artifact(deleted_artifact).await.unwrap();
+  let added = h.image(vendor).await;
+
+  let (incremental, incremental_archive) = h
+    .service
+    .write_backup(Vec::new(), BackupKind::Incremental {
+      base_snapshot_at: full.snapshot_at,
+    })
+    .await
+    .unwrap();
+  assert_eq!(incremental.base_snapshot_at, Some(full.snapshot_at));
+  assert_eq!(incremental.records[Image::TABLE_NAME], 1);
+  assert_eq!(incremental.artifact_data, 1);
+
+  let restored = Harness::new();
+  let report = restored
+    .service
+    .restore_backup([
+      std::io::Cursor::new(full_archive),
+      std::io::Cursor::new(incremental_archive),
+    ])
+    .await
+    .unwrap();
+  assert_eq!(report.archives, 2);
+  assert_eq!(report.artifact_data, 4);
+  // the deleted image and its artifact
+  assert_eq!(report.pruned, 2);
+
+  let mut images = restored
+    .images
+    .enumerate_images()
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|i| i.id)
+    .collect::<Vec<_>>();
+  images.sort();
+  let mut expected = vec![kept, added];
+  expected.sort();
+  assert_eq!(images, expected);
+  assert_eq!(
+    restored
+      .artifacts
+      .enumerate_artifacts()
+      .await
+      .unwrap()
+      .len(),
+    3
+  );
+  assert_eq!(
+    restored.users.enumerate_users().await.unwrap(),
+    h.users.enumerate_users().await.unwrap()
+  );
+
+  assert_eq!(stored_data(&restored, kept).await.as_ref(), b"image");
+  assert_eq!(stored_data(&restored, added).await.as_ref(), b"image");
+  let large = restored
+    .artifacts
+    .read_stored_artifact_data(&large.path)
+    .await
+    .unwrap()
+    .collect()
+    .await
+    .unwrap();
+  assert_eq!(large, large_data);
+  assert!(restored
+    .artifacts
+    .read_stored_artifact_data(&deleted_path)
+    .await
+    .is_err());
+}
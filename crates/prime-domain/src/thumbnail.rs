@@ -0,0 +1,111 @@
+//! Serving photo thumbnails, with the bytes of small renditions cached in
+//! memory.
+
+use std::{sync::Arc, time::Duration};
+
+use bytes::Bytes;
+use models::{ArtifactMimeType, PhotoRecordId};
+use repos::{
+  belt::{Belt, DEFAULT_CHUNK_SIZE},
+  Cache, CacheConfig, CacheMetrics, FetchModelError, ReadArtifactError,
+};
+use tracing::instrument;
+
+use crate::PrimeDomainService;
+
+/// Renditions larger than this are served, but never cached.
+const MAX_CACHED_RENDITION_SIZE: usize = 512 * 1024;
+
+/// The bounds of the thumbnail rendition cache.
+const THUMBNAIL_CACHE_CONFIG: CacheConfig = CacheConfig {
+  max_entries: 4096,
+  max_weight:  64 * 1024 * 1024,
+  ttl:         Duration::from_secs(60 * 60),
+};
+
+/// The decompressed bytes of a thumbnail rendition.
+#[derive(Clone, Debug)]
+pub(crate) struct CachedRendition {
+  data:      Bytes,
+  mime_type: Option<ArtifactMimeType>,
+}
+
+/// Caches thumbnail renditions by the [`Photo`](models::Photo) they belong
+/// to.
+pub(crate) type ThumbnailCache = Cache<PhotoRecordId, CachedRendition>;
+
+pub(crate) fn new_thumbnail_cache() -> ThumbnailCache {
+  Cache::with_weigher("photo_thumbnail", THUMBNAIL_CACHE_CONFIG, |r| {
+    r.data.len()
+  })
+}
+
+/// The possible errors of [`PrimeDomainService::read_photo_thumbnail()`].
+#[derive(Debug, thiserror::Error)]
+pub enum ReadPhotoThumbnailError {
+  /// Failed to fetch the photo or its thumbnail image.
+  #[error("failed to fetch thumbnail image: {0}")]
+  FetchError(FetchModelError),
+  /// The thumbnail image's artifact didn't exist.
+  #[error("missing artifact: {0}")]
+  MissingArtifact(models::ArtifactRecordId),
+  /// Failed to read the thumbnail image's artifact.
+  #[error("failed to read artifact: {0}")]
+  ReadArtifactError(ReadArtifactError),
+}
+
+impl PrimeDomainService {
+  /// Read the data of a [`Photo`](models::Photo)'s thumbnail, along with its
+  /// stated mime type. Small renditions are served from memory after the
+  /// first read.
+  #[instrument(skip(self))]
+  pub async fn read_photo_thumbnail(
+    &self,
+    id: PhotoRecordId,
+  ) -> Result<Option<(Belt, Option<ArtifactMimeType>)>, ReadPhotoThumbnailError>
+  {
+    if let Some(rendition) = self.thumbnail_cache.get(&id) {
+      return Ok(Some(rendition_belt(rendition)));
+    }
+
+    let fill = self.thumbnail_cache.fill(id);
+    let Some(image) = self
+      .fetch_thumbnail_image_for_photo(id)
+      .await
+      .map_err(ReadPhotoThumbnailError::FetchError)?
+    else {
+      return Ok(None);
+    };
+    let (data, mime_type) = self
+      .read_artifact_by_id(image.artifact)
+      .await
+      .map_err(ReadPhotoThumbnailError::ReadArtifactError)?
+      .ok_or(ReadPhotoThumbnailError::MissingArtifact(image.artifact))?;
+    let data = data.adapt_to_no_comp().collect().await.map_err(|e| {
+      ReadPhotoThumbnailError::ReadArtifactError(ReadArtifactError::IoError(e))
+    })?;
+
+    let rendition = CachedRendition { data, mime_type };
+    if rendition.data.len() <= MAX_CACHED_RENDITION_SIZE {
+      fill.insert(rendition.clone());
+    }
+    Ok(Some(rendition_belt(rendition)))
+  }
+
+  /// The live counters of the thumbnail rendition cache.
+  #[must_use]
+  pub fn thumbnail_cache_metrics(&self) -> Arc<CacheMetrics> {
+    self.thumbnail_cache.metrics()
+  }
+}
+
+fn rendition_belt(
+  rendition: CachedRendition,
+) -> (Belt, Option<ArtifactMimeType>) {
+  let data = rendition.data;
+  let belt = Belt::from_stream(
+    futures::stream::once(async move { Ok(data) }),
+    Some(DEFAULT_CHUNK_SIZE),
+  );
+  (belt, rendition.mime_type)
+}
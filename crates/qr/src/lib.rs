@@ -28,4 +28,15 @@ impl QrCodeGenerator {
 
     Ok(SvgBuilder::default().to_str(&qr))
   }
+
+  /// Generates a QR code for enrolling an authenticator app, from an
+  /// `otpauth://` URI.
+  pub fn generate_otpauth_link(
+    &self,
+    uri: &str,
+  ) -> Result<String, QRCodeError> {
+    let qr = QRBuilder::new(uri).build()?;
+
+    Ok(SvgBuilder::default().to_str(&qr))
+  }
 }
@@ -1,13 +1,31 @@
-use db::{CreateModelError, Database, FetchModelByIndexError, FetchModelError};
+mod encryption;
+
+use std::{collections::BTreeSet, fmt};
+
+use db::{
+  CreateModelError, Database, DeleteModelError, FetchModelByIndexError,
+  FetchModelError, PatchModelError,
+};
 use hex::health::{self, HealthAware};
 use models::{
   Artifact, ArtifactCreateRequest, ArtifactMimeType, ArtifactPath,
-  ArtifactRecordId, CompressionStatus, FileSize, StrictSlug, UserRecordId,
+  ArtifactRecordId, CompressionStatus, FileSize, Model, StrictSlug,
+  Timestamped, UserRecordId,
 };
 use storage::{
-  belt::Belt, ReadError as StorageReadError, StorageClient,
+  belt::{Belt, DEFAULT_CHUNK_SIZE},
+  ReadError as StorageReadError, StorageClient,
   WriteError as StorageWriteError,
 };
+use tracing::instrument;
+
+use self::encryption::{decrypt_stream, encrypt_stream};
+pub use self::encryption::{
+  ArtifactKeyError, MasterKeyring, MasterKeyringParseError,
+};
+use crate::{
+  MigrateModelsError, MigrationOptions, MigrationReport, RestoreModelError,
+};
 
 /// An error that occurs when reading the data of an [`Artifact`].
 #[derive(Debug, thiserror::Error, miette::Diagnostic)]
@@ -21,6 +39,9 @@ pub enum ReadArtifactError {
   /// An error that occurs when reading the data of an [`Artifact`].
   #[error("Failed to read Artifact data: {0}")]
   StorageReadError(StorageReadError),
+  /// An error that occurs when unwrapping the data key of an [`Artifact`].
+  #[error("Failed to decrypt Artifact data: {0}")]
+  KeyError(ArtifactKeyError),
   /// IO error.
   #[error("An IO error occurred: {0}")]
   IoError(std::io::Error),
@@ -35,13 +56,140 @@ pub enum CreateArtifactError {
   /// An error that occurs when writing the data of an [`Artifact`].
   #[error("Failed to write Artifact data: {0}")]
   StorageWriteError(StorageWriteError),
+  /// An error that occurs when generating the data key of an [`Artifact`].
+  #[error("Failed to encrypt Artifact data: {0}")]
+  KeyError(ArtifactKeyError),
+}
+
+/// An error that occurs when deleting an [`Artifact`].
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum DeleteArtifactError {
+  /// An error that occurs when fetching an [`Artifact`] model.
+  #[error(
+    "Failed to fetch Artifact model while attempting to delete Artifact: {0}"
+  )]
+  FetchModelError(FetchModelError),
+  /// An error that occurs when deleting an [`Artifact`] model.
+  #[error("Failed to delete Artifact model: {0}")]
+  DeleteModelError(DeleteModelError),
+  /// An error that occurs when deleting the data of an [`Artifact`].
+  #[error("Failed to delete Artifact data: {0}")]
+  StorageDeleteError(miette::Report),
+}
+
+/// An error that occurs when re-wrapping [`Artifact`] data keys.
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum RewrapArtifactKeysError {
+  /// An error that occurs when enumerating [`Artifact`] models.
+  #[error("Failed to enumerate Artifact models: {0}")]
+  EnumerateError(miette::Report),
+  /// An error that occurs when re-wrapping an [`Artifact`]'s data key.
+  #[error("Failed to re-wrap data key of Artifact {0}: {1}")]
+  KeyError(ArtifactRecordId, ArtifactKeyError),
+  /// An error that occurs when updating an [`Artifact`] model.
+  #[error("Failed to update Artifact model: {0}")]
+  PatchModelError(PatchModelError),
+}
+
+/// The interface for storing and retrieving [`Artifact`]s.
+#[async_trait::async_trait]
+pub trait ArtifactRepositoryLike:
+  health::HealthReporter + fmt::Debug + Send + Sync + 'static
+{
+  /// Fetch an [`Artifact`] by id.
+  async fn fetch_artifact_by_id(
+    &self,
+    id: ArtifactRecordId,
+  ) -> Result<Option<Artifact>, FetchModelError>;
+
+  /// Find an [`Artifact`] based on its unique (and indexed) storage path.
+  async fn fetch_artifact_by_path(
+    &self,
+    path: ArtifactPath,
+  ) -> Result<Option<Artifact>, FetchModelByIndexError>;
+
+  /// Read an [`Artifact`]'s data, identified by its id.
+  async fn read_artifact_by_id(
+    &self,
+    id: ArtifactRecordId,
+  ) -> Result<Option<(Belt, Option<ArtifactMimeType>)>, ReadArtifactError>;
+
+  /// Read an [`Artifact`]'s data exactly as stored, still compressed and,
+  /// if the artifact is encrypted, still sealed. Used for backups.
+  async fn read_stored_artifact_data(
+    &self,
+    path: &ArtifactPath,
+  ) -> Result<Belt, ReadArtifactError>;
+
+  /// Write an [`Artifact`]'s data exactly as it was stored, as read by
+  /// [`read_stored_artifact_data`](Self::read_stored_artifact_data). Used for
+  /// restoring backups.
+  async fn write_stored_artifact_data(
+    &self,
+    path: &ArtifactPath,
+    data: Belt,
+  ) -> Result<(), CreateArtifactError>;
+
+  /// Create and write an [`Artifact`] to storage.
+  ///
+  /// Data is compressed with zstd unless its stated mime type is already
+  /// entropy-coded (see [`ArtifactMimeType::is_precompressed`]), in which
+  /// case it is stored uncompressed.
+  async fn create_artifact(
+    &self,
+    data: Belt,
+    originator: UserRecordId,
+    stated_mime_type: Option<ArtifactMimeType>,
+  ) -> Result<Artifact, CreateArtifactError>;
+
+  /// Re-wrap the data keys of all encrypted [`Artifact`]s with the current
+  /// master key, so that retired master keys can be removed from the keyring.
+  /// Artifact data is not rewritten. Returns the number of artifacts updated.
+  async fn rewrap_artifact_keys(
+    &self,
+  ) -> Result<usize, RewrapArtifactKeysError>;
+
+  /// Delete an [`Artifact`] model and its data. Returns whether it existed.
+  ///
+  /// The model is deleted first, so a failure part-way through leaves
+  /// unreferenced data in storage rather than a model without data.
+  async fn delete_artifact(
+    &self,
+    id: ArtifactRecordId,
+  ) -> Result<bool, DeleteArtifactError>;
+
+  /// Upcast stored [`Artifact`]s written at older schema versions and re-write
+  /// them.
+  async fn migrate_artifacts(
+    &self,
+    options: MigrationOptions,
+  ) -> Result<MigrationReport, MigrateModelsError>;
+
+  /// Produce a list of all [`Artifact`]s.
+  async fn enumerate_artifacts(&self) -> miette::Result<Vec<Artifact>>;
+
+  /// Write a [`Artifact`] restored from a backup, replacing any stored record
+  /// with the same id.
+  async fn restore_artifact(
+    &self,
+    artifact: Artifact,
+  ) -> Result<(), RestoreModelError>;
+
+  /// Delete every stored [`Artifact`] whose id isn't in `keep`, along with
+  /// its data. Returns the number deleted.
+  async fn prune_artifacts(
+    &self,
+    keep: &BTreeSet<String>,
+  ) -> Result<usize, RestoreModelError>;
 }
 
-/// Stores and retrieves [`Artifact`]s.
+/// Stores and retrieves [`Artifact`]s in a [`Database`] and their data in
+/// storage.
 #[derive(Clone, Debug)]
 pub struct ArtifactRepository {
   storage_repo: StorageClient,
   db:           Database<Artifact>,
+  keyring:      Option<MasterKeyring>,
 }
 
 #[async_trait::async_trait]
@@ -60,27 +208,42 @@ impl health::HealthReporter for ArtifactRepository {
 
 impl ArtifactRepository {
   /// Create a new [`ArtifactRepository`].
+  ///
+  /// If a [`MasterKeyring`] is provided, new artifacts are encrypted at rest.
+  /// Without one, new artifacts are stored unencrypted and existing encrypted
+  /// artifacts can't be read.
   #[must_use]
   pub fn new(
     storage_repo: StorageClient,
     model_repo: Database<Artifact>,
+    keyring: Option<MasterKeyring>,
   ) -> Self {
     Self {
       storage_repo,
       db: model_repo,
+      keyring,
     }
   }
 
-  /// Fetch an [`Artifact`] by id.
-  pub async fn fetch_artifact_by_id(
+  async fn delete_data(&self, path: &ArtifactPath) -> miette::Result<()> {
+    self
+      .storage_repo
+      .delete(&path.to_path_buf())
+      .await
+      .map_err(|e| miette::miette!("{e}"))
+  }
+}
+
+#[async_trait::async_trait]
+impl ArtifactRepositoryLike for ArtifactRepository {
+  async fn fetch_artifact_by_id(
     &self,
     id: ArtifactRecordId,
   ) -> Result<Option<Artifact>, FetchModelError> {
     self.db.fetch_model_by_id(id).await
   }
 
-  /// Find an [`Artifact`] based on its unique (and indexed) storage path.
-  pub async fn fetch_artifact_by_path(
+  async fn fetch_artifact_by_path(
     &self,
     path: ArtifactPath,
   ) -> Result<Option<Artifact>, FetchModelByIndexError> {
@@ -93,8 +256,7 @@ impl ArtifactRepository {
       .await
   }
 
-  /// Read an [`Artifact`]'s data, identified by its id.
-  pub async fn read_artifact_by_id(
+  async fn read_artifact_by_id(
     &self,
     id: ArtifactRecordId,
   ) -> Result<Option<(Belt, Option<ArtifactMimeType>)>, ReadArtifactError> {
@@ -109,42 +271,109 @@ impl ArtifactRepository {
           .storage_repo
           .read(&artifact.path.to_path_buf())
           .await
-          .map_err(ReadArtifactError::StorageReadError)?
-          .set_declared_comp(
-            artifact
-              .comp_status
-              .algorithm()
-              .map(crate::utils::dvf_comp_to_belt_comp),
-          );
+          .map_err(ReadArtifactError::StorageReadError)?;
+        let data = match &artifact.encryption {
+          Some(encryption) => {
+            let data_key = self
+              .keyring
+              .as_ref()
+              .ok_or_else(|| {
+                ArtifactKeyError::UnknownMasterKey(
+                  encryption.master_key_id.clone(),
+                )
+              })
+              .and_then(|keyring| keyring.open_key(encryption))
+              .map_err(ReadArtifactError::KeyError)?;
+            Belt::from_stream(
+              decrypt_stream(data, &data_key, &encryption.stream_nonce),
+              Some(DEFAULT_CHUNK_SIZE),
+            )
+          }
+          None => data,
+        }
+        .set_declared_comp(
+          artifact
+            .comp_status
+            .algorithm()
+            .map(crate::utils::dvf_comp_to_belt_comp),
+        );
         Ok(Some((data, artifact.stated_mime_type)))
       }
       None => Ok(None),
     }
   }
 
-  /// Create and write an [`Artifact`] to storage.
-  pub async fn create_artifact(
+  async fn read_stored_artifact_data(
+    &self,
+    path: &ArtifactPath,
+  ) -> Result<Belt, ReadArtifactError> {
+    self
+      .storage_repo
+      .read(&path.to_path_buf())
+      .await
+      .map_err(ReadArtifactError::StorageReadError)
+  }
+
+  async fn write_stored_artifact_data(
+    &self,
+    path: &ArtifactPath,
+    data: Belt,
+  ) -> Result<(), CreateArtifactError> {
+    self
+      .storage_repo
+      .write(&path.to_path_buf(), data)
+      .await
+      .map_err(CreateArtifactError::StorageWriteError)?;
+    Ok(())
+  }
+
+  async fn create_artifact(
     &self,
     data: Belt,
     originator: UserRecordId,
     stated_mime_type: Option<ArtifactMimeType>,
   ) -> Result<Artifact, CreateArtifactError> {
+    let compress = !stated_mime_type
+      .as_ref()
+      .is_some_and(ArtifactMimeType::is_precompressed);
     let pre_comp_counter = data.counter();
-    let data = data.adapt_to_comp(storage::belt::CompressionAlgorithm::Zstd);
-
+    let data = if compress {
+      data.adapt_to_comp(storage::belt::CompressionAlgorithm::Zstd)
+    } else {
+      data.adapt_to_no_comp()
+    };
+    let post_comp_counter = data.counter();
+    // compress first, then encrypt; ciphertext doesn't compress
+    let (data, encryption) = match &self.keyring {
+      Some(keyring) => {
+        let (data_key, encryption) = keyring
+          .seal_new_key()
+          .map_err(CreateArtifactError::KeyError)?;
+        let data = Belt::from_stream(
+          encrypt_stream(data, &data_key, &encryption.stream_nonce),
+          Some(DEFAULT_CHUNK_SIZE),
+        );
+        (data, Some(encryption))
+      }
+      None => (data, None),
+    };
     let path = ArtifactPath::new_random();
-    let post_comp_size = self
+    self
       .storage_repo
       .write(&path.to_path_buf(), data)
       .await
       .map_err(CreateArtifactError::StorageWriteError)?;
-
-    let comp_status = CompressionStatus::Compressed {
-      compressed_size:   post_comp_size,
-      uncompressed_size: FileSize::new(pre_comp_counter.current()),
-      algorithm:         models::CompressionAlgorithm::Zstd,
+    let comp_status = if compress {
+      CompressionStatus::Compressed {
+        compressed_size:   FileSize::new(post_comp_counter.current()),
+        uncompressed_size: FileSize::new(pre_comp_counter.current()),
+        algorithm:         models::CompressionAlgorithm::Zstd,
+      }
+    } else {
+      CompressionStatus::Uncompressed {
+        size: FileSize::new(post_comp_counter.current()),
+      }
     };
-
     let artifact = self
       .db
       .create_model(
@@ -153,6 +382,7 @@ impl ArtifactRepository {
           originator,
           comp_status,
           stated_mime_type,
+          encryption,
         }
         .into(),
       )
@@ -160,4 +390,112 @@ impl ArtifactRepository {
       .map_err(CreateArtifactError::CreateModelError)?;
     Ok(artifact)
   }
+
+  #[instrument(skip(self))]
+  async fn rewrap_artifact_keys(
+    &self,
+  ) -> Result<usize, RewrapArtifactKeysError> {
+    let Some(keyring) = &self.keyring else {
+      return Ok(0);
+    };
+    let artifacts = self
+      .db
+      .enumerate_models()
+      .await
+      .map_err(RewrapArtifactKeysError::EnumerateError)?;
+    let mut count = 0;
+    for mut artifact in artifacts {
+      let Some(encryption) = &artifact.encryption else {
+        continue;
+      };
+      let Some(rewrapped) = keyring
+        .rewrap_key(encryption)
+        .map_err(|e| RewrapArtifactKeysError::KeyError(artifact.id, e))?
+      else {
+        continue;
+      };
+      artifact.encryption = Some(rewrapped);
+      artifact.touch();
+      self
+        .db
+        .patch_model(artifact.id, artifact)
+        .await
+        .map_err(RewrapArtifactKeysError::PatchModelError)?;
+      count += 1;
+    }
+    Ok(count)
+  }
+
+  #[instrument(skip(self))]
+  async fn delete_artifact(
+    &self,
+    id: ArtifactRecordId,
+  ) -> Result<bool, DeleteArtifactError> {
+    let Some(artifact) = self
+      .db
+      .fetch_model_by_id(id)
+      .await
+      .map_err(DeleteArtifactError::FetchModelError)?
+    else {
+      return Ok(false);
+    };
+    self
+      .db
+      .delete_model(id)
+      .await
+      .map_err(DeleteArtifactError::DeleteModelError)?;
+    self
+      .delete_data(&artifact.path)
+      .await
+      .map_err(DeleteArtifactError::StorageDeleteError)?;
+    Ok(true)
+  }
+
+  #[instrument(skip(self))]
+  async fn migrate_artifacts(
+    &self,
+    options: MigrationOptions,
+  ) -> Result<MigrationReport, MigrateModelsError> {
+    crate::migrate::migrate_models(&self.db, options).await
+  }
+
+  #[instrument(skip(self))]
+  async fn enumerate_artifacts(&self) -> miette::Result<Vec<Artifact>> {
+    self.db.enumerate_models().await
+  }
+
+  #[instrument(skip(self, artifact), fields(id = %artifact.id))]
+  async fn restore_artifact(
+    &self,
+    artifact: Artifact,
+  ) -> Result<(), RestoreModelError> {
+    crate::restore::restore_model(&self.db, artifact).await
+  }
+
+  #[instrument(skip(self, keep))]
+  async fn prune_artifacts(
+    &self,
+    keep: &BTreeSet<String>,
+  ) -> Result<usize, RestoreModelError> {
+    let artifacts = self.db.enumerate_models().await.map_err(|e| {
+      RestoreModelError::EnumerateError(Artifact::TABLE_NAME, e)
+    })?;
+
+    let mut count = 0;
+    for artifact in artifacts {
+      let id = artifact.id.to_string();
+      if keep.contains(&id) {
+        continue;
+      }
+      self.db.delete_model(artifact.id).await.map_err(|e| {
+        RestoreModelError::DeleteModelError(Artifact::TABLE_NAME, id.clone(), e)
+      })?;
+      self.delete_data(&artifact.path).await.map_err(|e| {
+        RestoreModelError::DeleteDataError(Artifact::TABLE_NAME, id, e)
+      })?;
+      count += 1;
+    }
+
+    Ok(count)
+  }
 }
@@ -0,0 +1,473 @@
+use std::{collections::HashMap, fmt, io, str::FromStr};
+
+use base64::Engine;
+use bytes::{Bytes, BytesMut};
+use chacha20poly1305::{
+  aead::{
+    generic_array::GenericArray,
+    rand_core::RngCore,
+    stream::{DecryptorBE32, EncryptorBE32},
+    Aead, AeadCore, KeyInit, OsRng,
+  },
+  Key, XChaCha20Poly1305, XNonce,
+};
+use futures::{Stream, StreamExt};
+use models::ArtifactEncryption;
+
+/// The size of the plaintext segments that artifact data is encrypted in.
+const SEGMENT_SIZE: usize = 64 * 1024;
+/// The size of the authentication tag appended to each encrypted segment.
+const TAG_SIZE: usize = 16;
+/// The size of the nonce prefix used for the STREAM construction.
+const STREAM_NONCE_SIZE: usize = 19;
+/// The size of the nonce used for wrapping data keys.
+const WRAP_NONCE_SIZE: usize = 24;
+/// The size of master keys and data keys.
+const KEY_SIZE: usize = 32;
+
+/// An error that occurs when parsing a [`MasterKeyring`].
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum MasterKeyringParseError {
+  /// The keyring contained no keys.
+  #[error("The master keyring contains no keys")]
+  Empty,
+  /// A keyring entry was not of the form `<id>:<base64 key>`.
+  #[error("Malformed master key entry, expected `<id>:<base64 key>`")]
+  MalformedEntry,
+  /// A key was not valid base64.
+  #[error("Master key \"{0}\" is not valid base64")]
+  InvalidEncoding(String),
+  /// A key was not the right length.
+  #[error("Master key \"{0}\" must be 32 bytes long")]
+  InvalidLength(String),
+  /// A key ID was used more than once.
+  #[error("Master key \"{0}\" is listed more than once")]
+  DuplicateId(String),
+}
+
+/// An error that occurs when wrapping or unwrapping an artifact data key.
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum ArtifactKeyError {
+  /// The master key used to wrap the data key is not in the keyring.
+  #[error("Master key \"{0}\" is not in the keyring")]
+  UnknownMasterKey(String),
+  /// The wrapped data key failed authentication.
+  #[error("Failed to unwrap artifact data key with master key \"{0}\"")]
+  UnwrapFailed(String),
+  /// The stored stream nonce was malformed.
+  #[error("Artifact stream nonce is malformed")]
+  MalformedNonce,
+  /// The data key could not be wrapped.
+  #[error("Failed to wrap artifact data key")]
+  WrapFailed,
+}
+
+/// A set of master keys used to wrap artifact data keys.
+///
+/// The first key is the current key, used for wrapping new data keys. The
+/// rest are retired keys, kept so that existing data keys can still be
+/// unwrapped until they are re-wrapped with the current key.
+#[derive(Clone)]
+pub struct MasterKeyring {
+  current: String,
+  keys:    HashMap<String, Key>,
+}
+
+impl fmt::Debug for MasterKeyring {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("MasterKeyring")
+      .field("current", &self.current)
+      .field("keys", &self.keys.keys().collect::<Vec<_>>())
+      .finish()
+  }
+}
+
+impl FromStr for MasterKeyring {
+  type Err = MasterKeyringParseError;
+
+  /// Parses a keyring from a comma-separated list of `<id>:<base64 key>`
+  /// entries, the first of which is the current key.
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let mut current = None;
+    let mut keys = HashMap::new();
+
+    for entry in s.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+      let (id, encoded) = entry
+        .split_once(':')
+        .filter(|(id, _)| !id.is_empty())
+        .ok_or(MasterKeyringParseError::MalformedEntry)?;
+
+      let key = base64::prelude::BASE64_STANDARD
+        .decode(encoded)
+        .map_err(|_| MasterKeyringParseError::InvalidEncoding(id.to_owned()))?;
+      if key.len() != KEY_SIZE {
+        return Err(MasterKeyringParseError::InvalidLength(id.to_owned()));
+      }
+
+      if keys.insert(id.to_owned(), *Key::from_slice(&key)).is_some() {
+        return Err(MasterKeyringParseError::DuplicateId(id.to_owned()));
+      }
+      current.get_or_insert_with(|| id.to_owned());
+    }
+
+    let current = current.ok_or(MasterKeyringParseError::Empty)?;
+    Ok(Self { current, keys })
+  }
+}
+
+impl MasterKeyring {
+  /// The ID of the key used for wrapping new data keys.
+  #[must_use]
+  pub fn current_key_id(&self) -> &str { &self.current }
+
+  fn key(&self, id: &str) -> Result<&Key, ArtifactKeyError> {
+    self
+      .keys
+      .get(id)
+      .ok_or_else(|| ArtifactKeyError::UnknownMasterKey(id.to_owned()))
+  }
+
+  /// Generates a new data key and wraps it with the current master key.
+  pub(crate) fn seal_new_key(
+    &self,
+  ) -> Result<(DataKey, ArtifactEncryption), ArtifactKeyError> {
+    let data_key = DataKey(XChaCha20Poly1305::generate_key(&mut OsRng));
+
+    let mut stream_nonce = vec![0; STREAM_NONCE_SIZE];
+    OsRng.fill_bytes(&mut stream_nonce);
+
+    let encryption = ArtifactEncryption {
+      master_key_id: self.current.clone(),
+      wrapped_key: self.wrap(&data_key)?,
+      stream_nonce,
+    };
+    Ok((data_key, encryption))
+  }
+
+  /// Unwraps the data key of an encrypted artifact.
+  pub(crate) fn open_key(
+    &self,
+    encryption: &ArtifactEncryption,
+  ) -> Result<DataKey, ArtifactKeyError> {
+    if encryption.stream_nonce.len() != STREAM_NONCE_SIZE {
+      return Err(ArtifactKeyError::MalformedNonce);
+    }
+
+    let master_key = self.key(&encryption.master_key_id)?;
+    let unwrap_failed =
+      || ArtifactKeyError::UnwrapFailed(encryption.master_key_id.clone());
+
+    if encryption.wrapped_key.len() < WRAP_NONCE_SIZE {
+      return Err(unwrap_failed());
+    }
+    let (nonce, wrapped) = encryption.wrapped_key.split_at(WRAP_NONCE_SIZE);
+    let key = XChaCha20Poly1305::new(master_key)
+      .decrypt(XNonce::from_slice(nonce), wrapped)
+      .map_err(|_| unwrap_failed())?;
+    if key.len() != KEY_SIZE {
+      return Err(unwrap_failed());
+    }
+
+    Ok(DataKey(*Key::from_slice(&key)))
+  }
+
+  /// Re-wraps the data key of an encrypted artifact with the current master
+  /// key. Returns `None` if it is already wrapped with the current key.
+  pub(crate) fn rewrap_key(
+    &self,
+    encryption: &ArtifactEncryption,
+  ) -> Result<Option<ArtifactEncryption>, ArtifactKeyError> {
+    if encryption.master_key_id == self.current {
+      return Ok(None);
+    }
+
+    let data_key = self.open_key(encryption)?;
+    Ok(Some(ArtifactEncryption {
+      master_key_id: self.current.clone(),
+      wrapped_key:   self.wrap(&data_key)?,
+      stream_nonce:  encryption.stream_nonce.clone(),
+    }))
+  }
+
+  fn wrap(&self, data_key: &DataKey) -> Result<Vec<u8>, ArtifactKeyError> {
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let wrapped = XChaCha20Poly1305::new(self.key(&self.current)?)
+      .encrypt(&nonce, data_key.0.as_slice())
+      .map_err(|_| ArtifactKeyError::WrapFailed)?;
+
+    let mut output = nonce.to_vec();
+    output.extend_from_slice(&wrapped);
+    Ok(output)
+  }
+}
+
+/// A per-artifact data key.
+pub(crate) struct DataKey(Key);
+
+fn authentication_error(_: chacha20poly1305::aead::Error) -> io::Error {
+  io::Error::new(
+    io::ErrorKind::InvalidData,
+    "artifact data failed authentication",
+  )
+}
+
+/// Encrypts a stream of artifact data with a data key.
+///
+/// Data is encrypted in fixed-size segments with the STREAM construction, so
+/// it can be decrypted incrementally and truncation is detected.
+pub(crate) fn encrypt_stream(
+  data: impl Stream<Item = io::Result<Bytes>> + Send + 'static,
+  data_key: &DataKey,
+  stream_nonce: &[u8],
+) -> impl Stream<Item = io::Result<Bytes>> + Send + 'static {
+  let encryptor = EncryptorBE32::from_aead(
+    XChaCha20Poly1305::new(&data_key.0),
+    GenericArray::from_slice(stream_nonce),
+  );
+
+  let state = (Box::pin(data), BytesMut::new(), Some(encryptor));
+  futures::stream::try_unfold(
+    state,
+    |(mut data, mut buffer, mut encryptor)| async move {
+      loop {
+        let Some(active) = encryptor.as_mut() else {
+          return Ok(None);
+        };
+        // only emit a full segment once we know it isn't the last one
+        if buffer.len() > SEGMENT_SIZE {
+          let segment = buffer.split_to(SEGMENT_SIZE);
+          let output = active
+            .encrypt_next(segment.as_ref())
+            .map_err(authentication_error)?;
+          return Ok(Some((Bytes::from(output), (data, buffer, encryptor))));
+        }
+        if let Some(chunk) = data.next().await {
+          buffer.extend_from_slice(&chunk?);
+          continue;
+        }
+        let last = encryptor.take().expect("encryptor is active");
+        let output = last
+          .encrypt_last(buffer.split().as_ref())
+          .map_err(authentication_error)?;
+        return Ok(Some((Bytes::from(output), (data, buffer, encryptor))));
+      }
+    },
+  )
+}
+
+/// Decrypts a stream of artifact data produced by [`encrypt_stream`].
+pub(crate) fn decrypt_stream(
+  data: impl Stream<Item = io::Result<Bytes>> + Send + 'static,
+  data_key: &DataKey,
+  stream_nonce: &[u8],
+) -> impl Stream<Item = io::Result<Bytes>> + Send + 'static {
+  const ENCRYPTED_SEGMENT_SIZE: usize = SEGMENT_SIZE + TAG_SIZE;
+
+  let decryptor = DecryptorBE32::from_aead(
+    XChaCha20Poly1305::new(&data_key.0),
+    GenericArray::from_slice(stream_nonce),
+  );
+
+  let state = (Box::pin(data), BytesMut::new(), Some(decryptor));
+  futures::stream::try_unfold(
+    state,
+    |(mut data, mut buffer, mut decryptor)| async move {
+      loop {
+        let Some(active) = decryptor.as_mut() else {
+          return Ok(None);
+        };
+        if buffer.len() > ENCRYPTED_SEGMENT_SIZE {
+          let segment = buffer.split_to(ENCRYPTED_SEGMENT_SIZE);
+          let output = active
+            .decrypt_next(segment.as_ref())
+            .map_err(authentication_error)?;
+          return Ok(Some((Bytes::from(output), (data, buffer, decryptor))));
+        }
+        if let Some(chunk) = data.next().await {
+          buffer.extend_from_slice(&chunk?);
+          continue;
+        }
+        let last = decryptor.take().expect("decryptor is active");
+        let output = last
+          .decrypt_last(buffer.split().as_ref())
+          .map_err(authentication_error)?;
+        return Ok(Some((Bytes::from(output), (data, buffer, decryptor))));
+      }
+    },
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const KEY_A: &str = "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=";
+  const KEY_B: &str = "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=";
+
+  fn keyring(s: &str) -> MasterKeyring { s.parse().expect("valid keyring") }
+
+  /// Feeds `data` through in `chunk_size` pieces, so segments straddle
+  /// chunks.
+  fn chunked(
+    data: &[u8],
+    chunk_size: usize,
+  ) -> impl Stream<Item = io::Result<Bytes>> + Send + 'static {
+    let chunks = data
+      .chunks(chunk_size)
+      .map(|c| Ok(Bytes::copy_from_slice(c)))
+      .collect::<Vec<_>>();
+    futures::stream::iter(chunks)
+  }
+
+  async fn collect(
+    stream: impl Stream<Item = io::Result<Bytes>>,
+  ) -> io::Result<Vec<u8>> {
+    let mut stream = Box::pin(stream);
+    let mut output = Vec::new();
+    while let Some(chunk) = stream.next().await {
+      output.extend_from_slice(&chunk?);
+    }
+    Ok(output)
+  }
+
+  async fn encrypt(
+    data: &[u8],
+  ) -> (MasterKeyring, ArtifactEncryption, Vec<u8>) {
+    let keyring = keyring(&format!("a:{KEY_A}"));
+    let (data_key, encryption) = keyring.seal_new_key().unwrap();
+    let ciphertext = collect(encrypt_stream(
+      chunked(data, 1000),
+      &data_key,
+      &encryption.stream_nonce,
+    ))
+    .await
+    .unwrap();
+    (keyring, encryption, ciphertext)
+  }
+
+  async fn decrypt(
+    keyring: &MasterKeyring,
+    encryption: &ArtifactEncryption,
+    ciphertext: &[u8],
+  ) -> io::Result<Vec<u8>> {
+    let data_key = keyring.open_key(encryption).unwrap();
+    collect(decrypt_stream(
+      chunked(ciphertext, 777),
+      &data_key,
+      &encryption.stream_nonce,
+    ))
+    .await
+  }
+
+  #[tokio::test]
+  async fn round_trips_around_segment_boundaries() {
+    for len in [
+      0,
+      1,
+      SEGMENT_SIZE - 1,
+      SEGMENT_SIZE,
+      SEGMENT_SIZE + 1,
+      2 * SEGMENT_SIZE,
+      3 * SEGMENT_SIZE + 5,
+    ] {
+      let data = (0..=u8::MAX).cycle().take(len).collect::<Vec<_>>();
+      let (keyring, encryption, ciphertext) = encrypt(&data).await;
+
+      // a full segment is held back until more data arrives, so the final
+      // one is sealed as the last segment even on a boundary
+      let segments = len.div_ceil(SEGMENT_SIZE).max(1);
+      assert_eq!(ciphertext.len(), len + segments * TAG_SIZE, "len {len}");
+      assert_eq!(
+        decrypt(&keyring, &encryption, &ciphertext).await.unwrap(),
+        data,
+        "len {len}"
+      );
+    }
+  }
+
+  #[tokio::test]
+  async fn detects_truncation() {
+    let data = vec![7; 2 * SEGMENT_SIZE];
+    let (keyring, encryption, ciphertext) = encrypt(&data).await;
+
+    // dropping the last segment leaves a stream of whole segments
+    assert_eq!(ciphertext.len(), 2 * (SEGMENT_SIZE + TAG_SIZE));
+    for end in [0, SEGMENT_SIZE + TAG_SIZE, ciphertext.len() - 1] {
+      let err = decrypt(&keyring, &encryption, &ciphertext[..end])
+        .await
+        .unwrap_err();
+      assert_eq!(err.kind(), io::ErrorKind::InvalidData, "end {end}");
+    }
+  }
+
+  #[tokio::test]
+  async fn detects_tampering() {
+    let data = vec![7; SEGMENT_SIZE + 10];
+    let (keyring, encryption, mut ciphertext) = encrypt(&data).await;
+    ciphertext[SEGMENT_SIZE / 2] ^= 1;
+
+    let err = decrypt(&keyring, &encryption, &ciphertext)
+      .await
+      .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+  }
+
+  #[test]
+  fn parses_keyrings() {
+    let keyring = keyring(&format!(" b:{KEY_B} , a:{KEY_A},"));
+    assert_eq!(keyring.current_key_id(), "b");
+    assert_eq!(keyring.keys.len(), 2);
+
+    let parse = |s: &str| s.parse::<MasterKeyring>().unwrap_err();
+    assert!(matches!(parse(""), MasterKeyringParseError::Empty));
+    assert!(matches!(parse(" , "), MasterKeyringParseError::Empty));
+    assert!(matches!(
+      parse(KEY_A),
+      MasterKeyringParseError::MalformedEntry
+    ));
+    assert!(matches!(
+      parse(&format!(":{KEY_A}")),
+      MasterKeyringParseError::MalformedEntry
+    ));
+    assert!(matches!(
+      parse("a:not base64!"),
+      MasterKeyringParseError::InvalidEncoding(id) if id == "a"
+    ));
+    assert!(matches!(
+      parse("a:AAAA"),
+      MasterKeyringParseError::InvalidLength(id) if id == "a"
+    ));
+    assert!(matches!(
+      parse(&format!("a:{KEY_A},a:{KEY_B}")),
+      MasterKeyringParseError::DuplicateId(id) if id == "a"
+    ));
+  }
+
+  #[tokio::test]
+  async fn rotated_keys_still_open_and_rewrap() {
+    let data = b"original photo".to_vec();
+    let (_, encryption, ciphertext) = encrypt(&data).await;
+
+    let rotated = keyring(&format!("b:{KEY_B},a:{KEY_A}"));
+    assert_eq!(
+      decrypt(&rotated, &encryption, &ciphertext).await.unwrap(),
+      data
+    );
+
+    let rewrapped = rotated.rewrap_key(&encryption).unwrap().unwrap();
+    assert_eq!(rewrapped.master_key_id, "b");
+    assert_eq!(rotated.rewrap_key(&rewrapped).unwrap(), None);
+    assert_eq!(
+      decrypt(&rotated, &rewrapped, &ciphertext).await.unwrap(),
+      data
+    );
+
+    // once the old key is retired, only the rewrapped key opens
+    let retired = keyring(&format!("b:{KEY_B}"));
+    assert!(matches!(
+      retired.open_key(&encryption),
+      Err(ArtifactKeyError::UnknownMasterKey(id)) if id == "a"
+    ));
+    assert!(retired.open_key(&rewrapped).is_ok());
+  }
+}
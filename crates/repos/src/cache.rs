@@ -0,0 +1,505 @@
+use std::{
+  collections::BTreeMap,
+  fmt,
+  sync::{
+    atomic::{AtomicU64, AtomicUsize, Ordering},
+    Arc, Mutex, MutexGuard, PoisonError,
+  },
+  time::{Duration, Instant},
+};
+
+/// The bounds of a [`Cache`].
+#[derive(Clone, Copy, Debug)]
+pub struct CacheConfig {
+  /// The most entries the cache holds at once.
+  pub max_entries: usize,
+  /// The most total weight the cache holds at once. Values heavier than this
+  /// are never cached.
+  pub max_weight:  usize,
+  /// How long an entry is served for after it was inserted.
+  pub ttl:         Duration,
+}
+
+impl Default for CacheConfig {
+  fn default() -> Self {
+    Self {
+      max_entries: 10_000,
+      max_weight:  64 * 1024 * 1024,
+      ttl:         Duration::from_secs(5 * 60),
+    }
+  }
+}
+
+/// Live counters for a [`Cache`]. Shared between the cache and whoever
+/// reports on it.
+#[derive(Debug)]
+pub struct CacheMetrics {
+  name:          &'static str,
+  hits:          AtomicU64,
+  misses:        AtomicU64,
+  insertions:    AtomicU64,
+  evictions:     AtomicU64,
+  expirations:   AtomicU64,
+  invalidations: AtomicU64,
+  entries:       AtomicUsize,
+  weight:        AtomicUsize,
+}
+
+impl CacheMetrics {
+  fn new(name: &'static str) -> Self {
+    Self {
+      name,
+      hits: AtomicU64::new(0),
+      misses: AtomicU64::new(0),
+      insertions: AtomicU64::new(0),
+      evictions: AtomicU64::new(0),
+      expirations: AtomicU64::new(0),
+      invalidations: AtomicU64::new(0),
+      entries: AtomicUsize::new(0),
+      weight: AtomicUsize::new(0),
+    }
+  }
+
+  /// The name of the cache.
+  #[must_use]
+  pub fn name(&self) -> &'static str { self.name }
+
+  /// Takes a snapshot of the counters.
+  #[must_use]
+  pub fn snapshot(&self) -> CacheStats {
+    CacheStats {
+      hits:          self.hits.load(Ordering::Relaxed),
+      misses:        self.misses.load(Ordering::Relaxed),
+      insertions:    self.insertions.load(Ordering::Relaxed),
+      evictions:     self.evictions.load(Ordering::Relaxed),
+      expirations:   self.expirations.load(Ordering::Relaxed),
+      invalidations: self.invalidations.load(Ordering::Relaxed),
+      entries:       self.entries.load(Ordering::Relaxed),
+      weight:        self.weight.load(Ordering::Relaxed),
+    }
+  }
+
+  fn count(counter: &AtomicU64, n: u64) {
+    counter.fetch_add(n, Ordering::Relaxed);
+  }
+}
+
+/// A snapshot of a [`Cache`]'s counters.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+  /// Lookups that found a live entry.
+  pub hits:          u64,
+  /// Lookups that found nothing, or an expired entry.
+  pub misses:        u64,
+  /// Values inserted.
+  pub insertions:    u64,
+  /// Entries removed to stay within bounds.
+  pub evictions:     u64,
+  /// Entries removed because they outlived the TTL.
+  pub expirations:   u64,
+  /// Entries removed because the value changed.
+  pub invalidations: u64,
+  /// Entries currently held.
+  pub entries:       usize,
+  /// Total weight currently held.
+  pub weight:        usize,
+}
+
+impl fmt::Display for CacheStats {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let hit_percent = (self.hits * 100)
+      .checked_div(self.hits + self.misses)
+      .unwrap_or(0);
+    write!(
+      f,
+      "{} hits, {} misses ({hit_percent}% hit rate), {} entries weighing {}, \
+       {} evictions, {} expirations, {} invalidations",
+      self.hits,
+      self.misses,
+      self.entries,
+      self.weight,
+      self.evictions,
+      self.expirations,
+      self.invalidations,
+    )
+  }
+}
+
+struct Entry<V> {
+  value:      V,
+  weight:     usize,
+  expires_at: Instant,
+  last_used:  u64,
+}
+
+/// The [`CacheFill`]s of one key that are in flight.
+struct FillState {
+  count: usize,
+  /// Whether the key changed since the oldest of them started.
+  stale: bool,
+}
+
+struct Inner<K, V> {
+  entries:   BTreeMap<K, Entry<V>>,
+  /// Keys by the tick they were last used at, least recent first.
+  recency:   BTreeMap<u64, K>,
+  /// Keys being read from the backing store, so a change can mark the
+  /// values being read as stale.
+  fills:     BTreeMap<K, FillState>,
+  next_tick: u64,
+  weight:    usize,
+}
+
+impl<K: Ord + Clone, V> Inner<K, V> {
+  fn tick(&mut self) -> u64 {
+    self.next_tick += 1;
+    self.next_tick
+  }
+
+  fn remove(&mut self, key: &K) -> Option<Entry<V>> {
+    let entry = self.entries.remove(key)?;
+    self.recency.remove(&entry.last_used);
+    self.weight -= entry.weight;
+    Some(entry)
+  }
+
+  fn mark_stale(&mut self, key: &K) {
+    if let Some(fill) = self.fills.get_mut(key) {
+      fill.stale = true;
+    }
+  }
+
+  fn remove_least_recent(&mut self) -> bool {
+    let Some((_, key)) = self.recency.pop_first() else {
+      return false;
+    };
+    if let Some(entry) = self.entries.remove(&key) {
+      self.weight -= entry.weight;
+    }
+    true
+  }
+}
+
+/// A bounded, in-memory cache with a TTL. When full, the least recently used
+/// entries are evicted first. Values are weighed on insertion, and the cache
+/// keeps its total weight under [`CacheConfig::max_weight`].
+///
+/// Values read from a backing store are inserted through a [`CacheFill`], so
+/// that a read that raced with a change never caches the value from before it.
+///
+/// Clones share the same entries.
+pub struct Cache<K, V> {
+  config:  CacheConfig,
+  weigher: fn(&V) -> usize,
+  inner:   Arc<Mutex<Inner<K, V>>>,
+  metrics: Arc<CacheMetrics>,
+}
+
+impl<K, V> Clone for Cache<K, V> {
+  fn clone(&self) -> Self {
+    Self {
+      config:  self.config,
+      weigher: self.weigher,
+      inner:   self.inner.clone(),
+      metrics: self.metrics.clone(),
+    }
+  }
+}
+
+impl<K, V> fmt::Debug for Cache<K, V> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("Cache")
+      .field("name", &self.metrics.name)
+      .field("config", &self.config)
+      .field("stats", &self.metrics.snapshot())
+      .finish_non_exhaustive()
+  }
+}
+
+impl<K: Ord + Clone, V: Clone> Cache<K, V> {
+  /// Create a new [`Cache`] where every value weighs the same.
+  #[must_use]
+  pub fn new(name: &'static str, config: CacheConfig) -> Self {
+    Self::with_weigher(name, config, |_| 1)
+  }
+
+  /// Create a new [`Cache`] that weighs values with `weigher`.
+  #[must_use]
+  pub fn with_weigher(
+    name: &'static str,
+    config: CacheConfig,
+    weigher: fn(&V) -> usize,
+  ) -> Self {
+    Self {
+      config,
+      weigher,
+      inner: Arc::new(Mutex::new(Inner {
+        entries:   BTreeMap::new(),
+        recency:   BTreeMap::new(),
+        fills:     BTreeMap::new(),
+        next_tick: 0,
+        weight:    0,
+      })),
+      metrics: Arc::new(CacheMetrics::new(name)),
+    }
+  }
+
+  /// The live counters of this cache.
+  #[must_use]
+  pub fn metrics(&self) -> Arc<CacheMetrics> { self.metrics.clone() }
+
+  fn lock(&self) -> MutexGuard<'_, Inner<K, V>> {
+    self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+  }
+
+  fn update_gauges(&self, inner: &Inner<K, V>) {
+    self
+      .metrics
+      .entries
+      .store(inner.entries.len(), Ordering::Relaxed);
+    self.metrics.weight.store(inner.weight, Ordering::Relaxed);
+  }
+
+  /// Look up a live entry, marking it as recently used.
+  pub fn get(&self, key: &K) -> Option<V> {
+    let mut inner = self.lock();
+    let now = Instant::now();
+
+    let expired = match inner.entries.get(key) {
+      None => {
+        CacheMetrics::count(&self.metrics.misses, 1);
+        return None;
+      }
+      Some(entry) => entry.expires_at <= now,
+    };
+    if expired {
+      inner.remove(key);
+      self.update_gauges(&inner);
+      CacheMetrics::count(&self.metrics.expirations, 1);
+      CacheMetrics::count(&self.metrics.misses, 1);
+      return None;
+    }
+
+    let tick = inner.tick();
+    let entry = inner.entries.get_mut(key)?;
+    let previous = std::mem::replace(&mut entry.last_used, tick);
+    let value = entry.value.clone();
+    inner.recency.remove(&previous);
+    inner.recency.insert(tick, key.clone());
+    CacheMetrics::count(&self.metrics.hits, 1);
+    Some(value)
+  }
+
+  /// Insert a value, replacing any entry for the same key, then evict the
+  /// least recently used entries until the cache is within its bounds.
+  pub fn insert(&self, key: K, value: V) {
+    let mut inner = self.lock();
+    inner.mark_stale(&key);
+    self.insert_locked(&mut inner, key, value);
+  }
+
+  /// Start reading the value for a key from the backing store, to be
+  /// inserted with [`CacheFill::insert`] once read. Call this before the read.
+  pub fn fill(&self, key: K) -> CacheFill<'_, K, V> {
+    let mut inner = self.lock();
+    inner
+      .fills
+      .entry(key.clone())
+      .or_insert(FillState {
+        count: 0,
+        stale: false,
+      })
+      .count += 1;
+    CacheFill { cache: self, key }
+  }
+
+  fn insert_locked(&self, inner: &mut Inner<K, V>, key: K, value: V) {
+    let weight = (self.weigher)(&value);
+    if weight > self.config.max_weight || self.config.max_entries == 0 {
+      return;
+    }
+
+    inner.remove(&key);
+    let tick = inner.tick();
+    inner.recency.insert(tick, key.clone());
+    inner.entries.insert(key, Entry {
+      value,
+      weight,
+      expires_at: Instant::now() + self.config.ttl,
+      last_used: tick,
+    });
+    inner.weight += weight;
+    CacheMetrics::count(&self.metrics.insertions, 1);
+
+    let mut evicted = 0;
+    while (inner.entries.len() > self.config.max_entries
+      || inner.weight > self.config.max_weight)
+      && inner.remove_least_recent()
+    {
+      evicted += 1;
+    }
+    CacheMetrics::count(&self.metrics.evictions, evicted);
+    self.update_gauges(inner);
+  }
+
+  /// Remove the entry for a key, if there is one.
+  pub fn invalidate(&self, key: &K) {
+    let mut inner = self.lock();
+    inner.mark_stale(key);
+    if inner.remove(key).is_some() {
+      CacheMetrics::count(&self.metrics.invalidations, 1);
+      self.update_gauges(&inner);
+    }
+  }
+
+  /// Remove every entry.
+  pub fn clear(&self) {
+    let mut inner = self.lock();
+    let count = inner.entries.len() as u64;
+    inner.entries.clear();
+    inner.recency.clear();
+    for fill in inner.fills.values_mut() {
+      fill.stale = true;
+    }
+    inner.weight = 0;
+    CacheMetrics::count(&self.metrics.invalidations, count);
+    self.update_gauges(&inner);
+  }
+}
+
+/// A read of one key from the backing store of a [`Cache`], started with
+/// [`Cache::fill`].
+#[must_use = "a fill only caches a value once it's inserted"]
+pub struct CacheFill<'a, K: Ord + Clone, V: Clone> {
+  cache: &'a Cache<K, V>,
+  key:   K,
+}
+
+impl<K: Ord + Clone, V: Clone> CacheFill<'_, K, V> {
+  /// Insert the value read, unless the key was inserted, invalidated or
+  /// cleared since the fill started, in which case it may be stale and is
+  /// dropped.
+  pub fn insert(self, value: V) {
+    let mut inner = self.cache.lock();
+    if inner.fills.get(&self.key).is_some_and(|fill| !fill.stale) {
+      self
+        .cache
+        .insert_locked(&mut inner, self.key.clone(), value);
+    }
+  }
+}
+
+impl<K: Ord + Clone, V: Clone> Drop for CacheFill<'_, K, V> {
+  fn drop(&mut self) {
+    let mut inner = self.cache.lock();
+    if let Some(fill) = inner.fills.get_mut(&self.key) {
+      fill.count -= 1;
+      if fill.count == 0 {
+        inner.fills.remove(&self.key);
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn config(max_entries: usize, max_weight: usize) -> CacheConfig {
+    CacheConfig {
+      max_entries,
+      max_weight,
+      ttl: Duration::from_secs(60),
+    }
+  }
+
+  #[test]
+  fn evicts_least_recently_used_entry() {
+    let cache = Cache::new("test", config(2, usize::MAX));
+    cache.insert(1, "a");
+    cache.insert(2, "b");
+    assert_eq!(cache.get(&1), Some("a"));
+    cache.insert(3, "c");
+
+    assert_eq!(cache.get(&2), None);
+    assert_eq!(cache.get(&1), Some("a"));
+    assert_eq!(cache.get(&3), Some("c"));
+    let stats = cache.metrics().snapshot();
+    assert_eq!(stats.evictions, 1);
+    assert_eq!(stats.entries, 2);
+  }
+
+  #[test]
+  fn stays_within_max_weight() {
+    let cache =
+      Cache::with_weigher("test", config(100, 10), |v: &Vec<u8>| v.len());
+    cache.insert(1, vec![0; 6]);
+    cache.insert(2, vec![0; 6]);
+    cache.insert(3, vec![0; 11]);
+
+    assert_eq!(cache.get(&1), None);
+    assert_eq!(cache.get(&2).map(|v| v.len()), Some(6));
+    // heavier than the whole cache, so never stored
+    assert_eq!(cache.get(&3), None);
+    assert_eq!(cache.metrics().snapshot().weight, 6);
+  }
+
+  #[test]
+  fn expires_entries_after_ttl() {
+    let cache = Cache::new("test", CacheConfig {
+      ttl: Duration::ZERO,
+      ..config(10, usize::MAX)
+    });
+    cache.insert(1, "a");
+
+    assert_eq!(cache.get(&1), None);
+    let stats = cache.metrics().snapshot();
+    assert_eq!(stats.expirations, 1);
+    assert_eq!(stats.entries, 0);
+  }
+
+  #[test]
+  fn counts_hits_misses_and_invalidations() {
+    let cache = Cache::new("test", config(10, usize::MAX));
+    cache.insert(1, "a");
+    assert_eq!(cache.get(&1), Some("a"));
+    assert_eq!(cache.get(&2), None);
+    cache.invalidate(&1);
+    assert_eq!(cache.get(&1), None);
+
+    let stats = cache.metrics().snapshot();
+    assert_eq!((stats.hits, stats.misses), (1, 2));
+    assert_eq!(stats.invalidations, 1);
+  }
+
+  #[test]
+  fn fills_insert_values_read_while_unchanged() {
+    let cache = Cache::new("test", config(10, usize::MAX));
+    cache.fill(1).insert("a");
+    assert_eq!(cache.get(&1), Some("a"));
+  }
+
+  #[test]
+  fn fills_drop_values_read_across_a_change() {
+    let cache = Cache::new("test", config(10, usize::MAX));
+
+    let fill = cache.fill(1);
+    cache.invalidate(&1);
+    fill.insert("before invalidation");
+    assert_eq!(cache.get(&1), None);
+
+    let fill = cache.fill(1);
+    cache.insert(1, "written");
+    fill.insert("before write");
+    assert_eq!(cache.get(&1), Some("written"));
+
+    let fill = cache.fill(2);
+    cache.clear();
+    fill.insert("before clear");
+    assert_eq!(cache.get(&2), None);
+
+    // a change only affects the fills in flight when it happened
+    cache.fill(2).insert("after clear");
+    assert_eq!(cache.get(&2), Some("after clear"));
+  }
+}
@@ -0,0 +1,375 @@
+use std::{collections::BTreeSet, sync::Arc};
+
+use db::{
+  CreateModelError, DeleteModelError, FetchModelByIndexError, FetchModelError,
+  PatchModelError,
+};
+use hex::health::{self, HealthAware};
+use miette::Result;
+use models::{
+  Image, ImageRecordId, Photo, PhotoGroup, PhotoGroupRecordId, PhotoRecordId,
+  UserRecordId,
+};
+use tracing::instrument;
+
+use crate::{
+  cache::{Cache, CacheConfig, CacheMetrics},
+  ImageRepositoryLike, MigrateModelsError, MigrationOptions, MigrationReport,
+  PhotoGroupRepositoryLike, PhotoRepositoryLike, RestoreModelError,
+};
+
+/// Implements `HealthReporter` for a caching repository by deferring to the
+/// repository it wraps.
+macro_rules! impl_caching_health_reporter {
+  ($ty:ident) => {
+    #[async_trait::async_trait]
+    impl health::HealthReporter for $ty {
+      fn name(&self) -> &'static str { stringify!($ty) }
+
+      async fn health_check(&self) -> health::ComponentHealth {
+        health::AdditiveComponentHealth::from_futures(vec![self
+          .inner
+          .health_report()])
+        .await
+        .into()
+      }
+    }
+  };
+}
+
+/// Serves [`Image`]s by id from a read-through [`Cache`] in front of another
+/// [`ImageRepositoryLike`].
+#[derive(Clone, Debug)]
+pub struct CachingImageRepository {
+  inner: Arc<dyn ImageRepositoryLike>,
+  cache: Cache<ImageRecordId, Image>,
+}
+
+impl_caching_health_reporter!(CachingImageRepository);
+
+impl CachingImageRepository {
+  /// Create a new [`CachingImageRepository`].
+  #[must_use]
+  pub fn new(inner: impl ImageRepositoryLike, config: CacheConfig) -> Self {
+    Self {
+      inner: Arc::new(inner),
+      cache: Cache::new("image", config),
+    }
+  }
+
+  /// The live counters of the cache.
+  #[must_use]
+  pub fn metrics(&self) -> Arc<CacheMetrics> { self.cache.metrics() }
+}
+
+#[async_trait::async_trait]
+impl ImageRepositoryLike for CachingImageRepository {
+  async fn create_image(
+    &self,
+    input: models::ImageCreateRequest,
+  ) -> Result<Image, CreateModelError> {
+    self.inner.create_image(input).await
+  }
+
+  #[instrument(skip(self))]
+  async fn fetch_image_by_id(
+    &self,
+    id: ImageRecordId,
+  ) -> Result<Option<Image>, FetchModelError> {
+    if let Some(image) = self.cache.get(&id) {
+      return Ok(Some(image));
+    }
+    let fill = self.cache.fill(id);
+    let image = self.inner.fetch_image_by_id(id).await?;
+    if let Some(image) = &image {
+      fill.insert(image.clone());
+    }
+    Ok(image)
+  }
+
+  async fn enumerate_images(&self) -> Result<Vec<Image>> {
+    self.inner.enumerate_images().await
+  }
+
+  async fn delete_image(
+    &self,
+    id: ImageRecordId,
+  ) -> Result<bool, DeleteModelError> {
+    let result = self.inner.delete_image(id).await;
+    self.cache.invalidate(&id);
+    result
+  }
+
+  async fn migrate_images(
+    &self,
+    options: MigrationOptions,
+  ) -> Result<MigrationReport, MigrateModelsError> {
+    let result = self.inner.migrate_images(options).await;
+    self.cache.clear();
+    result
+  }
+
+  async fn restore_image(&self, image: Image) -> Result<(), RestoreModelError> {
+    let id = image.id;
+    let result = self.inner.restore_image(image).await;
+    self.cache.invalidate(&id);
+    result
+  }
+
+  async fn prune_images(
+    &self,
+    keep: &BTreeSet<String>,
+  ) -> Result<usize, RestoreModelError> {
+    let result = self.inner.prune_images(keep).await;
+    self.cache.clear();
+    result
+  }
+}
+
+/// Serves [`Photo`]s by id from a read-through [`Cache`] in front of another
+/// [`PhotoRepositoryLike`].
+#[derive(Clone, Debug)]
+pub struct CachingPhotoRepository {
+  inner: Arc<dyn PhotoRepositoryLike>,
+  cache: Cache<PhotoRecordId, Photo>,
+}
+
+impl_caching_health_reporter!(CachingPhotoRepository);
+
+impl CachingPhotoRepository {
+  /// Create a new [`CachingPhotoRepository`].
+  #[must_use]
+  pub fn new(inner: impl PhotoRepositoryLike, config: CacheConfig) -> Self {
+    Self {
+      inner: Arc::new(inner),
+      cache: Cache::new("photo", config),
+    }
+  }
+
+  /// The live counters of the cache.
+  #[must_use]
+  pub fn metrics(&self) -> Arc<CacheMetrics> { self.cache.metrics() }
+}
+
+#[async_trait::async_trait]
+impl PhotoRepositoryLike for CachingPhotoRepository {
+  async fn create_photo(
+    &self,
+    input: models::PhotoCreateRequest,
+  ) -> Result<Photo, CreateModelError> {
+    self.inner.create_photo(input).await
+  }
+
+  #[instrument(skip(self))]
+  async fn fetch_photo_by_id(
+    &self,
+    id: PhotoRecordId,
+  ) -> Result<Option<Photo>, FetchModelError> {
+    if let Some(photo) = self.cache.get(&id) {
+      return Ok(Some(photo));
+    }
+    let fill = self.cache.fill(id);
+    let photo = self.inner.fetch_photo_by_id(id).await?;
+    if let Some(photo) = &photo {
+      fill.insert(photo.clone());
+    }
+    Ok(photo)
+  }
+
+  async fn update_photo(&self, photo: Photo) -> Result<Photo, PatchModelError> {
+    let id = photo.id;
+    self.cache.invalidate(&id);
+    let fill = self.cache.fill(id);
+    let photo = self.inner.update_photo(photo).await?;
+    fill.insert(photo.clone());
+    Ok(photo)
+  }
+
+  async fn fetch_photos_by_image(
+    &self,
+    image: ImageRecordId,
+  ) -> Result<Vec<Photo>, FetchModelByIndexError> {
+    self.inner.fetch_photos_by_image(image).await
+  }
+
+  async fn enumerate_photos(&self) -> Result<Vec<Photo>> {
+    self.inner.enumerate_photos().await
+  }
+
+  async fn delete_photo(
+    &self,
+    id: PhotoRecordId,
+  ) -> Result<bool, DeleteModelError> {
+    let result = self.inner.delete_photo(id).await;
+    self.cache.invalidate(&id);
+    result
+  }
+
+  async fn migrate_photos(
+    &self,
+    options: MigrationOptions,
+  ) -> Result<MigrationReport, MigrateModelsError> {
+    let result = self.inner.migrate_photos(options).await;
+    self.cache.clear();
+    result
+  }
+
+  async fn restore_photo(&self, photo: Photo) -> Result<(), RestoreModelError> {
+    let id = photo.id;
+    let result = self.inner.restore_photo(photo).await;
+    self.cache.invalidate(&id);
+    result
+  }
+
+  async fn prune_photos(
+    &self,
+    keep: &BTreeSet<String>,
+  ) -> Result<usize, RestoreModelError> {
+    let result = self.inner.prune_photos(keep).await;
+    self.cache.clear();
+    result
+  }
+}
+
+/// Serves [`PhotoGroup`]s by id from a read-through [`Cache`] in front of
+/// another [`PhotoGroupRepositoryLike`]. Lookups by user aren't cached.
+#[derive(Clone, Debug)]
+pub struct CachingPhotoGroupRepository {
+  inner: Arc<dyn PhotoGroupRepositoryLike>,
+  cache: Cache<PhotoGroupRecordId, PhotoGroup>,
+}
+
+impl_caching_health_reporter!(CachingPhotoGroupRepository);
+
+impl CachingPhotoGroupRepository {
+  /// Create a new [`CachingPhotoGroupRepository`].
+  #[must_use]
+  pub fn new(
+    inner: impl PhotoGroupRepositoryLike,
+    config: CacheConfig,
+  ) -> Self {
+    Self {
+      inner: Arc::new(inner),
+      cache: Cache::new("photo_group", config),
+    }
+  }
+
+  /// The live counters of the cache.
+  #[must_use]
+  pub fn metrics(&self) -> Arc<CacheMetrics> { self.cache.metrics() }
+}
+
+#[async_trait::async_trait]
+impl PhotoGroupRepositoryLike for CachingPhotoGroupRepository {
+  async fn create_photo_group(
+    &self,
+    input: models::PhotoGroupCreateRequest,
+  ) -> Result<PhotoGroup, CreateModelError> {
+    self.inner.create_photo_group(input).await
+  }
+
+  async fn create_photo_group_with_photos(
+    &self,
+    input: models::PhotoGroupCreateRequest,
+    photos: Vec<Photo>,
+  ) -> Result<PhotoGroup, CreateModelError> {
+    self
+      .inner
+      .create_photo_group_with_photos(input, photos)
+      .await
+  }
+
+  #[instrument(skip(self))]
+  async fn fetch_photo_group_by_id(
+    &self,
+    id: PhotoGroupRecordId,
+  ) -> Result<Option<PhotoGroup>, FetchModelError> {
+    if let Some(photo_group) = self.cache.get(&id) {
+      return Ok(Some(photo_group));
+    }
+    let fill = self.cache.fill(id);
+    let photo_group = self.inner.fetch_photo_group_by_id(id).await?;
+    if let Some(photo_group) = &photo_group {
+      fill.insert(photo_group.clone());
+    }
+    Ok(photo_group)
+  }
+
+  async fn update_photo_group(
+    &self,
+    photo_group: PhotoGroup,
+  ) -> Result<PhotoGroup, PatchModelError> {
+    let id = photo_group.id;
+    self.cache.invalidate(&id);
+    let fill = self.cache.fill(id);
+    let photo_group = self.inner.update_photo_group(photo_group).await?;
+    fill.insert(photo_group.clone());
+    Ok(photo_group)
+  }
+
+  async fn replace_photo_group(
+    &self,
+    expected: &PhotoGroup,
+    photo_group: PhotoGroup,
+  ) -> Result<Option<PhotoGroup>, PatchModelError> {
+    let id = photo_group.id;
+    self.cache.invalidate(&id);
+    let fill = self.cache.fill(id);
+    let photo_group = self
+      .inner
+      .replace_photo_group(expected, photo_group)
+      .await?;
+    if let Some(photo_group) = &photo_group {
+      fill.insert(photo_group.clone());
+    }
+    Ok(photo_group)
+  }
+
+  async fn fetch_photo_groups_by_user(
+    &self,
+    owner: UserRecordId,
+  ) -> Result<Vec<PhotoGroup>, FetchModelByIndexError> {
+    self.inner.fetch_photo_groups_by_user(owner).await
+  }
+
+  async fn enumerate_photo_groups(&self) -> Result<Vec<PhotoGroup>> {
+    self.inner.enumerate_photo_groups().await
+  }
+
+  async fn delete_photo_group(
+    &self,
+    id: PhotoGroupRecordId,
+  ) -> Result<bool, DeleteModelError> {
+    let result = self.inner.delete_photo_group(id).await;
+    self.cache.invalidate(&id);
+    result
+  }
+
+  async fn migrate_photo_groups(
+    &self,
+    options: MigrationOptions,
+  ) -> Result<MigrationReport, MigrateModelsError> {
+    let result = self.inner.migrate_photo_groups(options).await;
+    self.cache.clear();
+    result
+  }
+
+  async fn restore_photo_group(
+    &self,
+    photo_group: PhotoGroup,
+  ) -> Result<(), RestoreModelError> {
+    let id = photo_group.id;
+    let result = self.inner.restore_photo_group(photo_group).await;
+    self.cache.invalidate(&id);
+    result
+  }
+
+  async fn prune_photo_groups(
+    &self,
+    keep: &BTreeSet<String>,
+  ) -> Result<usize, RestoreModelError> {
+    let result = self.inner.prune_photo_groups(keep).await;
+    self.cache.clear();
+    result
+  }
+}
@@ -0,0 +1,1015 @@
+//! In-memory fakes of the repositories, for testing services without a
+//! database or storage.
+//!
+//! Each fake keeps its records in memory, shares them between its clones,
+//! and carries a [`FaultPlan`] for making chosen operations fail.
+
+use std::{
+  collections::{BTreeMap, BTreeSet, HashMap},
+  sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use bytes::{Bytes, BytesMut};
+use db::{
+  CreateModelError, DeleteModelError, FetchModelByIndexError, FetchModelError,
+  PatchModelError,
+};
+use futures::StreamExt;
+use hex::health;
+use miette::{miette, Result};
+use models::{
+  Artifact, ArtifactCreateRequest, ArtifactMimeType, ArtifactPath,
+  ArtifactRecordId, CompressionStatus, EmailAddress, FileSize, Image,
+  ImageCreateRequest, ImageRecordId, LoginAttempts, Model, Photo,
+  PhotoCreateRequest, PhotoGroup, PhotoGroupCreateRequest, PhotoGroupRecordId,
+  PhotoRecordId, Timestamped, User, UserCreateRequest, UserRecordId, Versioned,
+};
+use storage::belt::{Belt, DEFAULT_CHUNK_SIZE};
+
+use crate::{
+  ArtifactRepositoryLike, CreateArtifactError, DeleteArtifactError,
+  ImageRepositoryLike, LoginAttemptError, LoginAttemptRepositoryLike,
+  LoginAttemptSubject, LoginAttemptUpdate, MigrateModelsError,
+  MigrationOptions, MigrationReport, PhotoGroupRepositoryLike,
+  PhotoRepositoryLike, ReadArtifactError, RestoreModelError,
+  RewrapArtifactKeysError, SnapshotError, SnapshotRepositoryLike,
+  StoreSnapshot, UpdatedLoginAttempts, UserRepositoryLike, UserUpdate,
+};
+
+/// Makes chosen operations of a fake repository fail. Operations are named
+/// after the repository trait methods, e.g. `"create_photo"`.
+#[derive(Clone, Debug, Default)]
+pub struct FaultPlan(Arc<Mutex<HashMap<&'static str, usize>>>);
+
+impl FaultPlan {
+  fn lock(&self) -> MutexGuard<'_, HashMap<&'static str, usize>> {
+    self.0.lock().unwrap_or_else(PoisonError::into_inner)
+  }
+
+  /// Makes `operation` fail on every call after its next `successes` calls.
+  pub fn fail_after(&self, operation: &'static str, successes: usize) {
+    self.lock().insert(operation, successes);
+  }
+
+  /// Makes every call to `operation` fail.
+  pub fn fail(&self, operation: &'static str) { self.fail_after(operation, 0) }
+
+  /// Makes `operation` succeed again.
+  pub fn heal(&self, operation: &'static str) { self.lock().remove(operation); }
+
+  fn check(&self, operation: &'static str) -> Result<()> {
+    match self.lock().get_mut(operation) {
+      Some(0) => Err(miette!("injected failure in `{operation}`")),
+      Some(successes) => {
+        *successes -= 1;
+        Ok(())
+      }
+      None => Ok(()),
+    }
+  }
+}
+
+/// An in-memory table of models, keyed by id.
+#[derive(Debug)]
+struct FakeTable<M>(Arc<Mutex<BTreeMap<String, M>>>);
+
+impl<M> Clone for FakeTable<M> {
+  fn clone(&self) -> Self { Self(self.0.clone()) }
+}
+
+impl<M> Default for FakeTable<M> {
+  fn default() -> Self { Self(Arc::default()) }
+}
+
+impl<M: Model + Versioned + Clone> FakeTable<M> {
+  fn create(&self, model: M) -> Result<M, CreateModelError> {
+    let mut table = self.0.lock().unwrap();
+    let id = model.id().to_string();
+    if table.contains_key(&id) {
+      return Err(CreateModelError::Db(miette!(
+        "{} model {id} already exists",
+        M::TABLE_NAME
+      )));
+    }
+    table.insert(id, model.clone());
+    Ok(model)
+  }
+
+  fn fetch(&self, id: &str) -> Option<M> {
+    self.0.lock().unwrap().get(id).cloned()
+  }
+
+  fn find(&self, predicate: impl Fn(&M) -> bool) -> Vec<M> {
+    self
+      .0
+      .lock()
+      .unwrap()
+      .values()
+      .filter(|m| predicate(m))
+      .cloned()
+      .collect()
+  }
+
+  fn all(&self) -> Vec<M> { self.find(|_| true) }
+
+  fn lock(&self) -> MutexGuard<'_, BTreeMap<String, M>> {
+    self.0.lock().unwrap()
+  }
+
+  fn patch(&self, model: M) -> Result<M, PatchModelError> {
+    let mut table = self.0.lock().unwrap();
+    let id = model.id().to_string();
+    let Some(stored) = table.get_mut(&id) else {
+      return Err(PatchModelError::Db(miette!(
+        "{} model {id} does not exist",
+        M::TABLE_NAME
+      )));
+    };
+    *stored = model.clone();
+    Ok(model)
+  }
+
+  fn delete(&self, id: &str) -> bool {
+    self.0.lock().unwrap().remove(id).is_some()
+  }
+
+  fn restore(&self, model: M) {
+    self.0.lock().unwrap().insert(model.id().to_string(), model);
+  }
+
+  fn prune(&self, keep: &BTreeSet<String>) -> usize {
+    let mut table = self.0.lock().unwrap();
+    let before = table.len();
+    table.retain(|id, _| keep.contains(id));
+    before - table.len()
+  }
+
+  fn migrate(&self, options: MigrationOptions) -> MigrationReport {
+    let mut table = self.0.lock().unwrap();
+    let mut report = MigrationReport {
+      table: M::TABLE_NAME,
+      examined: table.len(),
+      ..Default::default()
+    };
+    for model in table.values_mut() {
+      if model.is_outdated() {
+        *report.outdated.entry(model.schema_version()).or_default() += 1;
+      } else if !options.rebuild_indices {
+        continue;
+      }
+      if !options.dry_run {
+        model.upcast();
+        report.written += 1;
+      }
+    }
+    report
+  }
+}
+
+macro_rules! impl_fake_health_reporter {
+  ($fake:ty) => {
+    #[async_trait::async_trait]
+    impl health::HealthReporter for $fake {
+      fn name(&self) -> &'static str { stringify!($fake) }
+
+      async fn health_check(&self) -> health::ComponentHealth {
+        health::AdditiveComponentHealth::from_futures(Vec::new())
+          .await
+          .into()
+      }
+    }
+  };
+}
+
+/// An in-memory fake of [`ArtifactRepositoryLike`]. Artifact data is kept
+/// uncompressed and unencrypted.
+#[derive(Clone, Debug, Default)]
+pub struct FakeArtifactRepository {
+  artifacts: FakeTable<Artifact>,
+  data:      Arc<Mutex<BTreeMap<String, Bytes>>>,
+  faults:    FaultPlan,
+}
+
+impl FakeArtifactRepository {
+  /// Create an empty [`FakeArtifactRepository`].
+  #[must_use]
+  pub fn new() -> Self { Self::default() }
+
+  /// The [`FaultPlan`] of this fake.
+  #[must_use]
+  pub fn faults(&self) -> &FaultPlan { &self.faults }
+
+  fn read_data(&self, path: &ArtifactPath) -> Option<Belt> {
+    let data = self.data.lock().unwrap().get(&path.to_string()).cloned()?;
+    Some(Belt::from_stream(
+      futures::stream::once(async move { Ok(data) }),
+      Some(DEFAULT_CHUNK_SIZE),
+    ))
+  }
+
+  async fn write_data(
+    &self,
+    path: &ArtifactPath,
+    data: Belt,
+  ) -> std::io::Result<u64> {
+    let mut data = std::pin::pin!(data);
+    let mut buffer = BytesMut::new();
+    while let Some(chunk) = data.next().await {
+      buffer.extend_from_slice(&chunk?);
+    }
+    let size = buffer.len() as u64;
+    self
+      .data
+      .lock()
+      .unwrap()
+      .insert(path.to_string(), buffer.freeze());
+    Ok(size)
+  }
+}
+
+impl_fake_health_reporter!(FakeArtifactRepository);
+
+#[async_trait::async_trait]
+impl ArtifactRepositoryLike for FakeArtifactRepository {
+  async fn fetch_artifact_by_id(
+    &self,
+    id: ArtifactRecordId,
+  ) -> Result<Option<Artifact>, FetchModelError> {
+    self
+      .faults
+      .check("fetch_artifact_by_id")
+      .map_err(FetchModelError::Db)?;
+    Ok(self.artifacts.fetch(&id.to_string()))
+  }
+
+  async fn fetch_artifact_by_path(
+    &self,
+    path: ArtifactPath,
+  ) -> Result<Option<Artifact>, FetchModelByIndexError> {
+    self
+      .faults
+      .check("fetch_artifact_by_path")
+      .map_err(FetchModelByIndexError::Db)?;
+    Ok(self.artifacts.find(|a| a.path == path).into_iter().next())
+  }
+
+  async fn read_artifact_by_id(
+    &self,
+    id: ArtifactRecordId,
+  ) -> Result<Option<(Belt, Option<ArtifactMimeType>)>, ReadArtifactError> {
+    self
+      .faults
+      .check("read_artifact_by_id")
+      .map_err(|e| ReadArtifactError::IoError(std::io::Error::other(e)))?;
+    let Some(artifact) = self.artifacts.fetch(&id.to_string()) else {
+      return Ok(None);
+    };
+    let data = self.read_data(&artifact.path).ok_or_else(|| {
+      ReadArtifactError::IoError(std::io::ErrorKind::NotFound.into())
+    })?;
+    Ok(Some((data, artifact.stated_mime_type)))
+  }
+
+  async fn read_stored_artifact_data(
+    &self,
+    path: &ArtifactPath,
+  ) -> Result<Belt, ReadArtifactError> {
+    self
+      .faults
+      .check("read_stored_artifact_data")
+      .map_err(|e| ReadArtifactError::IoError(std::io::Error::other(e)))?;
+    self.read_data(path).ok_or_else(|| {
+      ReadArtifactError::IoError(std::io::ErrorKind::NotFound.into())
+    })
+  }
+
+  async fn write_stored_artifact_data(
+    &self,
+    path: &ArtifactPath,
+    data: Belt,
+  ) -> Result<(), CreateArtifactError> {
+    self
+      .faults
+      .check("write_stored_artifact_data")
+      .map_err(|e| {
+        CreateArtifactError::CreateModelError(CreateModelError::Db(e))
+      })?;
+    self.write_data(path, data).await.map_err(|e| {
+      CreateArtifactError::CreateModelError(CreateModelError::Db(miette!(
+        "failed to collect artifact data: {e}"
+      )))
+    })?;
+    Ok(())
+  }
+
+  async fn create_artifact(
+    &self,
+    data: Belt,
+    originator: UserRecordId,
+    stated_mime_type: Option<ArtifactMimeType>,
+  ) -> Result<Artifact, CreateArtifactError> {
+    self.faults.check("create_artifact").map_err(|e| {
+      CreateArtifactError::CreateModelError(CreateModelError::Db(e))
+    })?;
+    let path = ArtifactPath::new_random();
+    let size = self.write_data(&path, data).await.map_err(|e| {
+      CreateArtifactError::CreateModelError(CreateModelError::Db(miette!(
+        "failed to collect artifact data: {e}"
+      )))
+    })?;
+    self
+      .artifacts
+      .create(
+        ArtifactCreateRequest {
+          path,
+          originator,
+          comp_status: CompressionStatus::Uncompressed {
+            size: FileSize::new(size),
+          },
+          stated_mime_type,
+          encryption: None,
+        }
+        .into(),
+      )
+      .map_err(CreateArtifactError::CreateModelError)
+  }
+
+  async fn rewrap_artifact_keys(
+    &self,
+  ) -> Result<usize, RewrapArtifactKeysError> {
+    Ok(0)
+  }
+
+  async fn delete_artifact(
+    &self,
+    id: ArtifactRecordId,
+  ) -> Result<bool, DeleteArtifactError> {
+    self.faults.check("delete_artifact").map_err(|e| {
+      DeleteArtifactError::DeleteModelError(DeleteModelError::Db(e))
+    })?;
+    let Some(artifact) = self.artifacts.fetch(&id.to_string()) else {
+      return Ok(false);
+    };
+    self.artifacts.delete(&id.to_string());
+    self.data.lock().unwrap().remove(&artifact.path.to_string());
+    Ok(true)
+  }
+
+  async fn migrate_artifacts(
+    &self,
+    options: MigrationOptions,
+  ) -> Result<MigrationReport, MigrateModelsError> {
+    Ok(self.artifacts.migrate(options))
+  }
+
+  async fn enumerate_artifacts(&self) -> Result<Vec<Artifact>> {
+    self.faults.check("enumerate_artifacts")?;
+    Ok(self.artifacts.all())
+  }
+
+  async fn restore_artifact(
+    &self,
+    artifact: Artifact,
+  ) -> Result<(), RestoreModelError> {
+    self.artifacts.restore(artifact);
+    Ok(())
+  }
+
+  async fn prune_artifacts(
+    &self,
+    keep: &BTreeSet<String>,
+  ) -> Result<usize, RestoreModelError> {
+    let mut data = self.data.lock().unwrap();
+    for artifact in self.artifacts.find(|a| !keep.contains(&a.id.to_string())) {
+      data.remove(&artifact.path.to_string());
+    }
+    Ok(self.artifacts.prune(keep))
+  }
+}
+
+/// An in-memory fake of [`ImageRepositoryLike`].
+#[derive(Clone, Debug, Default)]
+pub struct FakeImageRepository {
+  images: FakeTable<Image>,
+  faults: FaultPlan,
+}
+
+impl FakeImageRepository {
+  /// Create an empty [`FakeImageRepository`].
+  #[must_use]
+  pub fn new() -> Self { Self::default() }
+
+  /// The [`FaultPlan`] of this fake.
+  #[must_use]
+  pub fn faults(&self) -> &FaultPlan { &self.faults }
+}
+
+impl_fake_health_reporter!(FakeImageRepository);
+
+#[async_trait::async_trait]
+impl ImageRepositoryLike for FakeImageRepository {
+  async fn create_image(
+    &self,
+    input: ImageCreateRequest,
+  ) -> Result<Image, CreateModelError> {
+    self
+      .faults
+      .check("create_image")
+      .map_err(CreateModelError::Db)?;
+    self.images.create(input.into())
+  }
+
+  async fn fetch_image_by_id(
+    &self,
+    id: ImageRecordId,
+  ) -> Result<Option<Image>, FetchModelError> {
+    self
+      .faults
+      .check("fetch_image_by_id")
+      .map_err(FetchModelError::Db)?;
+    Ok(self.images.fetch(&id.to_string()))
+  }
+
+  async fn enumerate_images(&self) -> Result<Vec<Image>> {
+    self.faults.check("enumerate_images")?;
+    Ok(self.images.all())
+  }
+
+  async fn delete_image(
+    &self,
+    id: ImageRecordId,
+  ) -> Result<bool, DeleteModelError> {
+    self
+      .faults
+      .check("delete_image")
+      .map_err(DeleteModelError::Db)?;
+    Ok(self.images.delete(&id.to_string()))
+  }
+
+  async fn migrate_images(
+    &self,
+    options: MigrationOptions,
+  ) -> Result<MigrationReport, MigrateModelsError> {
+    Ok(self.images.migrate(options))
+  }
+
+  async fn restore_image(&self, image: Image) -> Result<(), RestoreModelError> {
+    self.images.restore(image);
+    Ok(())
+  }
+
+  async fn prune_images(
+    &self,
+    keep: &BTreeSet<String>,
+  ) -> Result<usize, RestoreModelError> {
+    Ok(self.images.prune(keep))
+  }
+}
+
+/// An in-memory fake of [`PhotoRepositoryLike`].
+#[derive(Clone, Debug, Default)]
+pub struct FakePhotoRepository {
+  photos: FakeTable<Photo>,
+  faults: FaultPlan,
+}
+
+impl FakePhotoRepository {
+  /// Create an empty [`FakePhotoRepository`].
+  #[must_use]
+  pub fn new() -> Self { Self::default() }
+
+  /// The [`FaultPlan`] of this fake.
+  #[must_use]
+  pub fn faults(&self) -> &FaultPlan { &self.faults }
+}
+
+impl_fake_health_reporter!(FakePhotoRepository);
+
+#[async_trait::async_trait]
+impl PhotoRepositoryLike for FakePhotoRepository {
+  async fn create_photo(
+    &self,
+    input: PhotoCreateRequest,
+  ) -> Result<Photo, CreateModelError> {
+    self
+      .faults
+      .check("create_photo")
+      .map_err(CreateModelError::Db)?;
+    self.photos.create(input.into())
+  }
+
+  async fn fetch_photo_by_id(
+    &self,
+    id: PhotoRecordId,
+  ) -> Result<Option<Photo>, FetchModelError> {
+    self
+      .faults
+      .check("fetch_photo_by_id")
+      .map_err(FetchModelError::Db)?;
+    Ok(self.photos.fetch(&id.to_string()))
+  }
+
+  async fn update_photo(
+    &self,
+    mut photo: Photo,
+  ) -> Result<Photo, PatchModelError> {
+    self
+      .faults
+      .check("update_photo")
+      .map_err(PatchModelError::Db)?;
+    photo.touch();
+    self.photos.patch(photo)
+  }
+
+  async fn fetch_photos_by_image(
+    &self,
+    image: ImageRecordId,
+  ) -> Result<Vec<Photo>, FetchModelByIndexError> {
+    self
+      .faults
+      .check("fetch_photos_by_image")
+      .map_err(FetchModelByIndexError::Db)?;
+    Ok(self.photos.find(|p| {
+      p.artifacts.original == image || p.artifacts.thumbnail == image
+    }))
+  }
+
+  async fn enumerate_photos(&self) -> Result<Vec<Photo>> {
+    self.faults.check("enumerate_photos")?;
+    Ok(self.photos.all())
+  }
+
+  async fn delete_photo(
+    &self,
+    id: PhotoRecordId,
+  ) -> Result<bool, DeleteModelError> {
+    self
+      .faults
+      .check("delete_photo")
+      .map_err(DeleteModelError::Db)?;
+    Ok(self.photos.delete(&id.to_string()))
+  }
+
+  async fn migrate_photos(
+    &self,
+    options: MigrationOptions,
+  ) -> Result<MigrationReport, MigrateModelsError> {
+    Ok(self.photos.migrate(options))
+  }
+
+  async fn restore_photo(&self, photo: Photo) -> Result<(), RestoreModelError> {
+    self.photos.restore(photo);
+    Ok(())
+  }
+
+  async fn prune_photos(
+    &self,
+    keep: &BTreeSet<String>,
+  ) -> Result<usize, RestoreModelError> {
+    Ok(self.photos.prune(keep))
+  }
+}
+
+/// An in-memory fake of [`PhotoGroupRepositoryLike`].
+#[derive(Clone, Debug, Default)]
+pub struct FakePhotoGroupRepository {
+  photo_groups: FakeTable<PhotoGroup>,
+  photos:       FakeTable<Photo>,
+  faults:       FaultPlan,
+}
+
+impl FakePhotoGroupRepository {
+  /// Create an empty [`FakePhotoGroupRepository`].
+  #[must_use]
+  pub fn new() -> Self { Self::default() }
+
+  /// Create an empty [`FakePhotoGroupRepository`] that creates the photos of
+  /// new groups in the records of `photos`, like the store shared by the real
+  /// repositories.
+  #[must_use]
+  pub fn with_photos(photos: &FakePhotoRepository) -> Self {
+    Self {
+      photos: photos.photos.clone(),
+      ..Self::default()
+    }
+  }
+
+  /// The [`FaultPlan`] of this fake.
+  #[must_use]
+  pub fn faults(&self) -> &FaultPlan { &self.faults }
+}
+
+impl_fake_health_reporter!(FakePhotoGroupRepository);
+
+#[async_trait::async_trait]
+impl PhotoGroupRepositoryLike for FakePhotoGroupRepository {
+  async fn create_photo_group(
+    &self,
+    input: PhotoGroupCreateRequest,
+  ) -> Result<PhotoGroup, CreateModelError> {
+    self
+      .faults
+      .check("create_photo_group")
+      .map_err(CreateModelError::Db)?;
+    self.photo_groups.create(input.into())
+  }
+
+  async fn create_photo_group_with_photos(
+    &self,
+    input: PhotoGroupCreateRequest,
+    photos: Vec<Photo>,
+  ) -> Result<PhotoGroup, CreateModelError> {
+    self
+      .faults
+      .check("create_photo_group_with_photos")
+      .map_err(CreateModelError::Db)?;
+    let photo_group = PhotoGroup::from(input);
+    // both tables are locked at once, like the store's write transaction
+    let mut photo_groups = self.photo_groups.lock();
+    let mut stored_photos = self.photos.lock();
+    if photo_groups.contains_key(&photo_group.id.to_string()) {
+      return Err(CreateModelError::Db(miette!(
+        "{} model {} already exists",
+        PhotoGroup::TABLE_NAME,
+        photo_group.id
+      )));
+    }
+    if let Some(photo) = photos
+      .iter()
+      .find(|p| stored_photos.contains_key(&p.id.to_string()))
+    {
+      return Err(CreateModelError::Db(miette!(
+        "{} model {} already exists",
+        Photo::TABLE_NAME,
+        photo.id
+      )));
+    }
+    stored_photos.extend(photos.into_iter().map(|p| (p.id.to_string(), p)));
+    photo_groups.insert(photo_group.id.to_string(), photo_group.clone());
+    Ok(photo_group)
+  }
+
+  async fn fetch_photo_group_by_id(
+    &self,
+    id: PhotoGroupRecordId,
+  ) -> Result<Option<PhotoGroup>, FetchModelError> {
+    self
+      .faults
+      .check("fetch_photo_group_by_id")
+      .map_err(FetchModelError::Db)?;
+    Ok(self.photo_groups.fetch(&id.to_string()))
+  }
+
+  async fn update_photo_group(
+    &self,
+    mut photo_group: PhotoGroup,
+  ) -> Result<PhotoGroup, PatchModelError> {
+    self
+      .faults
+      .check("update_photo_group")
+      .map_err(PatchModelError::Db)?;
+    photo_group.touch();
+    self.photo_groups.patch(photo_group)
+  }
+
+  async fn replace_photo_group(
+    &self,
+    expected: &PhotoGroup,
+    mut photo_group: PhotoGroup,
+  ) -> Result<Option<PhotoGroup>, PatchModelError> {
+    self
+      .faults
+      .check("replace_photo_group")
+      .map_err(PatchModelError::Db)?;
+    let mut photo_groups = self.photo_groups.lock();
+    let Some(stored) = photo_groups
+      .get_mut(&photo_group.id.to_string())
+      .filter(|stored| *stored == expected)
+    else {
+      return Ok(None);
+    };
+    photo_group.touch();
+    *stored = photo_group.clone();
+    Ok(Some(photo_group))
+  }
+
+  async fn fetch_photo_groups_by_user(
+    &self,
+    owner: UserRecordId,
+  ) -> Result<Vec<PhotoGroup>, FetchModelByIndexError> {
+    self
+      .faults
+      .check("fetch_photo_groups_by_user")
+      .map_err(FetchModelByIndexError::Db)?;
+    Ok(self.photo_groups.find(|g| g.vendor == owner))
+  }
+
+  async fn enumerate_photo_groups(&self) -> Result<Vec<PhotoGroup>> {
+    self.faults.check("enumerate_photo_groups")?;
+    Ok(self.photo_groups.all())
+  }
+
+  async fn delete_photo_group(
+    &self,
+    id: PhotoGroupRecordId,
+  ) -> Result<bool, DeleteModelError> {
+    self
+      .faults
+      .check("delete_photo_group")
+      .map_err(DeleteModelError::Db)?;
+    Ok(self.photo_groups.delete(&id.to_string()))
+  }
+
+  async fn migrate_photo_groups(
+    &self,
+    options: MigrationOptions,
+  ) -> Result<MigrationReport, MigrateModelsError> {
+    Ok(self.photo_groups.migrate(options))
+  }
+
+  async fn restore_photo_group(
+    &self,
+    photo_group: PhotoGroup,
+  ) -> Result<(), RestoreModelError> {
+    self.photo_groups.restore(photo_group);
+    Ok(())
+  }
+
+  async fn prune_photo_groups(
+    &self,
+    keep: &BTreeSet<String>,
+  ) -> Result<usize, RestoreModelError> {
+    Ok(self.photo_groups.prune(keep))
+  }
+}
+
+/// An in-memory fake of [`UserRepositoryLike`].
+#[derive(Clone, Debug, Default)]
+pub struct FakeUserRepository {
+  users:  FakeTable<User>,
+  faults: FaultPlan,
+}
+
+impl FakeUserRepository {
+  /// Create an empty [`FakeUserRepository`].
+  #[must_use]
+  pub fn new() -> Self { Self::default() }
+
+  /// The [`FaultPlan`] of this fake.
+  #[must_use]
+  pub fn faults(&self) -> &FaultPlan { &self.faults }
+}
+
+impl_fake_health_reporter!(FakeUserRepository);
+
+#[async_trait::async_trait]
+impl UserRepositoryLike for FakeUserRepository {
+  async fn create_user(
+    &self,
+    input: UserCreateRequest,
+  ) -> Result<User, CreateModelError> {
+    self
+      .faults
+      .check("create_user")
+      .map_err(CreateModelError::Db)?;
+    if !self.users.find(|u| u.email == input.email).is_empty() {
+      return Err(CreateModelError::Db(miette!(
+        "email index already holds {}",
+        input.email
+      )));
+    }
+    self.users.create(input.into())
+  }
+
+  async fn fetch_user_by_id(
+    &self,
+    id: UserRecordId,
+  ) -> Result<Option<User>, FetchModelError> {
+    self
+      .faults
+      .check("fetch_user_by_id")
+      .map_err(FetchModelError::Db)?;
+    Ok(self.users.fetch(&id.to_string()))
+  }
+
+  async fn fetch_user_by_email(
+    &self,
+    email: EmailAddress,
+  ) -> Result<Option<User>, FetchModelByIndexError> {
+    self
+      .faults
+      .check("fetch_user_by_email")
+      .map_err(FetchModelByIndexError::Db)?;
+    Ok(self.users.find(|u| u.email == email).into_iter().next())
+  }
+
+  async fn update_user(&self, mut user: User) -> Result<User, PatchModelError> {
+    self
+      .faults
+      .check("update_user")
+      .map_err(PatchModelError::Db)?;
+    if !self
+      .users
+      .find(|u| u.email == user.email && u.id != user.id)
+      .is_empty()
+    {
+      return Err(PatchModelError::Db(miette!(
+        "email index already holds {}",
+        user.email
+      )));
+    }
+    user.touch();
+    self.users.patch(user)
+  }
+
+  async fn update_user_with(
+    &self,
+    id: UserRecordId,
+    update: UserUpdate,
+  ) -> Result<Option<User>, PatchModelError> {
+    self
+      .faults
+      .check("update_user_with")
+      .map_err(PatchModelError::Db)?;
+    let mut users = self.users.lock();
+    let Some(mut user) = users.get(&id.to_string()).cloned() else {
+      return Ok(None);
+    };
+    if !update(&mut user) {
+      return Ok(None);
+    }
+    if users
+      .values()
+      .any(|u| u.email == user.email && u.id != user.id)
+    {
+      return Err(PatchModelError::Db(miette!(
+        "email index already holds {}",
+        user.email
+      )));
+    }
+    user.touch();
+    users.insert(id.to_string(), user.clone());
+    Ok(Some(user))
+  }
+
+  async fn delete_user(
+    &self,
+    id: UserRecordId,
+  ) -> Result<bool, DeleteModelError> {
+    self
+      .faults
+      .check("delete_user")
+      .map_err(DeleteModelError::Db)?;
+    Ok(self.users.delete(&id.to_string()))
+  }
+
+  async fn migrate_users(
+    &self,
+    options: MigrationOptions,
+  ) -> Result<MigrationReport, MigrateModelsError> {
+    Ok(self.users.migrate(options))
+  }
+
+  async fn enumerate_users(&self) -> Result<Vec<User>> {
+    self.faults.check("enumerate_users")?;
+    Ok(self.users.all())
+  }
+
+  async fn restore_user(&self, user: User) -> Result<(), RestoreModelError> {
+    self.users.restore(user);
+    Ok(())
+  }
+
+  async fn prune_users(
+    &self,
+    keep: &BTreeSet<String>,
+  ) -> Result<usize, RestoreModelError> {
+    Ok(self.users.prune(keep))
+  }
+}
+
+/// An in-memory fake of [`SnapshotRepositoryLike`], reading the records of
+/// the fakes it was built from.
+#[derive(Clone, Debug)]
+pub struct FakeSnapshotRepository {
+  artifacts:    FakeTable<Artifact>,
+  images:       FakeTable<Image>,
+  photos:       FakeTable<Photo>,
+  photo_groups: FakeTable<PhotoGroup>,
+  users:        FakeTable<User>,
+  faults:       FaultPlan,
+}
+
+impl FakeSnapshotRepository {
+  /// Create a [`FakeSnapshotRepository`] over the records of the given
+  /// fakes.
+  #[must_use]
+  pub fn new(
+    artifacts: &FakeArtifactRepository,
+    images: &FakeImageRepository,
+    photos: &FakePhotoRepository,
+    photo_groups: &FakePhotoGroupRepository,
+    users: &FakeUserRepository,
+  ) -> Self {
+    Self {
+      artifacts:    artifacts.artifacts.clone(),
+      images:       images.images.clone(),
+      photos:       photos.photos.clone(),
+      photo_groups: photo_groups.photo_groups.clone(),
+      users:        users.users.clone(),
+      faults:       FaultPlan::default(),
+    }
+  }
+
+  /// The [`FaultPlan`] of this fake.
+  #[must_use]
+  pub fn faults(&self) -> &FaultPlan { &self.faults }
+}
+
+impl_fake_health_reporter!(FakeSnapshotRepository);
+
+#[async_trait::async_trait]
+impl SnapshotRepositoryLike for FakeSnapshotRepository {
+  async fn snapshot(&self) -> Result<StoreSnapshot, SnapshotError> {
+    self.faults.check("snapshot").map_err(SnapshotError)?;
+    // every table is locked at once, like the store's read transaction
+    let users = self.users.lock();
+    let photo_groups = self.photo_groups.lock();
+    let photos = self.photos.lock();
+    let images = self.images.lock();
+    let artifacts = self.artifacts.lock();
+    Ok(StoreSnapshot {
+      users:        users.values().cloned().collect(),
+      photo_groups: photo_groups.values().cloned().collect(),
+      photos:       photos.values().cloned().collect(),
+      images:       images.values().cloned().collect(),
+      artifacts:    artifacts.values().cloned().collect(),
+    })
+  }
+}
+
+/// An in-memory fake of [`LoginAttemptRepositoryLike`].
+#[derive(Clone, Debug, Default)]
+pub struct FakeLoginAttemptRepository {
+  attempts: Arc<Mutex<HashMap<String, LoginAttempts>>>,
+  faults:   FaultPlan,
+}
+
+impl FakeLoginAttemptRepository {
+  /// Create an empty [`FakeLoginAttemptRepository`].
+  #[must_use]
+  pub fn new() -> Self { Self::default() }
+
+  /// The [`FaultPlan`] of this fake.
+  #[must_use]
+  pub fn faults(&self) -> &FaultPlan { &self.faults }
+
+  fn lock(&self) -> MutexGuard<'_, HashMap<String, LoginAttempts>> {
+    self.attempts.lock().unwrap_or_else(PoisonError::into_inner)
+  }
+}
+
+impl_fake_health_reporter!(FakeLoginAttemptRepository);
+
+#[async_trait::async_trait]
+impl LoginAttemptRepositoryLike for FakeLoginAttemptRepository {
+  async fn fetch_login_attempts(
+    &self,
+    subject: &LoginAttemptSubject,
+  ) -> Result<LoginAttempts, LoginAttemptError> {
+    self
+      .faults
+      .check("fetch_login_attempts")
+      .map_err(LoginAttemptError)?;
+    Ok(
+      self
+        .lock()
+        .get(&subject.to_string())
+        .cloned()
+        .unwrap_or_default(),
+    )
+  }
+
+  async fn update_login_attempts(
+    &self,
+    subject: &LoginAttemptSubject,
+    update: LoginAttemptUpdate,
+  ) -> Result<UpdatedLoginAttempts, LoginAttemptError> {
+    self
+      .faults
+      .check("update_login_attempts")
+      .map_err(LoginAttemptError)?;
+    let mut attempts = self.lock();
+    let key = subject.to_string();
+    let stored = attempts.get(&key).cloned().unwrap_or_default();
+    let Some(updated) = update(&stored) else {
+      return Ok(UpdatedLoginAttempts::Declined(stored));
+    };
+    if updated == LoginAttempts::default() {
+      attempts.remove(&key);
+    } else {
+      attempts.insert(key, updated.clone());
+    }
+    Ok(UpdatedLoginAttempts::Applied(updated))
+  }
+}
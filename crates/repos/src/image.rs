@@ -1,10 +1,61 @@
-use db::{CreateModelError, Database, FetchModelError};
+use std::{collections::BTreeSet, fmt};
+
+use db::{CreateModelError, Database, DeleteModelError, FetchModelError};
 use hex::health::{self, HealthAware};
 use miette::Result;
 use models::Image;
 use tracing::instrument;
 
-/// Stores and retrieves [`Image`]s.
+use crate::{
+  MigrateModelsError, MigrationOptions, MigrationReport, RestoreModelError,
+};
+
+/// The interface for storing and retrieving [`Image`]s.
+#[async_trait::async_trait]
+pub trait ImageRepositoryLike:
+  health::HealthReporter + fmt::Debug + Send + Sync + 'static
+{
+  /// Create a [`Image`] model.
+  async fn create_image(
+    &self,
+    input: models::ImageCreateRequest,
+  ) -> Result<Image, CreateModelError>;
+
+  /// Fetch a [`Image`] by id.
+  async fn fetch_image_by_id(
+    &self,
+    id: models::ImageRecordId,
+  ) -> Result<Option<Image>, FetchModelError>;
+
+  /// Produce a list of all [`Image`]s.
+  async fn enumerate_images(&self) -> Result<Vec<Image>>;
+
+  /// Delete an [`Image`] model. Returns whether it existed.
+  async fn delete_image(
+    &self,
+    id: models::ImageRecordId,
+  ) -> Result<bool, DeleteModelError>;
+
+  /// Upcast stored [`Image`]s written at older schema versions and re-write
+  /// them.
+  async fn migrate_images(
+    &self,
+    options: MigrationOptions,
+  ) -> Result<MigrationReport, MigrateModelsError>;
+
+  /// Write a [`Image`] restored from a backup, replacing any stored record
+  /// with the same id.
+  async fn restore_image(&self, image: Image) -> Result<(), RestoreModelError>;
+
+  /// Delete every stored [`Image`] whose id isn't in `keep`. Returns the number
+  /// deleted.
+  async fn prune_images(
+    &self,
+    keep: &BTreeSet<String>,
+  ) -> Result<usize, RestoreModelError>;
+}
+
+/// Stores and retrieves [`Image`]s in a [`Database`].
 #[derive(Clone, Debug)]
 pub struct ImageRepository {
   db: Database<Image>,
@@ -25,28 +76,57 @@ impl ImageRepository {
   /// Create a new [`ImageRepository`].
   #[must_use]
   pub fn new(model_repo: Database<Image>) -> Self { Self { db: model_repo } }
+}
 
-  /// Create a [`Image`] model.
+#[async_trait::async_trait]
+impl ImageRepositoryLike for ImageRepository {
   #[instrument(skip(self))]
-  pub async fn create_image(
+  async fn create_image(
     &self,
     input: models::ImageCreateRequest,
   ) -> Result<Image, CreateModelError> {
     self.db.create_model(input.into()).await
   }
 
-  /// Fetch a [`Image`] by id.
   #[instrument(skip(self))]
-  pub async fn fetch_image_by_id(
+  async fn fetch_image_by_id(
     &self,
     id: models::ImageRecordId,
   ) -> Result<Option<Image>, FetchModelError> {
     self.db.fetch_model_by_id(id).await
   }
 
-  /// Produce a list of all [`Image`]s.
   #[instrument(skip(self))]
-  pub async fn enumerate_images(&self) -> Result<Vec<Image>> {
+  async fn enumerate_images(&self) -> Result<Vec<Image>> {
     self.db.enumerate_models().await
   }
+
+  #[instrument(skip(self))]
+  async fn delete_image(
+    &self,
+    id: models::ImageRecordId,
+  ) -> Result<bool, DeleteModelError> {
+    self.db.delete_model(id).await
+  }
+
+  #[instrument(skip(self))]
+  async fn migrate_images(
+    &self,
+    options: MigrationOptions,
+  ) -> Result<MigrationReport, MigrateModelsError> {
+    crate::migrate::migrate_models(&self.db, options).await
+  }
+
+  #[instrument(skip(self, image), fields(id = %image.id))]
+  async fn restore_image(&self, image: Image) -> Result<(), RestoreModelError> {
+    crate::restore::restore_model(&self.db, image).await
+  }
+
+  #[instrument(skip(self, keep))]
+  async fn prune_images(
+    &self,
+    keep: &BTreeSet<String>,
+  ) -> Result<usize, RestoreModelError> {
+    crate::restore::prune_models(&self.db, keep).await
+  }
 }
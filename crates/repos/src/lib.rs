@@ -1,13 +1,39 @@
 //! Repositories for use in services.
 
 mod artifact;
+mod cache;
+mod caching;
+#[cfg(feature = "fake")]
+pub mod fake;
 mod image;
+mod login_attempt;
+mod migrate;
+mod model_txn;
 mod photo;
 mod photo_group;
+mod restore;
+mod snapshot;
+mod unit_of_work;
 mod user;
 mod utils;
 
-pub use db::{self, CreateModelError, FetchModelByIndexError, FetchModelError};
+pub use db::{
+  self, CreateModelError, DeleteModelError, FetchModelByIndexError,
+  FetchModelError, PatchModelError,
+};
 pub use storage::{self, belt};
 
-pub use self::{artifact::*, image::*, photo::*, photo_group::*, user::*};
+pub use self::{
+  artifact::*,
+  cache::{Cache, CacheConfig, CacheFill, CacheMetrics, CacheStats},
+  caching::*,
+  image::*,
+  login_attempt::*,
+  migrate::{MigrateModelsError, MigrationOptions, MigrationReport},
+  photo::*,
+  photo_group::*,
+  restore::RestoreModelError,
+  snapshot::*,
+  unit_of_work::{RollbackError, UndoError, UnitOfWork},
+  user::*,
+};
@@ -0,0 +1,220 @@
+use core::fmt;
+use std::{net::IpAddr, sync::LazyLock};
+
+use db::kv::{
+  Key, KeyValueStore, KvPrimitive, KvTransaction, StrictSlug, Value,
+};
+use hex::health::{self, HealthAware};
+use miette::miette;
+use models::{EmailAddress, LoginAttempts};
+use tracing::instrument;
+
+/// What a [`LoginAttempts`] count is kept for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LoginAttemptSubject {
+  /// Attempts made from one client address, against any account.
+  Client(IpAddr),
+  /// Attempts made against one email address, from any client.
+  Account(EmailAddress),
+  /// Signup links requested by one client address, for any email address.
+  SignUpLinkClient(IpAddr),
+  /// Signup links requested for one email address, from any client.
+  SignUpLink(EmailAddress),
+}
+
+impl fmt::Display for LoginAttemptSubject {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Client(ip) => write!(f, "client:{ip}"),
+      Self::Account(email) => {
+        write!(f, "account:{}", email.as_ref().to_lowercase())
+      }
+      Self::SignUpLinkClient(ip) => write!(f, "sign_up_link_client:{ip}"),
+      Self::SignUpLink(email) => {
+        write!(f, "sign_up_link:{}", email.as_ref().to_lowercase())
+      }
+    }
+  }
+}
+
+/// An error that occurs when reading or writing [`LoginAttempts`].
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+#[error("Failed to access login attempts: {0}")]
+pub struct LoginAttemptError(pub miette::Report);
+
+/// A change to a [`LoginAttempts`] count, applied atomically. Returning `None`
+/// declines the change, leaving the count as it was.
+pub type LoginAttemptUpdate =
+  Box<dyn FnOnce(&LoginAttempts) -> Option<LoginAttempts> + Send>;
+
+/// The count left by a [`LoginAttemptUpdate`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UpdatedLoginAttempts {
+  /// The update was applied, leaving this count.
+  Applied(LoginAttempts),
+  /// The update declined, leaving this count as it was.
+  Declined(LoginAttempts),
+}
+
+/// The interface for counting login attempts.
+#[async_trait::async_trait]
+pub trait LoginAttemptRepositoryLike:
+  health::HealthReporter + fmt::Debug + Send + Sync + 'static
+{
+  /// Fetch the [`LoginAttempts`] for a subject. Subjects without any are
+  /// given the default, empty count.
+  async fn fetch_login_attempts(
+    &self,
+    subject: &LoginAttemptSubject,
+  ) -> Result<LoginAttempts, LoginAttemptError>;
+
+  /// Apply `update` to the [`LoginAttempts`] for a subject, so that
+  /// concurrent updates don't overwrite each other or both act on the same
+  /// count. Returns the count left behind. An empty count is deleted rather
+  /// than stored.
+  async fn update_login_attempts(
+    &self,
+    subject: &LoginAttemptSubject,
+    update: LoginAttemptUpdate,
+  ) -> Result<UpdatedLoginAttempts, LoginAttemptError>;
+}
+
+/// Stores [`LoginAttempts`] directly in a [`KeyValueStore`].
+#[derive(Clone, Debug)]
+pub struct LoginAttemptRepository {
+  kv: KeyValueStore,
+}
+
+impl LoginAttemptRepository {
+  /// Create a new [`LoginAttemptRepository`].
+  #[must_use]
+  pub fn new(kv: KeyValueStore) -> Self { Self { kv } }
+}
+
+#[async_trait::async_trait]
+impl health::HealthReporter for LoginAttemptRepository {
+  fn name(&self) -> &'static str { stringify!(LoginAttemptRepository) }
+
+  async fn health_check(&self) -> health::ComponentHealth {
+    health::AdditiveComponentHealth::from_futures(vec![self.kv.health_report()])
+      .await
+      .into()
+  }
+}
+
+static LOGIN_ATTEMPTS_NS_SEGMENT: LazyLock<StrictSlug> =
+  LazyLock::new(|| StrictSlug::new("login_attempts".to_string()));
+
+/// The key of a subject's count. Addresses are hex-encoded, since slugs
+/// can't hold them as they are.
+fn subject_to_key(subject: &LoginAttemptSubject) -> Key {
+  let encoded = subject
+    .to_string()
+    .bytes()
+    .map(|b| format!("{b:02x}"))
+    .collect::<String>();
+  Key::new_lazy(&LOGIN_ATTEMPTS_NS_SEGMENT).with(StrictSlug::new(encoded))
+}
+
+fn decode_attempts(
+  value: Option<Value>,
+) -> Result<LoginAttempts, LoginAttemptError> {
+  value
+    .map(|v| v.deserialize())
+    .transpose()
+    .map(Option::unwrap_or_default)
+    .map_err(|e| {
+      LoginAttemptError(miette!("failed to deserialize login attempts: {e}"))
+    })
+}
+
+#[async_trait::async_trait]
+impl LoginAttemptRepositoryLike for LoginAttemptRepository {
+  #[instrument(skip(self))]
+  async fn fetch_login_attempts(
+    &self,
+    subject: &LoginAttemptSubject,
+  ) -> Result<LoginAttempts, LoginAttemptError> {
+    let mut txn =
+      self.kv.begin_optimistic_transaction().await.map_err(|e| {
+        LoginAttemptError(miette!(
+          "failed to start optimistic transaction: {e}"
+        ))
+      })?;
+
+    let value = match txn.get(&subject_to_key(subject)).await {
+      Ok(value) => value,
+      Err(e) => {
+        txn.rollback().await.map_err(|e| {
+          LoginAttemptError(miette!("failed to rollback transaction: {e}"))
+        })?;
+        return Err(LoginAttemptError(miette!(
+          "failed to get login attempts: {e}"
+        )));
+      }
+    };
+    txn.commit().await.map_err(|e| {
+      LoginAttemptError(miette!("failed to commit transaction: {e}"))
+    })?;
+
+    decode_attempts(value)
+  }
+
+  #[instrument(skip(self, update))]
+  async fn update_login_attempts(
+    &self,
+    subject: &LoginAttemptSubject,
+    update: LoginAttemptUpdate,
+  ) -> Result<UpdatedLoginAttempts, LoginAttemptError> {
+    let key = subject_to_key(subject);
+    let mut txn =
+      self.kv.begin_pessimistic_transaction().await.map_err(|e| {
+        LoginAttemptError(miette!(
+          "failed to start pessimistic transaction: {e}"
+        ))
+      })?;
+
+    let result = async {
+      let stored = decode_attempts(txn.get(&key).await.map_err(|e| {
+        LoginAttemptError(miette!("failed to get login attempts: {e}"))
+      })?)?;
+      let Some(attempts) = update(&stored) else {
+        return Ok(UpdatedLoginAttempts::Declined(stored));
+      };
+      if attempts == LoginAttempts::default() {
+        txn.delete(&key).await.map_err(|e| {
+          LoginAttemptError(miette!("failed to delete login attempts: {e}"))
+        })?;
+      } else {
+        let value = Value::serialize(&attempts).map_err(|e| {
+          LoginAttemptError(miette!("failed to serialize login attempts: {e}"))
+        })?;
+        txn.put(&key, value).await.map_err(|e| {
+          LoginAttemptError(miette!("failed to put login attempts: {e}"))
+        })?;
+      }
+      Ok(UpdatedLoginAttempts::Applied(attempts))
+    }
+    .await;
+    let attempts = match result {
+      Ok(attempts) => attempts,
+      Err(e) => {
+        txn.rollback().await.map_err(|e| {
+          LoginAttemptError(miette!("failed to rollback transaction: {e}"))
+        })?;
+        return Err(e);
+      }
+    };
+
+    if let Err(e) = txn.commit().await {
+      txn.rollback().await.map_err(|e| {
+        LoginAttemptError(miette!("failed to rollback transaction: {e}"))
+      })?;
+      return Err(LoginAttemptError(miette!(
+        "failed to commit transaction: {e}"
+      )));
+    }
+
+    Ok(attempts)
+  }
+}
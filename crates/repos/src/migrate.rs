@@ -0,0 +1,88 @@
+use std::collections::BTreeMap;
+
+use db::{Database, PatchModelError};
+use models::Versioned;
+
+/// Options for migrating stored models.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MigrationOptions {
+  /// Report what would be migrated without writing anything.
+  pub dry_run:         bool,
+  /// Re-write every record, not just outdated ones, so that all of their
+  /// index entries are rebuilt.
+  pub rebuild_indices: bool,
+}
+
+/// The outcome of migrating the stored records of one model.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MigrationReport {
+  /// The model's table name.
+  pub table:    &'static str,
+  /// The number of records examined.
+  pub examined: usize,
+  /// The number of outdated records, by the schema version they were
+  /// written at.
+  pub outdated: BTreeMap<u32, usize>,
+  /// The number of records written. Always zero for a dry run.
+  pub written:  usize,
+}
+
+impl MigrationReport {
+  /// The total number of outdated records.
+  #[must_use]
+  pub fn outdated_count(&self) -> usize { self.outdated.values().sum() }
+}
+
+/// An error that occurs when migrating stored models.
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum MigrateModelsError {
+  /// An error that occurs when enumerating models.
+  #[error("Failed to enumerate {0} models: {1}")]
+  EnumerateError(&'static str, miette::Report),
+  /// An error that occurs when updating a model.
+  #[error("Failed to update {0} model {1}: {2}")]
+  PatchModelError(&'static str, String, PatchModelError),
+}
+
+/// Upcasts stored models written at older schema versions and re-writes
+/// them, which also rewrites their index entries. Records are re-written
+/// without being marked as updated.
+///
+/// This replaces the one-off startup jobs that re-wrote every record: the
+/// timestamp backfill is the version 1 upcast of each model, and the photo
+/// group price migration is the photo group version 2 upcast. Records
+/// already at the current version are left alone.
+pub(crate) async fn migrate_models<M: Versioned>(
+  db: &Database<M>,
+  options: MigrationOptions,
+) -> Result<MigrationReport, MigrateModelsError> {
+  let models = db
+    .enumerate_models()
+    .await
+    .map_err(|e| MigrateModelsError::EnumerateError(M::TABLE_NAME, e))?;
+
+  let mut report = MigrationReport {
+    table: M::TABLE_NAME,
+    examined: models.len(),
+    ..Default::default()
+  };
+  for mut model in models {
+    if model.is_outdated() {
+      *report.outdated.entry(model.schema_version()).or_default() += 1;
+    } else if !options.rebuild_indices {
+      continue;
+    }
+    if options.dry_run {
+      continue;
+    }
+
+    model.upcast();
+    let id = model.id();
+    db.patch_model(id, model).await.map_err(|e| {
+      MigrateModelsError::PatchModelError(M::TABLE_NAME, id.to_string(), e)
+    })?;
+    report.written += 1;
+  }
+
+  Ok(report)
+}
@@ -0,0 +1,148 @@
+use core::fmt;
+use std::ops::Bound;
+
+use db::kv::{Key, KvPrimitive, StrictSlug, Value};
+use miette::{miette, Result};
+use models::{EitherSlug, Model, Ulid};
+
+/// The key `db` stores a model under, `<table>:<id>`.
+pub(crate) fn model_key<M: Model>(id: &impl fmt::Display) -> Key {
+  Key::new(StrictSlug::new(M::TABLE_NAME.to_owned()))
+    .with(StrictSlug::new(id.to_string()))
+}
+
+/// The range of keys `db` stores the models of `M` under. Index entries live
+/// under their own `<table>_index_<index>` segments, so they fall outside of
+/// it.
+pub(crate) fn model_key_range<M: Model>() -> (Bound<Key>, Bound<Key>) {
+  (
+    Bound::Included(model_key::<M>(&Ulid::nil())),
+    Bound::Included(model_key::<M>(&Ulid::from(u128::MAX))),
+  )
+}
+
+/// The key of a unique index entry, `<table>_index_<index>:<value>`, which
+/// holds the id of the one model with that value.
+fn unique_index_key<M: Model>(index: &str, value: EitherSlug) -> Key {
+  Key::new(StrictSlug::new(format!("{}_index_{index}", M::TABLE_NAME)))
+    .with_either(value)
+}
+
+/// The key of an index entry, `<table>_index_<index>:<value>:<id>`, one of
+/// which exists for every model with that value.
+fn index_key<M: Model>(
+  index: &str,
+  value: EitherSlug,
+  id: &impl fmt::Display,
+) -> Key {
+  unique_index_key::<M>(index, value).with(StrictSlug::new(id.to_string()))
+}
+
+async fn put_value<M: Model>(
+  txn: &mut impl KvPrimitive,
+  key: &Key,
+  value: &impl serde::Serialize,
+) -> Result<()> {
+  let value = Value::serialize(value)
+    .map_err(|e| miette!("failed to serialize {} entry: {e}", M::TABLE_NAME))?;
+  txn
+    .put(key, value)
+    .await
+    .map_err(|e| miette!("failed to put {} entry: {e}", M::TABLE_NAME))
+}
+
+async fn delete_value<M: Model>(
+  txn: &mut impl KvPrimitive,
+  key: &Key,
+) -> Result<()> {
+  txn
+    .delete(key)
+    .await
+    .map(|_| ())
+    .map_err(|e| miette!("failed to delete {} entry: {e}", M::TABLE_NAME))
+}
+
+/// Point the unique index entry for `value` at `id`, failing if another model
+/// already holds it.
+async fn claim_unique_index<M: Model>(
+  txn: &mut impl KvPrimitive,
+  index: &str,
+  value: EitherSlug,
+  id: &models::RecordId<M>,
+) -> Result<()> {
+  let key = unique_index_key::<M>(index, value);
+  let taken = txn
+    .get(&key)
+    .await
+    .map_err(|e| miette!("failed to get {} index entry: {e}", M::TABLE_NAME))?;
+  if taken.is_some() {
+    return Err(miette!(
+      "{} index `{index}` already holds the value of {id}",
+      M::TABLE_NAME
+    ));
+  }
+  put_value::<M>(txn, &key, id).await
+}
+
+/// Read a model in a transaction, returning `None` if it isn't stored.
+pub(crate) async fn get_model<M: Model>(
+  txn: &mut impl KvPrimitive,
+  id: &models::RecordId<M>,
+) -> Result<Option<M>> {
+  txn
+    .get(&model_key::<M>(id))
+    .await
+    .map_err(|e| miette!("failed to get {} model: {e}", M::TABLE_NAME))?
+    .map(|value| value.deserialize())
+    .transpose()
+    .map_err(|e| miette!("failed to deserialize {} model: {e}", M::TABLE_NAME))
+}
+
+/// Write a new model and its index entries in a transaction. Fails if a model
+/// with the same id is already stored, or if one of its unique index values is
+/// taken.
+pub(crate) async fn insert_model<M: Model>(
+  txn: &mut impl KvPrimitive,
+  model: &M,
+) -> Result<()> {
+  let id = model.id();
+  if get_model::<M>(txn, &id).await?.is_some() {
+    return Err(miette!("{} model {id} already exists", M::TABLE_NAME));
+  }
+
+  put_value::<M>(txn, &model_key::<M>(&id), model).await?;
+  for (index, getter) in M::UNIQUE_INDICES {
+    claim_unique_index(txn, index, getter(model), &id).await?;
+  }
+  for (index, getter) in M::INDICES {
+    put_value::<M>(txn, &index_key::<M>(index, getter(model), &id), &id)
+      .await?;
+  }
+  Ok(())
+}
+
+/// Overwrite the stored `old` with `new` in a transaction, moving whichever of
+/// its index entries changed. Fails if a changed unique index value is taken.
+pub(crate) async fn replace_model<M: Model>(
+  txn: &mut impl KvPrimitive,
+  old: &M,
+  new: &M,
+) -> Result<()> {
+  let id = new.id();
+  put_value::<M>(txn, &model_key::<M>(&id), new).await?;
+  for (index, getter) in M::UNIQUE_INDICES {
+    let (old_value, new_value) = (getter(old), getter(new));
+    if old_value != new_value {
+      delete_value::<M>(txn, &unique_index_key::<M>(index, old_value)).await?;
+      claim_unique_index(txn, index, new_value, &id).await?;
+    }
+  }
+  for (index, getter) in M::INDICES {
+    let (old_value, new_value) = (getter(old), getter(new));
+    if old_value != new_value {
+      delete_value::<M>(txn, &index_key::<M>(index, old_value, &id)).await?;
+      put_value::<M>(txn, &index_key::<M>(index, new_value, &id), &id).await?;
+    }
+  }
+  Ok(())
+}
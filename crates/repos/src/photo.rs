@@ -1,10 +1,74 @@
-use db::{CreateModelError, Database, FetchModelError};
+use std::{collections::BTreeSet, fmt};
+
+use db::{
+  CreateModelError, Database, DeleteModelError, FetchModelByIndexError,
+  FetchModelError, PatchModelError,
+};
 use hex::health::{self, HealthAware};
 use miette::Result;
-use models::Photo;
+use models::{EitherSlug, ImageRecordId, Photo, StrictSlug, Timestamped};
 use tracing::instrument;
 
-/// Stores and retrieves [`Photo`]s.
+use crate::{
+  MigrateModelsError, MigrationOptions, MigrationReport, RestoreModelError,
+};
+
+/// The interface for storing and retrieving [`Photo`]s.
+#[async_trait::async_trait]
+pub trait PhotoRepositoryLike:
+  health::HealthReporter + fmt::Debug + Send + Sync + 'static
+{
+  /// Create a [`Photo`] model.
+  async fn create_photo(
+    &self,
+    input: models::PhotoCreateRequest,
+  ) -> Result<Photo, CreateModelError>;
+
+  /// Fetch a [`Photo`] by id.
+  async fn fetch_photo_by_id(
+    &self,
+    id: models::PhotoRecordId,
+  ) -> Result<Option<Photo>, FetchModelError>;
+
+  /// Update a [`Photo`] model, marking it as updated now.
+  async fn update_photo(&self, photo: Photo) -> Result<Photo, PatchModelError>;
+
+  /// Find the [`Photo`]s whose original or thumbnail is an
+  /// [`Image`](models::Image).
+  async fn fetch_photos_by_image(
+    &self,
+    image: ImageRecordId,
+  ) -> Result<Vec<Photo>, FetchModelByIndexError>;
+
+  /// Produce a list of all [`Photo`]s.
+  async fn enumerate_photos(&self) -> Result<Vec<Photo>>;
+
+  /// Delete a [`Photo`] model. Returns whether it existed.
+  async fn delete_photo(
+    &self,
+    id: models::PhotoRecordId,
+  ) -> Result<bool, DeleteModelError>;
+
+  /// Upcast stored [`Photo`]s written at older schema versions and re-write
+  /// them.
+  async fn migrate_photos(
+    &self,
+    options: MigrationOptions,
+  ) -> Result<MigrationReport, MigrateModelsError>;
+
+  /// Write a [`Photo`] restored from a backup, replacing any stored record
+  /// with the same id.
+  async fn restore_photo(&self, photo: Photo) -> Result<(), RestoreModelError>;
+
+  /// Delete every stored [`Photo`] whose id isn't in `keep`. Returns the number
+  /// deleted.
+  async fn prune_photos(
+    &self,
+    keep: &BTreeSet<String>,
+  ) -> Result<usize, RestoreModelError>;
+}
+
+/// Stores and retrieves [`Photo`]s in a [`Database`].
 #[derive(Clone, Debug)]
 pub struct PhotoRepository {
   db: Database<Photo>,
@@ -25,28 +89,88 @@ impl PhotoRepository {
   /// Create a new [`PhotoRepository`].
   #[must_use]
   pub fn new(model_repo: Database<Photo>) -> Self { Self { db: model_repo } }
+}
 
-  /// Create a [`Photo`] model.
+#[async_trait::async_trait]
+impl PhotoRepositoryLike for PhotoRepository {
   #[instrument(skip(self))]
-  pub async fn create_photo(
+  async fn create_photo(
     &self,
     input: models::PhotoCreateRequest,
   ) -> Result<Photo, CreateModelError> {
     self.db.create_model(input.into()).await
   }
 
-  /// Fetch a [`Photo`] by id.
   #[instrument(skip(self))]
-  pub async fn fetch_photo_by_id(
+  async fn fetch_photo_by_id(
     &self,
     id: models::PhotoRecordId,
   ) -> Result<Option<Photo>, FetchModelError> {
     self.db.fetch_model_by_id(id).await
   }
 
-  /// Produce a list of all [`Photo`]s.
   #[instrument(skip(self))]
-  pub async fn enumerate_photos(&self) -> Result<Vec<Photo>> {
+  async fn update_photo(
+    &self,
+    mut photo: Photo,
+  ) -> Result<Photo, PatchModelError> {
+    photo.touch();
+    self.db.patch_model(photo.id, photo).await
+  }
+
+  #[instrument(skip(self))]
+  async fn fetch_photos_by_image(
+    &self,
+    image: ImageRecordId,
+  ) -> Result<Vec<Photo>, FetchModelByIndexError> {
+    let slug = || EitherSlug::Strict(StrictSlug::new(image.to_string()));
+    let mut photos = self
+      .db
+      .fetch_model_by_index("original".to_owned(), slug())
+      .await?;
+    for photo in self
+      .db
+      .fetch_model_by_index("thumbnail".to_owned(), slug())
+      .await?
+    {
+      if !photos.iter().any(|p| p.id == photo.id) {
+        photos.push(photo);
+      }
+    }
+    Ok(photos)
+  }
+
+  #[instrument(skip(self))]
+  async fn enumerate_photos(&self) -> Result<Vec<Photo>> {
     self.db.enumerate_models().await
   }
+
+  #[instrument(skip(self))]
+  async fn delete_photo(
+    &self,
+    id: models::PhotoRecordId,
+  ) -> Result<bool, DeleteModelError> {
+    self.db.delete_model(id).await
+  }
+
+  #[instrument(skip(self))]
+  async fn migrate_photos(
+    &self,
+    options: MigrationOptions,
+  ) -> Result<MigrationReport, MigrateModelsError> {
+    crate::migrate::migrate_models(&self.db, options).await
+  }
+
+  #[instrument(skip(self, photo), fields(id = %photo.id))]
+  async fn restore_photo(&self, photo: Photo) -> Result<(), RestoreModelError> {
+    crate::restore::restore_model(&self.db, photo).await
+  }
+
+  #[instrument(skip(self, keep))]
+  async fn prune_photos(
+    &self,
+    keep: &BTreeSet<String>,
+  ) -> Result<usize, RestoreModelError> {
+    crate::restore::prune_models(&self.db, keep).await
+  }
 }
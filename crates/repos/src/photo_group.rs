@@ -1,13 +1,105 @@
-use db::{CreateModelError, Database, FetchModelByIndexError, FetchModelError};
+use std::{collections::BTreeSet, fmt};
+
+use db::{
+  kv::{KeyValueStore, KvTransaction},
+  CreateModelError, Database, DeleteModelError, FetchModelByIndexError,
+  FetchModelError, PatchModelError,
+};
 use hex::health::{self, HealthAware};
-use miette::Result;
-use models::{EitherSlug, PhotoGroup, StrictSlug, UserRecordId};
+use miette::{miette, Result};
+use models::{
+  EitherSlug, Photo, PhotoGroup, StrictSlug, Timestamped, UserRecordId,
+};
 use tracing::instrument;
 
-/// Stores and retrieves [`PhotoGroup`]s.
+use crate::{
+  model_txn, MigrateModelsError, MigrationOptions, MigrationReport,
+  RestoreModelError,
+};
+
+/// The interface for storing and retrieving [`PhotoGroup`]s.
+#[async_trait::async_trait]
+pub trait PhotoGroupRepositoryLike:
+  health::HealthReporter + fmt::Debug + Send + Sync + 'static
+{
+  /// Create a [`PhotoGroup`] model.
+  async fn create_photo_group(
+    &self,
+    input: models::PhotoGroupCreateRequest,
+  ) -> Result<PhotoGroup, CreateModelError>;
+
+  /// Create a [`PhotoGroup`] model along with the [`Photo`]s it holds, in one
+  /// store transaction. Either all of them are stored or none are.
+  async fn create_photo_group_with_photos(
+    &self,
+    input: models::PhotoGroupCreateRequest,
+    photos: Vec<Photo>,
+  ) -> Result<PhotoGroup, CreateModelError>;
+
+  /// Fetch a [`PhotoGroup`] by id.
+  async fn fetch_photo_group_by_id(
+    &self,
+    id: models::PhotoGroupRecordId,
+  ) -> Result<Option<PhotoGroup>, FetchModelError>;
+
+  /// Update a [`PhotoGroup`] model, marking it as updated now.
+  async fn update_photo_group(
+    &self,
+    photo_group: PhotoGroup,
+  ) -> Result<PhotoGroup, PatchModelError>;
+
+  /// Update a [`PhotoGroup`] model, marking it as updated now, but only if the
+  /// stored record is still `expected`. Returns `None` without writing
+  /// anything if the record changed since `expected` was fetched, or no longer
+  /// exists.
+  async fn replace_photo_group(
+    &self,
+    expected: &PhotoGroup,
+    photo_group: PhotoGroup,
+  ) -> Result<Option<PhotoGroup>, PatchModelError>;
+
+  /// Fetch [`PhotoGroup`]s by user.
+  async fn fetch_photo_groups_by_user(
+    &self,
+    owner: UserRecordId,
+  ) -> Result<Vec<PhotoGroup>, FetchModelByIndexError>;
+
+  /// Produce a list of all [`PhotoGroup`]s.
+  async fn enumerate_photo_groups(&self) -> Result<Vec<PhotoGroup>>;
+
+  /// Delete a [`PhotoGroup`] model. Returns whether it existed.
+  async fn delete_photo_group(
+    &self,
+    id: models::PhotoGroupRecordId,
+  ) -> Result<bool, DeleteModelError>;
+
+  /// Upcast stored [`PhotoGroup`]s written at older schema versions and
+  /// re-write them.
+  async fn migrate_photo_groups(
+    &self,
+    options: MigrationOptions,
+  ) -> Result<MigrationReport, MigrateModelsError>;
+
+  /// Write a [`PhotoGroup`] restored from a backup, replacing any stored record
+  /// with the same id.
+  async fn restore_photo_group(
+    &self,
+    photo_group: PhotoGroup,
+  ) -> Result<(), RestoreModelError>;
+
+  /// Delete every stored [`PhotoGroup`] whose id isn't in `keep`. Returns the
+  /// number deleted.
+  async fn prune_photo_groups(
+    &self,
+    keep: &BTreeSet<String>,
+  ) -> Result<usize, RestoreModelError>;
+}
+
+/// Stores and retrieves [`PhotoGroup`]s in a [`Database`].
 #[derive(Clone, Debug)]
 pub struct PhotoGroupRepository {
   db: Database<PhotoGroup>,
+  kv: KeyValueStore,
 }
 
 #[async_trait::async_trait]
@@ -22,32 +114,132 @@ impl health::HealthReporter for PhotoGroupRepository {
 }
 
 impl PhotoGroupRepository {
-  /// Create a new [`PhotoGroupRepository`].
+  /// Create a new [`PhotoGroupRepository`]. The store is shared with the
+  /// [`PhotoRepository`](crate::PhotoRepository), so that groups can be
+  /// created along with their photos.
   #[must_use]
-  pub fn new(model_repo: Database<PhotoGroup>) -> Self {
-    Self { db: model_repo }
+  pub fn new(kv: KeyValueStore) -> Self {
+    Self {
+      db: Database::new_from_kv(kv.clone()),
+      kv,
+    }
   }
+}
 
-  /// Create a [`PhotoGroup`] model.
+#[async_trait::async_trait]
+impl PhotoGroupRepositoryLike for PhotoGroupRepository {
   #[instrument(skip(self))]
-  pub async fn create_photo_group(
+  async fn create_photo_group(
     &self,
     input: models::PhotoGroupCreateRequest,
   ) -> Result<PhotoGroup, CreateModelError> {
     self.db.create_model(input.into()).await
   }
 
-  /// Fetch a [`PhotoGroup`] by id.
+  #[instrument(skip(self, photos))]
+  async fn create_photo_group_with_photos(
+    &self,
+    input: models::PhotoGroupCreateRequest,
+    photos: Vec<Photo>,
+  ) -> Result<PhotoGroup, CreateModelError> {
+    let photo_group = PhotoGroup::from(input);
+    let mut txn =
+      self.kv.begin_pessimistic_transaction().await.map_err(|e| {
+        CreateModelError::Db(miette!(
+          "failed to start pessimistic transaction: {e}"
+        ))
+      })?;
+
+    let result = async {
+      for photo in &photos {
+        model_txn::insert_model(&mut txn, photo).await?;
+      }
+      model_txn::insert_model(&mut txn, &photo_group).await
+    }
+    .await;
+    if let Err(e) = result {
+      txn.rollback().await.map_err(|e| {
+        CreateModelError::Db(miette!("failed to rollback transaction: {e}"))
+      })?;
+      return Err(CreateModelError::Db(e));
+    }
+
+    if let Err(e) = txn.commit().await {
+      txn.rollback().await.map_err(|e| {
+        CreateModelError::Db(miette!("failed to rollback transaction: {e}"))
+      })?;
+      return Err(CreateModelError::Db(miette!(
+        "failed to commit transaction: {e}"
+      )));
+    }
+
+    Ok(photo_group)
+  }
+
   #[instrument(skip(self))]
-  pub async fn fetch_photo_group_by_id(
+  async fn fetch_photo_group_by_id(
     &self,
     id: models::PhotoGroupRecordId,
   ) -> Result<Option<PhotoGroup>, FetchModelError> {
     self.db.fetch_model_by_id(id).await
   }
 
-  /// Fetch [`PhotoGroup`]s by user.
-  pub async fn fetch_photo_groups_by_user(
+  #[instrument(skip(self))]
+  async fn update_photo_group(
+    &self,
+    mut photo_group: PhotoGroup,
+  ) -> Result<PhotoGroup, PatchModelError> {
+    photo_group.touch();
+    self.db.patch_model(photo_group.id, photo_group).await
+  }
+
+  #[instrument(skip(self, expected, photo_group), fields(id = %photo_group.id))]
+  async fn replace_photo_group(
+    &self,
+    expected: &PhotoGroup,
+    mut photo_group: PhotoGroup,
+  ) -> Result<Option<PhotoGroup>, PatchModelError> {
+    let mut txn =
+      self.kv.begin_pessimistic_transaction().await.map_err(|e| {
+        PatchModelError::Db(miette!(
+          "failed to start pessimistic transaction: {e}"
+        ))
+      })?;
+
+    let result = async {
+      let stored =
+        model_txn::get_model::<PhotoGroup>(&mut txn, &photo_group.id).await?;
+      let Some(stored) = stored.filter(|stored| stored == expected) else {
+        return Ok(None);
+      };
+      photo_group.touch();
+      model_txn::replace_model(&mut txn, &stored, &photo_group).await?;
+      Ok::<_, miette::Report>(Some(photo_group))
+    }
+    .await;
+    let photo_group = match result {
+      Ok(Some(photo_group)) => photo_group,
+      Ok(None) | Err(_) => {
+        txn.rollback().await.map_err(|e| {
+          PatchModelError::Db(miette!("failed to rollback transaction: {e}"))
+        })?;
+        return result.map_err(PatchModelError::Db);
+      }
+    };
+
+    if let Err(e) = txn.commit().await {
+      txn.rollback().await.map_err(|e| {
+        PatchModelError::Db(miette!("failed to rollback transaction: {e}"))
+      })?;
+      return Err(PatchModelError::Db(miette!(
+        "failed to commit transaction: {e}"
+      )));
+    }
+
+    Ok(Some(photo_group))
+  }
+
+  async fn fetch_photo_groups_by_user(
     &self,
     owner: UserRecordId,
   ) -> Result<Vec<PhotoGroup>, FetchModelByIndexError> {
@@ -60,9 +252,40 @@ impl PhotoGroupRepository {
       .await
   }
 
-  /// Produce a list of all [`PhotoGroup`]s.
   #[instrument(skip(self))]
-  pub async fn enumerate_photo_groups(&self) -> Result<Vec<PhotoGroup>> {
+  async fn enumerate_photo_groups(&self) -> Result<Vec<PhotoGroup>> {
     self.db.enumerate_models().await
   }
+
+  #[instrument(skip(self))]
+  async fn delete_photo_group(
+    &self,
+    id: models::PhotoGroupRecordId,
+  ) -> Result<bool, DeleteModelError> {
+    self.db.delete_model(id).await
+  }
+
+  #[instrument(skip(self))]
+  async fn migrate_photo_groups(
+    &self,
+    options: MigrationOptions,
+  ) -> Result<MigrationReport, MigrateModelsError> {
+    crate::migrate::migrate_models(&self.db, options).await
+  }
+
+  #[instrument(skip(self, photo_group), fields(id = %photo_group.id))]
+  async fn restore_photo_group(
+    &self,
+    photo_group: PhotoGroup,
+  ) -> Result<(), RestoreModelError> {
+    crate::restore::restore_model(&self.db, photo_group).await
+  }
+
+  #[instrument(skip(self, keep))]
+  async fn prune_photo_groups(
+    &self,
+    keep: &BTreeSet<String>,
+  ) -> Result<usize, RestoreModelError> {
+    crate::restore::prune_models(&self.db, keep).await
+  }
 }
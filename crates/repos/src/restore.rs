@@ -0,0 +1,81 @@
+use std::collections::BTreeSet;
+
+use db::{
+  CreateModelError, Database, DeleteModelError, FetchModelError,
+  PatchModelError,
+};
+use models::Model;
+
+/// An error that occurs when restoring models from a backup.
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum RestoreModelError {
+  /// An error that occurs when enumerating models.
+  #[error("Failed to enumerate {0} models: {1}")]
+  EnumerateError(&'static str, miette::Report),
+  /// An error that occurs when fetching a model.
+  #[error("Failed to fetch {0} model {1}: {2}")]
+  FetchModelError(&'static str, String, FetchModelError),
+  /// An error that occurs when creating a model.
+  #[error("Failed to create {0} model {1}: {2}")]
+  CreateModelError(&'static str, String, CreateModelError),
+  /// An error that occurs when updating a model.
+  #[error("Failed to update {0} model {1}: {2}")]
+  PatchModelError(&'static str, String, PatchModelError),
+  /// An error that occurs when deleting a model.
+  #[error("Failed to delete {0} model {1}: {2}")]
+  DeleteModelError(&'static str, String, DeleteModelError),
+  /// An error that occurs when deleting the stored data of a model.
+  #[error("Failed to delete data of {0} model {1}: {2}")]
+  DeleteDataError(&'static str, String, miette::Report),
+}
+
+/// Writes a model exactly as it was backed up, creating it or replacing the
+/// stored record with the same id. Index entries are written with it. The
+/// model is not marked as updated.
+pub(crate) async fn restore_model<M: Model>(
+  db: &Database<M>,
+  model: M,
+) -> Result<(), RestoreModelError> {
+  let id = model.id();
+  let existing = db.fetch_model_by_id(id).await.map_err(|e| {
+    RestoreModelError::FetchModelError(M::TABLE_NAME, id.to_string(), e)
+  })?;
+
+  if existing.is_some() {
+    db.patch_model(id, model).await.map_err(|e| {
+      RestoreModelError::PatchModelError(M::TABLE_NAME, id.to_string(), e)
+    })?;
+  } else {
+    db.create_model(model).await.map_err(|e| {
+      RestoreModelError::CreateModelError(M::TABLE_NAME, id.to_string(), e)
+    })?;
+  }
+
+  Ok(())
+}
+
+/// Deletes every stored model whose id isn't in `keep`. Returns the number
+/// of models deleted.
+pub(crate) async fn prune_models<M: Model>(
+  db: &Database<M>,
+  keep: &BTreeSet<String>,
+) -> Result<usize, RestoreModelError> {
+  let models = db
+    .enumerate_models()
+    .await
+    .map_err(|e| RestoreModelError::EnumerateError(M::TABLE_NAME, e))?;
+
+  let mut count = 0;
+  for model in models {
+    let id = model.id();
+    if keep.contains(&id.to_string()) {
+      continue;
+    }
+    db.delete_model(id).await.map_err(|e| {
+      RestoreModelError::DeleteModelError(M::TABLE_NAME, id.to_string(), e)
+    })?;
+    count += 1;
+  }
+
+  Ok(count)
+}
@@ -0,0 +1,159 @@
+use core::fmt;
+use std::collections::{BTreeMap, BTreeSet};
+
+use db::kv::{Key, KeyValueStore, KvPrimitive, KvTransaction, Value};
+use hex::health::{self, HealthAware};
+use miette::miette;
+use models::{Artifact, Image, Model, Photo, PhotoGroup, User};
+use serde::de::DeserializeOwned;
+use tracing::instrument;
+
+use crate::model_txn::model_key_range;
+
+/// Every stored model, read at a single point in time.
+#[derive(Clone, Debug, Default)]
+pub struct StoreSnapshot {
+  /// All [`User`]s.
+  pub users:        Vec<User>,
+  /// All [`PhotoGroup`]s.
+  pub photo_groups: Vec<PhotoGroup>,
+  /// All [`Photo`]s.
+  pub photos:       Vec<Photo>,
+  /// All [`Image`]s.
+  pub images:       Vec<Image>,
+  /// All [`Artifact`]s.
+  pub artifacts:    Vec<Artifact>,
+}
+
+impl StoreSnapshot {
+  /// The ids of every model in the snapshot, by table.
+  #[must_use]
+  pub fn live_ids(&self) -> BTreeMap<String, BTreeSet<String>> {
+    fn ids<M: Model>(models: &[M]) -> (String, BTreeSet<String>) {
+      (
+        M::TABLE_NAME.to_owned(),
+        models.iter().map(|m| m.id().to_string()).collect(),
+      )
+    }
+
+    BTreeMap::from([
+      ids(&self.users),
+      ids(&self.photo_groups),
+      ids(&self.photos),
+      ids(&self.images),
+      ids(&self.artifacts),
+    ])
+  }
+
+  /// Whether the snapshot holds no models at all.
+  #[must_use]
+  pub fn is_empty(&self) -> bool {
+    self.users.is_empty()
+      && self.photo_groups.is_empty()
+      && self.photos.is_empty()
+      && self.images.is_empty()
+      && self.artifacts.is_empty()
+  }
+}
+
+/// An error that occurs when taking a [`StoreSnapshot`].
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+#[error("Failed to take store snapshot: {0}")]
+pub struct SnapshotError(pub miette::Report);
+
+/// The interface for reading every stored model at once.
+#[async_trait::async_trait]
+pub trait SnapshotRepositoryLike:
+  health::HealthReporter + fmt::Debug + Send + Sync + 'static
+{
+  /// Read every stored model in one read transaction, so that the snapshot
+  /// never holds a record without the records it refers to, or the other way
+  /// around.
+  async fn snapshot(&self) -> Result<StoreSnapshot, SnapshotError>;
+}
+
+/// Reads [`StoreSnapshot`]s directly from the [`KeyValueStore`] the model
+/// repositories store into.
+#[derive(Clone, Debug)]
+pub struct SnapshotRepository {
+  kv: KeyValueStore,
+}
+
+impl SnapshotRepository {
+  /// Create a new [`SnapshotRepository`].
+  #[must_use]
+  pub fn new(kv: KeyValueStore) -> Self { Self { kv } }
+}
+
+#[async_trait::async_trait]
+impl health::HealthReporter for SnapshotRepository {
+  fn name(&self) -> &'static str { stringify!(SnapshotRepository) }
+
+  async fn health_check(&self) -> health::ComponentHealth {
+    health::AdditiveComponentHealth::from_futures(vec![self.kv.health_report()])
+      .await
+      .into()
+  }
+}
+
+fn decode_models<M: Model + DeserializeOwned>(
+  scanned: Result<Vec<(Key, Value)>, impl fmt::Display>,
+) -> Result<Vec<M>, SnapshotError> {
+  scanned
+    .map_err(|e| {
+      SnapshotError(miette!("failed to scan {} models: {e}", M::TABLE_NAME))
+    })?
+    .into_iter()
+    .map(|(_, value)| {
+      value.deserialize().map_err(|e| {
+        SnapshotError(miette!(
+          "failed to deserialize {} model: {e}",
+          M::TABLE_NAME
+        ))
+      })
+    })
+    .collect()
+}
+
+#[async_trait::async_trait]
+impl SnapshotRepositoryLike for SnapshotRepository {
+  #[instrument(skip(self))]
+  async fn snapshot(&self) -> Result<StoreSnapshot, SnapshotError> {
+    let mut txn =
+      self.kv.begin_optimistic_transaction().await.map_err(|e| {
+        SnapshotError(miette!("failed to start optimistic transaction: {e}"))
+      })?;
+
+    let result = async {
+      macro_rules! scan {
+        ($model:ty) => {{
+          let (start, end) = model_key_range::<$model>();
+          decode_models::<$model>(txn.scan(start, end, None).await)?
+        }};
+      }
+
+      Ok::<_, SnapshotError>(StoreSnapshot {
+        users:        scan!(User),
+        photo_groups: scan!(PhotoGroup),
+        photos:       scan!(Photo),
+        images:       scan!(Image),
+        artifacts:    scan!(Artifact),
+      })
+    }
+    .await;
+    let snapshot = match result {
+      Ok(snapshot) => snapshot,
+      Err(e) => {
+        txn.rollback().await.map_err(|e| {
+          SnapshotError(miette!("failed to rollback transaction: {e}"))
+        })?;
+        return Err(e);
+      }
+    };
+    txn.commit().await.map_err(|e| {
+      SnapshotError(miette!("failed to commit transaction: {e}"))
+    })?;
+
+    Ok(snapshot)
+  }
+}
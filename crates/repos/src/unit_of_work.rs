@@ -0,0 +1,139 @@
+use std::sync::Arc;
+
+use db::{CreateModelError, DeleteModelError, PatchModelError};
+use models::{Model, Photo, PhotoCreateRequest, PhotoRecordId};
+
+use crate::{PhotoRepositoryLike, RestoreModelError};
+
+/// A write that has been applied as part of a [`UnitOfWork`], and how to
+/// undo it.
+#[derive(Debug)]
+enum AppliedWrite {
+  CreatedPhoto(Arc<dyn PhotoRepositoryLike>, PhotoRecordId),
+  /// An updated photo, and the record it replaced.
+  UpdatedPhoto(Arc<dyn PhotoRepositoryLike>, Box<Photo>),
+}
+
+impl AppliedWrite {
+  async fn undo(self) -> Result<(), (&'static str, String, UndoError)> {
+    match self {
+      Self::CreatedPhoto(repo, id) => repo
+        .delete_photo(id)
+        .await
+        .map(|_| ())
+        .map_err(|e| (Photo::TABLE_NAME, id.to_string(), e.into())),
+      Self::UpdatedPhoto(repo, previous) => {
+        let id = previous.id;
+        repo
+          .restore_photo(*previous)
+          .await
+          .map_err(|e| (Photo::TABLE_NAME, id.to_string(), e.into()))
+      }
+    }
+  }
+}
+
+/// The reason a write applied by a [`UnitOfWork`] couldn't be undone.
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum UndoError {
+  /// Failed to delete a created model.
+  #[error(transparent)]
+  Delete(#[from] DeleteModelError),
+  /// Failed to write back the previous version of an updated model.
+  #[error(transparent)]
+  Restore(#[from] RestoreModelError),
+}
+
+/// An error that occurs when rolling back a [`UnitOfWork`]. Every write is
+/// still attempted to be undone; these are the ones that couldn't be.
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+#[error("Failed to undo {} writes: {0:?}", .0.len())]
+pub struct RollbackError(pub Vec<(&'static str, String, UndoError)>);
+
+/// A set of separate repository writes that should land together or not at
+/// all, undone by compensating writes.
+///
+/// The unit records every write it applies, and
+/// [`rollback()`](Self::rollback) undoes them in reverse order. Once all
+/// writes have succeeded, [`commit()`](Self::commit) forgets them.
+///
+/// This is weaker than a store transaction: readers see each write as soon as
+/// it's applied, and a crash, or an undo that fails, leaves writes behind.
+/// Writes that must be atomic belong in one transactional repository call,
+/// like [`create_photo_group_with_photos()`].
+///
+/// A unit dropped without being committed or rolled back leaves its writes
+/// in place, and logs that it did.
+///
+/// [`create_photo_group_with_photos()`]: crate::PhotoGroupRepositoryLike::create_photo_group_with_photos
+#[derive(Debug, Default)]
+#[must_use = "a unit of work must be committed or rolled back"]
+pub struct UnitOfWork {
+  applied: Vec<AppliedWrite>,
+}
+
+impl UnitOfWork {
+  /// Begin a new [`UnitOfWork`].
+  pub fn new() -> Self { Self::default() }
+
+  /// Create a [`Photo`] as part of this unit.
+  pub async fn create_photo(
+    &mut self,
+    repo: &Arc<dyn PhotoRepositoryLike>,
+    input: PhotoCreateRequest,
+  ) -> Result<Photo, CreateModelError> {
+    let photo = repo.create_photo(input).await?;
+    self
+      .applied
+      .push(AppliedWrite::CreatedPhoto(repo.clone(), photo.id));
+    Ok(photo)
+  }
+
+  /// Update a [`Photo`] as part of this unit. Rolling back writes back the
+  /// photo as it was before `edit`.
+  pub async fn update_photo(
+    &mut self,
+    repo: &Arc<dyn PhotoRepositoryLike>,
+    mut photo: Photo,
+    edit: impl FnOnce(&mut Photo) + Send,
+  ) -> Result<Photo, PatchModelError> {
+    let previous = photo.clone();
+    edit(&mut photo);
+    let photo = repo.update_photo(photo).await?;
+    self
+      .applied
+      .push(AppliedWrite::UpdatedPhoto(repo.clone(), Box::new(previous)));
+    Ok(photo)
+  }
+
+  /// Keep every write applied by this unit.
+  pub fn commit(mut self) { self.applied.clear(); }
+
+  /// Undo every write applied by this unit, most recent first.
+  pub async fn rollback(mut self) -> Result<(), RollbackError> {
+    let mut failures = Vec::new();
+    while let Some(write) = self.applied.pop() {
+      if let Err(failure) = write.undo().await {
+        failures.push(failure);
+      }
+    }
+
+    if failures.is_empty() {
+      Ok(())
+    } else {
+      Err(RollbackError(failures))
+    }
+  }
+}
+
+impl Drop for UnitOfWork {
+  fn drop(&mut self) {
+    if !self.applied.is_empty() {
+      tracing::warn!(
+        "unit of work dropped with {} uncommitted writes: {:?}",
+        self.applied.len(),
+        self.applied
+      );
+    }
+  }
+}
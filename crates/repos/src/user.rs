@@ -1,15 +1,94 @@
 use core::fmt;
+use std::collections::BTreeSet;
 
-use db::{CreateModelError, Database, FetchModelByIndexError, FetchModelError};
+use db::{
+  kv::{KeyValueStore, KvTransaction},
+  CreateModelError, Database, DeleteModelError, FetchModelByIndexError,
+  FetchModelError, PatchModelError,
+};
 use hex::health::{self, HealthAware};
-use miette::Result;
-use models::{EitherSlug, LaxSlug, User};
+use miette::{miette, Result};
+use models::{EitherSlug, LaxSlug, Timestamped, User};
 use tracing::instrument;
 
-/// Stores and retrieves [`User`]s.
+use crate::{
+  model_txn, MigrateModelsError, MigrationOptions, MigrationReport,
+  RestoreModelError,
+};
+
+/// A change to a [`User`], applied atomically. Returns whether the change
+/// should be written; returning `false` leaves the stored user as it was.
+pub type UserUpdate = Box<dyn FnOnce(&mut User) -> bool + Send>;
+
+/// The interface for storing and retrieving [`User`]s.
+#[async_trait::async_trait]
+pub trait UserRepositoryLike:
+  health::HealthReporter + fmt::Debug + Send + Sync + 'static
+{
+  /// Create a [`User`] model.
+  async fn create_user(
+    &self,
+    input: models::UserCreateRequest,
+  ) -> Result<User, CreateModelError>;
+
+  /// Fetch a [`User`] by id.
+  async fn fetch_user_by_id(
+    &self,
+    id: models::UserRecordId,
+  ) -> Result<Option<User>, FetchModelError>;
+
+  /// Fetch a [`User`] by email.
+  async fn fetch_user_by_email(
+    &self,
+    email: models::EmailAddress,
+  ) -> Result<Option<User>, FetchModelByIndexError>;
+
+  /// Update a [`User`] model, marking it as updated now.
+  async fn update_user(&self, user: User) -> Result<User, PatchModelError>;
+
+  /// Apply `update` to the stored [`User`] and write the result, marking it as
+  /// updated now, so that concurrent updates don't overwrite each other or
+  /// both act on the same stored state. Returns the updated user, or `None`
+  /// without writing anything if the user doesn't exist or `update` declined.
+  async fn update_user_with(
+    &self,
+    id: models::UserRecordId,
+    update: UserUpdate,
+  ) -> Result<Option<User>, PatchModelError>;
+
+  /// Delete a [`User`] by id. Returns whether it existed.
+  async fn delete_user(
+    &self,
+    id: models::UserRecordId,
+  ) -> Result<bool, DeleteModelError>;
+
+  /// Upcast stored [`User`]s written at older schema versions and re-write
+  /// them.
+  async fn migrate_users(
+    &self,
+    options: MigrationOptions,
+  ) -> Result<MigrationReport, MigrateModelsError>;
+
+  /// Produce a list of all [`User`]s.
+  async fn enumerate_users(&self) -> Result<Vec<User>>;
+
+  /// Write a [`User`] restored from a backup, replacing any stored record
+  /// with the same id.
+  async fn restore_user(&self, user: User) -> Result<(), RestoreModelError>;
+
+  /// Delete every stored [`User`] whose id isn't in `keep`. Returns the number
+  /// deleted.
+  async fn prune_users(
+    &self,
+    keep: &BTreeSet<String>,
+  ) -> Result<usize, RestoreModelError>;
+}
+
+/// Stores and retrieves [`User`]s in a [`Database`].
 #[derive(Clone)]
 pub struct UserRepository {
   model_repo: Database<User>,
+  kv:         KeyValueStore,
 }
 
 impl fmt::Debug for UserRepository {
@@ -27,6 +106,7 @@ impl fmt::Debug for UserRepository {
           >
         ),
       )
+      .field("kv", &self.kv)
       .finish()
   }
 }
@@ -47,29 +127,34 @@ impl health::HealthReporter for UserRepository {
 impl UserRepository {
   /// Create a new [`UserRepository`].
   #[must_use]
-  pub fn new(model_repo: Database<User>) -> Self { Self { model_repo } }
+  pub fn new(kv: KeyValueStore) -> Self {
+    Self {
+      model_repo: Database::new_from_kv(kv.clone()),
+      kv,
+    }
+  }
+}
 
-  /// Create a [`User`] model.
+#[async_trait::async_trait]
+impl UserRepositoryLike for UserRepository {
   #[instrument(skip(self))]
-  pub async fn create_user(
+  async fn create_user(
     &self,
     input: models::UserCreateRequest,
   ) -> Result<User, CreateModelError> {
     self.model_repo.create_model(input.into()).await
   }
 
-  /// Fetch a [`User`] by id.
   #[instrument(skip(self))]
-  pub async fn fetch_user_by_id(
+  async fn fetch_user_by_id(
     &self,
     id: models::UserRecordId,
   ) -> Result<Option<User>, FetchModelError> {
     self.model_repo.fetch_model_by_id(id).await
   }
 
-  /// Fetch a [`User`] by email.
   #[instrument(skip(self))]
-  pub async fn fetch_user_by_email(
+  async fn fetch_user_by_email(
     &self,
     email: models::EmailAddress,
   ) -> Result<Option<User>, FetchModelByIndexError> {
@@ -81,4 +166,93 @@ impl UserRepository {
       )
       .await
   }
+
+  #[instrument(skip(self, user), fields(id = %user.id))]
+  async fn update_user(&self, mut user: User) -> Result<User, PatchModelError> {
+    user.touch();
+    self.model_repo.patch_model(user.id, user).await
+  }
+
+  #[instrument(skip(self, update))]
+  async fn update_user_with(
+    &self,
+    id: models::UserRecordId,
+    update: UserUpdate,
+  ) -> Result<Option<User>, PatchModelError> {
+    let mut txn =
+      self.kv.begin_pessimistic_transaction().await.map_err(|e| {
+        PatchModelError::Db(miette!(
+          "failed to start pessimistic transaction: {e}"
+        ))
+      })?;
+
+    let result = async {
+      let Some(stored) = model_txn::get_model::<User>(&mut txn, &id).await?
+      else {
+        return Ok(None);
+      };
+      let mut user = stored.clone();
+      if !update(&mut user) {
+        return Ok(None);
+      }
+      user.touch();
+      model_txn::replace_model(&mut txn, &stored, &user).await?;
+      Ok::<_, miette::Report>(Some(user))
+    }
+    .await;
+    let user = match result {
+      Ok(Some(user)) => user,
+      Ok(None) | Err(_) => {
+        txn.rollback().await.map_err(|e| {
+          PatchModelError::Db(miette!("failed to rollback transaction: {e}"))
+        })?;
+        return result.map_err(PatchModelError::Db);
+      }
+    };
+
+    if let Err(e) = txn.commit().await {
+      txn.rollback().await.map_err(|e| {
+        PatchModelError::Db(miette!("failed to rollback transaction: {e}"))
+      })?;
+      return Err(PatchModelError::Db(miette!(
+        "failed to commit transaction: {e}"
+      )));
+    }
+
+    Ok(Some(user))
+  }
+
+  #[instrument(skip(self))]
+  async fn delete_user(
+    &self,
+    id: models::UserRecordId,
+  ) -> Result<bool, DeleteModelError> {
+    self.model_repo.delete_model(id).await
+  }
+
+  #[instrument(skip(self))]
+  async fn migrate_users(
+    &self,
+    options: MigrationOptions,
+  ) -> Result<MigrationReport, MigrateModelsError> {
+    crate::migrate::migrate_models(&self.model_repo, options).await
+  }
+
+  #[instrument(skip(self))]
+  async fn enumerate_users(&self) -> Result<Vec<User>> {
+    self.model_repo.enumerate_models().await
+  }
+
+  #[instrument(skip(self, user), fields(id = %user.id))]
+  async fn restore_user(&self, user: User) -> Result<(), RestoreModelError> {
+    crate::restore::restore_model(&self.model_repo, user).await
+  }
+
+  #[instrument(skip(self, keep))]
+  async fn prune_users(
+    &self,
+    keep: &BTreeSet<String>,
+  ) -> Result<usize, RestoreModelError> {
+    crate::restore::prune_models(&self.model_repo, keep).await
+  }
 }
@@ -1,5 +1,11 @@
 mod header;
+mod oidc_login_links;
 mod page_cover;
 mod photo;
+mod rich_text;
+mod time_ago;
 
-pub use self::{header::*, page_cover::*, photo::*};
+pub use self::{
+  header::*, oidc_login_links::*, page_cover::*, photo::*, rich_text::*,
+  time_ago::*,
+};
@@ -0,0 +1,45 @@
+use leptos::prelude::*;
+use lsc::button::*;
+
+use crate::server_fns::fetch_oidc_providers;
+
+/// Buttons for logging in with each configured `OpenID` Connect provider.
+/// Logging in with a provider also signs up new users.
+#[component]
+pub fn OidcLoginLinks(next_url: Option<String>) -> impl IntoView {
+  let providers = Resource::new(|| (), |()| fetch_oidc_providers());
+  let next_query = next_url.map(|nu| format!("?next={nu}")).unwrap_or_default();
+
+  let links = move || {
+    let next_query = next_query.clone();
+    Suspend::new(async move {
+      let providers = providers.await.unwrap_or_default();
+      (!providers.is_empty()).then(|| {
+        let links = providers
+          .into_iter()
+          .map(|p| {
+            let href = format!("/auth/oidc/{}{next_query}", p.id);
+            view! {
+              <Button
+                element_type=ButtonElementType::Link color={ButtonColor::Base}
+                size={ButtonSize::Large} {..} href=href rel="external"
+              >
+                "Continue with " { p.name }
+              </Button>
+            }
+          })
+          .collect_view();
+        view! {
+          <div class="mt-4 flex flex-col items-center gap-2">
+            <p class="text-base-dim">"or"</p>
+            { links }
+          </div>
+        }
+      })
+    })
+  };
+
+  view! {
+    <Suspense fallback=|| ()>{ links }</Suspense>
+  }
+}
@@ -5,28 +5,33 @@ use models::{Image, PhotoRecordId};
 
 use crate::server_fns::fetch_thumbnail_image_for_photo;
 
+/// Renders the thumbnail of a photo whose thumbnail [`Image`] is already
+/// known, falling back to its tiny preview while the full thumbnail loads.
 #[component]
-pub fn PhotoPreview(id: PhotoRecordId) -> impl IntoView {
-  let resource = Resource::new(move || id, fetch_thumbnail_image_for_photo);
+pub fn PhotoPreview(id: PhotoRecordId, thumbnail: Image) -> impl IntoView {
+  let url = format!("/api/photo_thumbnail/{id}");
+  let fallback_data = format!(
+    "data:image/avif;charset=utf-8;base64,{}",
+    base64::prelude::BASE64_STANDARD.encode(thumbnail.meta.tiny_preview.data)
+  );
+  view! {
+    <SmallImageWithFallback
+      url=url fallback_data=fallback_data
+      style=ImageStyle::Border
+    />
+  }
+}
 
-  let render_fn = move |i: Image| {
-    let url = format!("/api/photo_thumbnail/{id}");
-    let fallback_data = format!(
-      "data:image/avif;charset=utf-8;base64,{}",
-      base64::prelude::BASE64_STANDARD.encode(i.meta.tiny_preview.data)
-    );
-    view! {
-      <SmallImageWithFallback
-        url=url fallback_data=fallback_data
-        style=ImageStyle::Border
-      />
-    }
-  };
+/// Fetches the thumbnail [`Image`] of a photo, then renders its
+/// [`PhotoPreview`].
+#[component]
+pub fn PhotoPreviewFetcher(id: PhotoRecordId) -> impl IntoView {
+  let resource = Resource::new(move || id, fetch_thumbnail_image_for_photo);
 
   let suspended_fn = move || {
     Suspend::new(async move {
       match resource.await {
-        Ok(Some(i)) => render_fn(i).into_any(),
+        Ok(Some(i)) => view! { <PhotoPreview id=id thumbnail=i /> }.into_any(),
         Ok(None) => view! { "image not found" }.into_any(),
         Err(e) => {
           let e = e.to_string();
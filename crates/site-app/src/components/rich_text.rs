@@ -0,0 +1,57 @@
+use leptos::prelude::*;
+use pulldown_cmark::{html, Event, Options, Parser, Tag, TagEnd};
+
+/// The link schemes allowed in rendered Markdown.
+const ALLOWED_LINK_SCHEMES: &[&str] = &["http://", "https://", "mailto:"];
+
+fn is_allowed_link(url: &str) -> bool {
+  let url = url.trim().to_ascii_lowercase();
+  ALLOWED_LINK_SCHEMES.iter().any(|s| url.starts_with(s))
+}
+
+/// Renders user-written Markdown to HTML that's safe to inject.
+///
+/// Raw HTML is escaped rather than passed through, images are reduced to
+/// their alt text, and only links with an allowed scheme are kept.
+fn render_markdown(input: &str) -> String {
+  // whether each open link was kept, so that its end tag can match
+  let mut open_links = Vec::new();
+
+  let events = Parser::new_ext(input, Options::ENABLE_STRIKETHROUGH)
+    .filter_map(|event| match event {
+      Event::Html(raw) | Event::InlineHtml(raw) => Some(Event::Text(raw)),
+      Event::Start(Tag::Image { .. }) | Event::End(TagEnd::Image) => None,
+      Event::Start(Tag::Link { ref dest_url, .. }) => {
+        let allowed = is_allowed_link(dest_url);
+        open_links.push(allowed);
+        allowed.then_some(event)
+      }
+      Event::End(TagEnd::Link) => {
+        open_links.pop().unwrap_or_default().then_some(event)
+      }
+      event => Some(event),
+    });
+
+  let mut output = String::new();
+  html::push_html(&mut output, events);
+  output
+}
+
+/// Renders user-written Markdown as rich text.
+#[component]
+pub fn RichText(
+  /// The Markdown source.
+  #[prop(into)]
+  source: String,
+) -> impl IntoView {
+  const CLASS: &str = "text-base-dim max-w-prose flex flex-col gap-2 \
+                       [&_a]:underline [&_a]:text-primary-11 \
+                       dark:[&_a]:text-primarydark-11 [&_ul]:list-disc \
+                       [&_ol]:list-decimal [&_ul]:pl-6 [&_ol]:pl-6";
+
+  let html = render_markdown(&source);
+
+  view! {
+    <div class=CLASS inner_html=html />
+  }
+}
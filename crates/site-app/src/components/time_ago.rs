@@ -0,0 +1,32 @@
+use leptos::prelude::*;
+use models::Timestamp;
+
+const MINUTE: i64 = 60;
+const HOUR: i64 = 60 * MINUTE;
+const DAY: i64 = 24 * HOUR;
+const MONTH: i64 = 30 * DAY;
+const YEAR: i64 = 365 * DAY;
+
+fn time_ago(time: Timestamp, now: Timestamp) -> String {
+  let secs = (now - time).num_seconds().max(0);
+  let (count, unit) = match secs {
+    0..MINUTE => return "just now".to_owned(),
+    MINUTE..HOUR => (secs / MINUTE, "minute"),
+    HOUR..DAY => (secs / HOUR, "hour"),
+    DAY..MONTH => (secs / DAY, "day"),
+    MONTH..YEAR => (secs / MONTH, "month"),
+    _ => (secs / YEAR, "year"),
+  };
+  let plural = if count == 1 { "" } else { "s" };
+  format!("{count} {unit}{plural} ago")
+}
+
+#[component]
+pub fn TimeAgo(time: Timestamp) -> impl IntoView {
+  let datetime = time.to_rfc3339();
+  let text = time_ago(time, chrono::Utc::now());
+
+  view! {
+    <time datetime=datetime>{ text }</time>
+  }
+}
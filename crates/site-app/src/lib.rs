@@ -6,6 +6,7 @@
 
 mod components;
 mod pages;
+mod passkey;
 pub mod server_fns;
 
 use app_upload::UploadPhotoPage;
@@ -64,10 +65,17 @@ pub fn App() -> impl IntoView {
           <Route path=path!("/") view=HomePage />
           <Route path=path!("/sign-up") view=SignupPage />
           <Route path=path!("/log-in") view=LoginPage />
+          <Route path=path!("/forgot-password") view=ForgotPasswordPage />
+          <Route path=path!("/reset-password/:token") view=ResetPasswordPage />
           <Route path=path!("/log-out") view=protect(LogoutPage) />
+          <Route path=path!("/verify-email/:token") view=VerifyEmailPage />
+          <Route path=path!("/magic-link/:token") view=MagicLinkPage />
           <Route path=path!("/profile") view=protect(ProfilePage) />
+          <Route path=path!("/settings") view=protect(AccountSettingsPage) />
+          <Route path=path!("/settings/confirm/:token") view=protect(AccountSettingsPage) />
           <Route path=path!("/upload-photo") view=protect(UploadPhotoPage) />
           <Route path=path!("/photo-group/:id") view=PhotoGroupPage />
+          <Route path=path!("/photo-group/:id/edit") view=protect(EditPhotoGroupPage) />
           <Route path=path!("/component-testing/link") view=lsc::link::LinkMatrixTestPage />
           <Route path=path!("/component-testing/button") view=lsc::button::ButtonMatrixTestPage />
           <Route path=path!("/component-testing/field") view=lsc::field::FieldMatrixTestPage />
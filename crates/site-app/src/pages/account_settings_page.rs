@@ -0,0 +1,680 @@
+use base64::Engine;
+use base_components::{
+  utils::{
+    inputs::touched_input_bindings,
+    navigation::{navigate_to, reload},
+  },
+  Prose, Section, Title,
+};
+use leptos::prelude::*;
+use leptos_router::hooks::use_params_map;
+use lsc::{button::*, field::*};
+use models::{AccountDetails, PasskeyDetails};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  components::TimeAgo,
+  pages::EmailVerificationNotice,
+  passkey::{create_passkey, get_passkey},
+  server_fns::{
+    begin_passkey_reauthentication, begin_passkey_registration,
+    begin_totp_enrollment, change_email, change_name, change_password,
+    confirm_totp_enrollment, delete_account, disable_two_factor,
+    fetch_account_details, finish_passkey_registration, remove_passkey,
+    remove_password, request_reauthentication_link, IdentityProof,
+  },
+};
+
+/// How the user confirms it's them before the sensitive changes on this
+/// page.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct Confirmation {
+  /// Whether the user has a password to type.
+  has_password: bool,
+  /// Whether the user has a passkey to confirm with.
+  has_passkeys: bool,
+  /// The token from an emailed confirmation link, when the page was opened
+  /// through one.
+  link_token:   Option<String>,
+}
+
+/// Builds the proof a sensitive change is sent with: the confirmation link
+/// the page was opened through, the typed password, or a passkey prompt.
+async fn identity_proof(
+  confirmation: Confirmation,
+  password: Option<String>,
+) -> Result<IdentityProof, ServerFnError> {
+  if let Some(token) = confirmation.link_token {
+    return Ok(IdentityProof {
+      link_token: Some(token),
+      ..Default::default()
+    });
+  }
+  if confirmation.has_password {
+    return Ok(IdentityProof {
+      password: Some(password.unwrap_or_default()),
+      ..Default::default()
+    });
+  }
+  if confirmation.has_passkeys {
+    let options = begin_passkey_reauthentication().await?;
+    let assertion = get_passkey(&options).await.map_err(ServerFnError::new)?;
+    return Ok(IdentityProof {
+      passkey: Some(assertion),
+      ..Default::default()
+    });
+  }
+  Err(ServerFnError::new(
+    "Email yourself a confirmation link first.",
+  ))
+}
+
+#[component]
+pub fn AccountSettingsPage() -> impl IntoView {
+  let params = use_params_map();
+  let link_token = params.read_untracked().get("token");
+  let account = Resource::new(|| (), |()| fetch_account_details());
+
+  let suspended_fn = move || {
+    let link_token = link_token.clone();
+    Suspend::new(async move {
+      match account.await {
+        Ok(Some(account)) => {
+          view! { <AccountSettings account=account link_token=link_token /> }
+            .into_any()
+        }
+        Ok(None) => view! { "You're not logged in." }.into_any(),
+        Err(e) => {
+          let e = e.to_string();
+          view! { "failed to fetch account: " {e} }.into_any()
+        }
+      }
+    })
+  };
+
+  view! {
+    <Section>
+      <Title>"Account Settings"</Title>
+    </Section>
+    <Section>
+      <EmailVerificationNotice />
+    </Section>
+    <Suspense fallback=move || view! { "Loading..." }>
+      { suspended_fn }
+    </Suspense>
+  }
+}
+
+#[component]
+fn AccountSettings(
+  account: AccountDetails,
+  link_token: Option<String>,
+) -> impl IntoView {
+  let confirmation = Confirmation {
+    has_password: account.has_password,
+    has_passkeys: !account.passkeys.is_empty(),
+    link_token,
+  };
+
+  view! {
+    <Section>
+      <ChangeNameForm name=account.name.to_string() />
+    </Section>
+    <Section>
+      <ChangeEmailForm
+        email=account.email.to_string() confirmation=confirmation.clone()
+      />
+    </Section>
+    <Section>
+      <ChangePasswordForm confirmation=confirmation.clone() />
+    </Section>
+    <Section>
+      <PasskeysForm
+        passkeys=account.passkeys has_password=account.has_password
+      />
+    </Section>
+    <Section>
+      <TwoFactorForm
+        enabled=account.two_factor_enabled confirmation=confirmation.clone()
+      />
+    </Section>
+    <Section>
+      <ExportDataSection />
+    </Section>
+    <Section>
+      <DeleteAccountForm confirmation=confirmation />
+    </Section>
+  }
+}
+
+#[component]
+fn SettingsField(
+  field_id: &'static str,
+  field_label: &'static str,
+  #[prop(optional)] field_type: Option<&'static str>,
+  signal: RwSignal<Option<String>>,
+) -> impl IntoView {
+  let (read, write) = touched_input_bindings(signal);
+  view! {
+    <div class="flex flex-col gap-1">
+      <label class="" for=field_id>{ field_label }</label>
+      <Field size={FieldSize::Large} {..}
+        id=field_id type=field_type on:input=write prop:value=read
+      />
+    </div>
+  }
+}
+
+/// Renders the outcome of a settings form's action.
+fn action_result_view(
+  value: Option<Result<(), ServerFnError>>,
+  success: &'static str,
+) -> Option<AnyView> {
+  value.map(|v| match v {
+    Ok(()) => view! {
+      <p class="text-success-11 dark:text-successdark-11">{ success }</p>
+    }
+    .into_any(),
+    Err(e) => view! {
+      <p class="text-danger-11 dark:text-dangerdark-11">{ e.to_string() }</p>
+    }
+    .into_any(),
+  })
+}
+
+/// Asks for whatever the user confirms sensitive changes with: their
+/// password, a passkey when they submit, or a link emailed to them.
+#[component]
+fn ConfirmIdentityField(
+  field_id: &'static str,
+  confirmation: Confirmation,
+  password: RwSignal<Option<String>>,
+) -> impl IntoView {
+  if confirmation.link_token.is_some() {
+    view! { <Prose>"Confirmed by the link from your email."</Prose> }.into_any()
+  } else if confirmation.has_password {
+    view! {
+      <SettingsField
+        field_id=field_id field_label="Current Password"
+        field_type="password" signal=password
+      />
+    }
+    .into_any()
+  } else if confirmation.has_passkeys {
+    view! { <Prose>"You'll be asked to confirm with a passkey."</Prose> }
+      .into_any()
+  } else {
+    view! { <RequestConfirmationLink /> }.into_any()
+  }
+}
+
+#[component]
+fn RequestConfirmationLink() -> impl IntoView {
+  let action = Action::new(move |(): &()| request_reauthentication_link());
+  let action_value = action.value();
+  let action_pending = action.pending();
+
+  view! {
+    <Prose>
+      "Your account has no password, so confirm it's you with a link we \
+       email you. It leads back here and works once, for 15 minutes."
+    </Prose>
+    <div class="flex flex-row items-center gap-4">
+      <Button
+        color={ButtonColor::Base} disabled=action_pending
+        {..} on:click=move |_| { action.dispatch(()); }
+      >
+        "Email Me a Link"
+      </Button>
+      { move || action_result_view(
+        action_value.get(),
+        "Check your inbox for the link.",
+      ) }
+    </div>
+  }
+}
+
+#[island]
+fn ChangeNameForm(name: String) -> impl IntoView {
+  let name = RwSignal::new(Some(name));
+
+  let action =
+    Action::new(move |(): &()| change_name(name.get().unwrap_or_default()));
+  let action_value = action.value();
+  let action_pending = action.pending();
+
+  view! {
+    <div class="flex flex-col gap-4 max-w-md">
+      <p class="text-2xl">"Name"</p>
+      <SettingsField field_id="name" field_label="Full Name" signal=name />
+      <div class="flex flex-row items-center gap-4">
+        <Button
+          disabled=action_pending
+          {..} on:click=move |_| { action.dispatch(()); }
+        >
+          "Save Name"
+        </Button>
+        { move || action_result_view(action_value.get(), "Name saved.") }
+      </div>
+    </div>
+  }
+}
+
+#[island]
+fn ChangeEmailForm(email: String, confirmation: Confirmation) -> impl IntoView {
+  let email = RwSignal::new(Some(email));
+  let current_password = RwSignal::new(None::<String>);
+
+  let action = Action::new_local({
+    let confirmation = confirmation.clone();
+    move |(): &()| {
+      let proof = identity_proof(confirmation.clone(), current_password.get());
+      let email = email.get().unwrap_or_default();
+      async move { change_email(proof.await?, email).await }
+    }
+  });
+  let action_value = action.value();
+  let action_pending = action.pending();
+
+  view! {
+    <div class="flex flex-col gap-4 max-w-md">
+      <p class="text-2xl">"Email"</p>
+      <Prose>
+        "We'll send a verification link to your new address. Changing your \
+         email logs you out on your other devices."
+      </Prose>
+      <SettingsField
+        field_id="email" field_label="Email" field_type="email" signal=email
+      />
+      <ConfirmIdentityField
+        field_id="email_current_password" confirmation=confirmation
+        password=current_password
+      />
+      <div class="flex flex-row items-center gap-4">
+        <Button
+          disabled=action_pending
+          {..} on:click=move |_| { action.dispatch_local(()); }
+        >
+          "Change Email"
+        </Button>
+        { move || action_result_view(
+          action_value.get(),
+          "Email changed. Check your inbox to verify it.",
+        ) }
+      </div>
+    </div>
+  }
+}
+
+#[island]
+fn ChangePasswordForm(confirmation: Confirmation) -> impl IntoView {
+  let current_password = RwSignal::new(None::<String>);
+  let password = RwSignal::new(None::<String>);
+  let confirm_password = RwSignal::new(None::<String>);
+
+  let action = Action::new_local({
+    let confirmation = confirmation.clone();
+    move |(): &()| {
+      let proof = identity_proof(confirmation.clone(), current_password.get());
+      let password = password.get().unwrap_or_default();
+      let confirm_password = confirm_password.get().unwrap_or_default();
+      async move { change_password(proof.await?, password, confirm_password).await }
+    }
+  });
+  let action_value = action.value();
+  let action_pending = action.pending();
+
+  view! {
+    <div class="flex flex-col gap-4 max-w-md">
+      <p class="text-2xl">"Password"</p>
+      <Prose>"Changing your password logs you out on your other devices."</Prose>
+      <ConfirmIdentityField
+        field_id="current_password" confirmation=confirmation
+        password=current_password
+      />
+      <SettingsField
+        field_id="password" field_label="New Password" field_type="password"
+        signal=password
+      />
+      <SettingsField
+        field_id="confirm_password" field_label="Confirm New Password"
+        field_type="password" signal=confirm_password
+      />
+      <div class="flex flex-row items-center gap-4">
+        <Button
+          disabled=action_pending
+          {..} on:click=move |_| { action.dispatch_local(()); }
+        >
+          "Change Password"
+        </Button>
+        { move || action_result_view(action_value.get(), "Password changed.") }
+      </div>
+    </div>
+  }
+}
+
+#[island]
+fn PasskeysForm(
+  passkeys: Vec<PasskeyDetails>,
+  has_password: bool,
+) -> impl IntoView {
+  let can_remove = has_password || passkeys.len() > 1;
+
+  let add_action = Action::new_local(move |(): &()| async move {
+    let options = begin_passkey_registration().await?;
+    let attestation =
+      create_passkey(&options).await.map_err(ServerFnError::new)?;
+    finish_passkey_registration(attestation).await
+  });
+  let add_value = add_action.value();
+  let add_pending = add_action.pending();
+
+  let remove_action = Action::new(move |credential_id: &String| {
+    remove_passkey(credential_id.clone())
+  });
+  let remove_value = remove_action.value();
+
+  Effect::new(move |_| {
+    if matches!(add_value.get(), Some(Ok(())))
+      || matches!(remove_value.get(), Some(Ok(())))
+    {
+      reload();
+    }
+  });
+
+  let passkey_list = passkeys
+    .into_iter()
+    .map(|passkey| {
+      let credential_id =
+        base64::prelude::BASE64_URL_SAFE_NO_PAD.encode(&passkey.credential_id);
+      view! {
+        <li class="flex flex-row items-center gap-4">
+          <p class="flex-1">
+            "Added " <TimeAgo time=passkey.created_at />
+            { passkey.last_used_at.map(|t| view! {
+              ", last used " <TimeAgo time=t />
+            }) }
+          </p>
+          { can_remove.then(|| view! {
+            <Button
+              color={ButtonColor::Base}
+              {..} on:click=move |_| {
+                remove_action.dispatch(credential_id.clone());
+              }
+            >
+              "Remove"
+            </Button>
+          }) }
+        </li>
+      }
+    })
+    .collect_view();
+
+  view! {
+    <div class="flex flex-col gap-4 max-w-md">
+      <p class="text-2xl">"Passkeys"</p>
+      <Prose>
+        "Log in with your fingerprint, face, or device PIN instead of a \
+         password."
+      </Prose>
+      <ul class="flex flex-col gap-2">{ passkey_list }</ul>
+      <div class="flex flex-row items-center gap-4">
+        <Button
+          disabled=add_pending
+          {..} on:click=move |_| { add_action.dispatch_local(()); }
+        >
+          "Add a Passkey"
+        </Button>
+        { move || action_result_view(add_value.get(), "Passkey added.") }
+        { move || action_result_view(remove_value.get(), "Passkey removed.") }
+      </div>
+    </div>
+    { has_password.then(|| view! { <RemovePassword /> }) }
+  }
+}
+
+#[component]
+fn RemovePassword() -> impl IntoView {
+  let current_password = RwSignal::new(None::<String>);
+
+  let action = Action::new(move |(): &()| {
+    remove_password(current_password.get().unwrap_or_default())
+  });
+  let action_value = action.value();
+  let action_pending = action.pending();
+
+  view! {
+    <div class="mt-4 flex flex-col gap-4 max-w-md">
+      <p class="text-lg">"Passkeys Only"</p>
+      <Prose>
+        "Remove your password so your account can only be logged into with \
+         a passkey. This also turns off two-factor authentication, which \
+         passkeys don't need. You can set a new password later with the \
+         forgot password link."
+      </Prose>
+      <SettingsField
+        field_id="remove_password_current_password"
+        field_label="Current Password" field_type="password"
+        signal=current_password
+      />
+      <div class="flex flex-row items-center gap-4">
+        <Button
+          color={ButtonColor::Base} disabled=action_pending
+          {..} on:click=move |_| { action.dispatch(()); }
+        >
+          "Remove Password"
+        </Button>
+        { move || action_result_view(action_value.get(), "Password removed.") }
+      </div>
+    </div>
+  }
+}
+
+#[island]
+fn TwoFactorForm(enabled: bool, confirmation: Confirmation) -> impl IntoView {
+  view! {
+    <div class="flex flex-col gap-4 max-w-md">
+      <p class="text-2xl">"Two-Factor Authentication"</p>
+      { if enabled {
+        view! { <DisableTwoFactor confirmation=confirmation /> }.into_any()
+      } else {
+        view! { <EnrollTwoFactor /> }.into_any()
+      } }
+    </div>
+  }
+}
+
+#[component]
+fn DisableTwoFactor(confirmation: Confirmation) -> impl IntoView {
+  let current_password = RwSignal::new(None::<String>);
+
+  let action = Action::new_local({
+    let confirmation = confirmation.clone();
+    move |(): &()| {
+      let proof = identity_proof(confirmation.clone(), current_password.get());
+      async move { disable_two_factor(proof.await?).await }
+    }
+  });
+  let action_value = action.value();
+  let action_pending = action.pending();
+
+  view! {
+    <Prose>
+      "Logging in asks for a code from your authenticator app."
+    </Prose>
+    <ConfirmIdentityField
+      field_id="two_factor_current_password" confirmation=confirmation
+      password=current_password
+    />
+    <div class="flex flex-row items-center gap-4">
+      <Button
+        color={ButtonColor::Base} disabled=action_pending
+        {..} on:click=move |_| { action.dispatch_local(()); }
+      >
+        "Turn Off"
+      </Button>
+      { move || action_result_view(
+        action_value.get(),
+        "Two-factor authentication turned off.",
+      ) }
+    </div>
+  }
+}
+
+#[component]
+fn EnrollTwoFactor() -> impl IntoView {
+  let code = RwSignal::new(None::<String>);
+
+  let begin_action = Action::new(move |(): &()| begin_totp_enrollment());
+  let begin_value = begin_action.value();
+  let begin_pending = begin_action.pending();
+
+  let confirm_action = Action::new(move |(): &()| {
+    confirm_totp_enrollment(code.get().unwrap_or_default())
+  });
+  let confirm_value = confirm_action.value();
+  let confirm_pending = confirm_action.pending();
+
+  let prompt_view = move || match begin_value.get() {
+    None => view! {
+      <div>
+        <Button
+          disabled=begin_pending
+          {..} on:click=move |_| { begin_action.dispatch(()); }
+        >
+          "Set Up"
+        </Button>
+      </div>
+    }
+    .into_any(),
+    Some(Ok(prompt)) => view! {
+      <Prose>
+        "Scan this QR code with your authenticator app, or enter the key \
+         below, then enter the code it shows."
+      </Prose>
+      <div class="size-48 bg-white p-2" inner_html=prompt.qr_code_svg />
+      <p class="font-mono text-sm break-all">{ prompt.secret }</p>
+      <SettingsField field_id="totp_code" field_label="Code" signal=code />
+      <div>
+        <Button
+          disabled=confirm_pending
+          {..} on:click=move |_| { confirm_action.dispatch(()); }
+        >
+          "Confirm"
+        </Button>
+      </div>
+    }
+    .into_any(),
+    Some(Err(e)) => view! {
+      <p class="text-danger-11 dark:text-dangerdark-11">{ e.to_string() }</p>
+    }
+    .into_any(),
+  };
+
+  let confirm_view = move || {
+    confirm_value.get().map(|v| match v {
+      Ok(recovery_codes) => view! {
+        <p class="text-success-11 dark:text-successdark-11">
+          "Two-factor authentication is on."
+        </p>
+        <Prose>
+          "Save these recovery codes somewhere safe. Each one logs you in once \
+           if you lose your authenticator app, and they won't be shown again."
+        </Prose>
+        <ul class="font-mono grid grid-cols-2 gap-1">
+          { recovery_codes
+            .into_iter()
+            .map(|c| view! { <li>{ c }</li> })
+            .collect_view() }
+        </ul>
+      }
+      .into_any(),
+      Err(e) => view! {
+        <p class="text-danger-11 dark:text-dangerdark-11">{ e.to_string() }</p>
+      }
+      .into_any(),
+    })
+  };
+
+  view! {
+    <Prose>
+      "Protect your account with a code from an authenticator app, on top of \
+       your password."
+    </Prose>
+    { move || {
+      let confirmed = matches!(confirm_value.get(), Some(Ok(_)));
+      (!confirmed).then(prompt_view)
+    } }
+    { confirm_view }
+  }
+}
+
+#[component]
+fn ExportDataSection() -> impl IntoView {
+  use lsc::link::*;
+
+  view! {
+    <div class="flex flex-col gap-4 max-w-md">
+      <p class="text-2xl">"Your Data"</p>
+      <Prose>
+        "Download an archive of your account details, your photo groups, and \
+         the original photos you uploaded."
+      </Prose>
+      <div>
+        <Link size=LinkSize::Medium underline={LinkUnderline::Always} {..}
+          href="/api/account/export" rel="external" download=""
+        >
+          "Download my data"
+        </Link>
+      </div>
+    </div>
+  }
+}
+
+#[island]
+fn DeleteAccountForm(confirmation: Confirmation) -> impl IntoView {
+  let current_password = RwSignal::new(None::<String>);
+
+  let action = Action::new_local({
+    let confirmation = confirmation.clone();
+    move |(): &()| {
+      let proof = identity_proof(confirmation.clone(), current_password.get());
+      async move { delete_account(proof.await?).await }
+    }
+  });
+  let action_value = action.value();
+  let action_pending = action.pending();
+
+  Effect::new(move |_| {
+    if matches!(action_value.get(), Some(Ok(()))) {
+      navigate_to("/");
+    }
+  });
+
+  view! {
+    <div class="flex flex-col gap-4 max-w-md">
+      <p class="text-2xl">"Delete Account"</p>
+      <Prose>
+        "This permanently deletes your account and logs you out everywhere. \
+         Photo groups you've offered for sale stay available to their buyers, \
+         without your name on them. Everything else you uploaded is deleted."
+      </Prose>
+      <ConfirmIdentityField
+        field_id="delete_current_password" confirmation=confirmation
+        password=current_password
+      />
+      <div class="flex flex-row items-center gap-4">
+        <Button
+          color={ButtonColor::Danger} disabled=action_pending
+          {..} on:click=move |_| { action.dispatch_local(()); }
+        >
+          "Delete My Account"
+        </Button>
+        { move || action_result_view(
+          action_value.get(),
+          "Account deleted.",
+        ) }
+      </div>
+    </div>
+  }
+}
@@ -0,0 +1,543 @@
+use std::collections::BTreeMap;
+
+use app_upload::{
+  validate_license_inputs, validate_price_input, AddPhotosButton,
+  LicensePriceInput, LicensePricingFields, MetadataFields,
+};
+use base_components::{utils::navigation::navigate_to, Section, Title};
+use leptos::{either::Either, prelude::*};
+use models::{
+  AuthStatus, Currency, ImageRecordId, LicenseOffer, LicenseTier, Money,
+  PhotoGroupConfig, PhotoGroupEdit, PhotoGroupFullQuery, PhotoGroupMetadata,
+  PhotoGroupRecordId, PhotoRecordId,
+};
+
+use crate::{
+  pages::{extract_photo_group_id, NotFoundPage},
+  server_fns::{
+    archive_photo_group, delete_photo_group, edit_photo_group,
+    fetch_photo_group, fetch_photo_original_download_url,
+  },
+};
+
+#[component]
+pub fn EditPhotoGroupPage() -> impl IntoView {
+  let id = extract_photo_group_id();
+
+  move || match id() {
+    Some(id) => Either::Left(view! { <EditPhotoGroupFetcher id=id /> }),
+    None => Either::Right(view! { <NotFoundPage /> }),
+  }
+}
+
+#[component]
+fn EditPhotoGroupFetcher(id: PhotoGroupRecordId) -> impl IntoView {
+  let auth_status: AuthStatus = expect_context();
+  let user = auth_status.0.map(|u| u.id);
+  let pg_resource = Resource::new(move || id, fetch_photo_group);
+
+  let suspended_fn = move || {
+    Suspend::new(async move {
+      match pg_resource.await {
+        // only the vendor may edit the group
+        Ok(Some(pgq)) if Some(pgq.photo_group.vendor) == user => {
+          view! { <EditPhotoGroupPageInner pgq=pgq /> }.into_any()
+        }
+        Ok(_) => view! { <NotFoundPage /> }.into_any(),
+        Err(e) => {
+          let e = e.to_string();
+          view! { "failed to fetch photo group: " {e} }.into_any()
+        }
+      }
+    })
+  };
+
+  view! {
+    <Suspense fallback=move || view! { "Loading..." }>
+      { suspended_fn }
+    </Suspense>
+  }
+}
+
+#[component]
+fn EditPhotoGroupPageInner(pgq: PhotoGroupFullQuery) -> impl IntoView {
+  let photo_group = pgq.photo_group;
+  let price_overrides = pgq
+    .photos
+    .into_iter()
+    .filter(|p| !p.price_overrides.is_empty())
+    .map(|p| (p.id, p.price_overrides))
+    .collect::<Vec<_>>();
+
+  view! {
+    <Section>
+      <Title>"Edit Photo Group"</Title>
+    </Section>
+    <PhotoGroupEditor
+      id=photo_group.id
+      licenses=photo_group.config.licenses
+      photos=photo_group.photos.clone()
+      price_overrides=price_overrides
+      meta=photo_group.meta
+      archived=photo_group.archived_at.is_some()
+    />
+    <OriginalDownloadLinks id=photo_group.id photos=photo_group.photos />
+  }
+}
+
+/// Computes the edits needed to get from the original photo group to the
+/// edited state.
+fn compute_edits(
+  original_photos: &[PhotoRecordId],
+  photos: Vec<PhotoRecordId>,
+  new_images: Vec<ImageRecordId>,
+  licenses: Vec<LicenseOffer>,
+  meta: PhotoGroupMetadata,
+  price_changes: Vec<PhotoPriceChange>,
+) -> Vec<PhotoGroupEdit> {
+  let mut edits = original_photos
+    .iter()
+    .filter(|p| !photos.contains(p))
+    .map(|p| PhotoGroupEdit::RemovePhoto(*p))
+    .collect::<Vec<_>>();
+  edits.push(PhotoGroupEdit::ReorderPhotos(photos));
+  if !new_images.is_empty() {
+    edits.push(PhotoGroupEdit::AddImages(new_images));
+  }
+  edits.push(PhotoGroupEdit::SetConfig(PhotoGroupConfig { licenses }));
+  edits.push(PhotoGroupEdit::SetMetadata(meta));
+  // photo prices are checked against the new config, so they go after it
+  edits.extend(price_changes.into_iter().map(|(photo, tier, price)| {
+    PhotoGroupEdit::SetPhotoPrice { photo, tier, price }
+  }));
+  edits
+}
+
+/// A change to a photo's price override for a license tier.
+type PhotoPriceChange = (PhotoRecordId, LicenseTier, Option<Money>);
+
+#[island]
+fn PhotoGroupEditor(
+  id: PhotoGroupRecordId,
+  licenses: Vec<LicenseOffer>,
+  photos: Vec<PhotoRecordId>,
+  price_overrides: Vec<(PhotoRecordId, BTreeMap<LicenseTier, Money>)>,
+  meta: PhotoGroupMetadata,
+  archived: bool,
+) -> impl IntoView {
+  use lsc::{button::*, icons::*};
+
+  // groups offered for sale may have been sold, so they can only be archived
+  let for_sale = !licenses.is_empty();
+  let original_photos = StoredValue::new(photos.clone());
+  let photos = RwSignal::new(photos);
+  let new_images = RwSignal::new(Vec::<ImageRecordId>::new());
+  let meta = RwSignal::new(meta);
+
+  let currency = RwSignal::new(
+    licenses
+      .first()
+      .map_or_else(Currency::default, |o| o.group_price.currency()),
+  );
+  let license_inputs = RwSignal::new(LicensePriceInput::from_offers(&licenses));
+  let validated_licenses = Memo::new(move |_| {
+    validate_license_inputs(&license_inputs.read(), currency())
+  });
+
+  let original_overrides =
+    StoredValue::new(price_overrides.into_iter().collect::<BTreeMap<_, _>>());
+  let override_inputs = RwSignal::new(
+    original_overrides
+      .read_value()
+      .iter()
+      .flat_map(|(photo, prices)| {
+        prices
+          .iter()
+          .map(|(tier, price)| ((*photo, *tier), price.to_decimal_string()))
+      })
+      .collect::<BTreeMap<_, _>>(),
+  );
+  // the overrides that differ from what's stored, for the offered tiers
+  let validated_overrides = Memo::new(move |_| {
+    let Ok(licenses) = validated_licenses() else {
+      return Ok(Vec::new());
+    };
+    let original = original_overrides.read_value();
+    let inputs = override_inputs.read();
+    let mut changes: Vec<PhotoPriceChange> = Vec::new();
+    for photo in photos.read().iter().copied() {
+      for tier in licenses.iter().map(|o| o.tier) {
+        let price = match inputs.get(&(photo, tier)).map(|i| i.trim()) {
+          None | Some("") => None,
+          Some(input) => Some(
+            validate_price_input(input, currency())
+              .map_err(|e| format!("{tier} photo price: {e}"))?,
+          ),
+        };
+        let previous = original.get(&photo).and_then(|p| p.get(&tier));
+        if price.as_ref() != previous {
+          changes.push((photo, tier, price));
+        }
+      }
+    }
+    Ok::<_, String>(changes)
+  });
+
+  let field_error_text = move || {
+    let error_text = validated_licenses()
+      .err()
+      .or_else(|| validated_overrides().err())?;
+    Some(view! {
+      <p class="text-sm text-dangera-11 dark:text-dangerdarka-11">
+        { error_text }
+      </p>
+    })
+  };
+
+  let on_uploaded = Callback::new(move |image_id| {
+    new_images.update(|i| i.push(image_id));
+  });
+
+  let move_photo = move |photo: PhotoRecordId, offset: isize| {
+    photos.update(|p| {
+      let Some(index) = p.iter().position(|x| *x == photo) else {
+        return;
+      };
+      let Some(target) = index.checked_add_signed(offset) else {
+        return;
+      };
+      if target < p.len() {
+        p.swap(index, target);
+      }
+    });
+  };
+  let remove_photo = move |photo: PhotoRecordId| {
+    photos.update(|p| p.retain(|x| *x != photo));
+  };
+
+  let action = Action::new(move |(): &()| {
+    let edits = compute_edits(
+      &original_photos.read_value(),
+      photos.get(),
+      new_images.get(),
+      validated_licenses.get().expect("licenses are not valid"),
+      meta.get(),
+      validated_overrides
+        .get()
+        .expect("photo prices are not valid"),
+    );
+    edit_photo_group(id, edits)
+  });
+
+  let ready_to_save = Memo::new(move |_| {
+    validated_licenses().is_ok()
+      && validated_overrides().is_ok()
+      && !(photos.read().is_empty() && new_images.read().is_empty())
+      && !action.pending().get()
+  });
+  let disabled_signal = Signal::derive(move || !ready_to_save());
+
+  Effect::watch(
+    move || action.value().get(),
+    move |value, _, _| {
+      if let Some(Ok(())) = value {
+        navigate_to(&format!("/photo-group/{id}"));
+      }
+    },
+    false,
+  );
+
+  let archive_action =
+    Action::new(move |archived: &bool| archive_photo_group(id, *archived));
+  let delete_action = Action::new(move |(): &()| delete_photo_group(id));
+  let confirming_delete = RwSignal::new(false);
+
+  Effect::watch(
+    move || archive_action.value().get(),
+    move |value, _, _| {
+      if let Some(Ok(())) = value {
+        // once unarchived, the group is visible again; show it
+        let path = if archived {
+          "/profile".to_owned()
+        } else {
+          format!("/photo-group/{id}")
+        };
+        navigate_to(&path);
+      }
+    },
+    false,
+  );
+  Effect::watch(
+    move || delete_action.value().get(),
+    move |value, _, _| {
+      if let Some(Ok(())) = value {
+        navigate_to("/profile");
+      }
+    },
+    false,
+  );
+
+  let delete_handler = move |_| {
+    if confirming_delete() {
+      delete_action.dispatch(());
+    } else {
+      confirming_delete.set(true);
+    }
+  };
+  let delete_text = move || {
+    if confirming_delete() {
+      "Click Again to Delete Permanently"
+    } else {
+      "Delete Permanently"
+    }
+  };
+
+  let action_error_text = move || {
+    let error = [
+      ("save changes", action.value().get()),
+      ("change archive status", archive_action.value().get()),
+      ("delete photo group", delete_action.value().get()),
+    ]
+    .into_iter()
+    .find_map(|(what, value)| match value {
+      Some(Err(e)) => Some(format!("Failed to {what}: {e}")),
+      _ => None,
+    })?;
+    Some(view! {
+      <p class="text-sm text-dangera-11 dark:text-dangerdarka-11">
+        { error }
+      </p>
+    })
+  };
+
+  let new_images_text = move || match new_images.read().len() {
+    0 => None,
+    1 => Some("1 new photo will be added.".to_owned()),
+    n => Some(format!("{n} new photos will be added.")),
+  };
+
+  view! {
+    <Section>
+      <div class="flex flex-col gap-4 sm:flex-row sm:items-end">
+        <div class="flex flex-col gap-2">
+          <LicensePricingFields inputs=license_inputs currency=currency />
+          { field_error_text }
+        </div>
+        <div class="hidden sm:block flex-1" />
+        <Button
+          size=ButtonSize::Large disabled=disabled_signal
+          {..} on:click=move |_| { action.dispatch(()); }
+        >
+          "Save Changes"
+        </Button>
+      </div>
+      { action_error_text }
+    </Section>
+
+    <Section>
+      <MetadataFields meta=meta />
+    </Section>
+
+    <Section>
+      <div class="flex flex-row flex-wrap gap-4">
+        <For
+          each=move || photos.get()
+          key=move |p| *p
+          children=move |p| view! {
+            <div class="flex flex-col gap-2 items-center">
+              <img
+                src=format!("/api/photo_thumbnail/{p}")
+                class="max-h-48 rounded-lg border border-base-7 dark:border-basedark-7"
+              />
+              <div class="flex flex-row gap-2">
+                <Button
+                  color=ButtonColor::Base size=ButtonSize::Small
+                  {..} on:click=move |_| move_photo(p, -1)
+                >
+                  <ArrowLeftIcon {..} class="size-4" />
+                </Button>
+                <Button
+                  color=ButtonColor::Base size=ButtonSize::Small
+                  {..} on:click=move |_| move_photo(p, 1)
+                >
+                  <ArrowRightIcon {..} class="size-4" />
+                </Button>
+                <Button
+                  color=ButtonColor::Danger size=ButtonSize::Small
+                  {..} on:click=move |_| remove_photo(p)
+                >
+                  <TrashIcon {..} class="size-4" />
+                </Button>
+              </div>
+              <PhotoPriceOverrides photo=p inputs=override_inputs />
+            </div>
+          }
+        />
+      </div>
+    </Section>
+
+    <Section>
+      <div class="flex flex-col gap-2">
+        <AddPhotosButton on_uploaded=on_uploaded />
+        <p class="text-sm text-base-dim">{ new_images_text }</p>
+      </div>
+    </Section>
+
+    <Section>
+      <div class="flex flex-col gap-2">
+        <p class="text-2xl">"Danger Zone"</p>
+        <p class="text-sm text-base-dim">
+          "Archiving hides the group from everyone but you, and can be undone."
+        </p>
+        <p class="text-sm text-base-dim">
+          { if for_sale {
+            "Groups offered for sale can't be deleted, so that buyers keep \
+            their photos."
+          } else {
+            "Deleting removes the group and its photos for good."
+          } }
+        </p>
+        <div class="flex flex-row flex-wrap gap-2">
+          <Button
+            color=ButtonColor::Warning
+            {..} on:click=move |_| { archive_action.dispatch(!archived); }
+          >
+            { if archived { "Unarchive" } else { "Archive" } }
+          </Button>
+          { (!for_sale).then(|| view! {
+            <Button
+              color=ButtonColor::Danger {..} on:click=delete_handler
+            >
+              { delete_text }
+              <TrashIcon {..} class="size-4" />
+            </Button>
+          }) }
+        </div>
+      </div>
+    </Section>
+  }
+}
+
+/// Lets the vendor create download links for the full-size originals, either
+/// for anyone with the link or bound to a buyer's account.
+#[island]
+fn OriginalDownloadLinks(
+  id: PhotoGroupRecordId,
+  photos: Vec<PhotoRecordId>,
+) -> impl IntoView {
+  use lsc::{button::*, field::*};
+
+  let buyer_email = RwSignal::new(String::new());
+  let links = RwSignal::new(BTreeMap::<PhotoRecordId, String>::new());
+  let action = Action::new(move |photo: &PhotoRecordId| {
+    let photo = *photo;
+    let buyer_email = Some(buyer_email.get()).filter(|e| !e.trim().is_empty());
+    async move {
+      let url =
+        fetch_photo_original_download_url(id, photo, buyer_email).await?;
+      links.update(|l| {
+        l.insert(photo, url);
+      });
+      Ok::<_, ServerFnError>(())
+    }
+  });
+
+  let pending = action.pending();
+
+  let error_text = move || match action.value().get() {
+    Some(Err(e)) => Some(view! {
+      <p class="text-sm text-dangera-11 dark:text-dangerdarka-11">
+        { format!("Failed to create download link: {e}") }
+      </p>
+    }),
+    _ => None,
+  };
+
+  let rows = photos
+    .into_iter()
+    .enumerate()
+    .map(|(index, photo)| {
+      let link = move || {
+        links.read().get(&photo).cloned().map(|url| {
+          view! {
+            <a href=url.clone() class="text-sm underline break-all">{ url }</a>
+          }
+        })
+      };
+      view! {
+        <div class="flex flex-row flex-wrap items-center gap-2">
+          <Button
+            color=ButtonColor::Base size=ButtonSize::Small
+            disabled=pending
+            {..} on:click=move |_| { action.dispatch(photo); }
+          >
+            { format!("Photo {}", index + 1) }
+          </Button>
+          { link }
+        </div>
+      }
+    })
+    .collect_view();
+
+  view! {
+    <Section>
+      <div class="flex flex-col gap-2">
+        <p class="text-2xl">"Original Downloads"</p>
+        <p class="text-sm text-base-dim">
+          "Links to the full-size originals expire after an hour. Leave the \
+          buyer email empty to make a link anyone can use."
+        </p>
+        <Field {..}
+          placeholder="Buyer email (optional)" type="email"
+          on:input=move |e| buyer_email.set(event_target_value(&e))
+          prop:value=move || buyer_email.get()
+        />
+        { rows }
+        { error_text }
+      </div>
+    </Section>
+  }
+}
+
+#[component]
+fn PhotoPriceOverrides(
+  photo: PhotoRecordId,
+  inputs: RwSignal<BTreeMap<(PhotoRecordId, LicenseTier), String>>,
+) -> impl IntoView {
+  use lsc::field::*;
+
+  let fields = LicenseTier::ALL
+    .iter()
+    .map(|&tier| {
+      let read = move || {
+        inputs
+          .read()
+          .get(&(photo, tier))
+          .cloned()
+          .unwrap_or_default()
+      };
+      let write = move |e| {
+        let value = event_target_value(&e);
+        inputs.update(|i| {
+          i.insert((photo, tier), value);
+        });
+      };
+      view! {
+        <label class="text-base-dim">{ tier.name() }</label>
+        <Field size={FieldSize::Small} {..}
+          placeholder="Default" type="text" inputmode="decimal"
+          on:input=write prop:value=read
+        />
+      }
+    })
+    .collect_view();
+
+  view! {
+    <details class="text-sm">
+      <summary class="cursor-pointer text-base-dim">"Custom Prices"</summary>
+      <div class="grid grid-cols-[auto_1fr] items-center gap-2 pt-2">
+        { fields }
+      </div>
+    </details>
+  }
+}
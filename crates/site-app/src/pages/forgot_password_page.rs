@@ -0,0 +1,77 @@
+use base_components::{
+  utils::inputs::touched_input_bindings, FloatingBoxSection, Prose,
+};
+use leptos::prelude::*;
+use lsc::{button::*, field::*};
+
+use crate::server_fns::request_password_reset;
+
+#[component]
+pub fn ForgotPasswordPage() -> impl IntoView {
+  view! { <ForgotPasswordPageIsland /> }
+}
+
+#[island]
+fn ForgotPasswordPageIsland() -> impl IntoView {
+  let email = RwSignal::new(None::<String>);
+  let (read_email_callback, write_email_callback) =
+    touched_input_bindings(email);
+
+  let action = Action::new(move |(): &()| {
+    request_password_reset(email.get().unwrap_or_default())
+  });
+  let action_value = action.value();
+  let action_pending = action.pending();
+  let action_value_view = move || {
+    action_value.get().map(|v| match v {
+      Ok(()) => view! {
+        <p class="text-success-11 dark:text-successdark-11">
+          "If that address has an account, we've sent it a link to reset \
+           your password."
+        </p>
+      }
+      .into_any(),
+      Err(e) => view! {
+        <p class="text-danger-11 dark:text-dangerdark-11">{ e.to_string() }</p>
+      }
+      .into_any(),
+    })
+  };
+
+  view! {
+    <FloatingBoxSection>
+      <p class="text-3xl font-serif font-semibold tracking-tight">
+        "Forgot your password?"
+      </p>
+
+      <Prose>
+        "Enter your email and we'll send you a link to choose a new one."
+      </Prose>
+
+      <form class="mt-2 mb-4 flex flex-col gap-4">
+        <div class="flex flex-col gap-1">
+          <label class="" for="email">"Email"</label>
+          <Field size={FieldSize::Large} {..}
+            placeholder="Enter your email" type="email" id="email"
+            on:input=write_email_callback prop:value=read_email_callback
+          />
+        </div>
+      </form>
+
+      <div class="flex flex-row">
+        <div class="flex-1" />
+        <Button
+          size={ButtonSize::Large} disabled=action_pending
+          {..} on:click={move |_| {action.dispatch(());}}
+        >
+          "Send Link"
+          <lsc::icons::ArrowRightIcon {..} class="size-5" />
+        </Button>
+      </div>
+
+      { move || action_value_view().map(|v| view! {
+        <div class="self-center mt-4">{ v }</div>
+      })}
+    </FloatingBoxSection>
+  }
+}
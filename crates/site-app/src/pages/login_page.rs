@@ -7,19 +7,57 @@ use base_components::{
 };
 use leptos::prelude::*;
 use lsc::{button::*, field::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  components::OidcLoginLinks,
+  passkey::get_passkey,
+  server_fns::{begin_passkey_login, finish_passkey_login, request_magic_link},
+};
+
+/// The outcome of a login attempt.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LoginOutcome {
+  /// The user is logged in.
+  LoggedIn,
+  /// The email, password, or second factor code was wrong.
+  InvalidCredentials,
+  /// The password was right, but the account needs a second factor code.
+  SecondFactorRequired,
+  /// A login link was emailed, if the address can use one.
+  LinkSent,
+  /// There were too many recent attempts.
+  Throttled {
+    /// How many seconds to wait before trying again.
+    retry_after: u64,
+  },
+}
 
 #[component]
 pub fn LoginPage() -> impl IntoView {
   let query = leptos_router::hooks::use_query_map();
   let next_url = Signal::derive(move || query().get("next"));
+  let provider_failed =
+    query.with_untracked(|q| q.get("error").as_deref() == Some("provider"));
 
   view! {
-    <LoginPageIsland next_url={next_url.get_untracked()} />
+    <LoginPageIsland next_url={next_url.get_untracked()}>
+      { provider_failed.then(|| view! {
+        <p class="mt-4 self-center text-danger-11 dark:text-dangerdark-11">
+          "Logging in with that provider didn't work. Try again, or log in \
+           another way."
+        </p>
+      }) }
+      <OidcLoginLinks next_url={next_url.get_untracked()} />
+    </LoginPageIsland>
   }
 }
 
 #[island]
-pub fn LoginPageIsland(next_url: Option<String>) -> impl IntoView {
+pub fn LoginPageIsland(
+  next_url: Option<String>,
+  children: Children,
+) -> impl IntoView {
   use lsc::link::*;
 
   let signup_url = format!(
@@ -33,37 +71,96 @@ pub fn LoginPageIsland(next_url: Option<String>) -> impl IntoView {
 
   let email = RwSignal::new(None::<String>);
   let password = RwSignal::new(None::<String>);
+  let code = RwSignal::new(None::<String>);
+  let needs_code = RwSignal::new(false);
 
   let (read_email_callback, write_email_callback) =
     touched_input_bindings(email);
   let (read_password_callback, write_password_callback) =
     touched_input_bindings(password);
+  let (read_code_callback, write_code_callback) = touched_input_bindings(code);
 
   let action = Action::new(move |(): &()| {
     login(
       email.get().unwrap_or_default(),
       password.get().unwrap_or_default(),
+      needs_code.get().then(|| code.get().unwrap_or_default()),
     )
   });
   let action_value = action.value();
-  let action_value_view = move || {
-    action_value.get().map(|v| match v {
-      Ok(true) => leptos::either::EitherOf3::A(view! {
+
+  let passkey_action = Action::new_local(move |(): &()| async move {
+    let options = begin_passkey_login().await?;
+    let assertion = get_passkey(&options).await.map_err(ServerFnError::new)?;
+    Ok::<_, ServerFnError>(if finish_passkey_login(assertion).await? {
+      LoginOutcome::LoggedIn
+    } else {
+      LoginOutcome::InvalidCredentials
+    })
+  });
+  let passkey_action_value = passkey_action.value();
+
+  let link_action = Action::new(move |(): &()| {
+    let email = email.get().unwrap_or_default();
+    async move {
+      request_magic_link(email).await?;
+      Ok::<_, ServerFnError>(LoginOutcome::LinkSent)
+    }
+  });
+  let link_action_value = link_action.value();
+
+  // the latest outcome of any way of logging in
+  let outcome = RwSignal::new(None::<Result<LoginOutcome, ServerFnError>>);
+  Effect::new(move |_| {
+    if let Some(v) = action_value.get() {
+      outcome.set(Some(v));
+    }
+  });
+  Effect::new(move |_| {
+    if let Some(v) = passkey_action_value.get() {
+      outcome.set(Some(v));
+    }
+  });
+  Effect::new(move |_| {
+    if let Some(v) = link_action_value.get() {
+      outcome.set(Some(v));
+    }
+  });
+
+  let outcome_view = move || {
+    outcome.get().map(|v| match v {
+      Ok(LoginOutcome::LoggedIn) => leptos::either::EitherOf6::A(view! {
         <p class="text-success-11 dark:text-successdark-11">"Logging in..."</p>
       }),
-      Ok(false) => leptos::either::EitherOf3::B(view! {
+      Ok(LoginOutcome::InvalidCredentials) => leptos::either::EitherOf6::B(view! {
         <p class="text-warning-11 dark:text-warningdark-11">"Invalid credentials."</p>
       }),
-      Err(e) => leptos::either::EitherOf3::C(view! {
+      Ok(LoginOutcome::SecondFactorRequired) => leptos::either::EitherOf6::C(view! {
+        <p class="text-base-dim">
+          "Enter the code from your authenticator app, or a recovery code."
+        </p>
+      }),
+      Ok(LoginOutcome::LinkSent) => leptos::either::EitherOf6::D(view! {
+        <p class="text-base-dim">
+          "Check your email for a login link. If you don't have an account, \
+           the link creates one."
+        </p>
+      }),
+      Ok(LoginOutcome::Throttled { retry_after }) => leptos::either::EitherOf6::E(view! {
+        <p class="text-warning-11 dark:text-warningdark-11">
+          { format!("Too many login attempts. Try again in {retry_after} seconds.") }
+        </p>
+      }),
+      Err(e) => leptos::either::EitherOf6::F(view! {
         <p class="text-danger-11 dark:text-dangerdark-11">{ e.to_string() }</p>
       }),
     })
   };
 
-  Effect::new(move |_| {
-    if matches!(action_value.get(), Some(Ok(true))) {
-      navigate_to(&next_url);
-    }
+  Effect::new(move |_| match outcome.get() {
+    Some(Ok(LoginOutcome::LoggedIn)) => navigate_to(&next_url),
+    Some(Ok(LoginOutcome::SecondFactorRequired)) => needs_code.set(true),
+    _ => (),
   });
 
   view! {
@@ -96,9 +193,22 @@ pub fn LoginPageIsland(next_url: Option<String>) -> impl IntoView {
             on:input=write_password_callback prop:value=read_password_callback
           />
         </div>
+
+        <div class=move || {
+          if needs_code.get() { "flex flex-col gap-1" } else { "hidden" }
+        }>
+          <label class="" for="code">"Authentication Code"</label>
+          <Field size={FieldSize::Large} {..}
+            placeholder="123456" id="code" autocomplete="one-time-code"
+            on:input=write_code_callback prop:value=read_code_callback
+          />
+        </div>
       </form>
 
-      <div class="flex flex-row">
+      <div class="flex flex-row items-center">
+        <Link size=LinkSize::Medium underline={LinkUnderline::Hover} {..} href="/forgot-password">
+          "Forgot password?"
+        </Link>
         <div class="flex-1" />
         <Button size={ButtonSize::Large} {..} on:click={move |_| {action.dispatch(());}}>
           "Log in"
@@ -106,7 +216,25 @@ pub fn LoginPageIsland(next_url: Option<String>) -> impl IntoView {
         </Button>
       </div>
 
-      { move || action_value_view().map(|v| view! {
+      <div class="mt-4 flex flex-col items-center gap-2">
+        <p class="text-base-dim">"or"</p>
+        <Button
+          color={ButtonColor::Base} size={ButtonSize::Large}
+          {..} on:click={move |_| {passkey_action.dispatch_local(());}}
+        >
+          "Log in with a passkey"
+        </Button>
+        <Button
+          color={ButtonColor::Base} size={ButtonSize::Large}
+          {..} on:click={move |_| {link_action.dispatch(());}}
+        >
+          "Email me a login link"
+        </Button>
+      </div>
+
+      { children() }
+
+      { move || outcome_view().map(|v| view! {
         <div class="self-center mt-4">{ v }</div>
       })}
     </FloatingBoxSection>
@@ -114,10 +242,16 @@ pub fn LoginPageIsland(next_url: Option<String>) -> impl IntoView {
 }
 
 #[server(name = LoginActionParams)]
-#[tracing::instrument]
-async fn login(email: String, password: String) -> Result<bool, ServerFnError> {
-  use auth_domain::{AuthDomainService, AuthSession};
-  use models::{AuthUser, EmailAddress, UserSubmittedAuthCredentials};
+#[tracing::instrument(skip(password, code))]
+async fn login(
+  email: String,
+  password: String,
+  code: Option<String>,
+) -> Result<LoginOutcome, ServerFnError> {
+  use auth_domain::{AuthDomainService, AuthSession, AuthenticationError};
+  use models::{
+    AuthUser, ClientIp, EmailAddress, UserSubmittedAuthCredentials,
+  };
 
   let auth_service = use_context::<AuthDomainService>().ok_or_else(|| {
     tracing::error!("auth service not found");
@@ -132,16 +266,33 @@ async fn login(email: String, password: String) -> Result<bool, ServerFnError> {
   let email = EmailAddress::try_new(email)
     .map_err(|_| ServerFnError::new("Email address is invalid"))?;
 
-  let creds =
-    UserSubmittedAuthCredentials::EmailAndPassword { email, password };
+  let creds = match code {
+    Some(code) => UserSubmittedAuthCredentials::EmailPasswordAndCode {
+      email,
+      password,
+      code,
+    },
+    None => UserSubmittedAuthCredentials::EmailAndPassword { email, password },
+  };
 
-  let user = auth_service.user_authenticate(creds).await.map_err(|e| {
-    tracing::error!("failed to fetch user: {e}");
-    ServerFnError::new("Internal error")
-  })?;
+  let client = use_context::<ClientIp>().and_then(|c| c.0);
+
+  let user = match auth_service.user_authenticate_from(creds, client).await {
+    Ok(user) => user,
+    Err(AuthenticationError::SecondFactorRequired) => {
+      return Ok(LoginOutcome::SecondFactorRequired);
+    }
+    Err(AuthenticationError::Throttled { retry_after }) => {
+      return Ok(LoginOutcome::Throttled { retry_after });
+    }
+    Err(e) => {
+      tracing::error!("failed to fetch user: {e}");
+      return Err(ServerFnError::new("Internal error"));
+    }
+  };
 
   let Some(user) = user else {
-    return Ok(false);
+    return Ok(LoginOutcome::InvalidCredentials);
   };
   let public_user = AuthUser::from(user);
 
@@ -150,5 +301,5 @@ async fn login(email: String, password: String) -> Result<bool, ServerFnError> {
     ServerFnError::new("Internal error")
   })?;
 
-  Ok(true)
+  Ok(LoginOutcome::LoggedIn)
 }
@@ -0,0 +1,80 @@
+use base_components::{
+  utils::navigation::navigate_to, FloatingBoxSection, Prose,
+};
+use leptos::prelude::*;
+use leptos_router::hooks::use_params_map;
+use lsc::{button::*, link::*};
+
+use crate::server_fns::log_in_with_magic_link;
+
+#[component]
+pub fn MagicLinkPage() -> impl IntoView {
+  let params = use_params_map();
+  let token = params.read_untracked().get("token").unwrap_or_default();
+
+  view! { <MagicLinkPageIsland token=token /> }
+}
+
+/// Logs in when the button is pressed, rather than on load, because mail
+/// scanners open links to check them and would use the token up.
+#[island]
+fn MagicLinkPageIsland(token: String) -> impl IntoView {
+  let action =
+    Action::new(move |(): &()| log_in_with_magic_link(token.clone()));
+  let action_value = action.value();
+  let action_pending = action.pending();
+
+  Effect::new(move |_| {
+    if let Some(Ok(true)) = action_value.get() {
+      navigate_to("/");
+    }
+  });
+
+  let action_value_view = move || {
+    action_value.get().map(|v| match v {
+      Ok(true) => view! {
+        <p class="text-success-11 dark:text-successdark-11">"Logging in..."</p>
+      }
+      .into_any(),
+      Ok(false) => view! {
+        <p class="text-warning-11 dark:text-warningdark-11">
+          "This login link is invalid, expired, or already used. "
+          <Link {..} href="/log-in">"Ask for another"</Link>
+          "."
+        </p>
+      }
+      .into_any(),
+      Err(e) => view! {
+        <p class="text-danger-11 dark:text-dangerdark-11">{ e.to_string() }</p>
+      }
+      .into_any(),
+    })
+  };
+
+  view! {
+    <FloatingBoxSection>
+      <p class="text-3xl font-serif font-semibold tracking-tight">
+        "Log in to PicturePro"
+      </p>
+
+      <Prose>
+        "If you don't have an account yet, logging in creates one."
+      </Prose>
+
+      <div class="mt-4 flex flex-row">
+        <div class="flex-1" />
+        <Button
+          size={ButtonSize::Large} disabled=action_pending
+          {..} on:click={move |_| {action.dispatch(());}}
+        >
+          "Log in"
+          <lsc::icons::ArrowRightIcon {..} class="size-5" />
+        </Button>
+      </div>
+
+      { move || action_value_view().map(|v| view! {
+        <div class="self-center mt-4">{ v }</div>
+      })}
+    </FloatingBoxSection>
+  }
+}
@@ -1,13 +1,21 @@
+mod account_settings_page;
+mod edit_photo_group_page;
+mod forgot_password_page;
 mod home_page;
 mod login_page;
 mod logout_page;
+mod magic_link_page;
 mod not_found_page;
 mod photo_group_page;
 mod profile_page;
 mod protected_page;
+mod reset_password_page;
 mod signup_page;
+mod verify_email_page;
 
 pub use self::{
-  home_page::*, login_page::*, logout_page::*, not_found_page::*,
-  photo_group_page::*, profile_page::*, protected_page::*, signup_page::*,
+  account_settings_page::*, edit_photo_group_page::*, forgot_password_page::*,
+  home_page::*, login_page::*, logout_page::*, magic_link_page::*,
+  not_found_page::*, photo_group_page::*, profile_page::*, protected_page::*,
+  reset_password_page::*, signup_page::*, verify_email_page::*,
 };
@@ -1,15 +1,21 @@
 use std::str::FromStr;
 
 use base_components::{PhotoGroupQrCode, Section, Title};
+use chrono::NaiveDate;
 use leptos::{either::Either, prelude::*};
 use leptos_router::hooks::use_params_map;
-use models::{PhotoGroupFullQuery, PhotoGroupRecordId};
+use models::{
+  LicenseOffer, Locale, Money, Photo, PhotoGroupFullQuery, PhotoGroupMetadata,
+  PhotoGroupRecordId,
+};
 
 use crate::{
-  components::PhotoPreview, pages::NotFoundPage, server_fns::fetch_photo_group,
+  components::{PhotoPreview, RichText, TimeAgo},
+  pages::NotFoundPage,
+  server_fns::fetch_photo_group,
 };
 
-fn extract_photo_group_id() -> Signal<Option<PhotoGroupRecordId>> {
+pub(crate) fn extract_photo_group_id() -> Signal<Option<PhotoGroupRecordId>> {
   let params = use_params_map();
   Signal::derive(move || {
     let params_guard = params.read();
@@ -56,13 +62,24 @@ pub fn PhotoGroupFetcher(id: PhotoGroupRecordId) -> impl IntoView {
 #[component]
 pub fn PhotoGroupPageInner(pgq: PhotoGroupFullQuery) -> impl IntoView {
   let name = pgq.vendor_data.name.as_ref().to_owned();
+  let title = pgq
+    .photo_group
+    .meta
+    .title
+    .clone()
+    .unwrap_or_else(|| format!("Photos by {name}"));
+  let description = pgq
+    .photo_group
+    .meta
+    .description
+    .clone()
+    .map(|d| view! { <RichText source=d /> });
 
   view! {
     <Section>
-      <Title>
-        "Photos by " { name }
-      </Title>
+      <Title>{ title }</Title>
     </Section>
+    { description.map(|d| view! { <Section>{ d }</Section> }) }
     <Section>
       <PhotoGroupDetails pgq=pgq />
     </Section>
@@ -73,11 +90,16 @@ pub fn PhotoGroupPageInner(pgq: PhotoGroupFullQuery) -> impl IntoView {
 pub fn PhotoGroupDetails(pgq: PhotoGroupFullQuery) -> impl IntoView {
   use lsc::{button::*, icons::*};
 
+  let created_at = pgq.photo_group.created_at;
+  let vendor_name = pgq.vendor_data.name.as_ref().to_owned();
+  let archived_notice = pgq.photo_group.is_archived().then_some(view! {
+    <p class="text-warninga-11 dark:text-warningdarka-11">
+      "This photo group is archived, and is only visible to you."
+    </p>
+  });
   let photo_previews = pgq
-    .photo_group
-    .photos
-    .into_iter()
-    .map(|p| view! { <PhotoPreview id=p /> })
+    .photos_with_thumbnails()
+    .map(|(p, t)| view! { <PhotoPreview id=p.id thumbnail=t.clone() /> })
     .collect_view();
 
   view! {
@@ -89,12 +111,14 @@ pub fn PhotoGroupDetails(pgq: PhotoGroupFullQuery) -> impl IntoView {
       // <div class="my-4 w-[1px] border-l-2 border-dashed border-base-8 dark:border-basedark-8" />
 
       <div class="sm:min-w-64 flex flex-col gap-6 p-2 pb-8 sm:p-0 sm:pl-4 border-b-2 sm:border-b-0 sm:border-l-2 border-base-8 dark:border-basedark-8">
-        <p class="text-3xl">
-          "Price: "
-          <span class="font-bold">
-            { pgq.photo_group.config.usage_rights_price.to_string() }
-          </span>
+        <LicenseChoices
+          licenses=pgq.photo_group.config.licenses photos=pgq.photos
+        />
+        <p class="text-basea-11 dark:text-basedarka-11">
+          "Uploaded " <TimeAgo time=created_at /> " by " { vendor_name }
         </p>
+        <PhotoGroupEventDetails meta=pgq.photo_group.meta />
+        { archived_notice }
 
         <div class="flex flex-col gap-2">
           <Button
@@ -122,3 +146,101 @@ pub fn PhotoGroupDetails(pgq: PhotoGroupFullQuery) -> impl IntoView {
     </div>
   }
 }
+
+/// Formats an event date for display, e.g. "March 4, 2025".
+pub(crate) fn format_event_date(date: NaiveDate) -> String {
+  date.format("%B %-d, %Y").to_string()
+}
+
+#[component]
+fn PhotoGroupEventDetails(meta: PhotoGroupMetadata) -> impl IntoView {
+  let event_date = meta.event_date.map(|d| {
+    view! { <p>"Taken " { format_event_date(d) }</p> }
+  });
+  let location = meta.location.map(|l| view! { <p>"At " { l }</p> });
+  let tags = (!meta.tags.is_empty()).then(|| {
+    let chips = meta
+      .tags
+      .into_iter()
+      .map(|t| {
+        view! {
+          <span class="px-2 py-0.5 rounded-full text-sm bg-base-3 dark:bg-basedark-3 border border-base-6 dark:border-basedark-6">
+            { t }
+          </span>
+        }
+      })
+      .collect_view();
+    view! { <div class="flex flex-row flex-wrap gap-2">{ chips }</div> }
+  });
+
+  view! {
+    <div class="flex flex-col gap-2 text-basea-11 dark:text-basedarka-11">
+      { event_date }
+      { location }
+      { tags }
+    </div>
+  }
+}
+
+/// The range of single-photo prices under a license offer, if any photo is
+/// sold alone.
+fn photo_price_range(
+  offer: &LicenseOffer,
+  photos: &[Photo],
+  locale: &Locale,
+) -> Option<String> {
+  let prices = photos.iter().filter_map(|p| p.price(offer));
+  let min = prices.clone().min_by_key(Money::minor_units)?;
+  let max = prices.max_by_key(Money::minor_units)?;
+  let (min, max) = (min.format_locale(&locale.0), max.format_locale(&locale.0));
+  Some(if min == max {
+    min
+  } else {
+    format!("{min} – {max}")
+  })
+}
+
+#[component]
+fn LicenseChoices(
+  licenses: Vec<LicenseOffer>,
+  photos: Vec<Photo>,
+) -> impl IntoView {
+  if licenses.is_empty() {
+    return view! { <p class="text-3xl">"Not for sale"</p> }.into_any();
+  }
+
+  let locale = use_context::<Locale>().unwrap_or_default();
+  let choices = licenses
+    .into_iter()
+    .map(|offer| {
+      let photo_prices =
+        photo_price_range(&offer, &photos, &locale).map(|range| {
+          view! {
+            <p class="text-basea-11 dark:text-basedarka-11">
+              "Single photos: " { range }
+            </p>
+          }
+        });
+      view! {
+        <div class="flex flex-col gap-1">
+          <p class="text-xl">
+            { offer.tier.name() } ": "
+            <span class="font-bold">
+              { offer.group_price.format_locale(&locale.0) }
+            </span>
+          </p>
+          <p class="text-sm text-base-dim">{ offer.tier.description() }</p>
+          { photo_prices }
+        </div>
+      }
+    })
+    .collect_view();
+
+  view! {
+    <div class="flex flex-col gap-4">
+      <p class="text-3xl">"Licenses"</p>
+      { choices }
+    </div>
+  }
+  .into_any()
+}
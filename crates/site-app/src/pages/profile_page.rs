@@ -1,34 +1,79 @@
-use base_components::{Prose, Section, Title};
+use base_components::{
+  utils::navigation::{navigate_to, reload},
+  Prose, Section, Title,
+};
 use leptos::prelude::*;
-use models::PhotoGroup;
+use models::{ActiveSession, Locale, PhotoGroup};
 
 use crate::{
-  components::PhotoPreview, server_fns::fetch_photo_groups_for_user,
+  components::{PhotoPreviewFetcher, TimeAgo},
+  pages::{format_event_date, EmailVerificationNotice},
+  server_fns::{
+    fetch_active_sessions, fetch_photo_groups_for_user, log_out_everywhere,
+    revoke_session,
+  },
 };
 
 #[component]
 pub fn PhotoGroupPreview(pg: PhotoGroup) -> impl IntoView {
-  use lsc::link::*;
+  use lsc::{icons::*, link::*};
 
   let class = "bg-base-2 dark:bg-basedark-2 border border-base-7 \
                dark:border-basedark-7 rounded-lg flex flex-col p-4 gap-4 \
                shadow-md";
 
-  let price = format!("Price: {}", pg.config.usage_rights_price);
+  let locale = use_context::<Locale>().unwrap_or_default();
+  let price = match pg.config.lowest_group_price() {
+    Some(price) => format!("From {}", price.format_locale(&locale.0)),
+    None => "Not for sale".to_owned(),
+  };
   let url = format!("/photo-group/{}", pg.id);
+  let edit_url = format!("/photo-group/{}/edit", pg.id);
+  let created_at = pg.created_at;
+  let title = pg.meta.title.clone().map(|t| {
+    view! { <p class="text-xl">{ t }</p> }
+  });
+  let event_details = [
+    pg.meta.event_date.map(format_event_date),
+    pg.meta.location.clone(),
+  ]
+  .into_iter()
+  .flatten()
+  .collect::<Vec<_>>();
+  let event_details = (!event_details.is_empty()).then(|| {
+    view! {
+      <p class="text-sm text-basea-11 dark:text-basedarka-11">
+        { event_details.join(" · ") }
+      </p>
+    }
+  });
 
   view! {
     <div class=class>
-      <Link
-        color=LinkColor::Primary size=LinkSize::ExtraLarge
-        underline=LinkUnderline::Always attr:href=url
-      >{ price }</Link>
+      <div class="flex flex-row justify-between items-center gap-4">
+        <Link
+          color=LinkColor::Primary size=LinkSize::ExtraLarge
+          underline=LinkUnderline::Always attr:href=url
+        >{ price }</Link>
+        <Link
+          color=LinkColor::Base size=LinkSize::Medium
+          underline=LinkUnderline::Hover attr:href=edit_url
+        >
+          "Edit"
+          <Pencil1Icon {..} class="size-4 inline ml-1" />
+        </Link>
+      </div>
+      { title }
+      { event_details }
+      <p class="text-sm text-basea-11 dark:text-basedarka-11">
+        "Uploaded " <TimeAgo time=created_at />
+      </p>
       <div class="flex flex-row flex-wrap gap-4">
         <For
           each=move || pg.photos.clone()
           key=move |p| *p
           children=move |p| view! {
-            <PhotoPreview id=p />
+            <PhotoPreviewFetcher id=p />
           }
         />
       </div>
@@ -37,15 +82,23 @@ pub fn PhotoGroupPreview(pg: PhotoGroup) -> impl IntoView {
 }
 
 #[component]
-pub fn ProfilePhotoGroupPreview() -> impl IntoView {
-  let photo_groups =
-    Resource::new(move || (), move |()| fetch_photo_groups_for_user());
+pub fn ProfilePhotoGroupPreview(
+  #[prop(optional)] archived: bool,
+) -> impl IntoView {
+  let photo_groups = Resource::new(
+    move || archived,
+    move |archived| fetch_photo_groups_for_user(archived),
+  );
 
   let suspended_fn = move || {
     Suspend::new(async move {
       match photo_groups.await {
+        Ok(pgs) if archived && pgs.is_empty() => ().into_any(),
         Ok(pgs) => view! {
           <div class="flex flex-col gap-4">
+            { archived.then_some(view! {
+              <p class="text-2xl">"Archived"</p>
+            }) }
             { pgs.into_iter().map(|pg| view! { <PhotoGroupPreview pg=pg /> }).collect_view() }
           </div>
         }
@@ -67,15 +120,23 @@ pub fn ProfilePhotoGroupPreview() -> impl IntoView {
 
 #[component]
 pub fn ProfilePage() -> impl IntoView {
+  use lsc::link::*;
+
   view! {
     <Section>
       <Title>"User Profile"</Title>
     </Section>
+    <Section>
+      <EmailVerificationNotice />
+    </Section>
     <Section>
       <div class="flex flex-row gap-2 justify-between">
         <div class="space-y-4">
-          <Prose>"We've got nothing else to display here right now."</Prose>
-          <Prose>"Lorem ipsum dolor sit amet, consectetur adipiscing elit. Sed do eiusmod tempor incididunt ut labore et dolore magna aliqua."</Prose>
+          <Prose>"Your photo groups are below, newest first."</Prose>
+          <Prose>
+            "To change your name, email or password, visit your "
+            <Link {..} href="/settings">"account settings"</Link> "."
+          </Prose>
         </div>
         <UploadPhotoButton />
       </div>
@@ -84,6 +145,181 @@ pub fn ProfilePage() -> impl IntoView {
     <Section>
       <ProfilePhotoGroupPreview />
     </Section>
+
+    <Section>
+      <ProfilePhotoGroupPreview archived=true />
+    </Section>
+
+    <Section>
+      <ActiveSessions />
+    </Section>
+  }
+}
+
+/// A rough name for the device behind a `User-Agent`, e.g. "Firefox on
+/// Linux".
+fn device_name(user_agent: Option<&str>) -> String {
+  let Some(user_agent) = user_agent else {
+    return "Unknown device".to_owned();
+  };
+  // order matters: most browsers claim to be Safari, and Chromium-based
+  // ones claim to be Chrome
+  let browser = [
+    ("Edg/", "Edge"),
+    ("OPR/", "Opera"),
+    ("Firefox/", "Firefox"),
+    ("Chrome/", "Chrome"),
+    ("Safari/", "Safari"),
+  ]
+  .into_iter()
+  .find(|(token, _)| user_agent.contains(token))
+  .map_or("Unknown browser", |(_, name)| name);
+  let os = [
+    ("Android", "Android"),
+    ("iPhone", "iOS"),
+    ("iPad", "iPadOS"),
+    ("Windows", "Windows"),
+    ("Mac OS X", "macOS"),
+    ("CrOS", "ChromeOS"),
+    ("Linux", "Linux"),
+  ]
+  .into_iter()
+  .find(|(token, _)| user_agent.contains(token))
+  .map(|(_, name)| name);
+
+  match os {
+    Some(os) => format!("{browser} on {os}"),
+    None => browser.to_owned(),
+  }
+}
+
+#[component]
+fn ActiveSessions() -> impl IntoView {
+  let sessions = Resource::new(|| (), |()| fetch_active_sessions());
+
+  let suspended_fn = move || {
+    Suspend::new(async move {
+      match sessions.await {
+        Ok(sessions) => {
+          view! { <ActiveSessionsList sessions=sessions /> }.into_any()
+        }
+        Err(e) => {
+          let e = e.to_string();
+          view! { "failed to fetch sessions: " {e} }.into_any()
+        }
+      }
+    })
+  };
+
+  view! {
+    <div class="flex flex-col gap-4 max-w-xl">
+      <p class="text-2xl">"Where You're Logged In"</p>
+      <Suspense fallback=move || view! { "Loading..." }>
+        { suspended_fn }
+      </Suspense>
+    </div>
+  }
+}
+
+#[island]
+fn ActiveSessionsList(sessions: Vec<ActiveSession>) -> impl IntoView {
+  use lsc::button::*;
+
+  let revoke_action =
+    Action::new(move |handle: &String| revoke_session(handle.clone()));
+  let revoke_value = revoke_action.value();
+
+  let everywhere_action = Action::new(move |(): &()| log_out_everywhere());
+  let everywhere_value = everywhere_action.value();
+  let everywhere_pending = everywhere_action.pending();
+
+  Effect::new(move |_| {
+    if matches!(revoke_value.get(), Some(Ok(()))) {
+      reload();
+    }
+  });
+  Effect::new(move |_| {
+    if matches!(everywhere_value.get(), Some(Ok(()))) {
+      navigate_to("/login");
+    }
+  });
+
+  let error_view = move || {
+    [revoke_value.get(), everywhere_value.get()]
+      .into_iter()
+      .flatten()
+      .find_map(Result::err)
+      .map(|e| {
+        view! {
+          <p class="text-danger-11 dark:text-dangerdark-11">{ e.to_string() }</p>
+        }
+      })
+  };
+
+  let session_list = sessions
+    .into_iter()
+    .map(|session| {
+      let Some(activity) = session.activity else {
+        return view! {
+          <li class="flex flex-col">
+            <p>"Unknown device"</p>
+            <p class="text-sm text-basea-11 dark:text-basedarka-11">
+              "Not used recently"
+            </p>
+          </li>
+        }
+        .into_any();
+      };
+      let name = device_name(activity.user_agent.as_deref());
+      let ip = activity.ip.map(|ip| format!(" · {ip}"));
+      let handle = activity.handle;
+
+      view! {
+        <li class="flex flex-row items-center gap-4">
+          <div class="flex-1 flex flex-col">
+            <p>{ name }</p>
+            <p class="text-sm text-basea-11 dark:text-basedarka-11">
+              { if session.current {
+                view! { "This device" }.into_any()
+              } else {
+                view! { "Last active " <TimeAgo time=activity.last_seen_at /> }
+                  .into_any()
+              } }
+              { ip }
+              " · Logged in " <TimeAgo time=activity.first_seen_at />
+            </p>
+          </div>
+          { (!session.current).then(|| view! {
+            <Button
+              color={ButtonColor::Base}
+              {..} on:click=move |_| {
+                revoke_action.dispatch(handle.clone());
+              }
+            >
+              "Log Out"
+            </Button>
+          }) }
+        </li>
+      }
+      .into_any()
+    })
+    .collect_view();
+
+  view! {
+    <Prose>
+      "These are the browsers and devices logged into your account. If you \
+       don't recognize one, log it out and change your password."
+    </Prose>
+    <ul class="flex flex-col gap-4">{ session_list }</ul>
+    <div class="flex flex-row items-center gap-4">
+      <Button
+        color={ButtonColor::Danger} disabled=everywhere_pending
+        {..} on:click=move |_| { everywhere_action.dispatch(()); }
+      >
+        "Log Out Everywhere"
+      </Button>
+      { error_view }
+    </div>
   }
 }
 
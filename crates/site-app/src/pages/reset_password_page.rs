@@ -0,0 +1,98 @@
+use base_components::{
+  utils::inputs::touched_input_bindings, FloatingBoxSection, Prose,
+};
+use leptos::prelude::*;
+use leptos_router::hooks::use_params_map;
+use lsc::{button::*, field::*, link::*};
+
+use crate::server_fns::reset_password;
+
+#[component]
+pub fn ResetPasswordPage() -> impl IntoView {
+  let params = use_params_map();
+  let token = params.read_untracked().get("token").unwrap_or_default();
+
+  view! { <ResetPasswordPageIsland token=token /> }
+}
+
+#[island]
+fn ResetPasswordPageIsland(token: String) -> impl IntoView {
+  let password = RwSignal::new(None::<String>);
+  let confirm_password = RwSignal::new(None::<String>);
+  let (read_password_callback, write_password_callback) =
+    touched_input_bindings(password);
+  let (read_confirm_callback, write_confirm_callback) =
+    touched_input_bindings(confirm_password);
+
+  let action = Action::new(move |(): &()| {
+    reset_password(
+      token.clone(),
+      password.get().unwrap_or_default(),
+      confirm_password.get().unwrap_or_default(),
+    )
+  });
+  let action_value = action.value();
+  let action_pending = action.pending();
+  let action_value_view = move || {
+    action_value.get().map(|v| match v {
+      Ok(()) => view! {
+        <p class="text-success-11 dark:text-successdark-11">
+          "Your password has been changed. "
+          <Link {..} href="/log-in">"Log in"</Link>
+          " with your new password."
+        </p>
+      }
+      .into_any(),
+      Err(e) => view! {
+        <p class="text-danger-11 dark:text-dangerdark-11">{ e.to_string() }</p>
+      }
+      .into_any(),
+    })
+  };
+
+  view! {
+    <FloatingBoxSection>
+      <p class="text-3xl font-serif font-semibold tracking-tight">
+        "Choose a new password"
+      </p>
+
+      <Prose>
+        "Changing your password logs you out on every device."
+      </Prose>
+
+      <form class="mt-2 mb-4 flex flex-col gap-4">
+        <div class="flex flex-col gap-1">
+          <label class="" for="password">"New Password"</label>
+          <Field size={FieldSize::Large} {..}
+            placeholder="Enter your new password" type="password" id="password"
+            on:input=write_password_callback prop:value=read_password_callback
+          />
+        </div>
+
+        <div class="flex flex-col gap-1">
+          <label class="" for="confirm_password">"Confirm Password"</label>
+          <Field size={FieldSize::Large} {..}
+            placeholder="Enter your new password again" type="password"
+            id="confirm_password"
+            on:input=write_confirm_callback prop:value=read_confirm_callback
+          />
+        </div>
+      </form>
+
+      <div class="flex flex-row">
+        <div class="flex-1" />
+        <Button
+          size={ButtonSize::Large} disabled=action_pending
+          {..} on:click={move |_| {action.dispatch(());}}
+        >
+          "Reset Password"
+          <lsc::icons::ArrowRightIcon {..} class="size-5" />
+        </Button>
+      </div>
+
+      { move || action_value_view().map(|v| view! {
+        <div class="self-center mt-4">{ v }</div>
+      })}
+    </FloatingBoxSection>
+  }
+}
@@ -11,6 +11,8 @@ use leptos::prelude::*;
 use lsc::{button::*, field::*};
 use models::{EmailAddress, HumanName, HumanNameError, UserRecordId};
 
+use crate::components::OidcLoginLinks;
+
 #[derive(Clone, PartialEq)]
 enum SignupFormState {
   Untouched,
@@ -95,12 +97,17 @@ pub fn SignupPage() -> impl IntoView {
   let next_url = Signal::derive(move || query().get("next"));
 
   view! {
-    <SignupPageIsland next_url={next_url.get_untracked()} />
+    <SignupPageIsland next_url={next_url.get_untracked()}>
+      <OidcLoginLinks next_url={next_url.get_untracked()} />
+    </SignupPageIsland>
   }
 }
 
 #[island]
-pub fn SignupPageIsland(next_url: Option<String>) -> impl IntoView {
+pub fn SignupPageIsland(
+  next_url: Option<String>,
+  children: Children,
+) -> impl IntoView {
   use lsc::link::*;
 
   let login_url = format!(
@@ -293,6 +300,8 @@ pub fn SignupPageIsland(next_url: Option<String>) -> impl IntoView {
       { move || action_value_view().map(|v| view! {
         <div class="self-center mt-4">{ v }</div>
       })}
+
+      { children() }
     </FloatingBoxSection>
   }
 }
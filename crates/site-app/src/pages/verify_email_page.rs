@@ -0,0 +1,112 @@
+use base_components::{FloatingBoxSection, Prose};
+use leptos::prelude::*;
+use leptos_router::hooks::use_params_map;
+use lsc::{button::*, link::*};
+
+use crate::server_fns::{
+  fetch_email_verified, resend_verification_email, verify_email,
+};
+
+#[component]
+pub fn VerifyEmailPage() -> impl IntoView {
+  let params = use_params_map();
+  let token = move || params.read().get("token").unwrap_or_default();
+  let result = Resource::new(token, verify_email);
+
+  let suspended_fn = move || {
+    Suspend::new(async move {
+      match result.await {
+        Ok(()) => view! {
+          <p class="text-3xl font-serif font-semibold tracking-tight">
+            "Email verified"
+          </p>
+          <Prose>
+            "Thanks for confirming your email address. "
+            <Link {..} href="/profile">"Go to your profile."</Link>
+          </Prose>
+        }
+        .into_any(),
+        Err(e) => {
+          let e = e.to_string();
+          view! {
+            <p class="text-3xl font-serif font-semibold tracking-tight">
+              "We couldn't verify your email"
+            </p>
+            <Prose>
+              { e } ". You can send yourself a new link from your "
+              <Link {..} href="/profile">"profile"</Link> "."
+            </Prose>
+          }
+          .into_any()
+        }
+      }
+    })
+  };
+
+  view! {
+    <FloatingBoxSection>
+      <Suspense fallback=move || view! { "Verifying..." }>
+        { suspended_fn }
+      </Suspense>
+    </FloatingBoxSection>
+  }
+}
+
+/// Reminds the current user to verify their email address, if they haven't.
+#[component]
+pub fn EmailVerificationNotice() -> impl IntoView {
+  let verified = Resource::new(|| (), |()| fetch_email_verified());
+  let class = "flex flex-row gap-4 justify-between items-center p-4 \
+               rounded-lg border border-warning-7 dark:border-warningdark-7 \
+               bg-warning-2 dark:bg-warningdark-2";
+
+  let suspended_fn = move || {
+    Suspend::new(async move {
+      match verified.await {
+        Ok(Some(false)) => Some(view! {
+          <div class=class>
+            <Prose>
+              "Your email address isn't verified yet. Check your inbox for \
+               the link we sent you."
+            </Prose>
+            <ResendVerificationButton />
+          </div>
+        }),
+        _ => None,
+      }
+    })
+  };
+
+  view! {
+    <Suspense>
+      { suspended_fn }
+    </Suspense>
+  }
+}
+
+#[island]
+fn ResendVerificationButton() -> impl IntoView {
+  let action = Action::new(move |(): &()| resend_verification_email());
+  let action_value = action.value();
+  let pending = action.pending();
+
+  let status_text = move || {
+    let text = match action_value.get()? {
+      Ok(()) => "Sent! Check your inbox.".to_owned(),
+      Err(e) => format!("Failed to send: {e}"),
+    };
+    Some(view! { <p class="text-sm">{ text }</p> })
+  };
+
+  view! {
+    <div class="flex flex-col items-end gap-2">
+      <Button
+        color=ButtonColor::Base disabled=pending
+        {..} on:click=move |_| { action.dispatch(()); }
+      >
+        "Resend Link"
+      </Button>
+      { status_text }
+    </div>
+  }
+}
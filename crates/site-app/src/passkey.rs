@@ -0,0 +1,85 @@
+//! Browser glue for passkeys.
+//!
+//! `navigator.credentials` speaks in `ArrayBuffer`s, so a small shim converts
+//! to and from the base64url JSON the server uses.
+
+use models::{PasskeyAssertion, PasskeyAttestation};
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen(inline_js = r#"
+const decode = (s) => Uint8Array.from(
+  atob(s.replace(/-/g, "+").replace(/_/g, "/")),
+  (c) => c.charCodeAt(0),
+);
+const encode = (b) => btoa(String.fromCharCode(...new Uint8Array(b)))
+  .replace(/\+/g, "-").replace(/\//g, "_").replace(/=+$/, "");
+
+export async function createPasskey(optionsJson) {
+  const options = JSON.parse(optionsJson);
+  options.challenge = decode(options.challenge);
+  options.user.id = decode(options.user.id);
+  options.excludeCredentials = options.excludeCredentials
+    .map((c) => ({ ...c, id: decode(c.id) }));
+  const credential = await navigator.credentials.create({ publicKey: options });
+  return JSON.stringify({
+    credential_id: encode(credential.rawId),
+    client_data_json: encode(credential.response.clientDataJSON),
+    attestation_object: encode(credential.response.attestationObject),
+  });
+}
+
+export async function getPasskey(optionsJson) {
+  const options = JSON.parse(optionsJson);
+  options.challenge = decode(options.challenge);
+  if (options.allowCredentials) {
+    options.allowCredentials = options.allowCredentials
+      .map((c) => ({ ...c, id: decode(c.id) }));
+  }
+  const credential = await navigator.credentials.get({ publicKey: options });
+  const response = credential.response;
+  return JSON.stringify({
+    credential_id: encode(credential.rawId),
+    client_data_json: encode(response.clientDataJSON),
+    authenticator_data: encode(response.authenticatorData),
+    signature: encode(response.signature),
+    user_handle: response.userHandle ? encode(response.userHandle) : null,
+  });
+}
+"#)]
+extern "C" {
+  #[wasm_bindgen(catch, js_name = createPasskey)]
+  async fn create_passkey_js(options_json: &str) -> Result<JsValue, JsValue>;
+
+  #[wasm_bindgen(catch, js_name = getPasskey)]
+  async fn get_passkey_js(options_json: &str) -> Result<JsValue, JsValue>;
+}
+
+/// Turns the shim's answer into the server's format.
+fn parse_answer<T: serde::de::DeserializeOwned>(
+  answer: Result<JsValue, JsValue>,
+) -> Result<T, String> {
+  // the user dismissing the prompt lands here too
+  let json = answer
+    .map_err(|e| {
+      tracing::warn!("passkey prompt failed: {e:?}");
+      "The passkey prompt was cancelled or failed.".to_owned()
+    })?
+    .as_string()
+    .ok_or_else(|| "The browser gave an unexpected answer.".to_owned())?;
+  serde_json::from_str(&json)
+    .map_err(|_| "The browser gave an unexpected answer.".to_owned())
+}
+
+/// Asks the browser to create a passkey with the given registration options.
+pub async fn create_passkey(
+  options_json: &str,
+) -> Result<PasskeyAttestation, String> {
+  parse_answer(create_passkey_js(options_json).await)
+}
+
+/// Asks the browser to sign in with a passkey, with the given login options.
+pub async fn get_passkey(
+  options_json: &str,
+) -> Result<PasskeyAssertion, String> {
+  parse_answer(get_passkey_js(options_json).await)
+}
@@ -3,8 +3,11 @@
 //! Server functions for use all over the app.
 
 mod artifact;
+#[cfg(feature = "ssr")]
+mod belt_response;
 mod image;
 mod photo;
 mod photo_group;
+mod user;
 
-pub use self::{artifact::*, image::*, photo::*, photo_group::*};
+pub use self::{artifact::*, image::*, photo::*, photo_group::*, user::*};
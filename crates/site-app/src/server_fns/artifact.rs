@@ -1,5 +1,7 @@
 #[cfg(feature = "ssr")]
+mod download_artifact;
+#[cfg(feature = "ssr")]
 mod upload_artifact;
 
 #[cfg(feature = "ssr")]
-pub use self::upload_artifact::*;
+pub use self::{download_artifact::*, upload_artifact::*};
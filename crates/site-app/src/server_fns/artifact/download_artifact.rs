@@ -0,0 +1,115 @@
+#![cfg_attr(
+  debug_assertions,
+  expect(
+    clippy::items_after_statements,
+    reason = "axum::debug_handler triggers this"
+  )
+)]
+
+use std::str::FromStr;
+
+use auth_domain::AuthSession;
+use axum::{
+  body::Body,
+  extract::{Path, Query, State},
+  http::{
+    header::{CACHE_CONTROL, CONTENT_TYPE},
+    HeaderMap, HeaderValue, Response, StatusCode,
+  },
+  response::IntoResponse,
+};
+use models::{ArtifactRecordId, UserRecordId};
+use prime_domain::{
+  ArtifactDownloadClaims, PrimeDomainService, VerifyArtifactDownloadError,
+};
+use serde::Deserialize;
+
+use crate::server_fns::belt_response::{
+  artifact_content_type, efficiently_compressed_belt_http_response,
+};
+
+/// The query parameters of a signed artifact download URL.
+#[derive(Debug, Deserialize)]
+pub struct DownloadArtifactParams {
+  expires:   u64,
+  user:      Option<String>,
+  signature: String,
+}
+
+/// Downloads the bytes of an [`Artifact`](models::Artifact) through a signed,
+/// expiring URL. Doesn't require a session unless the URL is user-bound.
+#[axum::debug_handler]
+pub async fn download_artifact(
+  Path(id): Path<String>,
+  Query(params): Query<DownloadArtifactParams>,
+  State(pd): State<PrimeDomainService>,
+  auth_session: AuthSession,
+  headers: HeaderMap,
+) -> Result<Response<Body>, Response<Body>> {
+  let malformed =
+    || (StatusCode::BAD_REQUEST, "Malformed Download URL").into_response();
+
+  let artifact_id = ArtifactRecordId::from_str(&id).map_err(|_| malformed())?;
+  let user = params
+    .user
+    .as_deref()
+    .map(UserRecordId::from_str)
+    .transpose()
+    .map_err(|_| malformed())?;
+  let claims = ArtifactDownloadClaims {
+    artifact: artifact_id,
+    expires: params.expires,
+    user,
+  };
+
+  pd.verify_artifact_download(
+    &claims,
+    &params.signature,
+    auth_session.user.map(|u| u.id),
+  )
+  .map_err(|e| match e {
+    VerifyArtifactDownloadError::InvalidSignature => {
+      (StatusCode::FORBIDDEN, "Invalid Download URL").into_response()
+    }
+    VerifyArtifactDownloadError::Expired => {
+      (StatusCode::GONE, "Download URL Expired").into_response()
+    }
+    VerifyArtifactDownloadError::WrongUser => (
+      StatusCode::FORBIDDEN,
+      "Download URL Belongs To Another User",
+    )
+      .into_response(),
+    VerifyArtifactDownloadError::Clock(e) => {
+      tracing::error!("failed to verify download url: {e}");
+      (StatusCode::INTERNAL_SERVER_ERROR, "Internal Error").into_response()
+    }
+  })?;
+
+  let (artifact_data, artifact_mime_type) = pd
+    .read_artifact_by_id(artifact_id)
+    .await
+    .map_err(|e| {
+      tracing::error!("failed to read artifact data: {e}");
+      (StatusCode::INTERNAL_SERVER_ERROR, "Internal Error").into_response()
+    })?
+    .ok_or_else(|| {
+      tracing::warn!("artifact not found: {artifact_id}");
+      (StatusCode::NOT_FOUND, "Artifact Not Found").into_response()
+    })?;
+
+  let content_type = artifact_content_type(artifact_mime_type);
+  // the url was just verified against the clock, so it can't fail here
+  let remaining_secs = claims.remaining_secs().unwrap_or_default();
+  let cache_control =
+    HeaderValue::from_str(&format!("private, max-age={remaining_secs}"))
+      .expect("cache control header is always valid");
+
+  Ok(efficiently_compressed_belt_http_response(
+    &headers,
+    artifact_data,
+    HeaderMap::from_iter([
+      (CACHE_CONTROL, cache_control),
+      (CONTENT_TYPE, content_type),
+    ]),
+  ))
+}
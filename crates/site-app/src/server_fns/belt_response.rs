@@ -0,0 +1,66 @@
+use axum::{
+  body::Body,
+  http::{header::CONTENT_ENCODING, HeaderMap, HeaderValue, Response},
+  response::{IntoResponse, IntoResponseParts},
+};
+use belt::Belt;
+use models::ArtifactMimeType;
+
+const APPLICATION_OCTET_STREAM: HeaderValue =
+  HeaderValue::from_static("application/octet-stream");
+
+/// Converts an artifact's stated mime type into a `Content-Type` value.
+pub(crate) fn artifact_content_type(
+  mime_type: Option<ArtifactMimeType>,
+) -> HeaderValue {
+  mime_type
+    .and_then(|mt| HeaderValue::from_str(mt.as_ref()).ok())
+    .unwrap_or(APPLICATION_OCTET_STREAM)
+}
+
+/// Matches the compression of a [`Belt`] to the best available option indicated
+/// by request headers, and sets the appropriate headers in the response.
+pub(crate) fn efficiently_compressed_belt_http_response(
+  req_headers: &HeaderMap,
+  belt: Belt,
+  parts: impl IntoResponseParts,
+) -> Response<Body> {
+  let current_comp_http_name = belt.comp().map(|a| match a {
+    belt::CompressionAlgorithm::Zstd => "zstd",
+  });
+  let req_accept_comp = req_headers
+    .get("Accept-Encoding")
+    .map(|v| {
+      v.to_str()
+        .unwrap()
+        .split(',')
+        .map(str::trim)
+        .collect::<Vec<_>>()
+    })
+    .unwrap_or_default();
+
+  let mut out_headers = HeaderMap::with_capacity(1);
+  match current_comp_http_name {
+    // current compression is directly usable, so send as-is
+    Some(current_comp_http_name)
+      if req_accept_comp.contains(&current_comp_http_name) =>
+    {
+      out_headers.insert(
+        CONTENT_ENCODING,
+        current_comp_http_name.parse().expect(
+          "failed to convert current compression name into header value",
+        ),
+      );
+      (parts, out_headers, Body::from_stream(belt)).into_response()
+    }
+    // current compression isn't allowed, so decompress
+    Some(_) => (
+      parts,
+      out_headers,
+      Body::from_stream(belt.adapt_to_no_comp()),
+    )
+      .into_response(),
+    // currently uncompressed, so don't attempt to compress (for now)
+    None => (parts, out_headers, Body::from_stream(belt)).into_response(),
+  }
+}
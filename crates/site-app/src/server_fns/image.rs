@@ -13,28 +13,8 @@ pub async fn fetch_thumbnail_image_for_photo(
 
   let pd: PrimeDomainService = expect_context();
 
-  let photo = pd.fetch_photo(id).await.map_err(|e| {
-    tracing::error!("failed to fetch photo: {e}");
+  pd.fetch_thumbnail_image_for_photo(id).await.map_err(|e| {
+    tracing::error!("failed to fetch thumbnail image: {e}");
     ServerFnError::new("Internal Error")
-  })?;
-
-  let Some(photo) = photo else {
-    return Ok(None);
-  };
-
-  let image_id = photo.artifacts.thumbnail;
-
-  let image = pd
-    .fetch_image(image_id)
-    .await
-    .map_err(|e| {
-      tracing::error!("failed to fetch image: {e}");
-      ServerFnError::new("Internal Error")
-    })?
-    .ok_or_else(|| {
-      tracing::warn!("image {image_id} missing (referenced by photo {id})");
-      ServerFnError::new("Internal Error")
-    })?;
-
-  Ok(Some(image))
+  })
 }
@@ -1,11 +1,16 @@
 use leptos::prelude::*;
-use models::{PhotoGroup, PhotoGroupFullQuery, PhotoGroupRecordId};
+use models::{
+  PhotoGroup, PhotoGroupEdit, PhotoGroupFullQuery, PhotoGroupRecordId,
+  PhotoRecordId,
+};
 #[cfg(feature = "ssr")]
 pub use ssr::*;
 
-/// Fetches all [`PhotoGroup`]s for the current user.
+/// Fetches the current user's [`PhotoGroup`]s, either archived or not.
 #[server]
 pub async fn fetch_photo_groups_for_user(
+  /// Whether to fetch archived groups instead of active ones.
+  archived: bool,
 ) -> Result<Vec<PhotoGroup>, ServerFnError> {
   use models::AuthStatus;
   use prime_domain::PrimeDomainService;
@@ -22,30 +27,189 @@ pub async fn fetch_photo_groups_for_user(
       tracing::error!("failed to fetch photo groups: {e}");
       ServerFnError::new("Internal Error")
     })?;
-  photo_groups.sort_unstable_by_key(|pg| pg.id);
+  photo_groups.retain(|pg| pg.is_archived() == archived);
+  photo_groups.sort_unstable_by_key(|pg| pg.created_at);
   photo_groups.reverse();
 
   Ok(photo_groups)
 }
 
-/// Fetches a [`PhotoGroup`].
+/// Fetches a [`PhotoGroup`]. Archived groups are only returned to their
+/// vendor.
 #[server]
 pub async fn fetch_photo_group(
   /// The ID of the [`PhotoGroup`] to fetch.
   id: PhotoGroupRecordId,
 ) -> Result<Option<PhotoGroupFullQuery>, ServerFnError> {
+  use models::AuthStatus;
   use prime_domain::PrimeDomainService;
 
+  let auth_session: AuthStatus = expect_context();
   let pd: PrimeDomainService = expect_context();
 
   let photo_group = pd.fetch_photo_group(id).await.map_err(|e| {
     tracing::error!("failed to fetch photo group: {e}");
     ServerFnError::new("Internal Error")
   })?;
+  let requester = auth_session.0.map(|u| u.id);
+  let photo_group = photo_group.filter(|pgq| {
+    !pgq.photo_group.is_archived() || Some(pgq.photo_group.vendor) == requester
+  });
 
   Ok(photo_group)
 }
 
+/// Applies a set of [`PhotoGroupEdit`]s to a [`PhotoGroup`]. Only the group's
+/// vendor may edit it.
+#[server]
+pub async fn edit_photo_group(
+  /// The ID of the [`PhotoGroup`] to edit.
+  id: PhotoGroupRecordId,
+  /// The edits to apply, in order.
+  edits: Vec<PhotoGroupEdit>,
+) -> Result<(), ServerFnError> {
+  use models::AuthStatus;
+  use prime_domain::{EditPhotoGroupError, PrimeDomainService};
+
+  let auth_session: AuthStatus = expect_context();
+  let Some(user) = auth_session.0 else {
+    return Err(ServerFnError::new("unauthenticated"));
+  };
+
+  let pd: PrimeDomainService = expect_context();
+
+  match pd.edit_photo_group(user.id, id, edits).await {
+    Ok(_) => Ok(()),
+    Err(
+      e @ (EditPhotoGroupError::NotFound
+      | EditPhotoGroupError::Unauthorized
+      | EditPhotoGroupError::InvalidConfig(_)
+      | EditPhotoGroupError::TierNotOffered(_)
+      | EditPhotoGroupError::InvalidPhotoPrice(_)
+      | EditPhotoGroupError::MissingImage(_)
+      | EditPhotoGroupError::PhotoNotInGroup(_)
+      | EditPhotoGroupError::InvalidOrder
+      | EditPhotoGroupError::EmptyGroup
+      | EditPhotoGroupError::Conflict),
+    ) => Err(ServerFnError::new(e.to_string())),
+    Err(e) => {
+      tracing::error!("failed to edit photo group: {e}");
+      Err(ServerFnError::new("Internal Error"))
+    }
+  }
+}
+
+/// Archives or unarchives a [`PhotoGroup`]. Only the group's vendor may
+/// archive it.
+#[server]
+pub async fn archive_photo_group(
+  /// The ID of the [`PhotoGroup`] to archive.
+  id: PhotoGroupRecordId,
+  /// Whether the group should be archived.
+  archived: bool,
+) -> Result<(), ServerFnError> {
+  use models::AuthStatus;
+  use prime_domain::{ArchivePhotoGroupError, PrimeDomainService};
+
+  let auth_session: AuthStatus = expect_context();
+  let Some(user) = auth_session.0 else {
+    return Err(ServerFnError::new("unauthenticated"));
+  };
+
+  let pd: PrimeDomainService = expect_context();
+
+  match pd.set_photo_group_archived(user.id, id, archived).await {
+    Ok(_) => Ok(()),
+    Err(
+      e @ (ArchivePhotoGroupError::NotFound
+      | ArchivePhotoGroupError::Unauthorized
+      | ArchivePhotoGroupError::Conflict),
+    ) => Err(ServerFnError::new(e.to_string())),
+    Err(e) => {
+      tracing::error!("failed to archive photo group: {e}");
+      Err(ServerFnError::new("Internal Error"))
+    }
+  }
+}
+
+/// Permanently deletes a [`PhotoGroup`] and its photos. Only the group's
+/// vendor may delete it, and only if it isn't offered for sale.
+#[server]
+pub async fn delete_photo_group(
+  /// The ID of the [`PhotoGroup`] to delete.
+  id: PhotoGroupRecordId,
+) -> Result<(), ServerFnError> {
+  use models::AuthStatus;
+  use prime_domain::{DeletePhotoGroupError, PrimeDomainService};
+
+  let auth_session: AuthStatus = expect_context();
+  let Some(user) = auth_session.0 else {
+    return Err(ServerFnError::new("unauthenticated"));
+  };
+
+  let pd: PrimeDomainService = expect_context();
+
+  match pd.delete_photo_group(user.id, id).await {
+    Ok(()) => Ok(()),
+    Err(
+      e @ (DeletePhotoGroupError::NotFound
+      | DeletePhotoGroupError::Unauthorized
+      | DeletePhotoGroupError::Licensed),
+    ) => Err(ServerFnError::new(e.to_string())),
+    Err(e) => {
+      tracing::error!("failed to delete photo group: {e}");
+      Err(ServerFnError::new("Internal Error"))
+    }
+  }
+}
+
+/// Fetches a signed download URL for the original of a
+/// [`Photo`](models::Photo) in a [`PhotoGroup`]. The URL expires, and is bound
+/// to the buyer with the given email if there is one; otherwise it works for
+/// anyone who has it.
+#[server]
+pub async fn fetch_photo_original_download_url(
+  /// The ID of the [`PhotoGroup`] containing the photo.
+  photo_group: PhotoGroupRecordId,
+  /// The ID of the [`Photo`](models::Photo) to download.
+  photo: PhotoRecordId,
+  /// The email of the buyer to bind the URL to, if any.
+  buyer_email: Option<String>,
+) -> Result<String, ServerFnError> {
+  use models::{AuthStatus, BaseUrl, EmailAddress};
+  use prime_domain::{PhotoDownloadUrlError, PrimeDomainService};
+
+  let auth_session: AuthStatus = expect_context();
+  let Some(user) = auth_session.0 else {
+    return Err(ServerFnError::new("unauthenticated"));
+  };
+
+  let buyer = buyer_email
+    .as_deref()
+    .map(str::trim)
+    .filter(|e| !e.is_empty())
+    .map(EmailAddress::try_new)
+    .transpose()
+    .map_err(|_| ServerFnError::new("Buyer email address is invalid"))?;
+
+  let pd: PrimeDomainService = expect_context();
+  let base_url: BaseUrl = expect_context();
+
+  pd.photo_original_download_url(&base_url, user.id, photo_group, photo, buyer)
+    .await
+    .map_err(|e| match e {
+      PhotoDownloadUrlError::NotFound => ServerFnError::new("Photo not found"),
+      PhotoDownloadUrlError::Unauthorized => ServerFnError::new("Unauthorized"),
+      PhotoDownloadUrlError::BuyerNotFound => {
+        ServerFnError::new("No account has that email")
+      }
+      e => {
+        tracing::error!("failed to create photo download url: {e}");
+        ServerFnError::new("Internal Error")
+      }
+    })
+}
+
 #[cfg(feature = "ssr")]
 mod ssr {
   use std::str::FromStr;
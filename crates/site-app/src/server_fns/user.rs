@@ -0,0 +1,1094 @@
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "ssr")]
+mod export_user_data;
+#[cfg(feature = "ssr")]
+mod oidc_login;
+
+#[cfg(feature = "ssr")]
+pub use self::{export_user_data::*, oidc_login::*};
+
+/// An `OpenID` Connect provider users can log in with.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct OidcProviderLink {
+  /// The provider's ID, as used in its login URL.
+  pub id:   String,
+  /// The provider's name.
+  pub name: String,
+}
+
+/// How the current user confirms it's them before a sensitive change to
+/// their account. The first proof given is the one checked: a passkey, then
+/// a confirmation link, then the password.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct IdentityProof {
+  /// A passkey's answer to the challenge from
+  /// [`begin_passkey_reauthentication`].
+  pub passkey:    Option<models::PasskeyAssertion>,
+  /// The token from a link sent by [`request_reauthentication_link`].
+  pub link_token: Option<String>,
+  /// The user's current password.
+  pub password:   Option<String>,
+}
+
+/// Fetches the `OpenID` Connect providers users can log in with.
+#[server]
+#[allow(clippy::unused_async, reason = "server functions are async")]
+pub async fn fetch_oidc_providers(
+) -> Result<Vec<OidcProviderLink>, ServerFnError> {
+  use auth_domain::AuthDomainService;
+
+  let auth_service: AuthDomainService = expect_context();
+  Ok(
+    auth_service
+      .oidc_providers()
+      .iter()
+      .map(|p| OidcProviderLink {
+        id:   p.id.clone(),
+        name: p.name.clone(),
+      })
+      .collect(),
+  )
+}
+
+/// Fetches whether the current user's email address is verified, or `None`
+/// if nobody is logged in.
+#[server]
+pub async fn fetch_email_verified() -> Result<Option<bool>, ServerFnError> {
+  use auth_domain::AuthDomainService;
+  use models::AuthStatus;
+
+  let auth_session: AuthStatus = expect_context();
+  let Some(user) = auth_session.0 else {
+    return Ok(None);
+  };
+
+  let auth_service: AuthDomainService = expect_context();
+  let user = auth_service.fetch_user_by_id(user.id).await.map_err(|e| {
+    tracing::error!("failed to fetch user: {e}");
+    ServerFnError::new("Internal Error")
+  })?;
+
+  Ok(user.map(|u| u.email_verified))
+}
+
+/// Sends the current user another email verification link.
+#[server]
+pub async fn resend_verification_email() -> Result<(), ServerFnError> {
+  use auth_domain::{AuthDomainService, SendVerificationEmailError};
+  use models::AuthStatus;
+
+  let auth_session: AuthStatus = expect_context();
+  let Some(user) = auth_session.0 else {
+    return Err(ServerFnError::new("Unauthenticated"));
+  };
+
+  let auth_service: AuthDomainService = expect_context();
+  auth_service
+    .send_verification_email(user.id)
+    .await
+    .map_err(|e| match e {
+      SendVerificationEmailError::AlreadyVerified
+      | SendVerificationEmailError::Throttled(_) => {
+        ServerFnError::new(e.to_string())
+      }
+      e => {
+        tracing::error!("failed to send verification email: {e}");
+        ServerFnError::new("Internal Error")
+      }
+    })
+}
+
+/// Verifies an email address with the token from a verification link.
+#[server]
+pub async fn verify_email(
+  /// The token from the verification link.
+  token: String,
+) -> Result<(), ServerFnError> {
+  use auth_domain::{AuthDomainService, VerifyEmailError};
+
+  let auth_service: AuthDomainService = expect_context();
+  auth_service
+    .verify_email(&token)
+    .await
+    .map(|_| ())
+    .map_err(|e| match e {
+      VerifyEmailError::InvalidToken | VerifyEmailError::Expired => {
+        ServerFnError::new(e.to_string())
+      }
+      e => {
+        tracing::error!("failed to verify email: {e}");
+        ServerFnError::new("Internal Error")
+      }
+    })
+}
+
+/// Emails a password reset link to the given address, if it has an account.
+#[server]
+pub async fn request_password_reset(
+  /// The address to send the link to.
+  email: String,
+) -> Result<(), ServerFnError> {
+  use auth_domain::AuthDomainService;
+  use models::EmailAddress;
+
+  let email = EmailAddress::try_new(email)
+    .map_err(|_| ServerFnError::new("Email address is invalid"))?;
+
+  let auth_service: AuthDomainService = expect_context();
+  auth_service
+    .request_password_reset(email)
+    .await
+    .map_err(|e| {
+      tracing::error!("failed to request password reset: {e}");
+      ServerFnError::new("Internal Error")
+    })
+}
+
+/// Sets a new password with the token from a password reset link.
+#[server]
+pub async fn reset_password(
+  /// The token from the reset link.
+  token: String,
+  /// The new password.
+  password: String,
+  /// The new password again, to catch typos.
+  confirm_password: String,
+) -> Result<(), ServerFnError> {
+  use auth_domain::{AuthDomainService, ResetPasswordError};
+
+  if password.is_empty() {
+    return Err(ServerFnError::new("Password is empty"));
+  }
+  if password != confirm_password {
+    return Err(ServerFnError::new("Passwords do not match"));
+  }
+
+  let auth_service: AuthDomainService = expect_context();
+  auth_service
+    .reset_password(&token, &password)
+    .await
+    .map(|_| ())
+    .map_err(|e| match e {
+      ResetPasswordError::InvalidToken | ResetPasswordError::Expired => {
+        ServerFnError::new(e.to_string())
+      }
+      e => {
+        tracing::error!("failed to reset password: {e}");
+        ServerFnError::new("Internal Error")
+      }
+    })
+}
+
+/// Fetches the current user's [`AccountDetails`](models::AccountDetails), or
+/// `None` if nobody is logged in.
+#[server]
+pub async fn fetch_account_details(
+) -> Result<Option<models::AccountDetails>, ServerFnError> {
+  use auth_domain::AuthDomainService;
+  use models::AuthStatus;
+
+  let auth_session: AuthStatus = expect_context();
+  let Some(user) = auth_session.0 else {
+    return Ok(None);
+  };
+
+  let auth_service: AuthDomainService = expect_context();
+  let user = auth_service.fetch_user_by_id(user.id).await.map_err(|e| {
+    tracing::error!("failed to fetch user: {e}");
+    ServerFnError::new("Internal Error")
+  })?;
+
+  Ok(user.map(Into::into))
+}
+
+/// Changes the current user's name.
+#[server]
+pub async fn change_name(
+  /// The new name.
+  name: String,
+) -> Result<(), ServerFnError> {
+  use auth_domain::AuthDomainService;
+  use models::{AuthStatus, HumanName, HumanNameError};
+
+  let auth_session: AuthStatus = expect_context();
+  let Some(user) = auth_session.0 else {
+    return Err(ServerFnError::new("Unauthenticated"));
+  };
+
+  let name = HumanName::try_new(name).map_err(|e| match e {
+    HumanNameError::NotEmptyViolated => ServerFnError::new("Name is empty"),
+    HumanNameError::LenCharMaxViolated => {
+      ServerFnError::new("Name is too long")
+    }
+  })?;
+
+  let auth_service: AuthDomainService = expect_context();
+  auth_service
+    .change_name(user.id, name)
+    .await
+    .map(|_| ())
+    .map_err(|e| {
+      tracing::error!("failed to change name: {e}");
+      ServerFnError::new("Internal Error")
+    })
+}
+
+/// Changes the current user's email address.
+#[server]
+pub async fn change_email(
+  /// Confirms it's the user.
+  proof: IdentityProof,
+  /// The new email address.
+  email: String,
+) -> Result<(), ServerFnError> {
+  use auth_domain::{AuthDomainService, ChangeEmailError};
+  use models::{AuthStatus, EmailAddress};
+
+  let auth_session: AuthStatus = expect_context();
+  let Some(user) = auth_session.0 else {
+    return Err(ServerFnError::new("Unauthenticated"));
+  };
+
+  let email = EmailAddress::try_new(email)
+    .map_err(|_| ServerFnError::new("Email address is invalid"))?;
+
+  let reauth = ssr::reauthentication(proof).await?;
+  let auth_service: AuthDomainService = expect_context();
+  let user = auth_service
+    .change_email(user.id, &reauth, email)
+    .await
+    .map_err(|e| match e {
+      ChangeEmailError::Reauthentication(e) if e.is_rejection() => {
+        ServerFnError::new(e.to_string())
+      }
+      ChangeEmailError::EmailAlreadyUsed(_) => {
+        ServerFnError::new("Email is already in use")
+      }
+      e => {
+        tracing::error!("failed to change email: {e}");
+        ServerFnError::new("Internal Error")
+      }
+    })?;
+
+  ssr::refresh_session(user).await
+}
+
+/// Changes the current user's password, or sets one if they have none.
+#[server]
+pub async fn change_password(
+  /// Confirms it's the user.
+  proof: IdentityProof,
+  /// The new password.
+  password: String,
+  /// The new password again, to catch typos.
+  confirm_password: String,
+) -> Result<(), ServerFnError> {
+  use auth_domain::{AuthDomainService, ChangePasswordError};
+  use models::AuthStatus;
+
+  let auth_session: AuthStatus = expect_context();
+  let Some(user) = auth_session.0 else {
+    return Err(ServerFnError::new("Unauthenticated"));
+  };
+
+  if password.is_empty() {
+    return Err(ServerFnError::new("Password is empty"));
+  }
+  if password != confirm_password {
+    return Err(ServerFnError::new("Passwords do not match"));
+  }
+
+  let reauth = ssr::reauthentication(proof).await?;
+  let auth_service: AuthDomainService = expect_context();
+  let user = auth_service
+    .change_password(user.id, &reauth, &password)
+    .await
+    .map_err(|e| match e {
+      ChangePasswordError::Reauthentication(e) if e.is_rejection() => {
+        ServerFnError::new(e.to_string())
+      }
+      e => {
+        tracing::error!("failed to change password: {e}");
+        ServerFnError::new("Internal Error")
+      }
+    })?;
+
+  ssr::refresh_session(user).await
+}
+
+/// Permanently deletes the current user's account, after confirming it's
+/// them. Photo groups that may have been sold are kept with the vendor
+/// anonymized, and every session the user is logged into ends.
+#[server]
+pub async fn delete_account(
+  /// Confirms it's the user.
+  proof: IdentityProof,
+) -> Result<(), ServerFnError> {
+  use auth_domain::{AuthDomainService, AuthSession, ConfirmIdentityError};
+  use models::AuthStatus;
+  use prime_domain::PrimeDomainService;
+  use tower_sessions_kv_store::TowerSessionsKvStore;
+
+  let auth_session: AuthStatus = expect_context();
+  let Some(user) = auth_session.0 else {
+    return Err(ServerFnError::new("Unauthenticated"));
+  };
+
+  let reauth = ssr::reauthentication(proof).await?;
+  let auth_service: AuthDomainService = expect_context();
+  auth_service
+    .confirm_identity(user.id, &reauth)
+    .await
+    .map_err(|e| match e {
+      ConfirmIdentityError::Reauthentication(e) if e.is_rejection() => {
+        ServerFnError::new(e.to_string())
+      }
+      e => {
+        tracing::error!("failed to confirm identity: {e}");
+        ServerFnError::new("Internal Error")
+      }
+    })?;
+
+  let prime_domain_service: PrimeDomainService = expect_context();
+  let report = prime_domain_service
+    .delete_user_content(user.id)
+    .await
+    .map_err(|e| {
+      tracing::error!("failed to delete user content: {e}");
+      ServerFnError::new("Internal Error")
+    })?;
+  tracing::info!(
+    "deleting user {}: deleted {} photo groups, anonymized {}",
+    user.id,
+    report.deleted.len(),
+    report.anonymized.len(),
+  );
+
+  auth_service.delete_user(user.id).await.map_err(|e| {
+    tracing::error!("failed to delete user: {e}");
+    ServerFnError::new("Internal Error")
+  })?;
+
+  let session_store: TowerSessionsKvStore = expect_context();
+  if let Err(e) = session_store
+    .delete_sessions_for_user(&user.id.to_string())
+    .await
+  {
+    // the sessions can't load a deleted user, so they're dead regardless
+    tracing::error!("failed to purge sessions of deleted user: {e}");
+  }
+
+  let mut auth_session =
+    leptos_axum::extract::<AuthSession>().await.map_err(|_| {
+      tracing::error!("auth session not found");
+      ServerFnError::new("Internal error")
+    })?;
+  auth_session.logout().await.map_err(|e| {
+    tracing::error!("failed to log out: {e}");
+    ServerFnError::new("Internal error")
+  })?;
+
+  Ok(())
+}
+
+/// What the client needs to add an account to an authenticator app.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TotpEnrollmentPrompt {
+  /// The shared secret, base32-encoded for manual entry.
+  pub secret:      String,
+  /// The QR code holding the `otpauth://` URI, as an SVG document.
+  pub qr_code_svg: String,
+}
+
+/// Starts enrolling the current user in TOTP two-factor authentication.
+#[server]
+pub async fn begin_totp_enrollment(
+) -> Result<TotpEnrollmentPrompt, ServerFnError> {
+  use auth_domain::{AuthDomainService, BeginTotpEnrollmentError};
+  use models::AuthStatus;
+
+  let auth_session: AuthStatus = expect_context();
+  let Some(user) = auth_session.0 else {
+    return Err(ServerFnError::new("Unauthenticated"));
+  };
+
+  let auth_service: AuthDomainService = expect_context();
+  let start =
+    auth_service
+      .begin_totp_enrollment(user.id)
+      .await
+      .map_err(|e| match e {
+        BeginTotpEnrollmentError::AlreadyEnabled => {
+          ServerFnError::new(e.to_string())
+        }
+        e => {
+          tracing::error!("failed to begin totp enrollment: {e}");
+          ServerFnError::new("Internal Error")
+        }
+      })?;
+
+  Ok(TotpEnrollmentPrompt {
+    secret:      start.secret,
+    qr_code_svg: start.qr_code_svg,
+  })
+}
+
+/// Confirms the current user's TOTP enrollment with a code from their app,
+/// returning their recovery codes.
+#[server]
+pub async fn confirm_totp_enrollment(
+  /// The code from the authenticator app.
+  code: String,
+) -> Result<Vec<String>, ServerFnError> {
+  use auth_domain::{AuthDomainService, ConfirmTotpEnrollmentError};
+  use models::AuthStatus;
+
+  let auth_session: AuthStatus = expect_context();
+  let Some(user) = auth_session.0 else {
+    return Err(ServerFnError::new("Unauthenticated"));
+  };
+
+  let auth_service: AuthDomainService = expect_context();
+  let recovery_codes = auth_service
+    .confirm_totp_enrollment(user.id, &code)
+    .await
+    .map_err(|e| match e {
+      ConfirmTotpEnrollmentError::NotStarted
+      | ConfirmTotpEnrollmentError::AlreadyEnabled
+      | ConfirmTotpEnrollmentError::InvalidCode => {
+        ServerFnError::new(e.to_string())
+      }
+      e => {
+        tracing::error!("failed to confirm totp enrollment: {e}");
+        ServerFnError::new("Internal Error")
+      }
+    })?;
+
+  let user = auth_service
+    .fetch_user_by_id(user.id)
+    .await
+    .map_err(|e| {
+      tracing::error!("failed to fetch user: {e}");
+      ServerFnError::new("Internal Error")
+    })?
+    .ok_or_else(|| ServerFnError::new("User not found"))?;
+  ssr::refresh_session(user).await?;
+
+  Ok(recovery_codes)
+}
+
+/// Turns off the current user's two-factor authentication.
+#[server]
+pub async fn disable_two_factor(
+  /// Confirms it's the user.
+  proof: IdentityProof,
+) -> Result<(), ServerFnError> {
+  use auth_domain::{AuthDomainService, DisableTwoFactorError};
+  use models::AuthStatus;
+
+  let auth_session: AuthStatus = expect_context();
+  let Some(user) = auth_session.0 else {
+    return Err(ServerFnError::new("Unauthenticated"));
+  };
+
+  let reauth = ssr::reauthentication(proof).await?;
+  let auth_service: AuthDomainService = expect_context();
+  let user = auth_service
+    .disable_two_factor(user.id, &reauth)
+    .await
+    .map_err(|e| match e {
+      DisableTwoFactorError::Reauthentication(e) if e.is_rejection() => {
+        ServerFnError::new(e.to_string())
+      }
+      e => {
+        tracing::error!("failed to disable two-factor authentication: {e}");
+        ServerFnError::new("Internal Error")
+      }
+    })?;
+
+  ssr::refresh_session(user).await
+}
+
+/// Starts confirming it's the current user with one of their passkeys,
+/// returning the options for the browser.
+#[server]
+pub async fn begin_passkey_reauthentication() -> Result<String, ServerFnError> {
+  use auth_domain::{AuthDomainService, BeginPasskeyReauthenticationError};
+  use models::AuthStatus;
+
+  let auth_session: AuthStatus = expect_context();
+  let Some(user) = auth_session.0 else {
+    return Err(ServerFnError::new("Unauthenticated"));
+  };
+
+  let auth_service: AuthDomainService = expect_context();
+  let ceremony = auth_service
+    .begin_passkey_reauthentication(user.id)
+    .await
+    .map_err(|e| match e {
+      BeginPasskeyReauthenticationError::NoPasskeys => {
+        ServerFnError::new(e.to_string())
+      }
+      e => {
+        tracing::error!("failed to begin passkey reauthentication: {e}");
+        ServerFnError::new("Internal Error")
+      }
+    })?;
+  ssr::store_passkey_challenge(ceremony.challenge).await?;
+
+  Ok(ceremony.options_json)
+}
+
+/// Emails the current user a link confirming it's them, for changes they
+/// have no password to confirm.
+#[server]
+pub async fn request_reauthentication_link() -> Result<(), ServerFnError> {
+  use auth_domain::{AuthDomainService, RequestReauthenticationLinkError};
+  use models::AuthStatus;
+
+  let auth_session: AuthStatus = expect_context();
+  let Some(user) = auth_session.0 else {
+    return Err(ServerFnError::new("Unauthenticated"));
+  };
+
+  let auth_service: AuthDomainService = expect_context();
+  auth_service
+    .request_reauthentication_link(user.id)
+    .await
+    .map_err(|e| match e {
+      RequestReauthenticationLinkError::Throttled(_) => {
+        ServerFnError::new(e.to_string())
+      }
+      e => {
+        tracing::error!("failed to send reauthentication link: {e}");
+        ServerFnError::new("Internal Error")
+      }
+    })
+}
+
+/// Starts logging in with a passkey, returning the options for the browser.
+#[server]
+pub async fn begin_passkey_login() -> Result<String, ServerFnError> {
+  use auth_domain::AuthDomainService;
+
+  let auth_service: AuthDomainService = expect_context();
+  let ceremony = auth_service.begin_passkey_login();
+  ssr::store_passkey_challenge(ceremony.challenge).await?;
+
+  Ok(ceremony.options_json)
+}
+
+/// Finishes logging in with a passkey, with the browser's answer. Returns
+/// whether the passkey was accepted.
+#[server]
+#[tracing::instrument(skip(assertion))]
+pub async fn finish_passkey_login(
+  /// The browser's answer to the login challenge.
+  assertion: models::PasskeyAssertion,
+) -> Result<bool, ServerFnError> {
+  use auth_domain::{AuthDomainService, AuthSession};
+  use models::{AuthUser, UserSubmittedAuthCredentials};
+
+  let Some(challenge) = ssr::take_passkey_challenge().await? else {
+    return Ok(false);
+  };
+
+  let auth_service: AuthDomainService = expect_context();
+  let user = auth_service
+    .user_authenticate(UserSubmittedAuthCredentials::Passkey {
+      challenge,
+      assertion,
+    })
+    .await
+    .map_err(|e| {
+      tracing::error!("failed to authenticate passkey: {e}");
+      ServerFnError::new("Internal error")
+    })?;
+  let Some(user) = user else {
+    return Ok(false);
+  };
+
+  let mut auth_session =
+    leptos_axum::extract::<AuthSession>().await.map_err(|_| {
+      tracing::error!("auth session not found");
+      ServerFnError::new("Internal error")
+    })?;
+  auth_session
+    .login(&AuthUser::from(user))
+    .await
+    .map_err(|e| {
+      tracing::error!("failed to log in: {e}");
+      ServerFnError::new("Internal error")
+    })?;
+
+  Ok(true)
+}
+
+/// Emails a login link to an address. Opening it creates an account if there
+/// isn't one with the address.
+#[server]
+pub async fn request_magic_link(
+  /// The address to send the link to.
+  email: String,
+) -> Result<(), ServerFnError> {
+  use auth_domain::AuthDomainService;
+  use models::{ClientIp, EmailAddress};
+
+  let email = EmailAddress::try_new(email)
+    .map_err(|_| ServerFnError::new("Email address is invalid"))?;
+
+  let auth_service: AuthDomainService = expect_context();
+  let client = use_context::<ClientIp>().and_then(|c| c.0);
+  auth_service
+    .request_magic_link_from(email, client)
+    .await
+    .map_err(|e| {
+      tracing::error!("failed to request login link: {e}");
+      ServerFnError::new("Internal Error")
+    })
+}
+
+/// Logs in with the token from a login link. Returns whether the link was
+/// accepted.
+#[server]
+#[tracing::instrument(skip(token))]
+pub async fn log_in_with_magic_link(
+  /// The token from the login link.
+  token: String,
+) -> Result<bool, ServerFnError> {
+  use auth_domain::{AuthDomainService, AuthSession};
+  use models::{AuthUser, UserSubmittedAuthCredentials};
+
+  let auth_service: AuthDomainService = expect_context();
+  let user = auth_service
+    .user_authenticate(UserSubmittedAuthCredentials::MagicLink { token })
+    .await
+    .map_err(|e| {
+      tracing::error!("failed to authenticate login link: {e}");
+      ServerFnError::new("Internal error")
+    })?;
+  let Some(user) = user else {
+    return Ok(false);
+  };
+
+  let mut auth_session =
+    leptos_axum::extract::<AuthSession>().await.map_err(|_| {
+      tracing::error!("auth session not found");
+      ServerFnError::new("Internal error")
+    })?;
+  auth_session
+    .login(&AuthUser::from(user))
+    .await
+    .map_err(|e| {
+      tracing::error!("failed to log in: {e}");
+      ServerFnError::new("Internal error")
+    })?;
+
+  Ok(true)
+}
+
+/// Starts registering a passkey for the current user, returning the options
+/// for the browser.
+#[server]
+pub async fn begin_passkey_registration() -> Result<String, ServerFnError> {
+  use auth_domain::AuthDomainService;
+  use models::AuthStatus;
+
+  let auth_session: AuthStatus = expect_context();
+  let Some(user) = auth_session.0 else {
+    return Err(ServerFnError::new("Unauthenticated"));
+  };
+
+  let auth_service: AuthDomainService = expect_context();
+  let ceremony = auth_service
+    .begin_passkey_registration(user.id)
+    .await
+    .map_err(|e| {
+      tracing::error!("failed to begin passkey registration: {e}");
+      ServerFnError::new("Internal Error")
+    })?;
+  ssr::store_passkey_challenge(ceremony.challenge).await?;
+
+  Ok(ceremony.options_json)
+}
+
+/// Finishes registering a passkey for the current user, with the browser's
+/// answer.
+#[server]
+#[tracing::instrument(skip(attestation))]
+pub async fn finish_passkey_registration(
+  /// The browser's answer to the registration challenge.
+  attestation: models::PasskeyAttestation,
+) -> Result<(), ServerFnError> {
+  use auth_domain::{AuthDomainService, FinishPasskeyRegistrationError};
+  use models::AuthStatus;
+
+  let auth_session: AuthStatus = expect_context();
+  let Some(user) = auth_session.0 else {
+    return Err(ServerFnError::new("Unauthenticated"));
+  };
+
+  let Some(challenge) = ssr::take_passkey_challenge().await? else {
+    return Err(ServerFnError::new("No passkey registration in progress"));
+  };
+
+  let auth_service: AuthDomainService = expect_context();
+  let user = auth_service
+    .finish_passkey_registration(user.id, &challenge, &attestation)
+    .await
+    .map_err(|e| match e {
+      FinishPasskeyRegistrationError::WrongChallenge
+      | FinishPasskeyRegistrationError::Expired
+      | FinishPasskeyRegistrationError::AlreadyRegistered
+      | FinishPasskeyRegistrationError::Verification(_) => {
+        ServerFnError::new(e.to_string())
+      }
+      e => {
+        tracing::error!("failed to finish passkey registration: {e}");
+        ServerFnError::new("Internal Error")
+      }
+    })?;
+
+  ssr::refresh_session(user).await
+}
+
+/// Removes one of the current user's passkeys.
+#[server]
+pub async fn remove_passkey(
+  /// The passkey's base64url credential ID.
+  credential_id: String,
+) -> Result<(), ServerFnError> {
+  use auth_domain::{AuthDomainService, RemovePasskeyError};
+  use models::AuthStatus;
+
+  let auth_session: AuthStatus = expect_context();
+  let Some(user) = auth_session.0 else {
+    return Err(ServerFnError::new("Unauthenticated"));
+  };
+
+  let auth_service: AuthDomainService = expect_context();
+  let user = auth_service
+    .remove_passkey(user.id, &credential_id)
+    .await
+    .map_err(|e| match e {
+      RemovePasskeyError::PasskeyNotFound
+      | RemovePasskeyError::LastCredential => ServerFnError::new(e.to_string()),
+      e => {
+        tracing::error!("failed to remove passkey: {e}");
+        ServerFnError::new("Internal Error")
+      }
+    })?;
+
+  ssr::refresh_session(user).await
+}
+
+/// Removes the current user's password, so they log in with passkeys only.
+#[server]
+pub async fn remove_password(
+  /// The user's current password.
+  current_password: String,
+) -> Result<(), ServerFnError> {
+  use auth_domain::{AuthDomainService, RemovePasswordError};
+  use models::AuthStatus;
+
+  let auth_session: AuthStatus = expect_context();
+  let Some(user) = auth_session.0 else {
+    return Err(ServerFnError::new("Unauthenticated"));
+  };
+
+  let auth_service: AuthDomainService = expect_context();
+  let user = auth_service
+    .remove_password(user.id, &current_password)
+    .await
+    .map_err(|e| match e {
+      RemovePasswordError::IncorrectPassword => {
+        ServerFnError::new("Current password is incorrect")
+      }
+      RemovePasswordError::NoPasskeys
+      | RemovePasswordError::Throttled { .. } => {
+        ServerFnError::new(e.to_string())
+      }
+      e => {
+        tracing::error!("failed to remove password: {e}");
+        ServerFnError::new("Internal Error")
+      }
+    })?;
+
+  ssr::refresh_session(user).await
+}
+
+/// Fetches the sessions the current user is logged into, this one first and
+/// then the most recently used.
+#[server]
+pub async fn fetch_active_sessions(
+) -> Result<Vec<models::ActiveSession>, ServerFnError> {
+  use auth_domain::AuthSession;
+  use models::{ActiveSession, AuthStatus, SessionActivity};
+  use tower_sessions_kv_store::TowerSessionsKvStore;
+
+  let auth_status: AuthStatus = expect_context();
+  let Some(user) = auth_status.0 else {
+    return Err(ServerFnError::new("Unauthenticated"));
+  };
+
+  let auth_session =
+    leptos_axum::extract::<AuthSession>().await.map_err(|_| {
+      tracing::error!("auth session not found");
+      ServerFnError::new("Internal error")
+    })?;
+  let current = auth_session.session.id();
+  // this request's activity may not have been saved yet
+  let current_activity = auth_session
+    .session
+    .get::<SessionActivity>(SessionActivity::SESSION_KEY)
+    .await
+    .map_err(|e| {
+      tracing::error!("failed to read session activity: {e}");
+      ServerFnError::new("Internal Error")
+    })?;
+
+  let session_store: TowerSessionsKvStore = expect_context();
+  let records = session_store
+    .sessions_for_user(&user.id.to_string())
+    .await
+    .map_err(|e| {
+      tracing::error!("failed to fetch user sessions: {e}");
+      ServerFnError::new("Internal Error")
+    })?;
+
+  let mut sessions = records
+    .into_iter()
+    .filter(|record| ssr::record_has_auth_hash(record, &user.auth_hash_bytes))
+    .map(|record| {
+      let current = Some(record.id) == current;
+      ActiveSession {
+        activity: if current {
+          current_activity.clone()
+        } else {
+          ssr::record_activity(&record)
+        },
+        current,
+      }
+    })
+    .collect::<Vec<_>>();
+  sessions.sort_by_key(|s| {
+    (
+      !s.current,
+      std::cmp::Reverse(s.activity.as_ref().map(|a| a.last_seen_at)),
+    )
+  });
+
+  Ok(sessions)
+}
+
+/// Logs the current user out of one of their other sessions.
+#[server]
+pub async fn revoke_session(
+  /// The session's handle, from its activity.
+  handle: String,
+) -> Result<(), ServerFnError> {
+  use auth_domain::AuthSession;
+  use models::AuthStatus;
+  use tower_sessions_kv_store::TowerSessionsKvStore;
+
+  let auth_status: AuthStatus = expect_context();
+  let Some(user) = auth_status.0 else {
+    return Err(ServerFnError::new("Unauthenticated"));
+  };
+  let user_id = user.id.to_string();
+
+  let session_store: TowerSessionsKvStore = expect_context();
+  let records =
+    session_store
+      .sessions_for_user(&user_id)
+      .await
+      .map_err(|e| {
+        tracing::error!("failed to fetch user sessions: {e}");
+        ServerFnError::new("Internal Error")
+      })?;
+  let Some(record) = records.into_iter().find(|record| {
+    ssr::record_activity(record).is_some_and(|a| a.handle == handle)
+  }) else {
+    return Err(ServerFnError::new("Session not found"));
+  };
+
+  let auth_session =
+    leptos_axum::extract::<AuthSession>().await.map_err(|_| {
+      tracing::error!("auth session not found");
+      ServerFnError::new("Internal error")
+    })?;
+  if auth_session.session.id() == Some(record.id) {
+    return Err(ServerFnError::new(
+      "This is your current session; log out instead",
+    ));
+  }
+
+  let deleted = session_store
+    .delete_session_for_user(&user_id, &record.id)
+    .await
+    .map_err(|e| {
+      tracing::error!("failed to revoke session: {e}");
+      ServerFnError::new("Internal Error")
+    })?;
+  if !deleted {
+    return Err(ServerFnError::new("Session not found"));
+  }
+
+  Ok(())
+}
+
+/// Logs the current user out of every session, including this one.
+#[server]
+pub async fn log_out_everywhere() -> Result<(), ServerFnError> {
+  use auth_domain::AuthSession;
+  use models::AuthStatus;
+  use tower_sessions_kv_store::TowerSessionsKvStore;
+
+  let auth_status: AuthStatus = expect_context();
+  let Some(user) = auth_status.0 else {
+    return Err(ServerFnError::new("Unauthenticated"));
+  };
+
+  let session_store: TowerSessionsKvStore = expect_context();
+  let deleted = session_store
+    .delete_sessions_for_user(&user.id.to_string())
+    .await
+    .map_err(|e| {
+      tracing::error!("failed to delete user sessions: {e}");
+      ServerFnError::new("Internal Error")
+    })?;
+  tracing::info!("logged user {} out of {deleted} sessions", user.id);
+
+  let mut auth_session =
+    leptos_axum::extract::<AuthSession>().await.map_err(|_| {
+      tracing::error!("auth session not found");
+      ServerFnError::new("Internal error")
+    })?;
+  auth_session.logout().await.map_err(|e| {
+    tracing::error!("failed to log out: {e}");
+    ServerFnError::new("Internal error")
+  })?;
+
+  Ok(())
+}
+
+#[cfg(feature = "ssr")]
+mod ssr {
+  use leptos::prelude::ServerFnError;
+  use models::{AuthUser, PasskeyChallenge, SessionActivity, User};
+  use tower_sessions_kv_store::Record;
+
+  /// The session key holding the passkey challenge awaiting an answer.
+  const PASSKEY_CHALLENGE_KEY: &str = "passkey.challenge";
+
+  /// The session data key that `axum-login` stores the logged-in user under.
+  const AUTH_DATA_KEY: &str = "axum-login.data";
+
+  /// Where and when a stored session was used, if that's been recorded.
+  pub fn record_activity(record: &Record) -> Option<SessionActivity> {
+    let value = record.data.get(SessionActivity::SESSION_KEY)?;
+    serde_json::from_value(value.clone()).ok()
+  }
+
+  /// Whether a stored session was logged in with the user's current
+  /// credentials. Sessions from before they changed end on their next
+  /// request, so they don't count as logged in.
+  pub fn record_has_auth_hash(record: &Record, auth_hash: &[u8]) -> bool {
+    record
+      .data
+      .get(AUTH_DATA_KEY)
+      .and_then(|data| data.get("auth_hash"))
+      .and_then(|hash| serde_json::from_value::<Vec<u8>>(hash.clone()).ok())
+      .is_some_and(|hash| hash == auth_hash)
+  }
+
+  /// Logs the current session back in after the user's credentials change.
+  /// Every other session has the old auth hash, so they end on their next
+  /// request.
+  pub async fn refresh_session(user: User) -> Result<(), ServerFnError> {
+    use auth_domain::AuthSession;
+
+    let mut auth_session =
+      leptos_axum::extract::<AuthSession>().await.map_err(|_| {
+        tracing::error!("auth session not found");
+        ServerFnError::new("Internal error")
+      })?;
+    auth_session
+      .login(&AuthUser::from(user))
+      .await
+      .map_err(|e| {
+        tracing::error!("failed to refresh session: {e}");
+        ServerFnError::new("Internal error")
+      })
+  }
+
+  /// Keeps a passkey challenge in the session until the browser answers it,
+  /// replacing any earlier one.
+  pub async fn store_passkey_challenge(
+    challenge: PasskeyChallenge,
+  ) -> Result<(), ServerFnError> {
+    use auth_domain::AuthSession;
+
+    let auth_session =
+      leptos_axum::extract::<AuthSession>().await.map_err(|_| {
+        tracing::error!("auth session not found");
+        ServerFnError::new("Internal error")
+      })?;
+    auth_session
+      .session
+      .insert(PASSKEY_CHALLENGE_KEY, challenge)
+      .await
+      .map_err(|e| {
+        tracing::error!("failed to store passkey challenge: {e}");
+        ServerFnError::new("Internal error")
+      })
+  }
+
+  /// Turns the proof a form sent into a [`Reauthentication`], taking a
+  /// passkey's challenge out of the session.
+  ///
+  /// [`Reauthentication`]: auth_domain::Reauthentication
+  pub async fn reauthentication(
+    proof: super::IdentityProof,
+  ) -> Result<auth_domain::Reauthentication, ServerFnError> {
+    use auth_domain::Reauthentication;
+
+    if let Some(assertion) = proof.passkey {
+      let Some(challenge) = take_passkey_challenge().await? else {
+        return Err(ServerFnError::new("No passkey confirmation in progress"));
+      };
+      return Ok(Reauthentication::Passkey {
+        challenge,
+        assertion,
+      });
+    }
+    if let Some(token) = proof.link_token {
+      return Ok(Reauthentication::EmailLink(token));
+    }
+    Ok(Reauthentication::Password(
+      proof.password.unwrap_or_default(),
+    ))
+  }
+
+  /// Takes the session's passkey challenge, so each is answered only once.
+  pub async fn take_passkey_challenge(
+  ) -> Result<Option<PasskeyChallenge>, ServerFnError> {
+    use auth_domain::AuthSession;
+
+    let auth_session =
+      leptos_axum::extract::<AuthSession>().await.map_err(|_| {
+        tracing::error!("auth session not found");
+        ServerFnError::new("Internal error")
+      })?;
+    auth_session
+      .session
+      .remove(PASSKEY_CHALLENGE_KEY)
+      .await
+      .map_err(|e| {
+        tracing::error!("failed to take passkey challenge: {e}");
+        ServerFnError::new("Internal error")
+      })
+  }
+}
@@ -0,0 +1,78 @@
+#![cfg_attr(
+  debug_assertions,
+  expect(
+    clippy::items_after_statements,
+    reason = "axum::debug_handler triggers this"
+  )
+)]
+
+use std::io;
+
+use auth_domain::AuthSession;
+use axum::{
+  body::Body,
+  extract::State,
+  http::{
+    header::{CACHE_CONTROL, CONTENT_DISPOSITION, CONTENT_TYPE},
+    HeaderValue, Response, StatusCode,
+  },
+  response::IntoResponse,
+};
+use futures::{future, stream, StreamExt};
+use prime_domain::PrimeDomainService;
+use tokio_util::io::{ReaderStream, SyncIoBridge};
+
+/// How much of the archive may be buffered ahead of the client.
+const EXPORT_PIPE_CAPACITY: usize = 64 * 1024;
+
+/// Downloads an archive of everything the current user has stored, as
+/// written by
+/// [`write_user_data_export`](PrimeDomainService::write_user_data_export).
+///
+/// The archive is streamed to the client while it's written. If writing fails
+/// partway through, the response is aborted rather than ended, so the client
+/// doesn't mistake a truncated archive for a whole one.
+#[axum::debug_handler]
+pub async fn export_user_data(
+  State(pd): State<PrimeDomainService>,
+  auth_session: AuthSession,
+) -> Result<Response<Body>, Response<Body>> {
+  let Some(user) = auth_session.user else {
+    return Err((StatusCode::UNAUTHORIZED, "Unauthenticated").into_response());
+  };
+
+  let (reader, writer) = tokio::io::duplex(EXPORT_PIPE_CAPACITY);
+  let export = tokio::spawn(async move {
+    pd.write_user_data_export(user.id, SyncIoBridge::new(writer))
+      .await
+      .map(drop)
+  });
+  let failure = stream::once(async move {
+    let error = match export.await {
+      Ok(Ok(())) => return None,
+      Ok(Err(e)) => e.to_string(),
+      Err(e) => e.to_string(),
+    };
+    tracing::error!("failed to export user data: {error}");
+    Some(Err(io::Error::other(error)))
+  })
+  .filter_map(future::ready);
+  let archive = Body::from_stream(ReaderStream::new(reader).chain(failure));
+
+  Ok(
+    (
+      [
+        (CONTENT_TYPE, HeaderValue::from_static("application/x-tar")),
+        (
+          CONTENT_DISPOSITION,
+          HeaderValue::from_static(
+            "attachment; filename=\"picturepro-export.tar\"",
+          ),
+        ),
+        (CACHE_CONTROL, HeaderValue::from_static("no-store")),
+      ],
+      archive,
+    )
+      .into_response(),
+  )
+}
@@ -0,0 +1,120 @@
+use auth_domain::{AuthDomainService, AuthSession, OidcError};
+use axum::{
+  extract::{Path, Query, State},
+  response::{IntoResponse, Redirect, Response},
+};
+use base_components::utils::navigation::sanitize_auth_next_url;
+use models::{AuthUser, PendingOidcLogin, UserSubmittedAuthCredentials};
+use serde::{Deserialize, Serialize};
+
+/// The session key holding the login awaiting the provider's redirect.
+const PENDING_OIDC_LOGIN_KEY: &str = "oidc.pending";
+
+/// Where the login page sends users whose provider login failed.
+const OIDC_FAILED_URL: &str = "/log-in?error=provider";
+
+/// A login with a provider, kept in the session until it redirects back.
+#[derive(Serialize, Deserialize)]
+struct OidcLoginSession {
+  pending:  PendingOidcLogin,
+  next_url: String,
+}
+
+/// The query of the login URL.
+#[derive(Deserialize)]
+pub struct OidcLoginParams {
+  next: Option<String>,
+}
+
+/// The query of the provider's redirect back.
+#[derive(Deserialize)]
+pub struct OidcCallbackParams {
+  state: Option<String>,
+  code:  Option<String>,
+  error: Option<String>,
+}
+
+/// Sends the browser to log in with an `OpenID` Connect provider.
+pub async fn oidc_login(
+  Path(provider): Path<String>,
+  Query(params): Query<OidcLoginParams>,
+  State(auth_service): State<AuthDomainService>,
+  auth_session: AuthSession,
+) -> Response {
+  let authorization = match auth_service.begin_oidc_login(&provider).await {
+    Ok(authorization) => authorization,
+    Err(OidcError::UnknownProvider(_)) => {
+      return Redirect::to("/log-in").into_response();
+    }
+    Err(e) => {
+      tracing::error!("failed to begin login with {provider}: {e}");
+      return Redirect::to(OIDC_FAILED_URL).into_response();
+    }
+  };
+
+  let login = OidcLoginSession {
+    pending:  authorization.pending,
+    next_url: sanitize_auth_next_url(params.next),
+  };
+  if let Err(e) = auth_session
+    .session
+    .insert(PENDING_OIDC_LOGIN_KEY, login)
+    .await
+  {
+    tracing::error!("failed to store pending login: {e}");
+    return Redirect::to(OIDC_FAILED_URL).into_response();
+  }
+
+  Redirect::to(&authorization.url).into_response()
+}
+
+/// Finishes logging in with an `OpenID` Connect provider when it redirects
+/// the browser back.
+pub async fn oidc_callback(
+  Path(provider): Path<String>,
+  Query(params): Query<OidcCallbackParams>,
+  State(auth_service): State<AuthDomainService>,
+  mut auth_session: AuthSession,
+) -> Response {
+  // each login is answered once, however it goes
+  let login = match auth_session
+    .session
+    .remove::<OidcLoginSession>(PENDING_OIDC_LOGIN_KEY)
+    .await
+  {
+    Ok(Some(login)) if login.pending.provider == provider => login,
+    Ok(_) => return Redirect::to(OIDC_FAILED_URL).into_response(),
+    Err(e) => {
+      tracing::error!("failed to fetch pending login: {e}");
+      return Redirect::to(OIDC_FAILED_URL).into_response();
+    }
+  };
+  if let Some(error) = params.error {
+    tracing::info!("login with {provider} was refused: {error}");
+    return Redirect::to(OIDC_FAILED_URL).into_response();
+  }
+  let (Some(state), Some(code)) = (params.state, params.code) else {
+    return Redirect::to(OIDC_FAILED_URL).into_response();
+  };
+
+  let creds = UserSubmittedAuthCredentials::Oidc {
+    pending: login.pending,
+    state,
+    code,
+  };
+  let user = match auth_service.user_authenticate(creds).await {
+    Ok(Some(user)) => user,
+    Ok(None) => return Redirect::to(OIDC_FAILED_URL).into_response(),
+    Err(e) => {
+      tracing::error!("failed to log in with {provider}: {e}");
+      return Redirect::to(OIDC_FAILED_URL).into_response();
+    }
+  };
+
+  if let Err(e) = auth_session.login(&AuthUser::from(user)).await {
+    tracing::error!("failed to log in: {e}");
+    return Redirect::to(OIDC_FAILED_URL).into_response();
+  }
+
+  Redirect::to(&login.next_url).into_response()
+}
@@ -1,17 +1,71 @@
-use auth_domain::AuthDomainService;
-use axum::extract::FromRef;
+use std::sync::Arc;
+
+use auth_domain::{
+  mailer::{FileTransport, Mailer, SmtpSinkTransport},
+  AuthDomainService, OidcProviderConfig,
+};
+use axum::{extract::FromRef, http::HeaderName};
 use leptos::prelude::*;
 use miette::{Context, IntoDiagnostic, Result};
 use prime_domain::{
   repos::{
     db::{kv, Database},
     storage::StorageClient,
+    CacheConfig, CacheMetrics, CachingImageRepository,
+    CachingPhotoGroupRepository, CachingPhotoRepository,
+    LoginAttemptRepository, MasterKeyring, SnapshotRepository,
   },
-  PrimeDomainService,
+  ArtifactUrlSigner, PrimeDomainService,
 };
-use site_app::models::BaseUrl;
+use site_app::models::{BaseUrl, EmailAddress};
 use tower_sessions_kv_store::TowerSessionsKvStore;
 
+/// Reads the `OpenID` Connect providers from the environment.
+///
+/// `OIDC_PROVIDERS` lists provider IDs, e.g. `google,keycloak`. Each needs
+/// `OIDC_<ID>_CLIENT_ID` and `OIDC_<ID>_CLIENT_SECRET`, and
+/// `OIDC_<ID>_ISSUER` unless it's Google. `OIDC_<ID>_NAME` sets the
+/// name shown on the login button.
+fn oidc_providers_from_env() -> Result<Vec<OidcProviderConfig>> {
+  let Ok(ids) = std::env::var("OIDC_PROVIDERS") else {
+    return Ok(Vec::new());
+  };
+
+  ids
+    .split(',')
+    .map(str::trim)
+    .filter(|id| !id.is_empty())
+    .map(|id| {
+      let var = |name: &str| {
+        std::env::var(format!("OIDC_{}_{name}", id.to_uppercase()))
+      };
+      let required = |name: &str| {
+        var(name).into_diagnostic().with_context(|| {
+          format!(
+            "failed to read `OIDC_{}_{name}` environment variable",
+            id.to_uppercase()
+          )
+        })
+      };
+      let (default_issuer, default_name) = match id {
+        "google" => (Some("https://accounts.google.com"), "Google"),
+        _ => (None, id),
+      };
+
+      Ok(OidcProviderConfig {
+        id:            id.to_owned(),
+        name:          var("NAME").unwrap_or(default_name.to_owned()),
+        issuer:        match default_issuer {
+          Some(issuer) => var("ISSUER").unwrap_or(issuer.to_owned()),
+          None => required("ISSUER")?,
+        },
+        client_id:     required("CLIENT_ID")?,
+        client_secret: required("CLIENT_SECRET")?,
+      })
+    })
+    .collect()
+}
+
 #[derive(Clone, FromRef)]
 pub struct AppState {
   pub prime_domain_service: PrimeDomainService,
@@ -19,6 +73,10 @@ pub struct AppState {
   pub session_store:        TowerSessionsKvStore,
   pub leptos_options:       LeptosOptions,
   pub base_url:             BaseUrl,
+  pub cache_metrics:        Vec<Arc<CacheMetrics>>,
+  /// The request header holding the client's address, when a proxy in front
+  /// of the server sets it.
+  pub client_ip_header:     Option<HeaderName>,
 }
 
 impl AppState {
@@ -44,22 +102,96 @@ impl AppState {
         .unwrap_or("/tmp/picturepro-storage".to_owned()),
     );
 
+    let artifact_keyring = match std::env::var("ARTIFACT_MASTER_KEYS") {
+      Ok(keys) => Some(
+        keys
+          .parse::<MasterKeyring>()
+          .context("failed to parse `ARTIFACT_MASTER_KEYS`")?,
+      ),
+      Err(_) => {
+        tracing::warn!(
+          "`ARTIFACT_MASTER_KEYS` is not set; new artifacts will be stored \
+           unencrypted"
+        );
+        None
+      }
+    };
+
+    let url_signer = match std::env::var("DOWNLOAD_URL_SECRET") {
+      Ok(secret) => ArtifactUrlSigner::new(secret.as_bytes()),
+      Err(_) => {
+        tracing::warn!(
+          "`DOWNLOAD_URL_SECRET` is not set; signed download URLs will stop \
+           working on restart"
+        );
+        ArtifactUrlSigner::new_random()
+      }
+    };
+
+    // e.g. `Fly-Client-IP`; without it, throttling sees the proxy's address
+    let client_ip_header = std::env::var("CLIENT_IP_HEADER")
+      .ok()
+      .map(|h| h.parse::<HeaderName>())
+      .transpose()
+      .into_diagnostic()
+      .context("failed to parse `CLIENT_IP_HEADER` as a header name")?;
+
+    let mail_from = EmailAddress::try_new(
+      std::env::var("MAIL_FROM")
+        .unwrap_or("noreply@picturepro.localhost".to_owned()),
+    )
+    .into_diagnostic()
+    .context("failed to parse `MAIL_FROM` as an email address")?;
+    // `smtp:<host:port>` talks to a local sink; otherwise mail is written to
+    // a directory as `.eml` files
+    let mailer = match std::env::var("MAIL_TRANSPORT") {
+      Ok(transport) => match transport.strip_prefix("smtp:") {
+        Some(addr) => Mailer::new(mail_from, SmtpSinkTransport::new(addr)),
+        None => Mailer::new(
+          mail_from,
+          FileTransport::new(
+            transport.strip_prefix("file:").unwrap_or(&transport),
+          ),
+        ),
+      },
+      Err(_) => {
+        tracing::warn!(
+          "`MAIL_TRANSPORT` is not set; emails will be written to \
+           `/tmp/picturepro-mail`"
+        );
+        Mailer::new(mail_from, FileTransport::new("/tmp/picturepro-mail"))
+      }
+    };
+
     let kv_store = kv::KeyValueStore::new_redb(&kv_store_location)?;
 
     let session_store = TowerSessionsKvStore::new(kv_store.clone());
 
-    let image_repo = prime_domain::repos::ImageRepository::new(
-      Database::new_from_kv(kv_store.clone()),
-    );
-    let photo_repo = prime_domain::repos::PhotoRepository::new(
-      Database::new_from_kv(kv_store.clone()),
+    // the models fetched to render and serve every photo are cached
+    let image_repo = CachingImageRepository::new(
+      prime_domain::repos::ImageRepository::new(Database::new_from_kv(
+        kv_store.clone(),
+      )),
+      CacheConfig::default(),
     );
-    let photo_group_repo = prime_domain::repos::PhotoGroupRepository::new(
-      Database::new_from_kv(kv_store.clone()),
+    let photo_repo = CachingPhotoRepository::new(
+      prime_domain::repos::PhotoRepository::new(Database::new_from_kv(
+        kv_store.clone(),
+      )),
+      CacheConfig::default(),
     );
-    let user_repo = prime_domain::repos::UserRepository::new(
-      Database::new_from_kv(kv_store.clone()),
+    let photo_group_repo = CachingPhotoGroupRepository::new(
+      prime_domain::repos::PhotoGroupRepository::new(kv_store.clone()),
+      CacheConfig::default(),
     );
+    let mut cache_metrics = vec![
+      image_repo.metrics(),
+      photo_repo.metrics(),
+      photo_group_repo.metrics(),
+    ];
+    let user_repo = prime_domain::repos::UserRepository::new(kv_store.clone());
+    let login_attempt_repo = LoginAttemptRepository::new(kv_store.clone());
+    let snapshot_repo = SnapshotRepository::new(kv_store.clone());
 
     let storage_credentials = prime_domain::models::StorageCredentials::Local(
       prime_domain::models::LocalStorageCredentials(storage_location),
@@ -69,6 +201,7 @@ impl AppState {
     let artifact_repo = prime_domain::repos::ArtifactRepository::new(
       artifact_storage_client,
       Database::new_from_kv(kv_store),
+      artifact_keyring,
     );
 
     let image_processor = prime_domain::imaging::ImageProcessor::new();
@@ -80,8 +213,27 @@ impl AppState {
       photo_repo,
       photo_group_repo,
       user_repo.clone(),
+      snapshot_repo,
+      url_signer,
     );
-    let auth_domain_service = AuthDomainService::new(user_repo);
+    let auth_domain_service = AuthDomainService::new(
+      user_repo,
+      login_attempt_repo,
+      mailer,
+      base_url.clone(),
+    )
+    .with_oidc_providers(oidc_providers_from_env()?);
+    let auth_domain_service = match std::env::var("MAGIC_LINK_SECRET") {
+      Ok(secret) => auth_domain_service.with_link_secret(secret.as_bytes()),
+      Err(_) => {
+        tracing::warn!(
+          "`MAGIC_LINK_SECRET` is not set; emailed signup links will stop \
+           working on restart"
+        );
+        auth_domain_service
+      }
+    };
+    cache_metrics.push(prime_domain_service.thumbnail_cache_metrics());
 
     Ok(Self {
       prime_domain_service,
@@ -89,6 +241,8 @@ impl AppState {
       session_store,
       leptos_options: l_opts,
       base_url,
+      cache_metrics,
+      client_ip_header,
     })
   }
 }
@@ -0,0 +1,185 @@
+use std::{
+  fs::File,
+  io::{BufReader, BufWriter, Write},
+  path::{Path, PathBuf},
+  time::Duration,
+};
+
+use miette::{Context, IntoDiagnostic, Result};
+use prime_domain::{
+  repos::MigrationOptions, BackupKind, BackupManifest, PrimeDomainService,
+};
+
+/// Takes a backup to `BACKUP_DIR` every `BACKUP_INTERVAL_HOURS` hours, a full
+/// one every `BACKUP_FULL_EVERY` backups and incremental ones in between. The
+/// first backup after startup is always full.
+pub struct BackupSchedule {
+  dir:        PathBuf,
+  interval:   Duration,
+  full_every: u32,
+}
+
+impl BackupSchedule {
+  /// Reads the schedule from the environment. Returns `None` if
+  /// `BACKUP_DIR` is not set.
+  pub fn from_env() -> Result<Option<Self>> {
+    let Ok(dir) = std::env::var("BACKUP_DIR") else {
+      return Ok(None);
+    };
+    let interval_hours = match std::env::var("BACKUP_INTERVAL_HOURS") {
+      Ok(hours) => hours
+        .parse::<u64>()
+        .into_diagnostic()
+        .context("failed to parse `BACKUP_INTERVAL_HOURS`")?,
+      Err(_) => 24,
+    };
+    let full_every = match std::env::var("BACKUP_FULL_EVERY") {
+      Ok(count) => count
+        .parse::<u32>()
+        .into_diagnostic()
+        .context("failed to parse `BACKUP_FULL_EVERY`")?,
+      Err(_) => 7,
+    };
+
+    Ok(Some(Self {
+      dir:        dir.into(),
+      interval:   Duration::from_secs(interval_hours.max(1) * 60 * 60),
+      full_every: full_every.max(1),
+    }))
+  }
+
+  /// Takes backups forever.
+  pub async fn run(self, prime_domain_service: PrimeDomainService) {
+    let mut ticker = tokio::time::interval(self.interval);
+    let mut last: Option<BackupManifest> = None;
+    let mut since_full = 0;
+
+    loop {
+      ticker.tick().await;
+
+      let kind = match &last {
+        Some(last) if since_full < self.full_every => BackupKind::Incremental {
+          base_snapshot_at: last.snapshot_at,
+        },
+        _ => BackupKind::Full,
+      };
+      match write_backup(&prime_domain_service, &self.dir, kind).await {
+        Ok((path, manifest)) => {
+          tracing::info!(
+            "wrote backup {} with {} records and data of {} artifacts",
+            path.display(),
+            manifest.records.values().sum::<usize>(),
+            manifest.artifact_data,
+          );
+          since_full = if manifest.is_incremental() {
+            since_full + 1
+          } else {
+            1
+          };
+          last = Some(manifest);
+        }
+        // the next backup will be full, so the chain isn't left with a gap
+        Err(e) => {
+          tracing::error!("failed to write backup: {e:?}");
+          last = None;
+        }
+      }
+    }
+  }
+}
+
+/// Writes a backup archive into `dir`, only giving it its final name once
+/// it's complete.
+async fn write_backup(
+  prime_domain_service: &PrimeDomainService,
+  dir: &Path,
+  kind: BackupKind,
+) -> Result<(PathBuf, BackupManifest)> {
+  let name = format!(
+    "picturepro-{}-{}.tar.zst",
+    chrono::Utc::now().format("%Y%m%dT%H%M%SZ"),
+    match kind {
+      BackupKind::Full => "full",
+      BackupKind::Incremental { .. } => "incremental",
+    },
+  );
+  let path = dir.join(&name);
+  let partial_path = dir.join(format!(".{name}.partial"));
+
+  std::fs::create_dir_all(dir)
+    .into_diagnostic()
+    .context("failed to create backup directory")?;
+  let writer = BufWriter::new(
+    File::create(&partial_path)
+      .into_diagnostic()
+      .context("failed to create backup file")?,
+  );
+  let (manifest, mut writer) = prime_domain_service
+    .write_backup(writer, kind)
+    .await
+    .into_diagnostic()
+    .context("failed to write backup archive")?;
+  writer
+    .flush()
+    .into_diagnostic()
+    .context("failed to flush backup file")?;
+  writer
+    .get_ref()
+    .sync_all()
+    .into_diagnostic()
+    .context("failed to sync backup file")?;
+  std::fs::rename(&partial_path, &path)
+    .into_diagnostic()
+    .context("failed to rename backup file")?;
+
+  Ok((path, manifest))
+}
+
+/// Restores a full backup and the incremental backups built on it, in order,
+/// then rewrites every record so that all index entries are rebuilt.
+pub async fn restore(
+  prime_domain_service: &PrimeDomainService,
+  paths: &[PathBuf],
+) -> Result<()> {
+  let archives = paths
+    .iter()
+    .map(|path| {
+      File::open(path)
+        .map(BufReader::new)
+        .into_diagnostic()
+        .with_context(|| format!("failed to open `{}`", path.display()))
+    })
+    .collect::<Result<Vec<_>>>()?;
+
+  let report = prime_domain_service
+    .restore_backup(archives)
+    .await
+    .into_diagnostic()
+    .context("failed to restore backup")?;
+  tracing::info!(
+    "restored {} archives: {:?} records, data of {} artifacts, pruned {} \
+     records",
+    report.archives,
+    report.records,
+    report.artifact_data,
+    report.pruned,
+  );
+
+  let reports = prime_domain_service
+    .migrate_models(MigrationOptions {
+      dry_run:         false,
+      rebuild_indices: true,
+    })
+    .await
+    .into_diagnostic()
+    .context("failed to rebuild indices of restored models")?;
+  for report in reports {
+    tracing::info!(
+      "rebuilt indices of {} {} records",
+      report.written,
+      report.table
+    );
+  }
+
+  Ok(())
+}
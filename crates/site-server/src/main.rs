@@ -1,11 +1,16 @@
 mod app_state;
+mod backup;
 mod file_and_error_handler;
+mod session_activity;
+
+use std::net::{IpAddr, SocketAddr};
 
 use auth_domain::AuthSession;
 use axum::{
   body::Body,
-  extract::{Request, State},
+  extract::{ConnectInfo, Request, State},
   handler::Handler,
+  middleware,
   response::IntoResponse,
   routing::{get, post},
   Router,
@@ -20,15 +25,49 @@ use tower_http::{compression::CompressionLayer, trace::TraceLayer};
 
 use self::app_state::AppState;
 
+/// How often cache hit rates are logged.
+const CACHE_METRICS_INTERVAL: std::time::Duration =
+  std::time::Duration::from_secs(15 * 60);
+
+/// The client's address: from the configured header if a proxy sets one, or
+/// else the address the connection came from.
+fn client_ip(
+  app_state: &AppState,
+  peer: SocketAddr,
+  request: &Request,
+) -> IpAddr {
+  app_state
+    .client_ip_header
+    .as_ref()
+    .and_then(|header| request.headers().get(header))
+    .and_then(|value| value.to_str().ok())
+    .and_then(|value| value.trim().parse().ok())
+    .unwrap_or(peer.ip())
+}
+
+fn client_locale(request: &Request) -> models::Locale {
+  request
+    .headers()
+    .get(axum::http::header::ACCEPT_LANGUAGE)
+    .and_then(|value| value.to_str().ok())
+    .and_then(models::Locale::from_accept_language)
+    .unwrap_or_default()
+}
+
 fn context_provider(
   app_state: AppState,
   auth_session: AuthSession,
+  client_ip: IpAddr,
+  locale: models::Locale,
 ) -> impl Fn() + Clone {
   move || {
     provide_context(app_state.prime_domain_service.clone());
     provide_context(app_state.auth_domain_service.clone());
     provide_context(models::AuthStatus(auth_session.user.clone()));
+    provide_context(models::ClientIp(Some(client_ip)));
+    provide_context(locale.clone());
     provide_context(app_state.base_url.clone());
+    provide_context(app_state.session_store.clone());
   }
 }
 
@@ -36,11 +75,14 @@ fn context_provider(
 async fn leptos_routes_handler(
   auth_session: AuthSession,
   State(app_state): State<AppState>,
+  ConnectInfo(peer): ConnectInfo<SocketAddr>,
   request: Request<Body>,
 ) -> axum::response::Response {
   let leptos_options = app_state.leptos_options.clone();
+  let client = client_ip(&app_state, peer, &request);
+  let locale = client_locale(&request);
   leptos_axum::render_app_to_stream_with_context(
-    context_provider(app_state.clone(), auth_session),
+    context_provider(app_state.clone(), auth_session, client, locale),
     move || shell(leptos_options.clone()),
   )(request)
   .await
@@ -51,16 +93,23 @@ async fn leptos_routes_handler(
 async fn server_fn_handler(
   auth_session: AuthSession,
   State(app_state): State<AppState>,
+  ConnectInfo(peer): ConnectInfo<SocketAddr>,
   request: Request<Body>,
 ) -> axum::response::Response {
+  let client = client_ip(&app_state, peer, &request);
+  let locale = client_locale(&request);
   leptos_axum::handle_server_fns_with_context(
-    context_provider(app_state, auth_session),
+    context_provider(app_state, auth_session, client, locale),
     request,
   )
   .await
   .into_response()
 }
 
+fn env_flag(name: &str) -> bool {
+  std::env::var(name).is_ok_and(|v| matches!(v.as_str(), "1" | "true"))
+}
+
 #[tokio::main]
 async fn main() -> miette::Result<()> {
   tracing_subscriber::fmt()
@@ -88,8 +137,82 @@ async fn main() -> miette::Result<()> {
     .context("failed to initialize app state")?;
   tracing::info!("app state initialized");
 
+  // `site-server restore <archive>...` restores backups instead of serving
+  let mut args = std::env::args().skip(1);
+  if args.next().as_deref() == Some("restore") {
+    let paths = args.map(std::path::PathBuf::from).collect::<Vec<_>>();
+    return self::backup::restore(&app_state.prime_domain_service, &paths)
+      .await;
+  }
+
+  // upcast models stored at older schema versions before serving requests
+  let migration_options = prime_domain::repos::MigrationOptions {
+    dry_run:         env_flag("MIGRATIONS_DRY_RUN"),
+    rebuild_indices: env_flag("MIGRATIONS_REBUILD_INDICES"),
+  };
+  let reports = app_state
+    .prime_domain_service
+    .migrate_models(migration_options)
+    .await
+    .into_diagnostic()
+    .context("failed to migrate stored models")?;
+  for report in reports {
+    if report.outdated_count() == 0 && report.written == 0 {
+      continue;
+    }
+    tracing::info!(
+      "migrated {} table: examined {}, outdated by version {:?}, wrote {}{}",
+      report.table,
+      report.examined,
+      report.outdated,
+      report.written,
+      if migration_options.dry_run {
+        " (dry run)"
+      } else {
+        ""
+      },
+    );
+  }
+
+  // re-wrap artifact data keys left over from a master key rotation
+  tokio::spawn({
+    let prime_domain_service = app_state.prime_domain_service.clone();
+    async move {
+      match prime_domain_service.rewrap_artifact_keys().await {
+        Ok(0) => (),
+        Ok(count) => {
+          tracing::info!("re-wrapped data keys of {count} artifacts");
+        }
+        Err(e) => tracing::error!("failed to re-wrap artifact data keys: {e}"),
+      }
+    }
+  });
+
+  if let Some(schedule) = self::backup::BackupSchedule::from_env()? {
+    tokio::spawn(schedule.run(app_state.prime_domain_service.clone()));
+  } else {
+    tracing::warn!("`BACKUP_DIR` is not set; backups are disabled");
+  }
+
+  tokio::spawn({
+    let cache_metrics = app_state.cache_metrics.clone();
+    async move {
+      let mut ticker = tokio::time::interval(CACHE_METRICS_INTERVAL);
+      // the first tick completes immediately
+      ticker.tick().await;
+      loop {
+        ticker.tick().await;
+        for metrics in &cache_metrics {
+          tracing::info!("{} cache: {}", metrics.name(), metrics.snapshot());
+        }
+      }
+    }
+  });
+
+  // `Lax` so the session survives `OpenID` Connect providers redirecting back
   let session_layer =
-    tower_sessions::SessionManagerLayer::new(app_state.session_store.clone());
+    tower_sessions::SessionManagerLayer::new(app_state.session_store.clone())
+      .with_same_site(tower_sessions::cookie::SameSite::Lax);
   let auth_layer = AuthManagerLayerBuilder::new(
     app_state.auth_domain_service.clone(),
     session_layer,
@@ -116,6 +239,22 @@ async fn main() -> miette::Result<()> {
       "/api/photo_thumbnail/{id}",
       get(site_app::server_fns::fetch_photo_thumbnail),
     )
+    .route(
+      "/api/download/{id}",
+      get(site_app::server_fns::download_artifact),
+    )
+    .route(
+      "/api/account/export",
+      get(site_app::server_fns::export_user_data),
+    )
+    .route(
+      "/auth/oidc/{provider}",
+      get(site_app::server_fns::oidc_login),
+    )
+    .route(
+      "/auth/oidc/{provider}/callback",
+      get(site_app::server_fns::oidc_callback),
+    )
     .route("/api/{*fn_name}", post(server_fn_handler))
     .route(
       "/photo-group/{id}/qr",
@@ -126,7 +265,12 @@ async fn main() -> miette::Result<()> {
     .leptos_routes_with_handler(routes, leptos_routes_handler)
     .merge(api_router)
     .route_service("/{*path}", static_service)
-    .with_state(app_state)
+    .with_state(app_state.clone())
+    // inside the auth layer, which it reads the session from
+    .layer(middleware::from_fn_with_state(
+      app_state,
+      self::session_activity::track_session_activity,
+    ))
     .layer(TraceLayer::new_for_http())
     .layer(auth_layer);
 
@@ -137,10 +281,13 @@ async fn main() -> miette::Result<()> {
     .into_diagnostic()
     .with_context(|| format!("failed to bind listener to `{addr}`"))?;
   tracing::info!("listening on http://{}", &addr);
-  axum::serve(listener, app)
-    .await
-    .into_diagnostic()
-    .context("failed to serve app")?;
+  axum::serve(
+    listener,
+    app.into_make_service_with_connect_info::<SocketAddr>(),
+  )
+  .await
+  .into_diagnostic()
+  .context("failed to serve app")?;
 
   Ok(())
 }
@@ -0,0 +1,77 @@
+use std::net::SocketAddr;
+
+use auth_domain::AuthSession;
+use axum::{
+  extract::{ConnectInfo, Request, State},
+  http::header::USER_AGENT,
+  middleware::Next,
+  response::Response,
+};
+use chrono::TimeDelta;
+use site_app::models::{SessionActivity, Ulid};
+
+use crate::{app_state::AppState, client_ip};
+
+/// How stale a session's last-seen time may get before it's rewritten. Keeps
+/// every request from writing to the session store.
+const LAST_SEEN_RESOLUTION: TimeDelta = TimeDelta::minutes(5);
+
+/// Records where and when each logged-in session is used, so that users can
+/// see where they're logged in.
+pub async fn track_session_activity(
+  State(app_state): State<AppState>,
+  auth_session: AuthSession,
+  ConnectInfo(peer): ConnectInfo<SocketAddr>,
+  request: Request,
+  next: Next,
+) -> Response {
+  if auth_session.user.is_some() {
+    let now = chrono::Utc::now();
+    let ip = Some(client_ip(&app_state, peer, &request).to_string());
+    let user_agent = request
+      .headers()
+      .get(USER_AGENT)
+      .and_then(|value| value.to_str().ok())
+      .map(ToOwned::to_owned);
+
+    let session = &auth_session.session;
+    let existing = session
+      .get::<SessionActivity>(SessionActivity::SESSION_KEY)
+      .await
+      .unwrap_or_else(|e| {
+        tracing::warn!("failed to read session activity: {e}");
+        None
+      });
+    let activity = match existing {
+      Some(activity)
+        if activity.ip == ip
+          && activity.user_agent == user_agent
+          && now - activity.last_seen_at < LAST_SEEN_RESOLUTION =>
+      {
+        None
+      }
+      Some(activity) => Some(SessionActivity {
+        user_agent,
+        ip,
+        last_seen_at: now,
+        ..activity
+      }),
+      None => Some(SessionActivity {
+        handle: Ulid::new().to_string(),
+        user_agent,
+        ip,
+        first_seen_at: now,
+        last_seen_at: now,
+      }),
+    };
+    if let Some(activity) = activity {
+      if let Err(e) =
+        session.insert(SessionActivity::SESSION_KEY, activity).await
+      {
+        tracing::warn!("failed to record session activity: {e}");
+      }
+    }
+  }
+
+  next.run(request).await
+}
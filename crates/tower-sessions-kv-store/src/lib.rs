@@ -1,12 +1,16 @@
 //! A key-value store backend for the tower-sessions crate.
+//!
+//! Besides the session records, the store keeps an index of session ids for
+//! each logged-in user, so that a user's sessions can be listed, and purged
+//! at once. Ids of sessions that expired, ended, or moved to another account
+//! are pruned from a user's index whenever it's saved to or listed.
 
 use std::{borrow::Borrow, sync::LazyLock};
 
 use kv::{Key, KeyValueStore, KvPrimitive, KvTransaction, StrictSlug, Value};
+pub use tower_sessions::session::{Id, Record};
 use tower_sessions::{
-  session::{Id, Record},
-  session_store::Error,
-  SessionStore,
+  cookie::time::OffsetDateTime, session_store::Error, SessionStore,
 };
 
 /// A key-value store backend for the tower-sessions crate.
@@ -19,15 +23,235 @@ impl TowerSessionsKvStore {
   /// Create a new key-value store backend.
   #[must_use]
   pub fn new(kv: KeyValueStore) -> Self { Self { kv } }
+
+  /// Delete every session that the given user is logged into. Returns how
+  /// many sessions were deleted.
+  ///
+  /// `user_id` is compared against the id `axum-login` stores in the
+  /// session, as a string.
+  pub async fn delete_sessions_for_user(
+    &self,
+    user_id: &str,
+  ) -> Result<usize, Error> {
+    let mut txn =
+      self.kv.begin_pessimistic_transaction().await.map_err(|e| {
+        Error::Backend(format!("Failed to start pessimistic transaction: {e}"))
+      })?;
+
+    let result = async {
+      let index_key = user_id_to_index_key(user_id);
+      let index = decode_index(txn.get(&index_key).await.map_err(|e| {
+        Error::Backend(format!("Failed to get user session index: {e}"))
+      })?)?;
+
+      let mut deleted = 0;
+      for session_id in index {
+        let Ok(session_id) = session_id.parse::<Id>() else {
+          continue;
+        };
+        let key = session_id_to_key(&session_id);
+        let record: Option<Record> = txn
+          .get(&key)
+          .await
+          .map_err(|e| {
+            Error::Backend(format!("Failed to get session record: {e}"))
+          })?
+          .map(|v| v.deserialize())
+          .transpose()
+          .map_err(|e| {
+            Error::Decode(format!("Failed to deserialize session record: {e}"))
+          })?;
+        // the session may have since logged out, or into another account
+        if record.as_ref().and_then(record_user_id).as_deref() != Some(user_id)
+        {
+          continue;
+        }
+        txn.delete(&key).await.map_err(|e| {
+          Error::Backend(format!("Failed to delete session record: {e}"))
+        })?;
+        deleted += 1;
+      }
+
+      txn.delete(&index_key).await.map_err(|e| {
+        Error::Backend(format!("Failed to delete user session index: {e}"))
+      })?;
+      Ok(deleted)
+    }
+    .await;
+    let deleted = match result {
+      Ok(deleted) => deleted,
+      Err(e) => {
+        txn.rollback().await.map_err(|e| {
+          Error::Backend(format!("Failed to rollback transaction: {e}"))
+        })?;
+        return Err(e);
+      }
+    };
+
+    if let Err(e) = txn.commit().await {
+      txn.rollback().await.map_err(|e| {
+        Error::Backend(format!("Failed to rollback transaction: {e}"))
+      })?;
+      return Err(Error::Backend(format!("Failed to commit transaction: {e}")));
+    }
+
+    Ok(deleted)
+  }
+
+  /// List the unexpired sessions that the given user is logged into.
+  ///
+  /// `user_id` is compared as in [`delete_sessions_for_user`].
+  ///
+  /// [`delete_sessions_for_user`]: Self::delete_sessions_for_user
+  pub async fn sessions_for_user(
+    &self,
+    user_id: &str,
+  ) -> Result<Vec<Record>, Error> {
+    let mut txn =
+      self.kv.begin_pessimistic_transaction().await.map_err(|e| {
+        Error::Backend(format!("Failed to start pessimistic transaction: {e}"))
+      })?;
+
+    let result = live_sessions(&mut txn, user_id).await;
+    let records = match result {
+      Ok(records) => records,
+      Err(e) => {
+        txn.rollback().await.map_err(|e| {
+          Error::Backend(format!("Failed to rollback transaction: {e}"))
+        })?;
+        return Err(e);
+      }
+    };
+
+    if let Err(e) = txn.commit().await {
+      txn.rollback().await.map_err(|e| {
+        Error::Backend(format!("Failed to rollback transaction: {e}"))
+      })?;
+      return Err(Error::Backend(format!("Failed to commit transaction: {e}")));
+    }
+
+    Ok(records)
+  }
+
+  /// Delete one session, if the given user is logged into it. Returns
+  /// whether it was deleted.
+  pub async fn delete_session_for_user(
+    &self,
+    user_id: &str,
+    session_id: &Id,
+  ) -> Result<bool, Error> {
+    let Some(record) = self.load(session_id).await? else {
+      return Ok(false);
+    };
+    if record_user_id(&record).as_deref() != Some(user_id) {
+      return Ok(false);
+    }
+    self.delete(session_id).await?;
+    Ok(true)
+  }
 }
 
 static SESSION_NS_SEGMENT: LazyLock<StrictSlug> =
   LazyLock::new(|| StrictSlug::new("session".to_string()));
 
+static USER_SESSIONS_NS_SEGMENT: LazyLock<StrictSlug> =
+  LazyLock::new(|| StrictSlug::new("user_sessions".to_string()));
+
+/// The session data key that `axum-login` stores the logged-in user under.
+const AUTH_DATA_KEY: &str = "axum-login.data";
+
 fn session_id_to_key(id: &Id) -> Key {
   Key::new_lazy(&SESSION_NS_SEGMENT).with(StrictSlug::new(id.to_string()))
 }
 
+fn user_id_to_index_key(user_id: &str) -> Key {
+  Key::new_lazy(&USER_SESSIONS_NS_SEGMENT)
+    .with(StrictSlug::new(user_id.to_string()))
+}
+
+/// Pulls the id of the logged-in user, if any, out of a session record.
+fn record_user_id(record: &Record) -> Option<String> {
+  let user_id = record.data.get(AUTH_DATA_KEY)?.get("user_id")?;
+  match user_id.as_str() {
+    Some(user_id) => Some(user_id.to_string()),
+    None if user_id.is_null() => None,
+    None => Some(user_id.to_string()),
+  }
+}
+
+fn decode_index(value: Option<Value>) -> Result<Vec<String>, Error> {
+  value
+    .map(|v| v.deserialize())
+    .transpose()
+    .map(Option::unwrap_or_default)
+    .map_err(|e| {
+      Error::Decode(format!("Failed to deserialize user session index: {e}"))
+    })
+}
+
+fn encode_index(index: &[String]) -> Result<Value, Error> {
+  Value::serialize(&index).map_err(|e| {
+    Error::Encode(format!("Failed to serialize user session index: {e}"))
+  })
+}
+
+/// Reads the unexpired sessions in a user's index that they're still logged
+/// into, pruning the index down to them if any others were there.
+async fn live_sessions<T: KvPrimitive>(
+  txn: &mut T,
+  user_id: &str,
+) -> Result<Vec<Record>, Error> {
+  let index_key = user_id_to_index_key(user_id);
+  let index = decode_index(txn.get(&index_key).await.map_err(|e| {
+    Error::Backend(format!("Failed to get user session index: {e}"))
+  })?)?;
+
+  let now = OffsetDateTime::now_utc();
+  let mut records = Vec::with_capacity(index.len());
+  for session_id in &index {
+    let Ok(session_id) = session_id.parse::<Id>() else {
+      continue;
+    };
+    let record: Option<Record> = txn
+      .get(&session_id_to_key(&session_id))
+      .await
+      .map_err(|e| {
+        Error::Backend(format!("Failed to get session record: {e}"))
+      })?
+      .map(|v| v.deserialize())
+      .transpose()
+      .map_err(|e| {
+        Error::Decode(format!("Failed to deserialize session record: {e}"))
+      })?;
+    records.extend(record.filter(|r| {
+      r.expiry_date > now && record_user_id(r).as_deref() == Some(user_id)
+    }));
+  }
+
+  if records.len() < index.len() {
+    let pruned = records.iter().map(|r| r.id.to_string()).collect::<Vec<_>>();
+    write_index(txn, &index_key, &pruned).await?;
+  }
+  Ok(records)
+}
+
+/// Writes a user's session index, removing it once it's empty.
+async fn write_index<T: KvPrimitive>(
+  txn: &mut T,
+  index_key: &Key,
+  index: &[String],
+) -> Result<(), Error> {
+  if index.is_empty() {
+    txn.delete(index_key).await.map_err(|e| {
+      Error::Backend(format!("Failed to delete user session index: {e}"))
+    })
+  } else {
+    txn.put(index_key, encode_index(index)?).await.map_err(|e| {
+      Error::Backend(format!("Failed to put user session index: {e}"))
+    })
+  }
+}
+
 #[async_trait::async_trait]
 impl SessionStore for TowerSessionsKvStore {
   async fn save(&self, session_record: &Record) -> Result<(), Error> {
@@ -41,19 +265,33 @@ impl SessionStore for TowerSessionsKvStore {
         Error::Backend(format!("Failed to start pessimistic transaction: {e}"))
       })?;
 
-    let mut txn = {
-      if let Err(e) = txn.put(&key, value).await.map_err(|e| {
+    let result = async {
+      txn.put(&key, value).await.map_err(|e| {
         Error::Backend(format!("Failed to put session record: {e}"))
-      }) {
-        txn.rollback().await.map_err(|e| {
-          Error::Backend(format!("Failed to rollback transaction: {e}"))
-        })?;
-        return Err(Error::Backend(format!(
-          "Failed to put session record: {e}"
-        )));
+      })?;
+
+      let Some(user_id) = record_user_id(session_record) else {
+        return Ok(());
+      };
+      let mut index = live_sessions(&mut txn, &user_id)
+        .await?
+        .iter()
+        .map(|r| r.id.to_string())
+        .collect::<Vec<_>>();
+      let session_id = session_record.id.to_string();
+      if index.contains(&session_id) {
+        return Ok(());
       }
-      txn
-    };
+      index.push(session_id);
+      write_index(&mut txn, &user_id_to_index_key(&user_id), &index).await
+    }
+    .await;
+    if let Err(e) = result {
+      txn.rollback().await.map_err(|e| {
+        Error::Backend(format!("Failed to rollback transaction: {e}"))
+      })?;
+      return Err(e);
+    }
 
     if let Err(e) = txn.commit().await {
       txn.rollback().await.map_err(|e| {
@@ -119,19 +357,43 @@ impl SessionStore for TowerSessionsKvStore {
 
     let key = session_id_to_key(session_id);
 
-    let mut txn = {
-      if let Err(e) = txn.delete(&key).await.map_err(|e| {
-        Error::Backend(format!("Failed to delete session record: {e}"))
-      }) {
-        txn.rollback().await.map_err(|e| {
-          Error::Backend(format!("Failed to rollback transaction: {e}"))
+    let result = async {
+      let record: Option<Record> = txn
+        .get(&key)
+        .await
+        .map_err(|e| {
+          Error::Backend(format!("Failed to get session record: {e}"))
+        })?
+        .map(|v| v.deserialize())
+        .transpose()
+        .map_err(|e| {
+          Error::Decode(format!("Failed to deserialize session record: {e}"))
         })?;
-        return Err(Error::Backend(format!(
-          "Failed to delete session record: {e}"
-        )));
-      }
-      txn
-    };
+
+      txn.delete(&key).await.map_err(|e| {
+        Error::Backend(format!("Failed to delete session record: {e}"))
+      })?;
+
+      // drop the session from its user's index
+      let Some(user_id) = record.as_ref().and_then(record_user_id) else {
+        return Ok(());
+      };
+      let index_key = user_id_to_index_key(&user_id);
+      let mut index =
+        decode_index(txn.get(&index_key).await.map_err(|e| {
+          Error::Backend(format!("Failed to get user session index: {e}"))
+        })?)?;
+      let session_id = session_id.to_string();
+      index.retain(|id| *id != session_id);
+      write_index(&mut txn, &index_key, &index).await
+    }
+    .await;
+    if let Err(e) = result {
+      txn.rollback().await.map_err(|e| {
+        Error::Backend(format!("Failed to rollback transaction: {e}"))
+      })?;
+      return Err(e);
+    }
 
     if let Err(e) = txn.commit().await {
       txn.rollback().await.map_err(|e| {
@@ -143,3 +405,120 @@ impl SessionStore for TowerSessionsKvStore {
     Ok(())
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+
+  use tower_sessions::cookie::time::Duration;
+
+  use super::*;
+
+  fn store() -> TowerSessionsKvStore {
+    TowerSessionsKvStore::new(KeyValueStore::new_mock())
+  }
+
+  /// A session logged into `user_id`, expiring `ttl` from now.
+  fn record(user_id: Option<&str>, ttl: Duration) -> Record {
+    let mut data = HashMap::new();
+    if let Some(user_id) = user_id {
+      data.insert(
+        AUTH_DATA_KEY.to_owned(),
+        serde_json::json!({ "user_id": user_id }),
+      );
+    }
+    Record {
+      id: Id::default(),
+      data,
+      expiry_date: OffsetDateTime::now_utc() + ttl,
+    }
+  }
+
+  /// The raw session index for `user_id`.
+  async fn index(store: &TowerSessionsKvStore, user_id: &str) -> Vec<String> {
+    let mut txn = store.kv.begin_optimistic_transaction().await.unwrap();
+    let index =
+      decode_index(txn.get(&user_id_to_index_key(user_id)).await.unwrap())
+        .unwrap();
+    txn.commit().await.unwrap();
+    index
+  }
+
+  async fn listed_ids(store: &TowerSessionsKvStore, user_id: &str) -> Vec<Id> {
+    let mut ids = store
+      .sessions_for_user(user_id)
+      .await
+      .unwrap()
+      .into_iter()
+      .map(|r| r.id)
+      .collect::<Vec<_>>();
+    ids.sort_by_key(ToString::to_string);
+    ids
+  }
+
+  #[tokio::test]
+  async fn sessions_are_listed_and_revoked_per_user() {
+    let store = store();
+    let first = record(Some("alice"), Duration::hours(1));
+    let second = record(Some("alice"), Duration::hours(1));
+    let other = record(Some("bob"), Duration::hours(1));
+    let anonymous = record(None, Duration::hours(1));
+    for r in [&first, &second, &other, &anonymous] {
+      store.save(r).await.unwrap();
+    }
+    // saving again doesn't index the session twice
+    store.save(&first).await.unwrap();
+
+    let mut expected = vec![first.id, second.id];
+    expected.sort_by_key(ToString::to_string);
+    assert_eq!(listed_ids(&store, "alice").await, expected);
+    assert_eq!(index(&store, "alice").await.len(), 2);
+    assert_eq!(listed_ids(&store, "bob").await, vec![other.id]);
+
+    // users can only revoke their own sessions
+    assert!(!store
+      .delete_session_for_user("bob", &first.id)
+      .await
+      .unwrap());
+    assert!(store
+      .delete_session_for_user("alice", &first.id)
+      .await
+      .unwrap());
+    assert_eq!(listed_ids(&store, "alice").await, vec![second.id]);
+    assert_eq!(index(&store, "alice").await, vec![second.id.to_string()]);
+
+    assert_eq!(store.delete_sessions_for_user("alice").await.unwrap(), 1);
+    assert!(store.load(&second.id).await.unwrap().is_none());
+    assert!(index(&store, "alice").await.is_empty());
+    assert!(store.load(&other.id).await.unwrap().is_some());
+    assert!(store.load(&anonymous.id).await.unwrap().is_some());
+  }
+
+  #[tokio::test]
+  async fn index_drops_expired_and_moved_sessions() {
+    let store = store();
+    let expired = record(Some("alice"), Duration::hours(-1));
+    let live = record(Some("alice"), Duration::hours(1));
+    store.save(&expired).await.unwrap();
+    store.save(&live).await.unwrap();
+    // saving a session prunes the ones that expired
+    assert_eq!(index(&store, "alice").await, vec![live.id.to_string()]);
+
+    // a session that logs into another account leaves the old index when
+    // it's next listed
+    let mut moved = live.clone();
+    moved.data.insert(
+      AUTH_DATA_KEY.to_owned(),
+      serde_json::json!({ "user_id": "bob" }),
+    );
+    store.save(&moved).await.unwrap();
+    assert_eq!(index(&store, "alice").await.len(), 1);
+    assert!(listed_ids(&store, "alice").await.is_empty());
+    assert!(index(&store, "alice").await.is_empty());
+    assert_eq!(listed_ids(&store, "bob").await, vec![live.id]);
+
+    // as does one that logs out
+    store.delete(&live.id).await.unwrap();
+    assert!(index(&store, "bob").await.is_empty());
+  }
+}